@@ -0,0 +1,76 @@
+// Documents whose destination name tree is built from the
+// input: the kids of every node are arbitrary objects of the
+// document (including the node itself or its ancestors, which
+// makes cyclic trees), the names are arbitrary byte strings and
+// the destinations are valid or not.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lopdf::{dictionary, Document, Object, ObjectId};
+
+#[allow(dead_code)]
+#[path = "../../src/pdflib.rs"]
+mod pdflib;
+
+#[allow(dead_code)]
+#[path = "../../src/xmp.rs"]
+mod xmp;
+
+fuzz_target!(|data: &[u8]| {
+    let mut doc = Document::with_version("1.7");
+    let pages_id = doc.new_object_id();
+    let page_id = doc.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+    });
+    doc.objects.insert(pages_id, Object::Dictionary(dictionary! {
+        "Type" => "Pages",
+        "Kids" => vec![page_id.into()],
+        "Count" => 1,
+    }));
+
+    // one node per chunk: its first byte chooses between a leaf
+    // and an inner node, the others are the names or the kids
+    let nodes : Vec<&[u8]> = data.split(|b| *b == 0).collect();
+    let ids : Vec<ObjectId> = nodes.iter().map(|_| doc.new_object_id()).collect();
+    for (i, node) in nodes.iter().enumerate() {
+        let dict = match node.split_first() {
+            Some((kind, rest)) if kind % 2 == 0 => {
+                let kids : Vec<Object> = rest.iter()
+                    .map(|k| Object::Reference(ids[*k as usize % ids.len()]))
+                    .collect();
+                dictionary! { "Kids" => kids }
+            }
+            Some((_, rest)) => {
+                // the first byte of a name chooses the shape of its destination
+                let names : Vec<Object> = rest.chunks(8)
+                    .flat_map(|name| {
+                        let dest = vec![page_id.into(), "XYZ".into(), 0.into(), 0.into(), Object::Null];
+                        let value = match name[0] % 4 {
+                            0 => Object::Array(dest),
+                            1 => Object::Dictionary(dictionary! { "D" => dest }),
+                            2 => Object::Reference(ids[name[0] as usize % ids.len()]),
+                            _ => Object::Array(name.iter().map(|b| Object::Integer(*b as i64)).collect()),
+                        };
+                        [Object::string_literal(name.to_vec()), value]
+                    })
+                    .collect();
+                dictionary! { "Names" => names }
+            }
+            None => dictionary! {},
+        };
+        doc.objects.insert(ids[i], Object::Dictionary(dict));
+    }
+
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+        "Names" => dictionary! { "Dests" => ids[0] },
+    });
+    doc.trailer.set("Root", catalog_id);
+
+    if let Ok(doc) = pdflib::PdfDocument::try_from(doc) {
+        let _ = doc.link_map();
+    }
+});
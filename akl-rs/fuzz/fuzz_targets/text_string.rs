@@ -0,0 +1,17 @@
+// Text strings with arbitrary bytes: truncated utf-16,
+// unpaired surrogates, invalid utf-8.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[allow(dead_code)]
+#[path = "../../src/pdflib.rs"]
+mod pdflib;
+
+#[allow(dead_code)]
+#[path = "../../src/xmp.rs"]
+mod xmp;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = pdflib::parse_text_string(data);
+});
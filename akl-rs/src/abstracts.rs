@@ -0,0 +1,120 @@
+// Abstracts and keywords from the arXiv and Crossref APIs.
+//
+// Few pdf files carry their abstract in their metadata, but the
+// APIs of arXiv and Crossref know it from the arxiv id or the doi
+// of the document. The keywords are the arxiv categories (`cs.LO`),
+// or the Crossref subjects (see `crossref`). Failures are not
+// errors: the import goes on without abstract.
+
+use anyhow::{Result, Context};
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::crossref::Crossref;
+
+const ATOM : &str = "http://www.w3.org/2005/Atom";
+
+/// What the APIs know about a document.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub r#abstract : Option<String>,
+    pub keywords   : Vec<String>,
+}
+
+/// Collapses the whitespace of a text.
+fn collapse(s : &str) -> String {
+    s.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Removes the xml tags of a Crossref (JATS) abstract.
+pub fn strip_tags(s : &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut in_tag = false;
+    for c in s.chars() {
+        match c {
+            '<' => { in_tag = true; out.push(' '); }
+            '>' => { in_tag = false; }
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    let out = collapse(&out);
+    // the JATS abstracts often start with their own heading
+    out.strip_prefix("Abstract ").map(String::from).unwrap_or(out)
+}
+
+/// Reads the first entry of an arXiv API answer (an Atom feed).
+fn parse_arxiv(src : &str) -> Result<Summary> {
+    let mut summary = Summary::default();
+    let mut in_entry = false;
+    let mut in_summary = false;
+    let mut text = String::new();
+    for event in EventReader::from_str(src) {
+        match event.context("Parsing the arXiv API answer")? {
+            XmlEvent::StartElement { name, attributes, .. } if name.namespace.as_deref() == Some(ATOM) => {
+                match name.local_name.as_str() {
+                    "entry" => { in_entry = true; }
+                    "summary" if in_entry => { in_summary = true; }
+                    "category" if in_entry => {
+                        summary.keywords.extend(attributes.into_iter()
+                            .filter(|a| a.name.local_name == "term")
+                            .map(|a| a.value));
+                    }
+                    _ => {}
+                }
+            }
+            XmlEvent::EndElement { name } if name.namespace.as_deref() == Some(ATOM) => {
+                match name.local_name.as_str() {
+                    "entry" => break,
+                    "summary" => { in_summary = false; }
+                    _ => {}
+                }
+            }
+            XmlEvent::Characters(t) | XmlEvent::CData(t) if in_summary => text.push_str(&t),
+            _ => {}
+        }
+    }
+    let text = collapse(&text);
+    summary.r#abstract = (!text.is_empty()).then_some(text);
+    Ok(summary)
+}
+
+/// Asks the arXiv API about an arxiv id.
+pub fn arxiv(id : &str) -> Result<Summary> {
+    let query = serde_urlencoded::to_string([("id_list", id)])?;
+    log::debug!("Querying the arXiv API for {id}");
+    let answer = crate::polite::get(&format!("https://export.arxiv.org/api/query?{query}"))
+        .send()
+        .and_then(|r| r.error_for_status())
+        .context("Querying the arXiv API")?
+        .text()
+        .context("Reading the arXiv API answer")?;
+    parse_arxiv(&answer)
+}
+
+/// A query of an API, from an identifier.
+type Query<'a> = &'a dyn Fn(&str) -> Result<Summary>;
+
+/// Asks arXiv, then Crossref, about a document with these
+/// identifiers, logging the failures.
+pub fn lookup(identifiers : &[String], crossref : &Crossref) -> Summary {
+    let from_crossref = |doi : &str| -> Result<Summary> {
+        Ok(crossref.work(doi)?
+            .map(|w| Summary { r#abstract: w.r#abstract, keywords: w.keywords })
+            .unwrap_or_default())
+    };
+    let queries : [(&str, Option<String>, Query); 2] = [
+        ("arXiv", crate::identifiers::arxiv_id(identifiers), &arxiv),
+        ("Crossref", crate::identifiers::doi(identifiers), &from_crossref),
+    ];
+    for (api, id, query) in queries {
+        let Some(id) = id else {
+            continue;
+        };
+        match query(&id) {
+            Ok(s) if s.r#abstract.is_some() || !s.keywords.is_empty() => return s,
+            Ok(_) => log::info!("The {api} API knows no abstract for {id}"),
+            Err(e) => log::warn!("Could not query the {api} API for {id}: {e:#}"),
+        }
+    }
+    Summary::default()
+}
@@ -0,0 +1,194 @@
+// Vocabulary of the named destinations of the documents.
+//
+// The named destinations of a pdf are often cryptic (`thm.3.2`,
+// `section*.14`) and change between versions of a paper. Every
+// document can have a table of
+//
+// - aliases: memorable names of destinations (`main-theorem`),
+// - labels: the LaTeX labels of the source (`thm:main`),
+// - versions: destinations of a previous version, and the
+//   destination they became in the current one,
+//
+// through which the destinations of the links are resolved. The
+// tables are stored in the anchors.yaml file next to the index, and
+// the table of a document can be exported to a standalone yaml file
+// and imported by co-authors, without sharing whole libraries.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+use anyhow::{Result, Context};
+
+use crate::Document;
+
+/// Kinds of entries of the tables.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AnchorKind {
+    /// A memorable name of a destination.
+    Alias,
+    /// A label of the source of the document.
+    Label,
+    /// A destination of a previous version of the document.
+    Version,
+}
+
+/// The vocabulary of the destinations of a document.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AnchorTable {
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub aliases  : BTreeMap<String, String>,
+
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub labels   : BTreeMap<String, String>,
+
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub versions : BTreeMap<String, String>,
+}
+
+impl AnchorTable {
+    fn entries_mut(&mut self, kind : AnchorKind) -> &mut BTreeMap<String, String> {
+        match kind {
+            AnchorKind::Alias => &mut self.aliases,
+            AnchorKind::Label => &mut self.labels,
+            AnchorKind::Version => &mut self.versions,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aliases.is_empty() && self.labels.is_empty() && self.versions.is_empty()
+    }
+
+    /// Every entry, as (kind, name, destination).
+    pub fn entries(&self) -> impl Iterator<Item = (AnchorKind, &String, &String)> {
+        self.aliases.iter().map(|(n, d)| (AnchorKind::Alias, n, d))
+            .chain(self.labels.iter().map(|(n, d)| (AnchorKind::Label, n, d)))
+            .chain(self.versions.iter().map(|(n, d)| (AnchorKind::Version, n, d)))
+    }
+
+    /// Adds (or replaces) an entry.
+    pub fn set(&mut self, kind : AnchorKind, name : &str, dest : &str) {
+        self.entries_mut(kind).insert(name.into(), dest.into());
+    }
+
+    /// Removes an entry, returning whether it existed.
+    pub fn remove(&mut self, kind : AnchorKind, name : &str) -> bool {
+        self.entries_mut(kind).remove(name).is_some()
+    }
+
+    /// The destination of the current version designated by a name:
+    /// aliases and labels are replaced by their destination, and
+    /// destinations of previous versions are followed. Names may
+    /// refer to other names (a label to an alias, v1 → v2 → v3…).
+    pub fn resolve(&self, name : &str) -> String {
+        let mut dest = name.to_string();
+        // at most one step per entry, in case of a cycle
+        for _ in 0..=self.entries().count() {
+            let next = self.aliases.get(&dest)
+                .or_else(|| self.labels.get(&dest))
+                .or_else(|| self.versions.get(&dest));
+            match next {
+                Some(next) if *next != dest => { dest = next.clone(); }
+                _ => break,
+            }
+        }
+        dest
+    }
+
+    /// Adds the entries of another table, keeping the entries that
+    /// already exist. Returns the number of entries added and the
+    /// conflicting entries (kind, name, kept, ignored).
+    pub fn merge(&mut self, other : &AnchorTable) -> (usize, Vec<(AnchorKind, String, String, String)>) {
+        let mut added = 0;
+        let mut conflicts = vec![];
+        for (kind, name, dest) in other.entries() {
+            let entries = self.entries_mut(kind);
+            match entries.get(name) {
+                None => {
+                    entries.insert(name.clone(), dest.clone());
+                    added += 1;
+                }
+                Some(kept) if kept != dest => {
+                    conflicts.push((kind, name.clone(), kept.clone(), dest.clone()));
+                }
+                Some(_) => {}
+            }
+        }
+        (added, conflicts)
+    }
+}
+
+/// The anchor table of a document, in a standalone file.
+/// The document is found by checksum, or else by identifier.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SharedTable {
+    pub title       : String,
+
+    pub checksum    : String,
+
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub identifiers : Vec<String>,
+
+    #[serde(flatten)]
+    pub table       : AnchorTable,
+}
+
+impl SharedTable {
+    pub fn new(doc : &Document, table : AnchorTable) -> Self {
+        SharedTable {
+            title: doc.title.clone(),
+            checksum: doc.checksum.clone(),
+            identifiers: doc.identifiers.iter()
+                .filter(|i| !Path::new(i).is_absolute())
+                .cloned()
+                .collect(),
+            table,
+        }
+    }
+}
+
+/// The anchor tables of the library, by checksum.
+#[derive(Debug)]
+pub struct Anchors {
+    path : PathBuf,
+    pub tables : BTreeMap<String, AnchorTable>,
+}
+
+impl Anchors {
+    /// Loads the tables from a yaml file.
+    /// A missing file means no tables.
+    pub fn load(path : &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path).or_else(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound { Ok(String::new()) } else { Err(e) }
+        }).context("Reading the anchors")?;
+        let tables = if content.trim().is_empty() {
+            BTreeMap::new()
+        } else {
+            serde_yaml::from_str(&content)
+                .with_context(|| format!("Parsing the anchors {path:?}"))?
+        };
+        Ok(Anchors { path: path.into(), tables })
+    }
+
+    /// Saves the tables to their yaml file.
+    pub fn save(&self) -> Result<()> {
+        let file = std::fs::File::create(&self.path)
+            .context("Opening the anchors file")?;
+        let tables : BTreeMap<&String, &AnchorTable> = self.tables.iter()
+            .filter(|(_, t)| !t.is_empty())
+            .collect();
+        serde_yaml::to_writer(file, &tables)
+            .context("Writing the anchors file")
+    }
+
+    /// The table of a document (empty if there is none).
+    pub fn table(&self, doc : &Document) -> AnchorTable {
+        self.tables.get(&doc.checksum).cloned().unwrap_or_default()
+    }
+
+    /// The table of a document, to modify it.
+    pub fn table_mut(&mut self, doc : &Document) -> &mut AnchorTable {
+        self.tables.entry(doc.checksum.clone()).or_default()
+    }
+}
@@ -0,0 +1,428 @@
+// Identifiers of arXiv.
+//
+// Papers of arXiv have two syntaxes of ids: the ids since April 2007
+// are `YYMM.NNNN` (five digits from 2015 on, `2210.16580`), and the
+// older ones are made of the archive and a number (`math/0309456`),
+// often cited with the subject class (`math.LO/0309456`), which is not
+// part of the id. Both take a version (`2210.16580v2`). The pages of
+// a paper are `/abs/<id>`, `/pdf/<id>` (with or without `.pdf`) and
+// `/html/<id>` on arxiv.org; the other pages (`/list/…`, searches)
+// are not papers. The API of arXiv tells the latest version of papers,
+// and searches the new submissions of a category or an author. The
+// papers of an author are also listed by the feed of their ORCID.
+// The ORCIDs of the authors of a paper are not in the API, but in
+// the metadata of its doi at DataCite (`10.48550/arXiv.<id>`), for
+// the authors who linked their ORCID to their arXiv account.
+
+use std::time::Duration;
+
+use anyhow::{Result, Context};
+use chrono::{DateTime, Utc};
+use url::Url;
+use xml::reader::{EventReader, XmlEvent};
+
+/// An arxiv id, with its version if given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArxivId {
+    pub id      : String,
+    pub version : Option<String>,
+}
+
+/// Is this a non empty string of ascii digits?
+fn digits(s : &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())
+}
+
+/// A new style id (`2210.16580`).
+fn new_style(id : &str) -> Option<String> {
+    let (month, number) = id.split_once('.')?;
+    (month.len() == 4 && digits(month) && (4..=5).contains(&number.len()) && digits(number))
+        .then(|| id.to_string())
+}
+
+/// An old style id (`math/0309456`, or `math.LO/0309456`
+/// with the subject class, which is dropped).
+fn old_style(id : &str) -> Option<String> {
+    let (archive, number) = id.split_once('/')?;
+    let archive = match archive.split_once('.') {
+        Some((archive, class)) if !class.is_empty() && class.chars().all(|c| c.is_ascii_alphabetic()) => archive,
+        Some(_) => return None,
+        None => archive,
+    };
+    let valid_archive = !archive.is_empty() &&
+        archive.chars().all(|c| c.is_ascii_lowercase() || c == '-');
+    (valid_archive && number.len() == 7 && digits(number))
+        .then(|| format!("{archive}/{number}"))
+}
+
+/// Reads an arxiv id, with its version if any.
+pub fn parse_id(s : &str) -> Option<ArxivId> {
+    let s = s.trim().trim_matches('/');
+    let s = s.strip_prefix("arXiv:").or_else(|| s.strip_prefix("arxiv:")).unwrap_or(s);
+    let (id, version) = match s.rsplit_once('v') {
+        Some((id, version)) if digits(version) => (id, Some(version.to_string())),
+        _ => (s, None),
+    };
+    let id = new_style(id).or_else(|| old_style(id))?;
+    Some(ArxivId { id, version })
+}
+
+/// The paper of an arxiv.org url: its abstract, its pdf
+/// file, or its html version.
+pub fn from_url(url : &Url) -> Option<ArxivId> {
+    let host = url.host_str()?;
+    if !matches!(host, "arxiv.org" | "www.arxiv.org" | "export.arxiv.org") {
+        return None;
+    }
+    let path = url.path();
+    let id = ["/abs/", "/pdf/", "/html/"].iter().find_map(|p| path.strip_prefix(p))?;
+    parse_id(id.trim_end_matches('/').trim_end_matches(".pdf"))
+}
+
+/// The paper of an `arxiv:` identifier or of an arxiv.org url.
+pub fn from_identifier(url : &Url) -> Option<ArxivId> {
+    match url.scheme() {
+        "arxiv" => parse_id(url.path()),
+        _ => from_url(url),
+    }
+}
+
+/// Delay between two queries, as asked by arXiv.
+pub const DELAY : Duration = Duration::from_secs(3);
+
+/// Number of papers asked in one query.
+pub const BATCH : usize = 50;
+
+const ATOM : &str = "http://www.w3.org/2005/Atom";
+
+/// The papers of an answer of the API (an Atom feed),
+/// as the urls of their latest versions.
+fn parse_feed(src : &str) -> Result<Vec<ArxivId>> {
+    let mut papers = vec![];
+    let mut depth = 0;
+    let mut in_id = false;
+    let mut text = String::new();
+    for event in EventReader::from_str(src) {
+        match event.context("Parsing the arXiv API answer")? {
+            XmlEvent::StartElement { name, .. } => {
+                depth += 1;
+                // the ids of the entries, not the one of the feed
+                in_id = depth == 3 && name.namespace.as_deref() == Some(ATOM) && name.local_name == "id";
+                text.clear();
+            }
+            XmlEvent::EndElement { .. } => {
+                if in_id {
+                    papers.extend(Url::parse(text.trim()).ok().as_ref().and_then(from_url));
+                }
+                in_id = false;
+                depth -= 1;
+            }
+            XmlEvent::Characters(t) if in_id => text.push_str(&t),
+            _ => {}
+        }
+    }
+    Ok(papers)
+}
+
+/// The latest versions of papers (at most `BATCH` of them),
+/// by one query of the API.
+pub fn latest_versions(ids : &[String]) -> Result<Vec<ArxivId>> {
+    let list = ids.join(",");
+    let count = ids.len().to_string();
+    log::debug!("Querying the arXiv API for the versions of {list}");
+    parse_feed(&query(&serde_urlencoded::to_string([("id_list", list.as_str()), ("max_results", count.as_str())])?)?)
+}
+
+/// A paper found by a search of the API.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Submission {
+    /// The id of the latest version.
+    pub id        : ArxivId,
+    pub title     : String,
+    pub authors   : Vec<String>,
+    pub summary   : String,
+    /// When the first version was submitted.
+    pub published : DateTime<Utc>,
+}
+
+/// The papers of an answer of the API to a search.
+fn parse_submissions(src : &str) -> Result<Vec<Submission>> {
+    let mut submissions = vec![];
+    let mut depth = 0;
+    let mut text = String::new();
+    let (mut id, mut title, mut summary, mut published) = (None, String::new(), String::new(), None);
+    let mut authors = vec![];
+    for event in EventReader::from_str(src) {
+        match event.context("Parsing the arXiv API answer")? {
+            XmlEvent::StartElement { .. } => {
+                depth += 1;
+                text.clear();
+            }
+            XmlEvent::EndElement { name } if name.namespace.as_deref() == Some(ATOM) => {
+                // the text of the elements, without the line breaks
+                let value = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+                match (depth, name.local_name.as_str()) {
+                    (3, "id") => { id = Url::parse(&value).ok().as_ref().and_then(from_url); }
+                    (3, "title") => { title = value; }
+                    (3, "summary") => { summary = value; }
+                    (3, "published") => { published = DateTime::parse_from_rfc3339(&value).ok(); }
+                    (4, "name") => { authors.push(value); }
+                    (2, "entry") => {
+                        // the entries without id or date are skipped
+                        if let (Some(id), Some(published)) = (id.take(), published.take()) {
+                            submissions.push(Submission {
+                                id,
+                                title: title.clone(),
+                                authors: authors.clone(),
+                                summary: summary.clone(),
+                                published: published.with_timezone(&Utc),
+                            });
+                        }
+                        (id, published) = (None, None);
+                        title.clear();
+                        summary.clear();
+                        authors.clear();
+                    }
+                    _ => {}
+                }
+                depth -= 1;
+            }
+            XmlEvent::EndElement { .. } => { depth -= 1; }
+            XmlEvent::Characters(t) => text.push_str(&t),
+            _ => {}
+        }
+    }
+    Ok(submissions)
+}
+
+/// Asks the API, with a query string.
+fn query(params : &str) -> Result<String> {
+    crate::polite::get(&format!("https://export.arxiv.org/api/query?{params}"))
+        .send()
+        .and_then(|r| r.error_for_status())
+        .context("Querying the arXiv API")?
+        .text()
+        .context("Reading the arXiv API answer")
+}
+
+/// The papers of these ids (at most `BATCH` of them).
+pub fn submissions(ids : &[String]) -> Result<Vec<Submission>> {
+    let list = ids.join(",");
+    let count = ids.len().to_string();
+    log::debug!("Querying the arXiv API for {list}");
+    parse_submissions(&query(&serde_urlencoded::to_string([("id_list", list.as_str()), ("max_results", count.as_str())])?)?)
+}
+
+/// The papers of the author of an ORCID, from their arXiv feed.
+pub fn by_orcid(orcid : &str) -> Result<Vec<Submission>> {
+    log::debug!("Querying the arXiv feed of {orcid}");
+    let answer = crate::polite::get(&format!("https://arxiv.org/a/{orcid}.atom2"))
+        .send()
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("Querying the arXiv feed of {orcid}"))?
+        .text()
+        .context("Reading the arXiv feed")?;
+    parse_submissions(&answer)
+}
+
+/// The latest submissions matching a query of the API
+/// (`cat:cs.LO`, `au:Martens_W`…), at most `max` of them,
+/// the newest first.
+pub fn search(search : &str, max : usize) -> Result<Vec<Submission>> {
+    let max = max.to_string();
+    let params = serde_urlencoded::to_string([
+        ("search_query", search),
+        ("sortBy", "submittedDate"),
+        ("sortOrder", "descending"),
+        ("max_results", max.as_str()),
+    ])?;
+    log::debug!("Searching the arXiv API for {search}");
+    parse_submissions(&query(&params)?)
+}
+
+/// The latest submissions of an author, by name (`First Last`): the
+/// API searches the family name and the initial, and the papers of
+/// the other authors of that name are left out.
+pub fn by_author(name : &str, max : usize) -> Result<Vec<Submission>> {
+    let (first, last) = crate::latex::split_name(name);
+    let mut search = format!("au:{}", last.split_whitespace().collect::<Vec<&str>>().join("_"));
+    if let Some(initial) = first.chars().next() {
+        search.push('_');
+        search.push(initial);
+    }
+    let mut found = self::search(&search, max)?;
+    found.retain(|p| p.authors.iter().any(|a| crate::authors::compatible(a, name)));
+    Ok(found)
+}
+
+/// Reads the ORCIDs of the creators of a DataCite answer,
+/// with their names.
+fn parse_orcids(answer : &serde_json::Value) -> Vec<(String, String)> {
+    answer["data"]["attributes"]["creators"].as_array()
+        .map(|creators| creators.iter()
+            .filter_map(|creator| {
+                let orcid = creator["nameIdentifiers"].as_array()?.iter()
+                    .filter(|i| i["nameIdentifierScheme"].as_str() == Some("ORCID"))
+                    .find_map(|i| i["nameIdentifier"].as_str().and_then(crate::authors::normalize_orcid))?;
+                let name = match (creator["givenName"].as_str(), creator["familyName"].as_str()) {
+                    (Some(given), Some(family)) => format!("{given} {family}"),
+                    _ => crate::authors::display_name(creator["name"].as_str()?),
+                };
+                Some((name, orcid))
+            })
+            .collect())
+        .unwrap_or_default()
+}
+
+/// The ORCIDs of the authors of a paper (given by its id without
+/// version), with their names, from DataCite.
+pub fn orcids(id : &str) -> Result<Vec<(String, String)>> {
+    log::debug!("Querying DataCite for the authors of {id}");
+    let answer = crate::polite::get(&format!("https://api.datacite.org/dois/10.48550/arXiv.{id}"))
+        .send()
+        .context("Querying DataCite")?;
+    if answer.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(vec![]);
+    }
+    let answer : serde_json::Value = answer.error_for_status()
+        .context("Querying DataCite")?
+        .json()
+        .context("Reading the DataCite answer")?;
+    Ok(parse_orcids(&answer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(id : &str, version : Option<&str>) -> Option<ArxivId> {
+        Some(ArxivId { id: id.into(), version: version.map(String::from) })
+    }
+
+    fn url(s : &str) -> Option<ArxivId> {
+        from_url(&Url::parse(s).unwrap())
+    }
+
+    #[test]
+    fn new_style_ids() {
+        assert_eq!(parse_id("2210.16580"), id("2210.16580", None));
+        assert_eq!(parse_id("2210.16580v2"), id("2210.16580", Some("2")));
+        assert_eq!(parse_id("0704.0001v12"), id("0704.0001", Some("12")));
+        assert_eq!(parse_id("arXiv:1501.00001"), id("1501.00001", None));
+        assert_eq!(parse_id("2210.165"), None);
+        assert_eq!(parse_id("2210.165801"), None);
+        assert_eq!(parse_id("22101.16580"), None);
+        assert_eq!(parse_id("2210.16580v"), None);
+    }
+
+    #[test]
+    fn old_style_ids() {
+        assert_eq!(parse_id("math/0309456"), id("math/0309456", None));
+        assert_eq!(parse_id("math.LO/0309456v1"), id("math/0309456", Some("1")));
+        assert_eq!(parse_id("hep-th/9901001v3"), id("hep-th/9901001", Some("3")));
+        assert_eq!(parse_id("cs.DS/0101001"), id("cs/0101001", None));
+        assert_eq!(parse_id("math/030945"), None);
+        assert_eq!(parse_id("Math/0309456"), None);
+        assert_eq!(parse_id("math./0309456"), None);
+    }
+
+    #[test]
+    fn ids_with_v() {
+        // the archives with a `v` are not versions
+        assert_eq!(parse_id("solv-int/9901001"), id("solv-int/9901001", None));
+        assert_eq!(parse_id("solv-int/9901001v2"), id("solv-int/9901001", Some("2")));
+        assert_eq!(parse_id("chao-dyn/9901001v1"), id("chao-dyn/9901001", Some("1")));
+    }
+
+    #[test]
+    fn urls() {
+        assert_eq!(url("https://arxiv.org/abs/2210.16580"), id("2210.16580", None));
+        assert_eq!(url("https://arxiv.org/abs/2210.16580v2/"), id("2210.16580", Some("2")));
+        assert_eq!(url("https://arxiv.org/pdf/2210.16580v2"), id("2210.16580", Some("2")));
+        assert_eq!(url("https://arxiv.org/pdf/2210.16580v2.pdf"), id("2210.16580", Some("2")));
+        assert_eq!(url("https://arxiv.org/html/2210.16580v1"), id("2210.16580", Some("1")));
+        assert_eq!(url("http://export.arxiv.org/abs/math/0309456"), id("math/0309456", None));
+        assert_eq!(url("https://www.arxiv.org/pdf/solv-int/9901001v2.pdf"), id("solv-int/9901001", Some("2")));
+        assert_eq!(url("https://arxiv.org/abs/2210.16580?context=cs"), id("2210.16580", None));
+    }
+
+    #[test]
+    fn feed() {
+        let feed = r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <id>http://arxiv.org/api/abcdef</id>
+  <entry>
+    <id>http://arxiv.org/abs/2210.16580v3</id>
+    <title>A paper</title>
+  </entry>
+  <entry>
+    <id>http://arxiv.org/abs/math/0309456v2</id>
+  </entry>
+</feed>"#;
+        assert_eq!(parse_feed(feed).unwrap(), vec![
+            id("2210.16580", Some("3")).unwrap(),
+            id("math/0309456", Some("2")).unwrap(),
+        ]);
+    }
+
+    #[test]
+    fn submissions() {
+        let feed = r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xmlns:arxiv="http://arxiv.org/schemas/atom">
+  <id>http://arxiv.org/api/abcdef</id>
+  <title>ArXiv Query: search_query=cat:cs.LO</title>
+  <entry>
+    <id>http://arxiv.org/abs/2210.16580v2</id>
+    <published>2022-10-29T10:12:01Z</published>
+    <title>Regular Path Queries
+      on Graphs</title>
+    <summary>  We study
+  queries.
+</summary>
+    <author><name>Wim Martens</name></author>
+    <author>
+      <name>Tina Trautner</name>
+      <arxiv:affiliation>Bayreuth</arxiv:affiliation>
+    </author>
+    <arxiv:primary_category term="cs.DB"/>
+  </entry>
+  <entry>
+    <id>http://arxiv.org/abs/2210.16581v1</id>
+    <title>Without a date</title>
+  </entry>
+</feed>"#;
+        assert_eq!(parse_submissions(feed).unwrap(), vec![Submission {
+            id: id("2210.16580", Some("2")).unwrap(),
+            title: "Regular Path Queries on Graphs".into(),
+            authors: vec!["Wim Martens".into(), "Tina Trautner".into()],
+            summary: "We study queries.".into(),
+            published: "2022-10-29T10:12:01Z".parse().unwrap(),
+        }]);
+    }
+
+    #[test]
+    fn datacite_orcids() {
+        let answer = serde_json::json!({"data": {"attributes": {"creators": [
+            {"name": "Martens, Wim", "givenName": "Wim", "familyName": "Martens",
+             "nameIdentifiers": [{"nameIdentifier": "https://orcid.org/0000-0001-9480-3522",
+                                  "nameIdentifierScheme": "ORCID"}]},
+            {"name": "Trautner, Tina", "nameIdentifiers": []},
+            {"name": "Popa, Matthias",
+             "nameIdentifiers": [{"nameIdentifier": "https://orcid.org/0000-0002-1825-009x",
+                                  "nameIdentifierScheme": "ORCID"}]},
+        ]}}});
+        assert_eq!(parse_orcids(&answer), vec![
+            ("Wim Martens".to_string(), "0000-0001-9480-3522".to_string()),
+            ("Matthias Popa".to_string(), "0000-0002-1825-009X".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn other_pages() {
+        assert_eq!(url("https://arxiv.org/list/math.LO/recent"), None);
+        assert_eq!(url("https://arxiv.org/list/cs/new"), None);
+        assert_eq!(url("https://arxiv.org/"), None);
+        assert_eq!(url("https://arxiv.org/search/?query=automata"), None);
+        assert_eq!(url("https://example.org/abs/2210.16580"), None);
+    }
+}
@@ -0,0 +1,321 @@
+// Registry of the authors of the library.
+//
+// The same person arrives as "W. Martens", "Martens, Wim" and
+// "Wim Martens" depending on the source of the metadata, which
+// fragments the searches by author. The registry stores the
+// canonical name of the authors, with their aliases and optionally
+// their ORCID, in the authors.yaml file next to the index.
+//
+// On import, the names of the authors are replaced by their
+// canonical name: a name matches an author when it is (up to case,
+// accents, punctuation and the `Last, First` order) its name or
+// one of its aliases, or when it is compatible with a single
+// author of the registry (same family name, given names abbreviated
+// by their initials), in which case it becomes a new alias.
+// `akl authors merge` fixes the documents imported before.
+//
+// The ORCIDs given by the sources of the metadata settle the
+// ambiguous cases: a name with the ORCID of a registered author is
+// that author, whatever its spelling, and two authors with different
+// ORCIDs are different people, even with compatible names. An author
+// arriving with an ORCID is registered, so that the other spellings of
+// their name later join them.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+use anyhow::{Result, Context};
+
+use crate::latex;
+
+/// An author of the registry.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Author {
+    /// Canonical name, written `First Last`.
+    pub name : String,
+
+    /// Other spellings of the name.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub aliases : Vec<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub orcid : Option<String>,
+}
+
+impl Author {
+    /// All the spellings of the name of the author.
+    fn names(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.name).chain(self.aliases.iter())
+    }
+}
+
+/// Lowercase ascii words of a name, written `First Last`.
+fn words(name : &str) -> (Vec<String>, Vec<String>) {
+    let split = |s : &str| latex::to_ascii(s)
+        .to_lowercase()
+        .split(|c : char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(String::from)
+        .collect::<Vec<String>>();
+    let (first, last) = latex::split_name(name);
+    (split(&first), split(&last))
+}
+
+/// Comparison key of a name.
+fn name_key(name : &str) -> String {
+    let (first, last) = words(name);
+    [first, last].concat().join(" ")
+}
+
+/// Can the two names be the same person? The family names must be
+/// the same, and the given names must agree, a given name
+/// matching its initial.
+pub fn compatible(a : &str, b : &str) -> bool {
+    let ((fa, la), (fb, lb)) = (words(a), words(b));
+    !la.is_empty() && la == lb &&
+    !fa.is_empty() && !fb.is_empty() &&
+    fa.iter().zip(fb.iter()).all(|(x, y)| x.starts_with(y.as_str()) || y.starts_with(x.as_str()))
+}
+
+/// The ORCID of a name, in a list of names with their ORCIDs.
+pub fn orcid_of<'a>(orcids : &'a [(String, String)], name : &str) -> Option<&'a str> {
+    orcids.iter()
+        .find(|(n, _)| name_key(n) == name_key(name))
+        .or_else(|| orcids.iter().find(|(n, _)| compatible(n, name)))
+        .map(|(_, orcid)| orcid.as_str())
+}
+
+/// An ORCID (`0000-0002-1825-0097`), from itself or
+/// its url, if it is one.
+pub fn normalize_orcid(orcid : &str) -> Option<String> {
+    let orcid = orcid.trim();
+    let orcid = orcid.strip_prefix("https://orcid.org/")
+        .or_else(|| orcid.strip_prefix("http://orcid.org/"))
+        .unwrap_or(orcid)
+        .to_uppercase();
+    let groups : Vec<&str> = orcid.split('-').collect();
+    let valid = groups.len() == 4 && groups.iter().enumerate().all(|(i, g)| {
+        g.len() == 4 && g.chars().enumerate().all(|(j, c)| c.is_ascii_digit() || (i == 3 && j == 3 && c == 'X'))
+    });
+    valid.then_some(orcid)
+}
+
+/// A name written `First Last`, with single spaces.
+pub fn display_name(name : &str) -> String {
+    let name = match name.split_once(',') {
+        Some((last, first)) if !first.trim().is_empty() => format!("{} {}", first.trim(), last.trim()),
+        _ => name.to_string(),
+    };
+    name.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// The author registry of the library.
+#[derive(Debug)]
+pub struct Authors {
+    path : PathBuf,
+    pub authors : Vec<Author>,
+
+    /// Whether the registry has to be written back.
+    dirty : bool,
+}
+
+impl Authors {
+    /// Loads the registry from a yaml file.
+    /// A missing file means an empty registry.
+    pub fn load(path : &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path).or_else(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound { Ok(String::new()) } else { Err(e) }
+        }).context("Reading the author registry")?;
+        let authors = if content.trim().is_empty() {
+            vec![]
+        } else {
+            serde_yaml::from_str(&content)
+                .with_context(|| format!("Parsing the author registry {path:?}"))?
+        };
+        Ok(Authors { path: path.into(), authors, dirty: false })
+    }
+
+    /// Saves the registry to its yaml file, if it changed.
+    pub fn save(&self) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let file = std::fs::File::create(&self.path)
+            .context("Opening the author registry")?;
+        serde_yaml::to_writer(file, &self.authors)
+            .context("Writing the author registry")
+    }
+
+    /// Position of the author having this name or alias.
+    fn position(&self, name : &str) -> Option<usize> {
+        let key = name_key(name);
+        self.authors.iter().position(|a| a.names().any(|n| name_key(n) == key))
+    }
+
+    /// The author having this name or alias.
+    pub fn get(&self, name : &str) -> Option<&Author> {
+        self.position(name).map(|i| &self.authors[i])
+    }
+
+    /// Adds an author to the registry.
+    pub fn add(&mut self, name : &str, orcid : Option<String>) -> Result<()> {
+        if let Some(a) = self.get(name) {
+            anyhow::bail!("{name} is already in the registry, as {}", a.name);
+        }
+        if let Some(a) = orcid.as_deref().and_then(|o| self.with_orcid(o)) {
+            anyhow::bail!("{} is already the ORCID of {}", a.orcid.as_deref().unwrap_or_default(), a.name);
+        }
+        self.authors.push(Author { name: display_name(name), aliases: vec![], orcid });
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Adds an alias to a registered author.
+    pub fn add_alias(&mut self, name : &str, alias : &str) -> Result<()> {
+        let i = self.position(name)
+            .with_context(|| format!("There is no author named {name} in the registry"))?;
+        match self.position(alias) {
+            Some(j) if j == i => {}
+            Some(j) => anyhow::bail!("{alias} is already a name of {}", self.authors[j].name),
+            None => self.authors[i].aliases.push(alias.trim().to_string()),
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Merges names into an author, registered if needed: the names,
+    /// and the registered authors having them, become aliases of
+    /// the author.
+    pub fn merge(&mut self, into : &str, names : &[String]) -> Result<()> {
+        if self.position(into).is_none() {
+            self.add(into, None)?;
+        }
+        for name in names {
+            let i = self.position(into).context("Finding the merged author")?;
+            match self.position(name) {
+                Some(j) if j == i => {}
+                Some(j) => {
+                    let other = self.authors.remove(j);
+                    let i = self.position(into).context("Finding the merged author")?;
+                    let author = &mut self.authors[i];
+                    author.aliases.extend(other.names().cloned());
+                    author.orcid = author.orcid.take().or(other.orcid);
+                }
+                None => self.authors[i].aliases.push(name.trim().to_string()),
+            }
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Removes an author from the registry.
+    pub fn remove(&mut self, name : &str) -> Result<()> {
+        let i = self.position(name)
+            .with_context(|| format!("There is no author named {name} in the registry"))?;
+        self.authors.remove(i);
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Canonical name of an author: the name of the registered
+    /// author having this name or alias, or the name itself.
+    pub fn canonical(&self, name : &str) -> String {
+        match self.get(name) {
+            Some(a) => a.name.clone(),
+            None => display_name(name),
+        }
+    }
+
+    /// The registered author having this ORCID.
+    pub fn with_orcid(&self, orcid : &str) -> Option<&Author> {
+        self.authors.iter().find(|a| a.orcid.as_deref() == Some(orcid))
+    }
+
+    /// Canonical name of an author of an imported document, with its
+    /// ORCID if known. A name with the ORCID of a registered author,
+    /// or compatible with a single registered author (without another
+    /// ORCID), becomes one of its aliases.
+    pub fn resolve(&mut self, name : &str, orcid : Option<&str>) -> String {
+        if let Some(i) = orcid.and_then(|o| self.authors.iter().position(|a| a.orcid.as_deref() == Some(o))) {
+            if !self.authors[i].names().any(|n| name_key(n) == name_key(name)) {
+                log::info!("{name} is an alias of {} (same ORCID)", self.authors[i].name);
+                self.authors[i].aliases.push(name.trim().to_string());
+                self.dirty = true;
+            }
+            return self.authors[i].name.clone();
+        }
+        let found = self.position(name).or_else(|| {
+            let candidates : Vec<usize> = (0..self.authors.len())
+                .filter(|&i| orcid.is_none() || self.authors[i].orcid.is_none())
+                .filter(|&i| self.authors[i].names().any(|n| compatible(n, name)))
+                .collect();
+            match candidates.as_slice() {
+                [i] => {
+                    log::info!("{name} is an alias of {}", self.authors[*i].name);
+                    self.authors[*i].aliases.push(name.trim().to_string());
+                    self.dirty = true;
+                    Some(*i)
+                }
+                _ => None,
+            }
+        });
+        match (found, orcid) {
+            (Some(i), Some(orcid)) if self.authors[i].orcid.is_none() => {
+                self.authors[i].orcid = Some(orcid.into());
+                self.dirty = true;
+                self.authors[i].name.clone()
+            }
+            (Some(i), _) => self.authors[i].name.clone(),
+            (None, Some(orcid)) => {
+                log::info!("Registering {name} ({orcid})");
+                self.authors.push(Author { name: display_name(name), aliases: vec![], orcid: Some(orcid.into()) });
+                self.dirty = true;
+                display_name(name)
+            }
+            (None, None) => display_name(name),
+        }
+    }
+
+    /// Canonical names of the authors of an imported document, without
+    /// duplicates, and the ORCIDs of the `orcids` (names with their
+    /// ORCID) by canonical name.
+    pub fn resolve_all(&mut self, names : &[String], orcids : &[(String, String)]) -> (Vec<String>, BTreeMap<String, String>) {
+        let mut result : Vec<String> = vec![];
+        let mut found = BTreeMap::new();
+        for name in names {
+            let orcid = orcid_of(orcids, name);
+            let n = self.resolve(name, orcid);
+            if !n.is_empty() && !result.contains(&n) {
+                if let Some(orcid) = orcid {
+                    found.insert(n.clone(), orcid.to_string());
+                }
+                result.push(n);
+            }
+        }
+        (result, found)
+    }
+}
+
+/// Groups of names of a list that may be the same person,
+/// the most frequent name first: the names with the same ORCID
+/// (in `orcids`, by name), and the compatible names without
+/// different ORCIDs.
+pub fn similar_names(names : &[(String, usize)], orcids : &BTreeMap<String, String>) -> Vec<Vec<String>> {
+    let mut sorted = names.to_vec();
+    sorted.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let similar = |a : &str, b : &str| match (orcids.get(a), orcids.get(b)) {
+        (Some(x), Some(y)) => x == y,
+        _ => name_key(a) == name_key(b) || compatible(a, b),
+    };
+    let mut groups : Vec<Vec<String>> = vec![];
+    for (name, _) in sorted {
+        let group = groups.iter_mut().find(|g| g.iter().any(|n| similar(n, &name)));
+        match group {
+            Some(g) => g.push(name),
+            None => groups.push(vec![name]),
+        }
+    }
+    groups.retain(|g| g.len() > 1);
+    groups
+}
@@ -0,0 +1,147 @@
+// Backups of the library.
+//
+// A backup is a single gzipped tar archive, readable with the usual
+// tools, containing the index as yaml (whatever the backend), the
+// original files, optionally the modified files, and the other state
+// of the library (collections, configuration, events, reports).
+// Documents only refer to their files by name, relative to the
+// library directories, so a backup restores anywhere.
+//
+// The archive ends with a manifest listing the sha256 hash of every
+// other file; restoring (or verifying) checks all of them before
+// anything is written to the library.
+//
+// Layout: index.yaml, raw/<filename>, mod/<filename>, …, manifest.yaml
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Result, Context};
+use chrono::{DateTime, Utc};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+
+/// Version of the layout of the archives.
+const BACKUP_VERSION : u32 = 1;
+
+/// Name of the manifest in the archive.
+pub const MANIFEST : &str = "manifest.yaml";
+
+/// Contents of the archive, written last.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Manifest {
+    pub version   : u32,
+    pub created   : DateTime<Utc>,
+    /// Number of documents of the index.
+    pub documents : usize,
+    /// Sha256 hash of every file, by path in the archive.
+    pub files     : BTreeMap<String, String>,
+}
+
+fn sha256(bytes : &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Writer of backup archives.
+pub struct Writer {
+    out   : tar::Builder<GzEncoder<std::fs::File>>,
+    files : BTreeMap<String, String>,
+}
+
+impl Writer {
+    pub fn create(path : &Path) -> Result<Self> {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Creating {path:?}"))?;
+        let out = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        Ok(Writer { out, files: BTreeMap::new() })
+    }
+
+    fn write_entry(&mut self, name : &str, bytes : &[u8]) -> Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(bytes.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(Utc::now().timestamp().max(0) as u64);
+        // names longer than the header field get an extension entry
+        self.out.append_data(&mut header, name, bytes)?;
+        Ok(())
+    }
+
+    /// Adds a file to the archive.
+    pub fn add(&mut self, name : &str, bytes : &[u8]) -> Result<()> {
+        self.files.insert(name.to_string(), sha256(bytes));
+        self.write_entry(name, bytes)
+            .with_context(|| format!("Writing {name} to the backup"))
+    }
+
+    /// Adds a file of the disk to the archive.
+    pub fn add_file(&mut self, name : &str, path : &Path) -> Result<()> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Reading {path:?}"))?;
+        self.add(name, &bytes)
+    }
+
+    /// Writes the manifest and closes the archive.
+    pub fn finish(mut self, documents : usize) -> Result<Manifest> {
+        let manifest = Manifest {
+            version: BACKUP_VERSION,
+            created: Utc::now(),
+            documents,
+            files: std::mem::take(&mut self.files),
+        };
+        self.write_entry(MANIFEST, serde_yaml::to_string(&manifest)?.as_bytes())?;
+        self.out.into_inner()?.finish()?.sync_all()?;
+        Ok(manifest)
+    }
+}
+
+/// The path of an entry of the archive in the directory,
+/// refusing absolute paths and `..`.
+fn entry_path(dir : &Path, name : &str) -> Result<PathBuf> {
+    let relative = Path::new(name);
+    if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        anyhow::bail!("Invalid path {name:?} in the backup");
+    }
+    Ok(dir.join(relative))
+}
+
+/// Extracts an archive into a directory, and checks every
+/// file against the manifest.
+pub fn extract(archive : &Path, dir : &Path) -> Result<Manifest> {
+    let file = std::fs::File::open(archive)
+        .with_context(|| format!("Opening {archive:?}"))?;
+    let mut input = tar::Archive::new(GzDecoder::new(std::io::BufReader::new(file)));
+    let mut hashes = BTreeMap::new();
+    for entry in input.entries().context("Reading the backup")? {
+        let mut entry = entry.context("Reading the backup (truncated archive?)")?;
+        if entry.header().entry_type() != tar::EntryType::Regular {
+            continue;
+        }
+        let name = entry.path().context("Reading the backup")?.to_string_lossy().into_owned();
+        let mut data = vec![];
+        entry.read_to_end(&mut data).context("Reading the backup (truncated archive?)")?;
+        let path = entry_path(dir, &name)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, &data)
+            .with_context(|| format!("Extracting {name}"))?;
+        hashes.insert(name, sha256(&data));
+    }
+
+    let manifest : Manifest = serde_yaml::from_slice(
+        &std::fs::read(dir.join(MANIFEST)).context("The backup has no manifest")?)
+        .context("Parsing the manifest of the backup")?;
+    if manifest.version > BACKUP_VERSION {
+        anyhow::bail!("The backup was made by a newer version of akl (version {})", manifest.version);
+    }
+    for (name, hash) in &manifest.files {
+        match hashes.get(name) {
+            None => anyhow::bail!("The file {name} of the backup is missing"),
+            Some(h) if h != hash => anyhow::bail!("The file {name} of the backup is corrupted"),
+            Some(_) => {}
+        }
+    }
+    Ok(manifest)
+}
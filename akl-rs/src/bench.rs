@@ -0,0 +1,223 @@
+// Profiling of the passes of the pdf library.
+//
+// `akl bench <file.pdf>` times each pass of the conversion of a
+// document separately and counts the memory it allocates, so that
+// the slow passes on pathological documents (huge name trees,
+// thousands of annotations) can be spotted and compared between
+// versions. The allocations are only counted when akl is built with
+// the `count-allocations` feature, which replaces the allocator of
+// every command with a counting one:
+//
+//     cargo build --release --features count-allocations
+
+#[cfg(feature = "count-allocations")]
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, Context};
+
+use crate::pdflib::{self, PdfDocument};
+
+static ALLOCATIONS : AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED : AtomicUsize = AtomicUsize::new(0);
+
+/// Are the allocations counted?
+const COUNTED : bool = cfg!(feature = "count-allocations");
+
+/// The system allocator, counting the allocations.
+#[cfg(feature = "count-allocations")]
+pub struct CountingAllocator;
+
+#[cfg(feature = "count-allocations")]
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout : Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr : *mut u8, layout : Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr : *mut u8, layout : Layout, new_size : usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED.fetch_add(new_size.saturating_sub(layout.size()), Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Measures of one pass.
+#[derive(Debug, Clone)]
+pub struct Measure {
+    pub pass        : &'static str,
+    /// Fastest run.
+    pub time        : Duration,
+    /// Number of allocations of the fastest run,
+    /// when they are counted.
+    pub allocations : Option<usize>,
+    /// Bytes allocated by the fastest run, when
+    /// the allocations are counted.
+    pub bytes       : Option<usize>,
+    /// Size of the result (objects, destinations, annotations…).
+    pub items       : usize,
+}
+
+/// Runs a pass, measuring it.
+fn measure<T>(pass : &'static str, f : impl FnOnce() -> Result<T>) -> Result<(T, Measure)> {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = ALLOCATED.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let result = f().with_context(|| format!("Running the {pass} pass"))?;
+    let time = start.elapsed();
+    Ok((result, Measure {
+        pass,
+        time,
+        allocations: Some(ALLOCATIONS.load(Ordering::Relaxed) - allocations).filter(|_| COUNTED),
+        bytes: Some(ALLOCATED.load(Ordering::Relaxed) - bytes).filter(|_| COUNTED),
+        items: 0,
+    }))
+}
+
+/// Measures every pass once.
+fn run(bytes : &[u8], out : &Path) -> Result<Vec<Measure>> {
+    let mut measures = vec![];
+
+    let (pdf, mut m) = measure("parse", || Ok(lopdf::Document::load_mem(bytes)?))?;
+    m.items = pdf.objects.len();
+    measures.push(m);
+
+    let (dests, mut m) = measure("destinations", || {
+        Ok(pdflib::collect_named_destinations(&pdf, &pdflib::page_numbers(&pdf))?)
+    })?;
+    m.items = dests.len();
+    measures.push(m);
+
+    let (annots, mut m) = measure("annotations", || {
+        Ok(pdflib::page_annotations_iter(&pdf).count())
+    })?;
+    m.items = annots;
+    measures.push(m);
+
+    let mut pdoc = PdfDocument::try_from(pdf)?;
+    let (_, mut m) = measure("rewrite links", || Ok(pdoc.update_links(&|uri| uri)?))?;
+    m.items = annots;
+    measures.push(m);
+
+    let (_, mut m) = measure("destination links", || {
+        Ok(pdoc.add_destinations_links(|d| format!("akl://cite/?dest={}", d.name))?)
+    })?;
+    m.items = dests.len();
+    measures.push(m);
+
+    let (_, m) = measure("save", || Ok(pdoc.save_to(out)?))?;
+    measures.push(m);
+
+    Ok(measures)
+}
+
+/// Measures the passes on a pdf file, keeping the
+/// fastest of several runs for each pass.
+pub fn bench(path : &Path, runs : usize) -> Result<Vec<Measure>> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Reading {path:?}"))?;
+    let dir = tempfile::tempdir()?;
+    let out = dir.path().join("bench.pdf");
+    let mut best : Vec<Measure> = run(&bytes, &out)?;
+    for _ in 1..runs {
+        for (b, m) in best.iter_mut().zip(run(&bytes, &out)?) {
+            if m.time < b.time {
+                *b = m;
+            }
+        }
+    }
+    Ok(best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, Document, Object};
+
+    /// A one page document with a named destination
+    /// and a link to a web page.
+    fn fixture(path : &Path) {
+        let mut doc = Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        let link_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![0.into(), 0.into(), 100.into(), 20.into()],
+            "A" => dictionary! { "S" => "URI", "URI" => Object::string_literal("https://example.org") },
+        });
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            "Annots" => vec![link_id.into()],
+        });
+        doc.objects.insert(pages_id, Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => vec![page_id.into()],
+            "Count" => 1,
+        }));
+        let dest = vec![page_id.into(), "XYZ".into(), 0.into(), 0.into(), Object::Null];
+        let dests_id = doc.add_object(dictionary! {
+            "Names" => vec![Object::string_literal("intro"), Object::Array(dest)],
+        });
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+            "Names" => dictionary! { "Dests" => dests_id },
+        });
+        doc.trailer.set("Root", catalog_id);
+        doc.save(path).unwrap();
+    }
+
+    fn measures(runs : usize) -> Vec<Measure> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fixture.pdf");
+        fixture(&path);
+        bench(&path, runs).unwrap()
+    }
+
+    #[test]
+    fn every_pass() {
+        let passes : Vec<&str> = measures(1).iter().map(|m| m.pass).collect();
+        assert_eq!(passes, ["parse", "destinations", "annotations", "rewrite links", "destination links", "save"]);
+    }
+
+    #[test]
+    fn items() {
+        let m = measures(1);
+        assert!(m[0].items >= 5);
+        assert_eq!(m[1].items, 1);
+        assert_eq!(m[2].items, 1);
+        assert_eq!(m[3].items, 1);
+        assert_eq!(m[4].items, 1);
+    }
+
+    #[test]
+    fn several_runs() {
+        assert_eq!(measures(3).len(), 6);
+    }
+
+    #[test]
+    fn allocations_counted_with_the_feature() {
+        for m in measures(1) {
+            assert_eq!(m.allocations.is_some(), COUNTED);
+            assert_eq!(m.bytes.is_some(), COUNTED);
+        }
+    }
+
+    #[test]
+    fn not_a_pdf_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.pdf");
+        std::fs::write(&path, "not a pdf").unwrap();
+        assert!(bench(&path, 1).is_err());
+        assert!(bench(&dir.path().join("missing.pdf"), 1).is_err());
+    }
+}
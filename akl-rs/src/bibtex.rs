@@ -0,0 +1,443 @@
+// Reading and writing BibTeX files.
+//
+// `akl import --bibtex refs.bib` imports the entries of an existing
+// bibliography: the pdf files are downloaded from the arxiv id, url
+// or doi of each entry, and the fields of the entry take precedence
+// over the metadata of the pdf files. `akl export --format bibtex`
+// does the converse, so that the library can be cited from LaTeX.
+//
+// The parser follows BibTeX: text outside of the entries is a
+// comment, values are braced, quoted, numbers or `@string` macros,
+// concatenated with `#`. A malformed entry is reported and the
+// parser resumes at the next `@`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+use anyhow::Result;
+use crate::{Document, identifiers, latex, stopwords};
+use crate::doctype::DocType;
+
+/// Predefined macros of BibTeX.
+const MONTHS : &[(&str, &str)] = &[
+    ("jan", "January"), ("feb", "February"), ("mar", "March"),
+    ("apr", "April"), ("may", "May"), ("jun", "June"),
+    ("jul", "July"), ("aug", "August"), ("sep", "September"),
+    ("oct", "October"), ("nov", "November"), ("dec", "December"),
+];
+
+/// An entry of a BibTeX file.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// Entry type, lowercase (`article`, `phdthesis`…).
+    pub kind   : String,
+
+    /// Citation key.
+    pub key    : String,
+
+    /// Raw values of the fields, by lowercase name.
+    pub fields : BTreeMap<String, String>,
+
+    /// Line of the entry in the file.
+    pub line   : usize,
+}
+
+impl Entry {
+    /// Decoded value of a field, if present and not empty.
+    pub fn field(&self, name : &str) -> Option<String> {
+        self.fields.get(name)
+            .map(|v| latex::to_unicode(v).split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|v| !v.is_empty())
+    }
+
+    pub fn title(&self) -> Option<String> {
+        self.field("title")
+    }
+
+    /// Authors, written `First Last` (`Last, Jr, First`
+    /// becomes `First Last Jr`). `and others` is dropped.
+    pub fn authors(&self) -> Vec<String> {
+        let Some(raw) = self.fields.get("author") else {
+            return vec![];
+        };
+        split_names(raw).into_iter()
+            .map(|name| latex::to_unicode(&name))
+            .filter(|name| name != "others")
+            .map(|name| {
+                let parts : Vec<&str> = name.split(',').map(str::trim).collect();
+                match parts.as_slice() {
+                    [last, first] => format!("{first} {last}"),
+                    [last, jr, first] => format!("{first} {last} {jr}"),
+                    _ => name.clone(),
+                }
+            })
+            .map(|name| name.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect()
+    }
+
+    /// Year of the entry, from the `year` or the `date` field.
+    pub fn year(&self) -> Option<u32> {
+        let year = self.field("year").or_else(|| self.field("date"))?;
+        let digits : String = year.chars().take_while(char::is_ascii_digit).collect();
+        digits.parse().ok()
+    }
+
+    /// Journal or conference of the entry.
+    pub fn venues(&self) -> Vec<String> {
+        ["journal", "journaltitle", "booktitle"].iter()
+            .filter_map(|f| self.field(f))
+            .collect()
+    }
+
+    pub fn doi(&self) -> Option<String> {
+        let doi = self.field("doi")?;
+        let doi = doi.trim_start_matches("https://doi.org/")
+                     .trim_start_matches("http://dx.doi.org/");
+        Some(doi.to_string())
+    }
+
+    /// The arxiv id, from the `eprint` field of arxiv entries
+    /// or from a doi of the form `10.48550/arXiv.…`.
+    pub fn arxiv_id(&self) -> Option<String> {
+        let archive = self.field("archiveprefix").or_else(|| self.field("eprinttype"));
+        if archive.is_some_and(|a| a.eq_ignore_ascii_case("arxiv")) {
+            if let Some(id) = self.field("eprint") {
+                return Some(id.trim_start_matches("arXiv:").to_string());
+            }
+        }
+        let doi = self.doi()?;
+        let prefix = "10.48550/arxiv.";
+        doi.to_lowercase()
+           .starts_with(prefix)
+           .then(|| doi[prefix.len()..].to_string())
+    }
+
+    /// Uris to download the entry from, the most
+    /// reliable first: arxiv, then the url, then the doi.
+    pub fn uris(&self) -> Vec<String> {
+        let mut uris = vec![];
+        uris.extend(self.arxiv_id().map(|id| format!("arxiv:{id}")));
+        uris.extend(self.field("url").filter(|u| u.starts_with("http")));
+        uris.extend(self.doi().map(|doi| format!("doi:{doi}")));
+        uris.dedup();
+        uris
+    }
+}
+
+/// Splits an author field on the `and` outside of braces.
+fn split_names(s : &str) -> Vec<String> {
+    let mut names = vec![];
+    let mut depth = 0;
+    let mut current = String::new();
+    for word in s.split_whitespace() {
+        if depth == 0 && word.eq_ignore_ascii_case("and") {
+            names.push(std::mem::take(&mut current));
+            continue;
+        }
+        for c in word.chars() {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    names.push(current);
+    names.retain(|n| !n.is_empty());
+    names
+}
+
+struct Parser<'a> {
+    src     : &'a str,
+    pos     : usize,
+    strings : HashMap<String, String>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    fn line(&self, pos : usize) -> usize {
+        self.src[..pos].matches('\n').count() + 1
+    }
+
+    fn expect(&mut self, c : char) -> Result<()> {
+        self.skip_whitespace();
+        match self.bump() {
+            Some(n) if n == c => Ok(()),
+            Some(n) => anyhow::bail!("expected `{c}` but found `{n}` on line {}", self.line(self.pos)),
+            None => anyhow::bail!("expected `{c}` but found the end of the file"),
+        }
+    }
+
+    /// Names of entry types, fields and macros.
+    fn identifier(&mut self) -> Result<String> {
+        self.skip_whitespace();
+        let start = self.pos;
+        while self.peek().is_some_and(|c| !c.is_whitespace() && !"{}()=,#\"@".contains(c)) {
+            self.bump();
+        }
+        if start == self.pos {
+            anyhow::bail!("expected a name on line {}", self.line(self.pos));
+        }
+        Ok(self.src[start..self.pos].to_string())
+    }
+
+    /// Contents up to the closing delimiter, with balanced braces.
+    fn delimited(&mut self, close : char) -> Result<String> {
+        let start = self.pos;
+        let mut depth = 0;
+        loop {
+            match self.bump() {
+                None => anyhow::bail!("unclosed value starting on line {}", self.line(start)),
+                Some(c) if c == close && depth == 0 => break,
+                Some('{') => depth += 1,
+                Some('}') => depth -= 1,
+                Some(_) => {}
+            }
+        }
+        Ok(self.src[start..self.pos - close.len_utf8()].to_string())
+    }
+
+    /// A value: pieces concatenated with `#`.
+    fn value(&mut self) -> Result<String> {
+        let mut value = String::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('{') => { self.bump(); value.push_str(&self.delimited('}')?); }
+                Some('"') => { self.bump(); value.push_str(&self.delimited('"')?); }
+                Some(c) if c.is_ascii_digit() => {
+                    while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        value.extend(self.bump());
+                    }
+                }
+                _ => {
+                    let name = self.identifier()?.to_lowercase();
+                    let expansion = self.strings.get(&name)
+                        .cloned()
+                        .or_else(|| MONTHS.iter().find(|(m,_)| *m == name).map(|(_, v)| v.to_string()));
+                    match expansion {
+                        Some(v) => value.push_str(&v),
+                        None => anyhow::bail!("undefined string `{name}` on line {}", self.line(self.pos)),
+                    }
+                }
+            }
+            self.skip_whitespace();
+            if self.peek() != Some('#') {
+                return Ok(value);
+            }
+            self.bump();
+        }
+    }
+
+    /// Parses the entry following an `@`. Returns `None`
+    /// for comments, preambles and string definitions.
+    fn entry(&mut self) -> Result<Option<Entry>> {
+        let line = self.line(self.pos);
+        let kind = self.identifier()?.to_lowercase();
+        self.skip_whitespace();
+        let close = match self.bump() {
+            Some('{') => '}',
+            Some('(') => ')',
+            _ => anyhow::bail!("expected `{{` after @{kind} on line {line}"),
+        };
+        match kind.as_str() {
+            "comment" | "preamble" => {
+                self.delimited(close)?;
+                return Ok(None);
+            }
+            "string" => {
+                let name = self.identifier()?.to_lowercase();
+                self.expect('=')?;
+                let value = self.value()?;
+                self.expect(close)?;
+                self.strings.insert(name, value);
+                return Ok(None);
+            }
+            _ => {}
+        }
+        let key = self.identifier()?;
+        let mut fields = BTreeMap::new();
+        loop {
+            self.skip_whitespace();
+            match self.bump() {
+                Some(',') => {}
+                Some(c) if c == close => break,
+                _ => anyhow::bail!("expected `,` or `{close}` in entry {key} on line {}", self.line(self.pos)),
+            }
+            self.skip_whitespace();
+            if self.peek() == Some(close) {
+                continue;
+            }
+            let name = self.identifier()?.to_lowercase();
+            self.expect('=')?;
+            let value = self.value()?;
+            fields.insert(name, value);
+        }
+        Ok(Some(Entry { kind, key, fields, line }))
+    }
+}
+
+/// Parses the entries of a BibTeX file. Each malformed
+/// entry gives an error, and is otherwise skipped.
+pub fn parse(src : &str) -> Vec<Result<Entry>> {
+    let mut parser = Parser { src, pos: 0, strings: HashMap::new() };
+    let mut entries = vec![];
+    while let Some(at) = parser.src[parser.pos..].find('@') {
+        parser.pos += at + 1;
+        let start = parser.pos;
+        match parser.entry() {
+            Ok(Some(entry)) => entries.push(Ok(entry)),
+            Ok(None) => {}
+            Err(e) => {
+                let line = parser.line(start);
+                entries.push(Err(e.context(format!("Malformed entry on line {line}"))));
+                parser.pos = start;
+            }
+        }
+    }
+    entries
+}
+
+/// Lowercase ascii letters and digits of a string.
+fn key_part(s : &str) -> String {
+    latex::to_ascii(s).chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Citation key of a document, before disambiguation: the key of
+/// DBLP when it is known (`DBLP:conf/icalp/Martens18`), or else the
+/// surname of the first author, the year and the first significant
+/// word of the title (`leonard2021shared`).
+fn base_key(doc : &Document) -> String {
+    if let Some(key) = identifiers::dblp_key(&doc.identifiers) {
+        return format!("DBLP:{key}");
+    }
+    let author = doc.authors.first()
+        .map(|a| latex::format_author(a))
+        .and_then(|a| a.split(',').next().map(key_part))
+        .filter(|a| !a.is_empty())
+        .unwrap_or_else(|| "anon".into());
+    let word = stopwords::words(&doc.title).into_iter()
+        .find(|w| !stopwords::is_common(w))
+        .unwrap_or_default();
+    format!("{author}{}{word}", doc.year)
+}
+
+/// Letters telling apart the documents sharing a key:
+/// `a` to `z`, then `aa`, `ab`…
+fn suffix(mut i : usize) -> String {
+    let mut s = String::new();
+    loop {
+        s.insert(0, (b'a' + (i % 26) as u8) as char);
+        if i < 26 {
+            return s;
+        }
+        i = i / 26 - 1;
+    }
+}
+
+/// Citation keys of the documents of the library, by checksum.
+/// Documents sharing a key are told apart by a letter (`a`, `b`…)
+/// given in the order of their checksums, so that the keys do not
+/// depend on the order of the documents nor on the exported subset.
+pub fn citation_keys(docs : &[Document]) -> HashMap<String, String> {
+    let mut by_key : BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for doc in docs {
+        by_key.entry(base_key(doc)).or_default().push(&doc.checksum);
+    }
+    let mut keys = HashMap::new();
+    for (key, mut checksums) in by_key {
+        checksums.sort();
+        if checksums.len() == 1 {
+            keys.insert(checksums[0].to_string(), key);
+            continue;
+        }
+        for (i, checksum) in checksums.into_iter().enumerate() {
+            keys.insert(checksum.to_string(), format!("{key}{}", suffix(i)));
+        }
+    }
+    keys
+}
+
+/// The BibTeX entry type of a document, and the field of its venue.
+fn entry_type(doc : &Document) -> (&'static str, &'static str) {
+    match doc.doc_type {
+        Some(DocType::Article) => ("article", "journal"),
+        Some(DocType::Preprint) | Some(DocType::Slides) | Some(DocType::Blog) => ("misc", "howpublished"),
+        Some(DocType::Thesis) => ("phdthesis", "school"),
+        Some(DocType::Book) => ("book", "publisher"),
+        Some(DocType::Report) => ("techreport", "institution"),
+        None if doc.context.is_empty() => ("misc", "journal"),
+        None => ("article", "journal"),
+    }
+}
+
+/// Writes the BibTeX entry of a document: an entry of its type,
+/// or without type an `@article` in the venue of its first context,
+/// or a `@misc` without context.
+pub fn write_entry<W : Write>(out : &mut W, key : &str, doc : &Document) -> Result<()> {
+    write_entry_with(out, key, doc, vec![])
+}
+
+/// Writes the BibTeX entry of a document, with additional fields.
+pub fn write_entry_with<W : Write>(out : &mut W, key : &str, doc : &Document, extra : Vec<(&str, String)>) -> Result<()> {
+    let mut fields : Vec<(&str, String)> = vec![];
+    if !doc.authors.is_empty() {
+        fields.push(("author", latex::format_authors(&doc.authors)));
+    }
+    fields.push(("title", latex::protect_title(&doc.title)));
+    let (kind, venue_field) = entry_type(doc);
+    if let Some(venue) = doc.context.first() {
+        fields.push((venue_field, latex::escape(venue)));
+    }
+    fields.push(("year", doc.year.to_string()));
+    if let Some(pages) = &doc.pages {
+        // page ranges take an en dash
+        let bounds : Vec<&str> = pages.split('-').filter(|p| !p.is_empty()).collect();
+        fields.push(("pages", bounds.join("--")));
+    }
+    if let Some(doi) = identifiers::doi(&doc.identifiers) {
+        fields.push(("doi", doi));
+    }
+    if let Some(isbn) = identifiers::isbn(&doc.identifiers) {
+        fields.push(("isbn", isbn));
+    }
+    if let Some(arxiv) = identifiers::arxiv_id(&doc.identifiers) {
+        fields.push(("eprint", arxiv));
+        fields.push(("archivePrefix", "arXiv".into()));
+    }
+    if let Some(url) = identifiers::web_url(&doc.identifiers) {
+        fields.push(("url", url.clone()));
+    }
+
+    fields.extend(extra);
+
+    writeln!(out, "@{kind}{{{key},")?;
+    let width = fields.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, value) in fields {
+        writeln!(out, "  {name:<width$} = {{{value}}},")?;
+    }
+    writeln!(out, "}}")?;
+    Ok(())
+}
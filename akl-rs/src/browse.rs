@@ -0,0 +1,130 @@
+// Browse views of the library: trees of symbolic links.
+//
+// With `browse_dir` in the configuration, akl keeps in that directory
+// trees of links to the modified files, `by-year/2023/…`,
+// `by-author/martens/…` and `by-tag/automata/…`, so that a file
+// manager or a plain `ls` can browse the library without the picker.
+// Every command that changes the index updates the trees, and
+// `akl browse` rebuilds them. Only the links that akl created are
+// removed from the directory, never other files.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, Context};
+
+use crate::{Document, latex, naming};
+
+/// The trees of links.
+const VIEWS : &[&str] = &["by-year", "by-author", "by-tag"];
+
+/// The links of the views, by path relative to the directory
+/// (the metadata-only documents have no file to link to).
+fn links(docs : &[Document], mod_dir : &Path) -> BTreeMap<PathBuf, PathBuf> {
+    let mut links = BTreeMap::new();
+    for doc in docs.iter().filter(|d| !d.metadata_only) {
+        let target = mod_dir.join(&doc.filename);
+        let mut folders = vec![PathBuf::from("by-year").join(doc.year.to_string())];
+        for author in &doc.authors {
+            let (_, last) = latex::split_name(author);
+            folders.push(PathBuf::from("by-author").join(naming::slug(&last)));
+        }
+        for tag in &doc.tags {
+            folders.push(PathBuf::from("by-tag").join(naming::slug(tag)));
+        }
+        for folder in folders.into_iter().filter(|f| f.file_name().is_some_and(|n| !n.is_empty())) {
+            links.insert(folder.join(&doc.filename), target.clone());
+        }
+    }
+    links
+}
+
+/// The links of akl under a directory, with their targets:
+/// the symbolic links to files of the modified directory.
+fn existing(dir : &Path, mod_dir : &Path, found : &mut BTreeMap<PathBuf, PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Listing {dir:?}"))? {
+        let path = entry?.path();
+        let meta = std::fs::symlink_metadata(&path)?;
+        if meta.is_symlink() {
+            let target = std::fs::read_link(&path)?;
+            if target.starts_with(mod_dir) {
+                found.insert(path, target);
+            }
+        } else if meta.is_dir() {
+            existing(&path, mod_dir, found)?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes the empty directories under a directory.
+fn remove_empty(dir : &Path) -> Result<()> {
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Listing {dir:?}"))? {
+        let path = entry?.path();
+        if std::fs::symlink_metadata(&path)?.is_dir() {
+            remove_empty(&path)?;
+            if std::fs::read_dir(&path)?.next().is_none() {
+                std::fs::remove_dir(&path)
+                    .with_context(|| format!("Removing {path:?}"))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target : &Path, link : &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target : &Path, link : &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+/// Brings the views of the directory up to date with the
+/// documents, and returns the number of links created and removed.
+pub fn update(dir : &Path, docs : &[Document], mod_dir : &Path) -> Result<(usize, usize)> {
+    let wanted = links(docs, mod_dir);
+    let mut found = BTreeMap::new();
+    for view in VIEWS {
+        let path = dir.join(view);
+        if path.exists() {
+            existing(&path, mod_dir, &mut found)?;
+        }
+    }
+    let mut removed = 0;
+    for (link, target) in &found {
+        let relative = link.strip_prefix(dir).unwrap_or(link);
+        if wanted.get(relative) != Some(target) {
+            std::fs::remove_file(link)
+                .with_context(|| format!("Removing the link {link:?}"))?;
+            removed += 1;
+        }
+    }
+    let mut created = 0;
+    for (relative, target) in &wanted {
+        let link = dir.join(relative);
+        if found.get(&link) == Some(target) {
+            continue;
+        }
+        if let Some(parent) = link.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Creating {parent:?}"))?;
+        }
+        if std::fs::symlink_metadata(&link).is_ok() {
+            log::warn!("Not replacing {link:?}, which is not a link of akl");
+            continue;
+        }
+        symlink(target, &link)
+            .with_context(|| format!("Creating the link {link:?}"))?;
+        created += 1;
+    }
+    for view in VIEWS {
+        let path = dir.join(view);
+        if path.exists() {
+            remove_empty(&path)?;
+        }
+    }
+    Ok((created, removed))
+}
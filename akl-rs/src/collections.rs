@@ -0,0 +1,122 @@
+// Collections of documents.
+//
+// A collection is either a named list of documents chosen by
+// hand, or a smart collection defined by a stored query such as
+// `author:martens year:>2020` (see `query`), evaluated every time
+// it is used.
+//
+// The stored queries are read with the query language of find: a
+// word without field searches the authors, the context, the tags
+// and the keywords as well as the title. The first smart
+// collections only searched the title, and now match more
+// documents; `title:word` gives back the old meaning.
+// Collections are stored in the collections.yaml file next to
+// the index.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+use anyhow::{Result, Context};
+
+use crate::Document;
+use crate::query::Query;
+
+/// A named collection.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Collection {
+    /// Name of the collection.
+    pub name : String,
+
+    /// Checksums of the documents added by hand.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub documents : Vec<String>,
+
+    /// Query defining a smart collection.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub query : Option<String>,
+}
+
+impl Collection {
+    /// Does the document belong to the collection?
+    pub fn contains(&self, doc : &Document) -> Result<bool> {
+        if self.documents.contains(&doc.checksum) {
+            return Ok(true);
+        }
+        match &self.query {
+            Some(q) => Ok(Query::parse(q)?.matches(doc)),
+            None => Ok(false),
+        }
+    }
+}
+
+/// All the collections of the library.
+#[derive(Debug)]
+pub struct Collections {
+    path : PathBuf,
+    pub collections : Vec<Collection>,
+}
+
+impl Collections {
+    /// Loads the collections from a yaml file.
+    /// A missing file means no collections.
+    pub fn load(path : &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path).or_else(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound { Ok(String::new()) } else { Err(e) }
+        }).context("Reading the collections")?;
+        let collections = if content.trim().is_empty() {
+            vec![]
+        } else {
+            serde_yaml::from_str(&content)
+                .with_context(|| format!("Parsing the collections {path:?}"))?
+        };
+        Ok(Collections { path: path.into(), collections })
+    }
+
+    /// Saves the collections to their yaml file.
+    pub fn save(&self) -> Result<()> {
+        let file = std::fs::File::create(&self.path)
+            .context("Opening the collections file")?;
+        serde_yaml::to_writer(file, &self.collections)
+            .context("Writing the collections file")
+    }
+
+    /// Finds a collection by name.
+    pub fn get(&self, name : &str) -> Result<&Collection> {
+        self.collections.iter()
+            .find(|c| c.name == name)
+            .with_context(|| format!("There is no collection named {name}"))
+    }
+
+    /// Finds a collection by name, to modify it.
+    pub fn get_mut(&mut self, name : &str) -> Result<&mut Collection> {
+        self.collections.iter_mut()
+            .find(|c| c.name == name)
+            .with_context(|| format!("There is no collection named {name}"))
+    }
+
+    /// Creates a new collection.
+    pub fn create(&mut self, name : &str, query : Option<String>) -> Result<()> {
+        if self.collections.iter().any(|c| c.name == name) {
+            anyhow::bail!("The collection {name} already exists");
+        }
+        if let Some(q) = &query {
+            Query::parse(q)?;
+        }
+        self.collections.push(Collection {
+            name: name.into(),
+            documents: vec![],
+            query,
+        });
+        Ok(())
+    }
+
+    /// Deletes a collection (not its documents).
+    pub fn delete(&mut self, name : &str) -> Result<()> {
+        let before = self.collections.len();
+        self.collections.retain(|c| c.name != name);
+        if self.collections.len() == before {
+            anyhow::bail!("There is no collection named {name}");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,263 @@
+// User configuration, stored in the config.yaml file
+// next to the index. Every field has a default value,
+// so that a missing or partial configuration file is valid.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+use anyhow::{Result, Context};
+
+use crate::storage::Backend;
+use crate::identifiers::{IdentifierKind, DEFAULT_PRIORITY};
+use crate::stopwords::StopWords;
+
+/// Credentials used to download documents from a given host.
+/// The password itself is stored in the system keyring.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SiteCredential {
+    /// Host name (e.g. `www.example.org`).
+    pub host : String,
+
+    /// User name sent to the host.
+    pub username : String,
+
+    /// Name of the keyring entry containing the password.
+    pub keyring : String,
+}
+
+/// Password used to decrypt documents.
+/// The password itself is stored in the system keyring.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DocumentPassword {
+    /// Prefix of the URIs of the documents using this password.
+    pub uri : String,
+
+    /// Name of the keyring entry containing the password.
+    pub keyring : String,
+}
+
+/// The user configuration.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// Storage backend of the library index.
+    pub backend : Backend,
+
+    /// Number of days removed documents are kept in the trash
+    /// (0 keeps them until the trash is emptied).
+    pub trash_retention_days : u32,
+
+    /// Order in which the kinds of identifiers are preferred
+    /// when choosing the canonical identifier of a document.
+    pub identifier_priority : Vec<IdentifierKind>,
+
+    /// Template of the filenames of the documents, see `naming`.
+    pub filename_template : String,
+
+    /// Languages of the titles, whose stop words are removed
+    /// from the filenames (en, fr and de have built-in lists).
+    pub languages : Vec<String>,
+
+    /// Additional stop words, by language.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub stop_words : BTreeMap<String, Vec<String>>,
+
+    /// Number of significant words of `{short_title}`.
+    pub short_title_words : usize,
+
+    /// Picker used by `akl pick`: a command reading the choices
+    /// on its standard input (rofi or fzf by default).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub picker : Option<String>,
+
+    /// Credentials used when downloading documents.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub credentials : Vec<SiteCredential>,
+
+    /// Passwords used to decrypt encrypted documents.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub passwords : Vec<DocumentPassword>,
+
+    /// Commit the index to a git repository after
+    /// every change, see `git`.
+    pub git_history : bool,
+
+    /// Remote the library is synchronized with, see `sync`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sync : Option<crate::remote::RemoteConfig>,
+
+    /// Directory of the index, when it is not the configuration
+    /// directory (see `akl relocate`). The configuration file
+    /// itself stays in the configuration directory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index_dir : Option<PathBuf>,
+
+    /// Directory of the original files of the documents.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_dir : Option<PathBuf>,
+
+    /// Directory of the modified files of the documents.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mod_dir : Option<PathBuf>,
+
+    /// Directory of the logs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_dir : Option<PathBuf>,
+
+    /// Directory of the browse views, see `browse`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub browse_dir : Option<PathBuf>,
+
+    /// Email address given to the metadata APIs (Crossref, which
+    /// serves identified clients better, and Unpaywall, which
+    /// needs it to find the open access copies of the dois).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contact_email : Option<String>,
+
+    /// Seconds after which a download is abandoned when the
+    /// server does not answer (to connect, or to send more data).
+    pub download_timeout : u64,
+
+    /// Number of retries of the downloads failing on
+    /// transient errors (see `download`).
+    pub download_retries : u32,
+
+    /// Size in megabytes beyond which a download is abandoned.
+    pub max_download_size : u64,
+
+    /// Cookie files whose cookies are sent with
+    /// the downloads, see `cookies`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cookies : Vec<PathBuf>,
+
+    /// Proxies of the downloads of some hosts, see `proxy`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub proxies : Vec<crate::proxy::ProxyRule>,
+
+    /// Rewritings of the urls of some hosts to go through
+    /// the proxy of an institution, see `proxy`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub institutional_access : Vec<crate::proxy::AccessRule>,
+
+    /// Number of documents of a batch import
+    /// downloaded and parsed at the same time.
+    pub import_workers : usize,
+
+    /// Ask Semantic Scholar for the id, the citation count and the
+    /// abstract of the imported documents. It is slow (a request
+    /// every few seconds), so it is off by default.
+    pub scholar_on_import : bool,
+
+    /// Ask DBLP for the keys of the imported documents whose venue
+    /// is unknown. Off by default, `akl dblp` records them later.
+    pub dblp_on_import : bool,
+
+    /// Delays in seconds between two requests to a host
+    /// and its subdomains, see `polite`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub rate_limits : BTreeMap<String, f64>,
+
+    /// Folder of the browser downloads scanned by `akl inbox scan`,
+    /// the downloads folder of the user by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub downloads_folder : Option<PathBuf>,
+
+    /// Commands opening the documents that are not pdf files, by
+    /// format (`epub: foliate`), see `format`. The documents of the
+    /// other formats open in the default application of the system.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub viewers : BTreeMap<crate::format::Format, String>,
+
+    /// Directory of the downloads in progress: `downloads` in the
+    /// data directory of the library, set when it is opened.
+    #[serde(skip)]
+    pub download_dir : Option<PathBuf>,
+
+    /// Providers declared by the user, see `providers`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub providers : Vec<crate::providers::ProviderRule>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            backend: Backend::default(),
+            trash_retention_days: 30,
+            identifier_priority: DEFAULT_PRIORITY.to_vec(),
+            filename_template: crate::naming::DEFAULT_TEMPLATE.into(),
+            languages: vec!["en".into(), "fr".into(), "de".into()],
+            stop_words: BTreeMap::new(),
+            short_title_words: 3,
+            picker: None,
+            credentials: vec![],
+            passwords: vec![],
+            git_history: false,
+            sync: None,
+            index_dir: None,
+            raw_dir: None,
+            mod_dir: None,
+            log_dir: None,
+            browse_dir: None,
+            contact_email: None,
+            download_timeout: 30,
+            download_retries: 3,
+            max_download_size: 1000,
+            cookies: vec![],
+            proxies: vec![],
+            institutional_access: vec![],
+            import_workers: 4,
+            scholar_on_import: false,
+            dblp_on_import: false,
+            rate_limits: BTreeMap::new(),
+            downloads_folder: None,
+            viewers: BTreeMap::new(),
+            download_dir: None,
+            providers: vec![],
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from a yaml file.
+    /// A missing file is the default configuration.
+    pub fn load(path : &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let file = std::fs::File::open(path)
+            .context("Opening the configuration file")?;
+        // an empty file is not a valid yaml document
+        if file.metadata()?.len() == 0 {
+            return Ok(Config::default());
+        }
+        serde_yaml::from_reader(file)
+            .with_context(|| format!("Parsing the configuration file {path:?}"))
+    }
+
+    /// Saves the configuration to a yaml file.
+    pub fn save(&self, path : &Path) -> Result<()> {
+        let file = std::fs::File::create(path)
+            .context("Opening the configuration file")?;
+        serde_yaml::to_writer(file, self)
+            .context("Writing the configuration file")
+    }
+
+    /// Stop words of the configured languages.
+    pub fn stop_words(&self) -> StopWords {
+        StopWords::new(&self.languages, &self.stop_words)
+    }
+
+    /// Finds the credentials to use for a given host.
+    pub fn credential_for(&self, host : &str) -> Option<&SiteCredential> {
+        self.credentials.iter().find(|c| c.host == host)
+    }
+
+    /// Finds the password to use for a given URI, using
+    /// the longest matching prefix.
+    pub fn password_for(&self, uri : &str) -> Option<&DocumentPassword> {
+        self.passwords.iter()
+            .filter(|p| uri.starts_with(&p.uri))
+            .max_by_key(|p| p.uri.len())
+    }
+}
@@ -0,0 +1,111 @@
+// Conflicts between a document and its re-import.
+//
+// `akl import --force` replaces a document by a fresh import, whose
+// metadata comes from the pdf file and the command line. The fields
+// edited by hand since the first import would be silently lost: the
+// fields that differ are kept as they were with `--keep-local`, or
+// chosen one by one in interactive mode. Tags, identifiers and former
+// filenames are never lost, since the new import cannot know them.
+
+use anyhow::Result;
+use serde::{Serialize, Deserialize};
+
+use crate::Document;
+
+/// Fields of the metadata that a re-import may change.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Field {
+    Title,
+    Authors,
+    Year,
+    Context,
+    Abstract,
+}
+
+impl std::fmt::Display for Field {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Field::Title => "title",
+            Field::Authors => "authors",
+            Field::Year => "year",
+            Field::Context => "context",
+            Field::Abstract => "abstract",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// A field whose value in the library differs from the new import.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub field    : Field,
+    pub local    : String,
+    pub imported : String,
+}
+
+/// The value of a field, as shown to the user.
+fn value(field : Field, doc : &Document) -> String {
+    match field {
+        Field::Title => doc.title.clone(),
+        Field::Authors => doc.authors.join(", "),
+        Field::Year => doc.year.to_string(),
+        Field::Context => doc.context.join(", "),
+        Field::Abstract => doc.r#abstract.clone().unwrap_or_default(),
+    }
+}
+
+/// The fields that differ between the document of the library
+/// and its new import.
+pub fn conflicts(local : &Document, imported : &Document) -> Vec<Conflict> {
+    [Field::Title, Field::Authors, Field::Year, Field::Context, Field::Abstract].into_iter()
+        .map(|field| Conflict { field, local: value(field, local), imported: value(field, imported) })
+        .filter(|c| c.local != c.imported)
+        .collect()
+}
+
+/// Copies a field of the document of the library into the new import.
+pub fn keep_local(field : Field, local : &Document, into : &mut Document) {
+    match field {
+        Field::Title => into.title = local.title.clone(),
+        Field::Authors => into.authors = local.authors.clone(),
+        Field::Year => into.year = local.year,
+        Field::Context => into.context = local.context.clone(),
+        Field::Abstract => into.r#abstract = local.r#abstract.clone(),
+    }
+}
+
+/// Carries over what the new import cannot know: tags, identifiers,
+/// the abstract and the keywords when the import has none, and the
+/// former filenames
+/// (so that links to the previous files keep working).
+pub fn preserve(local : &Document, into : &mut Document) {
+    into.add_tags(&local.tags);
+    for i in &local.identifiers {
+        if !into.identifiers.contains(i) {
+            into.identifiers.push(i.clone());
+        }
+    }
+    if into.r#abstract.is_none() {
+        into.r#abstract = local.r#abstract.clone();
+    }
+    if into.keywords.is_empty() {
+        into.keywords = local.keywords.clone();
+    }
+    for f in std::iter::once(&local.filename).chain(&local.former_filenames) {
+        if *f != into.filename && !into.former_filenames.contains(f) {
+            into.former_filenames.push(f.clone());
+        }
+    }
+}
+
+/// Asks the user whether to keep the value of the library.
+pub fn ask(conflict : &Conflict) -> Result<bool> {
+    eprintln!("The {} changed:", conflict.field);
+    eprintln!("  library: {}", conflict.local);
+    eprintln!("  import:  {}", conflict.imported);
+    eprint!("Keep the value of the library? [Y/n] ");
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(!matches!(answer.trim(), "n" | "N"))
+}
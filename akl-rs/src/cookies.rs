@@ -0,0 +1,135 @@
+// Cookies of the downloads.
+//
+// Some sources only send their pdf files to a logged in session:
+// the cookies of the session are taken from the cookie files of the
+// configuration, and sent with the downloads of their domains.
+//
+//     cookies:
+//       - /home/me/cookies.txt
+//       - /home/me/.mozilla/firefox/abcd1234.default-release
+//
+// A file is either a cookie file in the Netscape format (written by
+// curl, wget, and the cookie export extensions of the browsers), or
+// the cookie database of a Firefox profile (`cookies.sqlite`, or the
+// profile directory containing it). The databases of the other
+// browsers are encrypted, and must be exported. The values of the
+// cookies are secrets: they are never logged nor stored.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, Context};
+use url::Url;
+
+/// A cookie of a session (not `Debug`, as its value is a secret).
+struct Cookie {
+    /// Domain of the cookie, without leading dot.
+    domain     : String,
+    /// Is the cookie sent to the subdomains of its domain?
+    subdomains : bool,
+    path       : String,
+    /// Is the cookie only sent over https?
+    secure     : bool,
+    /// Expiration time (seconds since the epoch), 0 for
+    /// the cookies of the session.
+    expires    : i64,
+    name       : String,
+    value      : String,
+}
+
+/// Reads a cookie file in the Netscape format: one cookie by line,
+/// with tab separated domain, subdomains flag, path, secure flag,
+/// expiration, name and value.
+fn parse_netscape(text : &str) -> Vec<Cookie> {
+    text.lines()
+        .filter_map(|line| {
+            // curl marks the http only cookies this way
+            let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+            if line.starts_with('#') {
+                return None;
+            }
+            let fields : Vec<&str> = line.split('\t').collect();
+            let [domain, subdomains, path, secure, expires, name, value] = fields[..] else {
+                return None;
+            };
+            Some(Cookie {
+                domain: domain.trim_start_matches('.').to_string(),
+                subdomains: subdomains.eq_ignore_ascii_case("TRUE") || domain.starts_with('.'),
+                path: path.to_string(),
+                secure: secure.eq_ignore_ascii_case("TRUE"),
+                expires: expires.parse().unwrap_or(0),
+                name: name.to_string(),
+                value: value.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Reads the cookie database of a Firefox profile. Firefox locks
+/// it while running, so a copy is read.
+fn read_firefox(path : &Path) -> Result<Vec<Cookie>> {
+    let copy = tempfile::NamedTempFile::new().context("Copying the cookie database")?;
+    std::fs::copy(path, copy.path()).with_context(|| format!("Copying the cookie database {path:?}"))?;
+    let conn = rusqlite::Connection::open_with_flags(copy.path(), rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Opening the cookie database {path:?}"))?;
+    let mut statement = conn.prepare("SELECT host, path, isSecure, expiry, name, value FROM moz_cookies")
+        .with_context(|| format!("Reading the cookie database {path:?}"))?;
+    let cookies = statement.query_map([], |row| {
+        let host : String = row.get(0)?;
+        Ok(Cookie {
+            domain: host.trim_start_matches('.').to_string(),
+            subdomains: host.starts_with('.'),
+            path: row.get(1)?,
+            secure: row.get::<_, i64>(2)? != 0,
+            expires: row.get(3)?,
+            name: row.get(4)?,
+            value: row.get(5)?,
+        })
+    })?;
+    Ok(cookies.collect::<rusqlite::Result<Vec<Cookie>>>()?)
+}
+
+/// Reads the cookies of a file of the configuration.
+fn read(path : &Path) -> Result<Vec<Cookie>> {
+    let path : PathBuf = if path.is_dir() { path.join("cookies.sqlite") } else { path.to_path_buf() };
+    if path.extension().is_some_and(|e| e == "sqlite") {
+        return read_firefox(&path);
+    }
+    let text = std::fs::read_to_string(&path)
+        .with_context(|| format!("Reading the cookie file {path:?}"))?;
+    Ok(parse_netscape(&text))
+}
+
+/// Does a cookie go with a request to a url?
+fn applies(cookie : &Cookie, url : &Url, now : i64) -> bool {
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    let domain = host == cookie.domain ||
+        (cookie.subdomains && host.strip_suffix(cookie.domain.as_str()).is_some_and(|s| s.ends_with('.')));
+    domain &&
+        url.path().starts_with(&cookie.path) &&
+        (!cookie.secure || url.scheme() == "https") &&
+        (cookie.expires == 0 || cookie.expires > now)
+}
+
+/// The `Cookie` header of a request to a url, from the cookie files,
+/// `None` when no cookie goes with it. Unreadable files are skipped.
+pub fn header(files : &[PathBuf], url : &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let now = chrono::Utc::now().timestamp();
+    let cookies : Vec<Cookie> = files.iter()
+        .flat_map(|f| read(f).unwrap_or_else(|e| {
+            log::warn!("Ignoring the cookies of {f:?}: {e:#}");
+            vec![]
+        }))
+        .filter(|c| applies(c, &url, now))
+        .collect();
+    if cookies.is_empty() {
+        return None;
+    }
+    // only the names: the values are secrets
+    log::debug!("Sending the cookies {} to {}",
+                cookies.iter().map(|c| c.name.as_str()).collect::<Vec<&str>>().join(", "),
+                url.host_str().unwrap_or_default());
+    Some(cookies.iter().map(|c| format!("{}={}", c.name, c.value)).collect::<Vec<String>>().join("; "))
+}
@@ -0,0 +1,167 @@
+// Metadata of the documents from the Crossref API.
+//
+// The Info dictionary of a pdf file is often garbage (the LaTeX class
+// as title, the typesetting software as author), while Crossref knows
+// the metadata registered by the publisher of a doi: title, authors,
+// year, venue and pages. The answers, including the dois unknown to
+// Crossref, are cached in `crossref/` of the cache directory, and
+// the requests carry a user-agent with the contact address of the
+// configuration, as Crossref asks of polite clients (see `polite`).
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, Context};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// What Crossref knows about a doi.
+#[derive(Debug, Default, Clone)]
+pub struct Work {
+    pub title       : Option<String>,
+    pub authors     : Vec<String>,
+    /// The ORCIDs of the authors having one, with their name.
+    pub orcids      : Vec<(String, String)>,
+    pub year        : Option<u32>,
+    /// The journal or the proceedings.
+    pub venue       : Option<String>,
+    pub pages       : Option<String>,
+    pub r#abstract  : Option<String>,
+    pub keywords    : Vec<String>,
+    /// Other identifiers of the work (the doi of a HAL deposit…).
+    pub identifiers : Vec<String>,
+}
+
+/// First string of a Crossref list (titles are lists).
+fn first(value : &Value) -> Option<String> {
+    value.as_array()
+        .and_then(|a| a.iter().find_map(|v| v.as_str()))
+        .map(|s| s.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .filter(|s| !s.is_empty())
+}
+
+/// Year of a Crossref date (`{"date-parts": [[2023, 5, 1]]}`).
+pub fn year(date : &Value) -> Option<u32> {
+    date["date-parts"][0][0].as_u64().and_then(|y| u32::try_from(y).ok())
+}
+
+/// The ORCIDs of a list of authors in the Crossref (or CSL-JSON)
+/// format, given as urls, with the names of the authors.
+pub fn orcids(authors : &Value, name : impl Fn(&Value) -> Option<String>) -> Vec<(String, String)> {
+    authors.as_array()
+        .map(|a| a.iter()
+            .filter_map(|author| {
+                let orcid = author["ORCID"].as_str().and_then(crate::authors::normalize_orcid)?;
+                Some((name(author)?, orcid))
+            })
+            .collect())
+        .unwrap_or_default()
+}
+
+/// The name of an author of a Crossref answer.
+fn author_name(author : &Value) -> Option<String> {
+    let family = author["family"].as_str().or(author["name"].as_str())?;
+    Some(match author["given"].as_str() {
+        Some(given) => format!("{given} {family}"),
+        None => family.to_string(),
+    })
+}
+
+/// Reads the `message` of a Crossref answer.
+fn parse(message : &Value) -> Work {
+    let authors = message["author"].as_array()
+        .map(|a| a.iter().filter_map(author_name).collect())
+        .unwrap_or_default();
+    Work {
+        title: first(&message["title"]),
+        authors,
+        orcids: orcids(&message["author"], author_name),
+        year: ["issued", "published-print", "published-online", "created"].iter()
+            .find_map(|field| year(&message[field])),
+        venue: first(&message["container-title"]),
+        pages: message["page"].as_str().map(String::from),
+        r#abstract: message["abstract"].as_str()
+            .map(crate::abstracts::strip_tags)
+            .filter(|a| !a.is_empty()),
+        keywords: message["subject"].as_array()
+            .map(|s| s.iter().filter_map(|k| k.as_str()).map(String::from).collect())
+            .unwrap_or_default(),
+        identifiers: vec![],
+    }
+}
+
+/// A client of the Crossref API, with its cache.
+pub struct Crossref {
+    cache : PathBuf,
+}
+
+impl Crossref {
+    pub fn new(cache : &Path) -> Self {
+        Crossref { cache: cache.join("crossref") }
+    }
+
+    /// Cached answer of a doi (dois are case insensitive).
+    fn cache_file(&self, doi : &str) -> PathBuf {
+        let hash = Sha256::digest(doi.to_lowercase().as_bytes());
+        self.cache.join(format!("{hash:x}.json"))
+    }
+
+    /// The `message` of the answer of Crossref about a doi,
+    /// `null` when Crossref does not know it.
+    fn message(&self, doi : &str) -> Result<Value> {
+        let cached = self.cache_file(doi);
+        if let Ok(file) = std::fs::File::open(&cached) {
+            match serde_json::from_reader(file) {
+                Ok(message) => return Ok(message),
+                Err(e) => log::warn!("Ignoring the invalid cached answer {cached:?}: {e}"),
+            }
+        }
+        log::debug!("Querying the Crossref API for {doi}");
+        let answer = crate::polite::get(&format!("https://api.crossref.org/works/{doi}"))
+            .send()
+            .context("Querying the Crossref API")?;
+        let message = if answer.status() == reqwest::StatusCode::NOT_FOUND {
+            Value::Null
+        } else {
+            let mut answer : Value = answer.error_for_status()
+                .context("Querying the Crossref API")?
+                .json()
+                .context("Parsing the Crossref API answer")?;
+            answer["message"].take()
+        };
+        let write = std::fs::create_dir_all(&self.cache)
+            .and_then(|_| std::fs::write(&cached, serde_json::to_vec(&message)?));
+        if let Err(e) = write {
+            log::warn!("Could not cache the Crossref answer for {doi}: {e}");
+        }
+        Ok(message)
+    }
+
+    /// The works found by a bibliographic query (title and authors),
+    /// with their dois and types (`journal-article`, `posted-content`
+    /// for preprints…). Searches are not cached.
+    pub fn search(&self, query : &str) -> Result<Vec<(String, String, Work)>> {
+        let query = serde_urlencoded::to_string([("query.bibliographic", query), ("rows", "5")])?;
+        log::debug!("Searching the Crossref API: {query}");
+        let answer : Value = crate::polite::get(&format!("https://api.crossref.org/works?{query}"))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .context("Searching the Crossref API")?
+            .json()
+            .context("Parsing the Crossref API answer")?;
+        Ok(answer["message"]["items"].as_array()
+            .map(|items| items.iter()
+                .filter_map(|item| Some((
+                    item["DOI"].as_str()?.to_lowercase(),
+                    item["type"].as_str().unwrap_or_default().to_string(),
+                    parse(item),
+                )))
+                .collect())
+            .unwrap_or_default())
+    }
+
+    /// What Crossref knows about a doi, `None` when it does not know it.
+    pub fn work(&self, doi : &str) -> Result<Option<Work>> {
+        let message = self.message(doi)?;
+        Ok((!message.is_null()).then(|| parse(&message)))
+    }
+}
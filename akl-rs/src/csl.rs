@@ -0,0 +1,107 @@
+// CSL-JSON export, for Pandoc and citeproc.
+//
+// The items use the same citation keys as the BibTeX export, so
+// that a document is cited the same way from LaTeX and Markdown.
+// Preprints are described the way Zotero does: an `article`
+// published by arXiv, numbered by its arxiv id.
+
+use serde::Serialize;
+
+use crate::{Document, identifiers, latex};
+use crate::doctype::DocType;
+
+/// A structured author name.
+#[derive(Serialize, Debug, Clone)]
+pub struct Name {
+    pub family : String,
+
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub given  : String,
+}
+
+/// A date, as a list of (year, month, day) prefixes.
+#[derive(Serialize, Debug, Clone)]
+pub struct Date {
+    #[serde(rename = "date-parts")]
+    pub date_parts : Vec<Vec<u32>>,
+}
+
+/// An item of a CSL-JSON bibliography.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct Item {
+    pub id              : String,
+
+    #[serde(rename = "type")]
+    pub kind            : &'static str,
+
+    pub title           : String,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub author          : Vec<Name>,
+
+    pub issued          : Date,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_title : Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page            : Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub publisher       : Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub number          : Option<String>,
+
+    #[serde(rename = "DOI", skip_serializing_if = "Option::is_none")]
+    pub doi             : Option<String>,
+
+    #[serde(rename = "ISBN", skip_serializing_if = "Option::is_none")]
+    pub isbn            : Option<String>,
+
+    #[serde(rename = "URL", skip_serializing_if = "Option::is_none")]
+    pub url             : Option<String>,
+}
+
+/// The CSL-JSON item of a document: an item of its type, or without
+/// type an `article-journal` in the venue of its first context, an
+/// arxiv `article`, or a `document`.
+pub fn item(key : &str, doc : &Document) -> Item {
+    let arxiv = identifiers::arxiv_id(&doc.identifiers);
+    let venue = doc.context.first().cloned();
+    let kind = match (doc.doc_type, &venue, &arxiv) {
+        (Some(DocType::Article), _, _) => "article-journal",
+        (Some(DocType::Preprint), _, _) => "article",
+        (Some(DocType::Thesis), _, _) => "thesis",
+        (Some(DocType::Book), _, _) => "book",
+        (Some(DocType::Slides), _, _) => "speech",
+        (Some(DocType::Report), _, _) => "report",
+        (Some(DocType::Blog), _, _) => "post-weblog",
+        (None, Some(_), _) => "article-journal",
+        (None, None, Some(_)) => "article",
+        (None, None, None) => "document",
+    };
+    let preprint = venue.is_none() && arxiv.is_some();
+    let url = identifiers::web_url(&doc.identifiers).cloned()
+        .or_else(|| arxiv.as_ref().map(|id| format!("https://arxiv.org/abs/{id}")));
+    Item {
+        id: key.to_string(),
+        kind,
+        title: doc.title.clone(),
+        author: doc.authors.iter()
+            .map(|a| {
+                let (given, family) = latex::split_name(a);
+                Name { family, given }
+            })
+            .collect(),
+        issued: Date { date_parts: vec![vec![doc.year]] },
+        container_title: venue,
+        page: doc.pages.clone(),
+        publisher: preprint.then(|| "arXiv".to_string()),
+        number: arxiv.filter(|_| preprint).map(|id| format!("arXiv:{id}")),
+        doi: identifiers::doi(&doc.identifiers),
+        isbn: identifiers::isbn(&doc.identifiers),
+        url,
+    }
+}
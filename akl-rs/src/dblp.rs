@@ -0,0 +1,154 @@
+// Records of the documents in DBLP.
+//
+// DBLP knows most of the computer science literature, and its keys
+// (`conf/icalp/Martens18`) are the citation keys of many existing
+// bibliographies (`DBLP:conf/icalp/Martens18`). A document is looked
+// up by its title and first author, and a record is accepted only
+// when its title matches, and its doi or else its year. The key is
+// stored as a `dblp:` identifier, which the BibTeX export uses as
+// citation key. The page of a person (`m/WimMartens`) lists their
+// papers, with links to their arXiv versions.
+
+use anyhow::{Result, Context};
+use serde_json::Value;
+
+use crate::{Document, identifiers, latex};
+use crate::published::Publication;
+
+/// Number of records asked for each search.
+const HITS : &str = "10";
+
+/// Pause between two searches of a batch, as DBLP
+/// refuses the clients sending too many requests.
+pub const DELAY : std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Lowercase words of a title, without punctuation
+/// (DBLP ends its titles with a period).
+pub fn normalize(title : &str) -> String {
+    latex::to_ascii(title)
+        .to_lowercase()
+        .split(|c : char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// A record of a search answer.
+struct Record {
+    key   : String,
+    title : String,
+    year  : Option<u32>,
+    doi   : Option<String>,
+    venue : Option<String>,
+}
+
+impl Record {
+    fn parse(info : &Value) -> Option<Self> {
+        Some(Record {
+            key: info["key"].as_str()?.to_string(),
+            title: info["title"].as_str()?.to_string(),
+            year: info["year"].as_str().and_then(|y| y.parse().ok()),
+            doi: info["doi"].as_str().map(|d| d.to_lowercase()),
+            venue: match &info["venue"] {
+                Value::Array(v) => v.first().and_then(Value::as_str).map(String::from),
+                v => v.as_str().map(String::from),
+            },
+        })
+    }
+
+    /// Is this the record of the preprint on arXiv?
+    fn is_corr(&self) -> bool {
+        self.key.starts_with("journals/corr/")
+    }
+}
+
+/// Chooses the record of a document among search results: the one
+/// with the doi of the document, or else with its title and year,
+/// preferring the published version to the arXiv preprint.
+fn choose(doc : &Document, records : Vec<Record>) -> Option<String> {
+    let title = normalize(&doc.title);
+    let doi = identifiers::doi(&doc.identifiers).map(|d| d.to_lowercase());
+    let mut candidates : Vec<Record> = records.into_iter()
+        .filter(|r| normalize(&r.title) == title)
+        .collect();
+    if let Some(found) = candidates.iter().find(|r| doi.is_some() && r.doi == doi) {
+        return Some(found.key.clone());
+    }
+    candidates.retain(|r| r.year == Some(doc.year));
+    candidates.sort_by_key(Record::is_corr);
+    candidates.into_iter().next().map(|r| r.key)
+}
+
+/// The records of DBLP found by the title and
+/// first author of a document.
+fn search(doc : &Document) -> Result<Vec<Record>> {
+    let mut query = doc.title.clone();
+    if let Some(author) = doc.authors.first() {
+        query.push(' ');
+        query.push_str(&latex::split_name(author).1);
+    }
+    let query = serde_urlencoded::to_string([("q", query.as_str()), ("format", "json"), ("h", HITS)])?;
+    log::debug!("Querying DBLP for {}", doc.title);
+    let answer : Value = crate::polite::get(&format!("https://dblp.org/search/publ/api?{query}"))
+        .send()
+        .and_then(|r| r.error_for_status())
+        .context("Querying the DBLP API")?
+        .json()
+        .context("Parsing the DBLP API answer")?;
+    Ok(answer["result"]["hits"]["hit"].as_array()
+        .map(|hits| hits.iter().filter_map(|h| Record::parse(&h["info"])).collect())
+        .unwrap_or_default())
+}
+
+/// The DBLP key of a document, if DBLP knows it.
+pub fn lookup(doc : &Document) -> Result<Option<String>> {
+    Ok(choose(doc, search(doc)?))
+}
+
+/// The published version of a preprint according to DBLP: a record
+/// of the same title with a doi, outside of arXiv (CoRR).
+pub fn publication(doc : &Document) -> Result<Option<Publication>> {
+    let title = normalize(&doc.title);
+    Ok(search(doc)?.into_iter()
+        .filter(|r| !r.is_corr() && normalize(&r.title) == title)
+        .find_map(|r| Some(Publication {
+            doi: r.doi?,
+            venue: r.venue,
+            identifiers: vec![format!("dblp:{}", r.key)],
+            source: "DBLP",
+        })))
+}
+
+/// The arxiv ids of the papers of a person of DBLP, given
+/// by their pid (`m/WimMartens`), the newest first.
+pub fn arxiv_papers(pid : &str) -> Result<Vec<String>> {
+    use xml::reader::{EventReader, XmlEvent};
+    log::debug!("Querying DBLP for the papers of {pid}");
+    let answer = crate::polite::get(&format!("https://dblp.org/pid/{pid}.xml"))
+        .send()
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("Querying DBLP for the papers of {pid}"))?
+        .text()
+        .context("Reading the DBLP answer")?;
+    let mut ids : Vec<String> = vec![];
+    let mut in_ee = false;
+    let mut text = String::new();
+    for event in EventReader::from_str(&answer) {
+        match event.context("Parsing the DBLP answer")? {
+            XmlEvent::StartElement { name, .. } => {
+                in_ee = name.local_name == "ee";
+                text.clear();
+            }
+            XmlEvent::EndElement { .. } if in_ee => {
+                let id = url::Url::parse(text.trim()).ok().as_ref().and_then(crate::arxiv::from_url);
+                if let Some(id) = id.filter(|id| !ids.contains(&id.id)) {
+                    ids.push(id.id);
+                }
+                in_ee = false;
+            }
+            XmlEvent::Characters(t) if in_ee => text.push_str(&t),
+            _ => {}
+        }
+    }
+    Ok(ids)
+}
@@ -0,0 +1,226 @@
+// Interactions with the desktop environment.
+//
+// Notifications, clipboard and viewers only make sense when
+// akl runs on a graphical session. In headless mode (e.g. when
+// akl runs on a remote machine driven over SSH) everything is
+// printed on the standard output instead.
+//
+// Notifications can carry actions (open or cite the document that
+// was just imported…). They need a process that outlives the
+// notification to run them, hence are only shown by the handler.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, Context};
+
+use copypasta_ext::prelude::*;
+use copypasta_ext::x11_fork::ClipboardContext;
+
+/// The desktop environment akl runs in.
+#[derive(Clone, Debug, Default)]
+pub struct Desktop {
+    /// No notifications, clipboard or viewers.
+    pub headless : bool,
+
+    /// Is the process running long enough to
+    /// answer the actions of the notifications?
+    pub persistent : bool,
+}
+
+/// What an action of a notification does.
+#[derive(Clone, Debug)]
+pub enum Action {
+    /// Runs an akl uri, in the handler when there is one.
+    Uri(String),
+    /// Shows a file in the file manager.
+    Reveal(PathBuf),
+}
+
+impl Action {
+    fn run(&self) -> Result<()> {
+        match self {
+            Action::Uri(uri) => match crate::handler::forward(uri) {
+                Some(result) => result,
+                None => {
+                    std::process::Command::new(std::env::current_exe()?)
+                        .arg(uri)
+                        .spawn()
+                        .context("Starting akl")?;
+                    Ok(())
+                }
+            },
+            Action::Reveal(path) => {
+                let dir = path.parent().unwrap_or(path);
+                open::that(dir).with_context(|| format!("Opening {dir:?}"))
+            }
+        }
+    }
+}
+
+/// A notification updated in place as a task progresses. Only the
+/// freedesktop servers can replace a notification: elsewhere, the
+/// progress is not notified (a notification every second would
+/// pile up).
+#[derive(Default)]
+pub struct ProgressNotification {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    handle : Option<notify_rust::NotificationHandle>,
+}
+
+impl ProgressNotification {
+    /// Shows the progress, as a percentage when it is known.
+    #[cfg(all(unix, not(target_os = "macos")))]
+    pub fn update(&mut self, summary : &str, body : &str, percent : Option<u8>) {
+        let mut notification = notify_rust::Notification::new();
+        notification.summary(summary).body(body).timeout(notify_rust::Timeout::Never);
+        if let Some(percent) = percent {
+            notification.hint(notify_rust::NotificationHint::CustomInt("value".into(), percent.into()));
+        }
+        if let Some(handle) = &self.handle {
+            notification.id(handle.id());
+        }
+        match notification.show() {
+            Ok(handle) => { self.handle = Some(handle); }
+            Err(e) => log::warn!("Could not show the progress notification: {e}"),
+        }
+    }
+
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    pub fn update(&mut self, _summary : &str, _body : &str, _percent : Option<u8>) {}
+
+    /// Closes the notification.
+    pub fn close(self) {
+        #[cfg(all(unix, not(target_os = "macos")))]
+        if let Some(handle) = self.handle {
+            handle.close();
+        }
+    }
+}
+
+/// The page a pdf file was left at in evince (and the viewers
+/// sharing its metadata, e.g. xreader), which records it in the gvfs
+/// metadata of the file (queried over D-Bus by `gio`).
+pub fn last_viewed_page(path : &Path) -> Option<u32> {
+    let output = std::process::Command::new("gio")
+        .args(["info", "--attributes", "metadata::evince::page"])
+        .arg(path)
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout).lines()
+        .find_map(|l| l.trim().strip_prefix("metadata::evince::page:")?.trim().parse::<u32>().ok())
+        // evince counts the pages from 0
+        .map(|page| page + 1)
+}
+
+/// Is there a graphical session to talk to?
+///
+/// On windows and macos we assume that there always is one,
+/// elsewhere we look for an X11 or Wayland display.
+fn has_display() -> bool {
+    if cfg!(any(target_os = "windows", target_os = "macos")) {
+        return true;
+    }
+    std::env::var_os("DISPLAY").is_some() ||
+    std::env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+impl Desktop {
+    /// Detects the desktop environment, unless
+    /// headless mode is explicitly requested.
+    pub fn detect(headless : bool) -> Self {
+        let headless = headless || !has_display();
+        if headless {
+            log::info!("Running in headless mode");
+        }
+        Desktop { headless, persistent: false }
+    }
+
+    /// Notifies the user.
+    pub fn notify(&self, summary : &str, body : &str) -> Result<()> {
+        if self.headless {
+            println!("{summary}: {body}");
+            Ok(())
+        } else {
+            notifica::notify(summary, body)
+                .map_err(|e| anyhow::anyhow!("{e}"))
+                .context("Sending a desktop notification")
+        }
+    }
+
+    /// Notifies the user, offering actions given as (label, action)
+    /// pairs where the platform supports it (freedesktop) and the
+    /// process is persistent. In headless mode, the actions are
+    /// printed below the notification.
+    pub fn notify_with_actions(&self, summary : &str, body : &str, actions : Vec<(String, Action)>) -> Result<()> {
+        if self.headless {
+            println!("{summary}: {body}");
+            for (label, action) in &actions {
+                match action {
+                    Action::Uri(uri) => println!("  {label}: {uri}"),
+                    Action::Reveal(path) => println!("  {label}: {}", path.display()),
+                }
+            }
+            return Ok(());
+        }
+        if !self.persistent || actions.is_empty() {
+            return self.notify(summary, body);
+        }
+        self.notify_freedesktop(summary, body, actions)
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn notify_freedesktop(&self, summary : &str, body : &str, actions : Vec<(String, Action)>) -> Result<()> {
+        let mut notification = notify_rust::Notification::new();
+        notification.summary(summary).body(body);
+        for (i, (label, _)) in actions.iter().enumerate() {
+            notification.action(&i.to_string(), label);
+        }
+        // the connection of the notification cannot change of
+        // thread: it is shown by the thread waiting for the action
+        std::thread::spawn(move || {
+            let handle = match notification.show() {
+                Ok(handle) => handle,
+                Err(e) => {
+                    log::error!("Could not send a desktop notification: {e}");
+                    return;
+                }
+            };
+            handle.wait_for_action(|id| {
+                let Some((label, action)) = id.parse::<usize>().ok().and_then(|i| actions.get(i)) else {
+                    return;
+                };
+                log::info!("Notification action {label}");
+                if let Err(e) = action.run() {
+                    log::error!("The notification action {label} failed: {e:#}");
+                }
+            });
+        });
+        Ok(())
+    }
+
+    #[cfg(not(all(unix, not(target_os = "macos"))))]
+    fn notify_freedesktop(&self, summary : &str, body : &str, _actions : Vec<(String, Action)>) -> Result<()> {
+        self.notify(summary, body)
+    }
+
+    /// A notification showing the progress of a task, not shown
+    /// before its first update.
+    pub fn progress_notification(&self) -> ProgressNotification {
+        ProgressNotification::default()
+    }
+
+    /// Puts some text in the clipboard.
+    pub fn copy(&self, text : String) -> Result<()> {
+        if self.headless {
+            println!("{text}");
+            Ok(())
+        } else {
+            let mut ctx = ClipboardContext::new()
+                .map_err(|e| anyhow::anyhow!("{e}"))
+                .context("Opening the clipboard")?;
+            ctx.set_contents(text)
+                .map_err(|e| anyhow::anyhow!("{e}"))
+                .context("Setting the clipboard contents")
+        }
+    }
+}
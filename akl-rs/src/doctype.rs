@@ -0,0 +1,74 @@
+// Types of the documents.
+//
+// The type of a document (article, thesis, slides…) is given on
+// import, read from the BibTeX entry type or the Zotero item type,
+// or deduced from an arxiv uri. It chooses the BibTeX and CSL-JSON
+// entry types of the exports, is available to the filename
+// templates (`{type}`) and filters the find command (`type:thesis`).
+// Documents without type are exported as before.
+
+use std::fmt;
+
+use serde::{Serialize, Deserialize};
+
+/// What kind of document it is.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum DocType {
+    /// Published in a journal or in proceedings.
+    Article,
+    /// Not (yet) published, e.g. on arxiv.
+    Preprint,
+    Thesis,
+    Book,
+    /// Slides of a talk.
+    Slides,
+    /// Technical report.
+    Report,
+    /// Blog post or web page.
+    Blog,
+}
+
+impl DocType {
+    /// The type of a BibTeX entry type (`@phdthesis`).
+    pub fn from_bibtex(kind : &str) -> Option<Self> {
+        match kind.to_lowercase().as_str() {
+            "article" | "inproceedings" | "conference" | "incollection" => Some(DocType::Article),
+            "unpublished" => Some(DocType::Preprint),
+            "phdthesis" | "mastersthesis" | "thesis" => Some(DocType::Thesis),
+            "book" | "inbook" => Some(DocType::Book),
+            "techreport" | "report" => Some(DocType::Report),
+            "online" | "electronic" | "www" => Some(DocType::Blog),
+            _ => None,
+        }
+    }
+
+    /// The type of a Zotero item type (`journalArticle`).
+    pub fn from_zotero(kind : &str) -> Option<Self> {
+        match kind {
+            "journalArticle" | "conferencePaper" | "bookSection" => Some(DocType::Article),
+            "preprint" | "manuscript" => Some(DocType::Preprint),
+            "thesis" => Some(DocType::Thesis),
+            "book" => Some(DocType::Book),
+            "presentation" => Some(DocType::Slides),
+            "report" => Some(DocType::Report),
+            "blogPost" | "webpage" | "forumPost" => Some(DocType::Blog),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DocType {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DocType::Article => "article",
+            DocType::Preprint => "preprint",
+            DocType::Thesis => "thesis",
+            DocType::Book => "book",
+            DocType::Slides => "slides",
+            DocType::Report => "report",
+            DocType::Blog => "blog",
+        };
+        write!(f, "{name}")
+    }
+}
@@ -0,0 +1,208 @@
+// Downloads of the documents.
+//
+// The servers of the publishers are slow, flaky and protective. A
+// download is retried on the failures that may not happen again
+// (connection errors, timeouts, answers 5xx and rate limiting), after
+// an exponential backoff or the delay asked by a `Retry-After` header,
+// and an interrupted transfer resumes where it stopped when the server
+// accepts ranges. The answers that would not change (403, 404…) fail
+// at once, with an error saying what the server meant.
+//
+// The body is written to a file as it arrives rather than kept in
+// memory, reporting its progress (see `progress`), and a download
+// larger than the configured maximum size is abandoned. Each attempt
+// waits for the delay of its host (see `polite`).
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::time::Duration;
+
+use anyhow::Result;
+use reqwest::StatusCode;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header;
+
+use crate::progress::Progress;
+
+/// Delay before the first retry, doubled after each failure.
+const BACKOFF : Duration = Duration::from_secs(1);
+
+/// Longest delay accepted from a `Retry-After` header.
+const MAX_RETRY_AFTER : Duration = Duration::from_secs(120);
+
+/// A client whose connections and reads time out after `timeout`
+/// seconds, using `proxy` rather than the proxies of the environment.
+pub fn client(timeout : u64, proxy : Option<reqwest::Proxy>) -> Result<Client> {
+    let builder = Client::builder()
+        .timeout(Duration::from_secs(timeout))
+        .connect_timeout(Duration::from_secs(timeout));
+    let builder = match proxy {
+        Some(proxy) => builder.proxy(proxy),
+        None => builder,
+    };
+    Ok(builder.build()?)
+}
+
+/// The error of an answer that no retry would change, or of
+/// rate limiting once the retries are exhausted.
+fn status_error(url : &str, status : StatusCode) -> Option<anyhow::Error> {
+    let host = reqwest::Url::parse(url).ok()
+        .and_then(|u| u.host_str().map(String::from))
+        .unwrap_or_default();
+    let reason = match status {
+        StatusCode::UNAUTHORIZED =>
+            format!("{host} needs a login: configure the credentials of {host}"),
+        StatusCode::FORBIDDEN =>
+            format!("{host} refused the download: the document may need a login, \
+                     a subscription, or a browser (see the akl-provider programs)"),
+        StatusCode::NOT_FOUND | StatusCode::GONE =>
+            "the document does not exist: check the url".into(),
+        StatusCode::TOO_MANY_REQUESTS =>
+            format!("{host} limits the rate of downloads: try again later"),
+        _ => return None,
+    };
+    Some(anyhow::anyhow!("Downloading {url} failed ({status}): {reason}"))
+}
+
+/// Is the failure of an answer worth a retry?
+fn is_transient(status : StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::REQUEST_TIMEOUT || status.is_server_error()
+}
+
+/// The delay asked by a `Retry-After` header, in seconds
+/// (the dates of the header are not supported).
+fn retry_after(answer : &Response) -> Option<Duration> {
+    let secs : u64 = answer.headers().get(header::RETRY_AFTER)?.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(secs).min(MAX_RETRY_AFTER))
+}
+
+/// How an attempt failed.
+enum Failure {
+    /// Retrying may succeed, after the delay asked by the server if any.
+    Transient(anyhow::Error, Option<Duration>),
+    /// Retrying would fail the same way.
+    Permanent(anyhow::Error),
+}
+
+/// The error of a download larger than `max_size` bytes.
+fn too_large(url : &str, max_size : u64) -> Failure {
+    Failure::Permanent(anyhow::anyhow!(
+        "Downloading {url} failed: the file is larger than {} MB (see max_download_size in the configuration)",
+        max_size / 1_000_000))
+}
+
+/// Empties a file.
+fn truncate(file : &mut File) -> std::io::Result<()> {
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(())
+}
+
+/// What a server said about a file it sent.
+pub struct Answer {
+    pub status       : StatusCode,
+    /// The announced type of the file (`application/pdf`).
+    pub content_type : Option<String>,
+}
+
+/// A file counting the bytes written to it, and
+/// reporting them to a progress.
+struct Counted<'a> {
+    file     : &'a mut File,
+    written  : u64,
+    total    : Option<u64>,
+    progress : &'a mut Progress,
+}
+
+impl Write for Counted<'_> {
+    fn write(&mut self, buf : &[u8]) -> std::io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        self.progress.update(self.written, self.total);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// One attempt, appending the body to `file` (which holds the
+/// beginning of the body after an interrupted attempt).
+fn attempt(url : &str, request : RequestBuilder, file : &mut File, max_size : u64, progress : &mut Progress) -> Result<Answer, Failure> {
+    let io = |e : std::io::Error| Failure::Permanent(anyhow::Error::new(e).context("Writing the downloaded file"));
+    let received = file.seek(SeekFrom::End(0)).map_err(io)?;
+    crate::polite::wait(url);
+    let request = if received == 0 {
+        request
+    } else {
+        log::info!("Resuming the download of {url} after {received} bytes");
+        request.header(header::RANGE, format!("bytes={received}-"))
+    };
+    let mut answer = request.send()
+        .map_err(|e| Failure::Transient(anyhow::Error::new(e).context(format!("Downloading {url}")), None))?;
+    let status = answer.status();
+    log::debug!("Status {status:?}");
+    if is_transient(status) {
+        let error = status_error(url, status)
+            .unwrap_or_else(|| anyhow::anyhow!("Downloading {url} failed ({status})"));
+        return Err(Failure::Transient(error, retry_after(&answer)));
+    }
+    if let Some(error) = status_error(url, status) {
+        return Err(Failure::Permanent(error));
+    }
+    // the server ignored the range, and sends everything again
+    let start = if status == StatusCode::PARTIAL_CONTENT { received } else { 0 };
+    if start == 0 {
+        truncate(file).map_err(io)?;
+    }
+    if answer.content_length().is_some_and(|l| start + l > max_size) {
+        return Err(too_large(url, max_size));
+    }
+    let resumable = answer.headers().get(header::ACCEPT_RANGES)
+        .is_some_and(|r| r.as_bytes() == b"bytes");
+    let total = answer.content_length().map(|l| start + l);
+    let mut counted = Counted { file, written: start, total, progress };
+    let copied = std::io::copy(&mut (&mut answer).take(max_size - start + 1), &mut counted);
+    match copied {
+        Ok(copied) if start + copied > max_size => return Err(too_large(url, max_size)),
+        Ok(_) => {}
+        Err(e) => {
+            if !resumable {
+                truncate(file).map_err(io)?;
+            }
+            return Err(Failure::Transient(anyhow::Error::new(e).context(format!("Reading the answer of {url}")), None));
+        }
+    }
+    Ok(Answer {
+        status: if status == StatusCode::PARTIAL_CONTENT { StatusCode::OK } else { status },
+        content_type: answer.headers().get(header::CONTENT_TYPE)
+            .and_then(|t| t.to_str().ok())
+            .map(String::from),
+    })
+}
+
+/// Downloads a url to an empty file with the requests built by
+/// `request`, retrying at most `retries` times and giving up beyond
+/// `max_size` bytes.
+pub fn fetch(url : &str, request : &dyn Fn() -> RequestBuilder, retries : u32, file : &mut File, max_size : u64) -> Result<Answer> {
+    let mut delay = BACKOFF;
+    let mut tries = 0;
+    let mut progress = Progress::new(url);
+    loop {
+        match attempt(url, request(), file, max_size, &mut progress) {
+            Ok(answer) => return Ok(answer),
+            Err(Failure::Permanent(e)) => return Err(e),
+            Err(Failure::Transient(e, _)) if tries >= retries => {
+                return Err(e.context(format!("Giving up {url} after {} attempts", tries + 1)));
+            }
+            Err(Failure::Transient(e, asked)) => {
+                let wait = asked.unwrap_or(delay);
+                log::warn!("{e:#}, trying again in {}s", wait.as_secs());
+                std::thread::sleep(wait);
+                delay *= 2;
+                tries += 1;
+            }
+        }
+    }
+}
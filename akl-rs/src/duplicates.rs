@@ -0,0 +1,172 @@
+// Detection and merging of duplicate documents.
+//
+// The same paper is often imported twice: from arxiv and from
+// the publisher, or from two urls of the same file. The two
+// entries have different checksums, but share an identifier
+// (once the urls are normalized) or have almost the same title
+// and authors.
+
+use std::collections::HashSet;
+
+use url::Url;
+
+use crate::{Document, latex, stopwords};
+
+/// Minimal similarity of the titles of two duplicates.
+const TITLE_SIMILARITY : f64 = 0.85;
+
+/// Why two documents look like duplicates.
+#[derive(Debug, Clone)]
+pub enum Reason {
+    /// They share an identifier.
+    SameIdentifier(String),
+    /// Their titles are similar and they share an author.
+    SimilarMetadata,
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reason::SameIdentifier(i) => write!(f, "same identifier {i}"),
+            Reason::SimilarMetadata => write!(f, "similar title and authors"),
+        }
+    }
+}
+
+/// Version independent form of an identifier: `arxiv:2101.00001`
+/// for every url or version of an arxiv paper, `doi:…` for the
+/// doi.org urls, and lowercase urls without their fragment.
+pub fn normalize(ident : &str) -> String {
+    let ident = ident.trim();
+    let Ok(mut url) = Url::parse(ident) else {
+        return ident.to_lowercase();
+    };
+    url.set_fragment(None);
+    if let Some(paper) = crate::arxiv::from_identifier(&url) {
+        return format!("arxiv:{}", paper.id);
+    }
+    match (url.scheme(), url.host_str()) {
+        ("http" | "https", Some("doi.org" | "dx.doi.org")) => {
+            format!("doi:{}", url.path().trim_start_matches('/')).to_lowercase()
+        }
+        _ => url.to_string().to_lowercase(),
+    }
+}
+
+/// Removes the `v2` suffix of an arxiv id.
+pub fn strip_version(id : &str) -> &str {
+    match id.rfind('v') {
+        Some(i) if i > 0 && i + 1 < id.len() && id[i + 1..].chars().all(|c| c.is_ascii_digit()) => &id[..i],
+        _ => id,
+    }
+}
+
+/// Significant words of a title.
+fn title_words(title : &str) -> HashSet<String> {
+    stopwords::words(title)
+        .into_iter()
+        .filter(|w| !stopwords::is_common(w))
+        .collect()
+}
+
+/// Surnames of the authors, written either `First Last` or `Last, First`.
+fn surnames(doc : &Document) -> HashSet<String> {
+    doc.authors.iter()
+        .filter_map(|a| {
+            let a = latex::to_ascii(a).to_lowercase();
+            match a.split_once(',') {
+                Some((last, _)) => Some(last.trim().to_string()),
+                None => a.split_whitespace().last().map(String::from),
+            }
+        })
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Jaccard similarity of the words of two titles.
+fn title_similarity(a : &str, b : &str) -> f64 {
+    let (a, b) = (title_words(a), title_words(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f64 / a.union(&b).count() as f64
+}
+
+/// Are the two titles (almost) the same?
+pub fn same_title(a : &str, b : &str) -> bool {
+    title_similarity(a, b) >= TITLE_SIMILARITY
+}
+
+/// Are the two documents duplicates, and why?
+pub fn compare(a : &Document, b : &Document) -> Option<Reason> {
+    let idents : HashSet<String> = a.identifiers.iter().map(|i| normalize(i)).collect();
+    if let Some(i) = b.identifiers.iter().find(|i| idents.contains(&normalize(i))) {
+        return Some(Reason::SameIdentifier(i.clone()));
+    }
+    let (sa, sb) = (surnames(a), surnames(b));
+    let shared_author = (sa.is_empty() && sb.is_empty()) || !sa.is_disjoint(&sb);
+    if shared_author && same_title(&a.title, &b.title) {
+        return Some(Reason::SimilarMetadata);
+    }
+    None
+}
+
+/// All the pairs of duplicates of a list of documents.
+pub fn find(docs : &[Document]) -> Vec<(&Document, &Document, Reason)> {
+    let mut pairs = vec![];
+    for (i, a) in docs.iter().enumerate() {
+        for b in &docs[i + 1..] {
+            if let Some(reason) = compare(a, b) {
+                pairs.push((a, b, reason));
+            }
+        }
+    }
+    pairs
+}
+
+/// Merges the metadata of a duplicate into a document: the
+/// identifiers, context, tags and destinations are united, and
+/// missing fields are taken from the duplicate.
+pub fn merge(into : &mut Document, other : &Document) {
+    for i in &other.identifiers {
+        if !into.identifiers.contains(i) {
+            into.identifiers.push(i.clone());
+        }
+    }
+    for c in &other.context {
+        if !into.context.contains(c) {
+            into.context.push(c.clone());
+        }
+    }
+    into.add_tags(&other.tags);
+    for (name, dests) in &other.destinations {
+        let entry = into.destinations.entry(name.clone()).or_default();
+        for d in dests {
+            if !entry.contains(d) {
+                entry.push(d.clone());
+            }
+        }
+    }
+    if into.authors.is_empty() {
+        into.authors = other.authors.clone();
+    }
+    for (author, orcid) in &other.orcids {
+        if into.authors.contains(author) {
+            into.orcids.entry(author.clone()).or_insert_with(|| orcid.clone());
+        }
+    }
+    if into.r#abstract.is_none() {
+        into.r#abstract = other.r#abstract.clone();
+    }
+    for k in &other.keywords {
+        if !into.keywords.contains(k) {
+            into.keywords.push(k.clone());
+        }
+    }
+    // links to the files of the duplicate keep working
+    for f in std::iter::once(&other.filename).chain(&other.former_filenames) {
+        if *f != into.filename && !into.former_filenames.contains(f) {
+            into.former_filenames.push(f.clone());
+        }
+    }
+}
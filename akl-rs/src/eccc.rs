@@ -0,0 +1,137 @@
+// Reports of the Electronic Colloquium on Computational Complexity.
+//
+// An ECCC report is known by its year and number (`2023/123`, written
+// `TR23-123` in citations), its page is
+// `https://eccc.weizmann.ac.il/report/2023/123/` and its pdf file is
+// behind `download/`; revisions have their own files
+// (`report/2023/123/revision/1/download/`). The pdf files carry no
+// usable metadata, and the pages no citation tags: the title and the
+// authors are read from the page itself. Reports are identified as
+// `eccc:2023/123`, or `eccc:2023/123/<revision>`.
+
+use anyhow::{Result, Context};
+use url::Url;
+
+use crate::crossref::Work;
+
+const HOST : &str = "eccc.weizmann.ac.il";
+
+/// Splits an ECCC id (`2023/123`, `2023/123/<revision>` or
+/// `TR23-123`) into the id of the report and its revision.
+pub fn parse_id(id : &str) -> Option<(String, Option<String>)> {
+    let id = id.trim_matches('/');
+    let (year, number, revision) = match id.strip_prefix("TR") {
+        Some(tr) => {
+            let (year, number) = tr.split_once('-')?;
+            (format!("20{year}"), number, None)
+        }
+        None => {
+            let mut parts = id.split('/');
+            let (year, number) = (parts.next()?, parts.next()?);
+            let revision = parts.next().map(String::from);
+            if parts.next().is_some() {
+                return None;
+            }
+            (year.to_string(), number, revision)
+        }
+    };
+    let digits = |s : &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    if year.len() != 4 || !digits(&year) || !digits(number) || revision.as_deref().is_some_and(|r| !digits(r)) {
+        return None;
+    }
+    Some((format!("{year}/{number}"), revision))
+}
+
+/// The report of an ECCC url: its page, its pdf file,
+/// or a revision.
+pub fn from_url(url : &Url) -> Option<(String, Option<String>)> {
+    if url.host_str() != Some(HOST) {
+        return None;
+    }
+    let path = url.path().strip_prefix("/report/")?;
+    let path = path.trim_end_matches('/').trim_end_matches("download").trim_end_matches('/');
+    let (report, revision) = match path.split_once("/revision/") {
+        Some((report, revision)) => (report, Some(revision)),
+        None => (path, None),
+    };
+    let (id, _) = parse_id(report)?;
+    Some((id, revision.map(String::from)))
+}
+
+/// The identifier of a report.
+pub fn identifier(id : &str, revision : Option<&str>) -> String {
+    match revision {
+        Some(r) => format!("eccc:{id}/{r}"),
+        None => format!("eccc:{id}"),
+    }
+}
+
+/// Url of the pdf file of a report, the first version by default.
+pub fn pdf_url(id : &str, revision : Option<&str>) -> String {
+    match revision {
+        Some(r) => format!("https://{HOST}/report/{id}/revision/{r}/download/"),
+        None => format!("https://{HOST}/report/{id}/download/"),
+    }
+}
+
+/// Text of the html elements `<tag …>…</tag>` of a page.
+fn elements(html : &str, tag : &str) -> Vec<(String, String)> {
+    let mut found = vec![];
+    let (open, close) = (format!("<{tag}"), format!("</{tag}>"));
+    let mut rest = html;
+    while let Some(start) = rest.find(&open) {
+        let element = &rest[start..];
+        let (Some(head), Some(end)) = (element.find('>'), element.find(&close)) else {
+            break;
+        };
+        if head < end {
+            let text = crate::abstracts::strip_tags(&element[head + 1..end]);
+            found.push((element[..head].to_string(), crate::landing::unescape(&text)));
+        }
+        rest = &element[end..];
+    }
+    found
+}
+
+/// What the page of a report says about it: the title is the
+/// heading following the one with the report number, and the
+/// authors are the links to the pages of authors.
+pub fn record(id : &str) -> Result<Option<Work>> {
+    log::debug!("Reading the ECCC page of {id}");
+    let answer = crate::polite::get(&format!("https://{HOST}/report/{id}/"))
+        .send()
+        .context("Reading the ECCC page")?;
+    if answer.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let page = answer.error_for_status()
+        .context("Reading the ECCC page")?
+        .text()
+        .context("Reading the ECCC page")?;
+    let headings : Vec<String> = elements(&page, "h4").into_iter().map(|(_, text)| text).collect();
+    let title = headings.iter()
+        .skip_while(|h| !h.starts_with("TR"))
+        .nth(1)
+        .cloned();
+    let authors = elements(&page, "a").into_iter()
+        .filter(|(head, _)| head.contains("href=\"/author/"))
+        .map(|(_, name)| name)
+        .filter(|name| !name.is_empty())
+        .fold(vec![], |mut authors : Vec<String>, name| {
+            if !authors.contains(&name) {
+                authors.push(name);
+            }
+            authors
+        });
+    Ok(title.map(|title| Work {
+        title: Some(title),
+        authors,
+        orcids: vec![],
+        year: id.get(..4).and_then(|y| y.parse().ok()),
+        venue: None,
+        pages: None,
+        r#abstract: None,
+        keywords: vec![],
+        identifiers: vec![],
+    }))
+}
@@ -0,0 +1,134 @@
+// Enrichment of the metadata of the documents.
+//
+// Documents imported from a pdf file, or before akl asked the APIs,
+// often miss identifiers, an abstract or a venue that the APIs know:
+// Semantic Scholar gives the doi of the arXiv papers and the arxiv id
+// of the published ones, arXiv and Crossref give the abstracts,
+// Crossref the venues of the dois, and DBLP and Crossref the published
+// versions of the preprints (see `published`). Each API is queried at
+// its own pace (see `polite`), and failures are not errors: the
+// document is left as it is.
+
+use crate::{Document, abstracts, crossref::Crossref, doctype::DocType, identifiers, published, scholar};
+use crate::identifiers::IdentifierKind;
+
+/// A change of a document proposed by an API.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Identifier(String),
+    Abstract(String),
+    Venue(String),
+    /// The preprint is published.
+    Article,
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Identifier(ident) => write!(f, "identifier {ident}"),
+            Change::Abstract(a) if a.chars().count() > 60 => {
+                write!(f, "abstract {}…", a.chars().take(60).collect::<String>())
+            }
+            Change::Abstract(a) => write!(f, "abstract {a}"),
+            Change::Venue(venue) => write!(f, "venue {venue}"),
+            Change::Article => write!(f, "type article"),
+        }
+    }
+}
+
+/// Logs the failure of a query.
+fn logged<T>(api : &str, doc : &Document, result : anyhow::Result<T>) -> Option<T> {
+    result.map_err(|e| log::warn!("Could not query {api} for {}: {e:#}", doc.filename)).ok()
+}
+
+/// The changes the APIs propose for a document, with the APIs proposing them.
+pub fn propose(doc : &Document, crossref : &Crossref) -> Vec<(Change, &'static str)> {
+    let mut changes = vec![];
+    let mut idents = doc.identifiers.clone();
+    let has = |idents : &[String], kind| idents.iter().any(|i| identifiers::kind_of(i) == kind);
+
+    // the doi of the arXiv papers, and the arxiv id of the others
+    let mut paper = None;
+    if !has(&idents, IdentifierKind::Doi) || !has(&idents, IdentifierKind::Arxiv) {
+        paper = logged("Semantic Scholar", doc, scholar::paper(&idents)).flatten().filter(|p| p.is(doc));
+        for ident in paper.iter().flat_map(|p| &p.identifiers) {
+            let ident = identifiers::canonicalize(ident);
+            if !has(&idents, identifiers::kind_of(&ident)) {
+                changes.push((Change::Identifier(ident.clone()), "Semantic Scholar"));
+                idents.push(ident);
+            }
+        }
+    }
+    let doi = identifiers::doi(&idents);
+
+    let mut work = None;
+    let mut crossref_work = || {
+        if work.is_none() {
+            if let Some(doi) = &doi {
+                work = Some(logged("Crossref", doc, crossref.work(doi)).flatten());
+            }
+        }
+        work.clone().flatten()
+    };
+
+    if doc.r#abstract.is_none() {
+        let from_scholar = paper.as_ref().and_then(|p| p.r#abstract.clone());
+        let found = match (from_scholar, identifiers::arxiv_id(&idents)) {
+            (Some(a), _) => Some((a, "Semantic Scholar")),
+            (None, Some(id)) => {
+                logged("arXiv", doc, abstracts::arxiv(&id))
+                    .and_then(|s| s.r#abstract)
+                    .map(|a| (a, "arXiv"))
+            }
+            (None, None) => None,
+        };
+        let found = found.or_else(|| crossref_work().and_then(|w| w.r#abstract).map(|a| (a, "Crossref")));
+        if let Some((a, api)) = found {
+            changes.push((Change::Abstract(a), api));
+        }
+    }
+
+    if doc.context.is_empty() {
+        let venue = doi.as_deref()
+            .filter(|d| !published::is_preprint_doi(d))
+            .and_then(|_| crossref_work())
+            .and_then(|w| w.venue);
+        if let Some(venue) = venue {
+            changes.push((Change::Venue(venue), "Crossref"));
+        }
+    }
+
+    // the publication of a preprint
+    let mut enriched = doc.clone();
+    enriched.identifiers = idents;
+    if published::is_unpublished(&enriched) {
+        if let Some(publication) = logged("DBLP and Crossref", doc, published::lookup(doc, crossref)).flatten() {
+            let idents = std::iter::once(format!("doi:{}", publication.doi))
+                .chain(publication.identifiers.iter().cloned())
+                .map(|i| identifiers::canonicalize(&i))
+                .filter(|i| !enriched.identifiers.contains(i));
+            changes.extend(idents.map(|i| (Change::Identifier(i), publication.source)));
+            changes.extend(publication.venue.map(|v| (Change::Venue(v), publication.source)));
+            if doc.doc_type == Some(DocType::Preprint) {
+                changes.push((Change::Article, publication.source));
+            }
+        }
+    }
+    changes
+}
+
+/// Applies changes to a document.
+pub fn apply(doc : &mut Document, changes : &[(Change, &'static str)]) {
+    for (change, _) in changes {
+        match change {
+            Change::Identifier(ident) => {
+                if !doc.identifiers.contains(ident) {
+                    doc.identifiers.push(ident.clone());
+                }
+            }
+            Change::Abstract(a) => { doc.r#abstract = Some(a.clone()); }
+            Change::Venue(venue) => { doc.context = vec![venue.clone()]; }
+            Change::Article => { doc.doc_type = Some(DocType::Article); }
+        }
+    }
+}
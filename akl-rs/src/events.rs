@@ -0,0 +1,143 @@
+// Library event log.
+//
+// Every change to the library (and every document opened) is
+// appended to a json-lines file, so that one can review what
+// happened, especially when several processes and machines
+// work on the same library.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+use anyhow::{Result, Context};
+use chrono::{DateTime, Utc, NaiveDate};
+
+use crate::Document;
+
+/// Kinds of events.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Import,
+    Edit,
+    Remove,
+    Restore,
+    Open,
+    Rename,
+    Cite,
+}
+
+/// An event of the library.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Event {
+    /// When did it happen.
+    pub time : DateTime<Utc>,
+
+    /// What happened.
+    pub kind : EventKind,
+
+    /// Checksum of the document concerned.
+    pub checksum : String,
+
+    /// Title of the document concerned (at that time).
+    pub title : String,
+
+    /// Additional details.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub detail : Option<String>,
+
+    /// Page of the document that was opened or cited.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub page : Option<u32>,
+
+    /// Named destination of the document that was opened or cited.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub dest : Option<String>,
+}
+
+/// The append-only event log.
+#[derive(Debug, Clone)]
+pub struct EventLog {
+    path : PathBuf,
+}
+
+impl EventLog {
+    pub fn new(path : &Path) -> Self {
+        EventLog { path: path.into() }
+    }
+
+    /// Appends an event to the log, with the position in the
+    /// document (a page or a destination) it concerns if any.
+    pub fn record_at(&self, kind : EventKind, doc : &Document, page : Option<u32>, dest : Option<String>, detail : Option<String>) -> Result<()> {
+        let event = Event {
+            time: Utc::now(),
+            kind,
+            checksum: doc.checksum.clone(),
+            title: doc.title.clone(),
+            detail,
+            page,
+            dest,
+        };
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)
+            .context("Opening the event log")?;
+        // a single write per line, so that concurrent
+        // appends do not interleave
+        let line = format!("{}\n", serde_json::to_string(&event)?);
+        file.write_all(line.as_bytes())
+            .context("Writing to the event log")
+    }
+
+    /// Events that happened after a given date, oldest first.
+    pub fn since(&self, since : Option<DateTime<Utc>>) -> Result<Vec<Event>> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        let file = std::fs::File::open(&self.path)
+            .context("Opening the event log")?;
+        let mut events = vec![];
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            match serde_json::from_str::<Event>(&line) {
+                Ok(e) if since.is_none_or(|s| e.time >= s) => { events.push(e); }
+                Ok(_) => {}
+                Err(e) => { log::warn!("Ignoring invalid event {line}: {e}"); }
+            }
+        }
+        Ok(events)
+    }
+}
+
+/// When the documents were last opened, by checksum.
+pub fn last_opened(events : &[Event]) -> HashMap<String, DateTime<Utc>> {
+    let mut opened : HashMap<String, DateTime<Utc>> = HashMap::new();
+    for e in events.iter().filter(|e| e.kind == EventKind::Open) {
+        let last = opened.entry(e.checksum.clone()).or_insert(e.time);
+        *last = (*last).max(e.time);
+    }
+    opened
+}
+
+/// Parses a date given by the user: either an absolute date
+/// (`2023-05-01`) or a duration in the past (`3d`, `12h`, `2w`).
+pub fn parse_since(s : &str) -> Result<DateTime<Utc>> {
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    }
+    // the unit may be any character, split on its boundary
+    let (i, unit) = s.char_indices().last()
+        .context("Empty date or duration")?;
+    let n : i64 = s[..i].parse()
+        .with_context(|| format!("Invalid date or duration {s}"))?;
+    let duration = match unit {
+        'h' => chrono::Duration::try_hours(n),
+        'd' => chrono::Duration::try_days(n),
+        'w' => chrono::Duration::try_weeks(n),
+        _ => anyhow::bail!("Invalid duration unit in {s} (expected h, d or w)"),
+    };
+    duration.and_then(|d| Utc::now().checked_sub_signed(d))
+        .with_context(|| format!("The duration {s} is too long"))
+}
@@ -0,0 +1,65 @@
+// Exporting the library to other tools.
+
+use std::io::Write;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+use anyhow::{Result, Context};
+
+use crate::{Document, bibtex, csl};
+
+/// Output formats of the export command.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// A BibTeX bibliography.
+    #[default]
+    Bibtex,
+    /// A CSL-JSON bibliography, for Pandoc.
+    #[serde(rename = "csl-json")]
+    #[value(name = "csl-json")]
+    CslJson,
+    /// The documents not yet sent to Zotero, pushed to the running
+    /// Zotero, or else written as a BibTeX file for Zotero to import
+    /// (with the pdf files and the tags).
+    Zotero,
+}
+
+/// Exports documents of the library. The whole library is
+/// needed to give the documents stable citation keys, and
+/// the directory of the modified files to link them.
+pub fn export<W : Write>(out : &mut W, library : &[Document], docs : &[Document], format : ExportFormat, mod_path : &Path) -> Result<()> {
+    let keys = bibtex::citation_keys(library);
+    let key = |doc : &Document| keys.get(&doc.checksum)
+        .with_context(|| format!("No citation key for {}", doc.filename));
+    match format {
+        ExportFormat::Bibtex => {
+            for (i, doc) in docs.iter().enumerate() {
+                if i > 0 {
+                    writeln!(out)?;
+                }
+                bibtex::write_entry(out, key(doc)?, doc)?;
+            }
+        }
+        ExportFormat::Zotero => {
+            for (i, doc) in docs.iter().enumerate() {
+                if i > 0 {
+                    writeln!(out)?;
+                }
+                let mut extra = vec![("file", mod_path.join(&doc.filename).to_string_lossy().to_string())];
+                if !doc.tags.is_empty() {
+                    extra.push(("keywords", doc.tags.join(", ")));
+                }
+                bibtex::write_entry_with(out, key(doc)?, doc, extra)?;
+            }
+        }
+        ExportFormat::CslJson => {
+            let items = docs.iter()
+                .map(|doc| Ok(csl::item(key(doc)?, doc)))
+                .collect::<Result<Vec<csl::Item>>>()?;
+            serde_json::to_writer_pretty(&mut *out, &items)?;
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
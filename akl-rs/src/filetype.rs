@@ -0,0 +1,155 @@
+// Recognizing the files that are not pdf documents.
+//
+// Servers happily answer a pdf url with a landing page, a login
+// form or an error page, and local files may be PostScript or
+// archives. The type of the file is guessed from its first bytes
+// before parsing it, so that the user is told what was received,
+// and what to do about it. Some web pages are known: the pages of
+// arXiv generating a pdf file, and the captchas of bot checks.
+
+use std::fmt;
+use std::io::Read;
+
+/// Kinds of files, as guessed from their first bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind {
+    Pdf,
+    Html,
+    Xml,
+    Json,
+    PostScript,
+    Djvu,
+    Epub,
+    Zip,
+    Gzip,
+    Png,
+    Jpeg,
+    Empty,
+    Unknown,
+}
+
+impl fmt::Display for FileKind {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FileKind::Pdf => "a pdf document",
+            FileKind::Html => "a web page",
+            FileKind::Xml => "an xml document",
+            FileKind::Json => "a json document",
+            FileKind::PostScript => "a PostScript document",
+            FileKind::Djvu => "a DjVu document",
+            FileKind::Epub => "an epub book",
+            FileKind::Zip => "a zip archive",
+            FileKind::Gzip => "a gzip archive",
+            FileKind::Png => "a png image",
+            FileKind::Jpeg => "a jpeg image",
+            FileKind::Empty => "an empty file",
+            FileKind::Unknown => "a file of unknown type",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Does `haystack` contain `needle`, ignoring the ascii case?
+fn contains_ignore_case(haystack : &[u8], needle : &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w.eq_ignore_ascii_case(needle))
+}
+
+/// Guesses the kind of a file from its first bytes.
+pub fn sniff(bytes : &[u8]) -> FileKind {
+    let head = &bytes[..bytes.len().min(1024)];
+    // pdf readers accept some garbage before the header
+    if head.windows(5).any(|w| w == b"%PDF-") {
+        return FileKind::Pdf;
+    }
+    let text = head.strip_prefix(b"\xef\xbb\xbf").unwrap_or(head);
+    let start = text.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(text.len());
+    let text = &text[start..];
+    match text {
+        [] => FileKind::Empty,
+        _ if text.starts_with(b"%!PS") => FileKind::PostScript,
+        _ if text.starts_with(b"AT&TFORM") => FileKind::Djvu,
+        _ if text.starts_with(b"PK\x03\x04") && contains_ignore_case(head, b"application/epub+zip") => FileKind::Epub,
+        _ if text.starts_with(b"PK\x03\x04") => FileKind::Zip,
+        _ if text.starts_with(b"\x1f\x8b") => FileKind::Gzip,
+        _ if text.starts_with(b"\x89PNG") => FileKind::Png,
+        _ if text.starts_with(b"\xff\xd8\xff") => FileKind::Jpeg,
+        _ if contains_ignore_case(text, b"<!doctype html") || contains_ignore_case(text, b"<html") => FileKind::Html,
+        _ if text.starts_with(b"<?xml") => FileKind::Xml,
+        _ if text.starts_with(b"{") || text.starts_with(b"[") => FileKind::Json,
+        _ => FileKind::Unknown,
+    }
+}
+
+/// Known pages sent instead of a pdf file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorPage {
+    /// The pdf file is being generated (arXiv generates the
+    /// pdf files of new versions on demand).
+    Generating,
+    /// A captcha or a bot check.
+    Captcha,
+}
+
+/// Recognizes the known pages sent instead of a pdf file.
+pub fn error_page(bytes : &[u8]) -> Option<ErrorPage> {
+    const GENERATING : [&[u8]; 3] = [b"being generated", b"regenerating", b"being regenerated"];
+    const CAPTCHA : [&[u8]; 6] = [b"captcha", b"cf-chl", b"challenge-platform", b"just a moment...",
+                                  b"are you a robot", b"unusual traffic"];
+    if GENERATING.iter().any(|m| contains_ignore_case(bytes, m)) {
+        Some(ErrorPage::Generating)
+    } else if CAPTCHA.iter().any(|m| contains_ignore_case(bytes, m)) {
+        Some(ErrorPage::Captcha)
+    } else {
+        None
+    }
+}
+
+/// The first bytes of a file, enough to guess its kind.
+pub fn head(path : &std::path::Path) -> std::io::Result<Vec<u8>> {
+    let mut head = vec![];
+    std::fs::File::open(path)?.take(1024).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+/// What to do with a file that is not a pdf document.
+fn advice(kind : FileKind, origin : &str, bytes : &[u8]) -> String {
+    match kind {
+        FileKind::Html if error_page(bytes) == Some(ErrorPage::Generating) =>
+            "The server is still generating the pdf file: try again in a few minutes".into(),
+        FileKind::Html if error_page(bytes) == Some(ErrorPage::Captcha) =>
+            "The server suspects a robot and asks to solve a captcha: download the file \
+             in a browser and import it, or use an akl-provider program".into(),
+        FileKind::Html => match crate::landing::pdf_url(origin, bytes) {
+            Some(url) => format!("This looks like the landing page of the document, \
+                                  which links to its pdf file: try `akl import --uri {url}`"),
+            None => "This is probably a landing page, a login page or an error page: \
+                     open it in a browser and import the link to the pdf file instead, \
+                     or import a snapshot of the page with --snapshot".into(),
+        },
+        FileKind::PostScript => "Convert it to a pdf document first (e.g. with ps2pdf)".into(),
+        FileKind::Djvu | FileKind::Epub => "Download it and import the file, \
+                                           which is kept in its format".into(),
+        FileKind::Zip | FileKind::Gzip => "Extract the pdf document from the archive first \
+                                           (arxiv serves the sources of some papers this way)".into(),
+        FileKind::Empty => "The download may have been interrupted, or the file truncated".into(),
+        _ => "Only pdf documents can be imported".into(),
+    }
+}
+
+/// Checks that a file (read from `origin`) is a pdf document,
+/// explaining what was received otherwise. `status` is the
+/// status of the http answer, for downloaded files.
+pub fn ensure_pdf(origin : &str, bytes : &[u8], status : Option<reqwest::StatusCode>) -> anyhow::Result<()> {
+    let kind = sniff(bytes);
+    if kind == FileKind::Pdf {
+        return Ok(());
+    }
+    match status.filter(|s| !s.is_success()) {
+        Some(status) => anyhow::bail!(
+            "{origin} is not a pdf document but {kind} ({} bytes, with the http status {status}). \
+             The server could not find the document, or refused to send it: \
+             check the url, and whether the document needs a login", bytes.len()),
+        None => anyhow::bail!("{origin} is not a pdf document but {kind} ({} bytes). {}",
+                              bytes.len(), advice(kind, origin, bytes)),
+    }
+}
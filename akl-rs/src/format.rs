@@ -0,0 +1,65 @@
+// Formats of the files of the documents.
+//
+// Most documents are pdf files, but blog posts, lecture notes and
+// books also come as web pages, EPUB books and DjVu scans. The files
+// of the other formats are stored as they are (a web page as a
+// snapshot, see `snapshot`): the rewriting of the links, the named
+// destinations and the outlines are features of the pdf files only.
+// The filename of a document takes the extension of its format, and
+// `akl open` opens it with the viewer configured for its format, or
+// else with the default application of the system:
+//
+//     viewers:
+//       epub: foliate
+//       djvu: zathura
+
+use std::fmt;
+
+use serde::{Serialize, Deserialize};
+
+use crate::filetype::FileKind;
+
+/// The format of the file of a document.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Pdf,
+    /// A web page, as a single html file.
+    Html,
+    Epub,
+    Djvu,
+}
+
+impl Format {
+    pub fn is_pdf(&self) -> bool {
+        *self == Format::Pdf
+    }
+
+    /// The extension of the files of the format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Pdf => "pdf",
+            Format::Html => "html",
+            Format::Epub => "epub",
+            Format::Djvu => "djvu",
+        }
+    }
+
+    /// The format of a kind of file, if documents can have it.
+    pub fn of_kind(kind : FileKind) -> Option<Self> {
+        match kind {
+            FileKind::Pdf => Some(Format::Pdf),
+            FileKind::Html => Some(Format::Html),
+            FileKind::Epub => Some(Format::Epub),
+            FileKind::Djvu => Some(Format::Djvu),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
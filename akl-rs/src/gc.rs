@@ -0,0 +1,68 @@
+// Garbage collection of the library files.
+//
+// Manual deletions and interrupted imports leave the raw and mod
+// directories out of sync with the index: files without an index
+// entry, index entries without a file, and half written files.
+// The original files are looked for in the shards of the store.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, Context};
+
+use crate::Document;
+use crate::store;
+
+/// What the garbage collector found.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Files of the library without an index entry.
+    pub orphans  : Vec<PathBuf>,
+
+    /// Index entries whose original file is missing.
+    pub dangling : Vec<Document>,
+
+    /// Leftovers of interrupted writes.
+    pub stale    : Vec<PathBuf>,
+}
+
+/// Is this file a leftover of an interrupted write?
+fn is_stale(path : &Path) -> bool {
+    let name = path.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    name.starts_with('.') || name.ends_with(".tmp") || name.ends_with('~')
+}
+
+/// Collects the orphaned and stale files of the given directories,
+/// and the documents missing from the raw directory.
+pub fn collect(docs : &[Document], raw : &Path, modified : &Path) -> Result<Report> {
+    let originals : HashSet<PathBuf> = docs.iter().map(|d| store::locate(raw, d)).collect();
+    let known : HashSet<&str> = docs.iter().map(|d| d.filename.as_str()).collect();
+    let mut report = Report::default();
+    for path in store::files(raw)? {
+        if is_stale(&path) {
+            report.stale.push(path);
+        } else if !originals.contains(&path) {
+            report.orphans.push(path);
+        }
+    }
+    for entry in std::fs::read_dir(modified).with_context(|| format!("Listing {modified:?}"))? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        if is_stale(&path) {
+            report.stale.push(path);
+        } else if path.file_name().is_none_or(|n| !known.contains(n.to_string_lossy().as_ref())) {
+            report.orphans.push(path);
+        }
+    }
+    report.dangling = docs.iter()
+        .filter(|d| !d.metadata_only && !store::locate(raw, d).exists())
+        .cloned()
+        .collect();
+    report.orphans.sort();
+    report.stale.sort();
+    Ok(report)
+}
@@ -0,0 +1,88 @@
+// History of the library in a git repository.
+//
+// With `git_history: true` in the configuration, the directory of
+// the index (index, configuration, collections, authors…) is a git
+// repository, and every command that changes these files commits
+// them, with the command line and the operations of the journal as
+// message. `akl log` shows this history, and `akl revert-to` brings
+// the files back to an earlier commit. The documents themselves are
+// not committed. The repository can be pushed and pulled like any
+// other to share the metadata between machines.
+
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Result, Context};
+
+/// Files of the directory that are not part of the history (the
+/// data of a project library lives in the same directory).
+const GITIGNORE : &str = "*.lock\n*.bak\n*.sqlite-journal\n*.sqlite-wal\n*.sqlite-shm\n\
+                          raw/\nmod/\ntrash/\njournal/\njournal.jsonl\nreports/\nsessions/\n";
+
+/// Runs git in a directory, returning its output.
+fn git(dir : &Path, args : &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C").arg(dir)
+        .args(args)
+        .output()
+        .context("Running git (is it installed?)")?;
+    if !output.status.success() {
+        anyhow::bail!("git {} failed: {}", args.join(" "),
+                      String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Makes the directory a git repository, if it is not one yet.
+pub fn init(dir : &Path) -> Result<()> {
+    if dir.join(".git").exists() {
+        return Ok(());
+    }
+    log::info!("Creating a git repository in {dir:?}");
+    git(dir, &["init", "--quiet"])?;
+    std::fs::write(dir.join(".gitignore"), GITIGNORE)
+        .context("Writing the .gitignore of the library")?;
+    commit(dir, "Start of the history of the library")?;
+    Ok(())
+}
+
+/// Commits the changes of the directory, if there are some.
+/// Without a configured git identity, the commits are made as akl.
+pub fn commit(dir : &Path, message : &str) -> Result<bool> {
+    git(dir, &["add", "--all"])?;
+    if git(dir, &["status", "--porcelain"])?.trim().is_empty() {
+        return Ok(false);
+    }
+    let mut args = vec![];
+    if git(dir, &["config", "user.email"]).is_err() {
+        args.extend(["-c", "user.name=akl", "-c", "user.email=akl@localhost"]);
+    }
+    args.extend(["commit", "--quiet", "--message", message]);
+    git(dir, &args)?;
+    Ok(true)
+}
+
+/// The last commits, one line each.
+pub fn log(dir : &Path, limit : usize) -> Result<String> {
+    if !dir.join(".git").exists() {
+        anyhow::bail!("The library has no history (set git_history in the configuration)");
+    }
+    git(dir, &["log", &format!("--max-count={limit}"), "--date=format:%Y-%m-%d %H:%M", "--format=%h  %ad  %s"])
+}
+
+/// Brings the files of the directory back to their state at a commit,
+/// and commits the result.
+pub fn revert_to(dir : &Path, target : &str) -> Result<()> {
+    let hash = git(dir, &["rev-parse", "--verify", "--quiet", &format!("{target}^{{commit}}")])
+        .with_context(|| format!("Unknown commit {target}"))?;
+    let hash = hash.trim();
+    // the files added since the commit are removed as well
+    git(dir, &["rm", "-r", "--quiet", "--cached", "--ignore-unmatch", "."])?;
+    git(dir, &["checkout", hash, "--", "."])?;
+    for file in git(dir, &["ls-files", "--others", "--exclude-standard"])?.lines() {
+        std::fs::remove_file(dir.join(file))
+            .with_context(|| format!("Removing {file}"))?;
+    }
+    commit(dir, &format!("akl revert-to {}", &hash[..hash.len().min(12)]))?;
+    Ok(())
+}
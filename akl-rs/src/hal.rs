@@ -0,0 +1,120 @@
+// Documents of HAL, the French open archive.
+//
+// A deposit of HAL has an id made of the portal it was deposited in
+// and eight digits (`hal-01234567`, `tel-…` for theses, `inria-…`),
+// and versions (`hal-01234567v2`), like arxiv. Its pages live on
+// hal.science (formerly hal.archives-ouvertes.fr) and the sites of
+// the portals (`theses.hal.science`, `inria.hal.science`), its pdf
+// file at `<id>/document`, and the HAL search API gives its metadata.
+// Deposits are identified as `hal:<id>`, with the version if given.
+
+use anyhow::{Result, Context};
+use serde_json::Value;
+use url::Url;
+
+use crate::crossref::Work;
+
+/// Is this a host of HAL?
+pub fn is_host(host : &str) -> bool {
+    host == "hal.science" || host.ends_with(".hal.science") ||
+    host == "hal.archives-ouvertes.fr" || host.ends_with(".archives-ouvertes.fr")
+}
+
+/// Splits a HAL id into the id of the deposit and its version
+/// (`hal-01234567v2` into `hal-01234567` and `2`).
+pub fn parse_id(id : &str) -> Option<(String, Option<String>)> {
+    let (portal, rest) = id.rsplit_once('-')?;
+    if portal.is_empty() || !portal.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+        return None;
+    }
+    let (number, version) = match rest.split_once('v') {
+        Some((number, version)) => (number, Some(version)),
+        None => (rest, None),
+    };
+    if number.len() != 8 || !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    if version.is_some_and(|v| v.is_empty() || !v.chars().all(|c| c.is_ascii_digit())) {
+        return None;
+    }
+    Some((format!("{portal}-{number}"), version.map(String::from)))
+}
+
+/// The deposit of a HAL url (`https://hal.science/hal-01234567v2/document`).
+pub fn from_url(url : &Url) -> Option<(String, Option<String>)> {
+    url.host_str().filter(|h| is_host(h))?;
+    parse_id(url.path_segments()?.next()?)
+}
+
+/// The identifier of a deposit.
+pub fn identifier(id : &str, version : Option<&str>) -> String {
+    match version {
+        Some(v) => format!("hal:{id}v{v}"),
+        None => format!("hal:{id}"),
+    }
+}
+
+/// Url of the pdf file of a deposit, the latest version by default.
+pub fn pdf_url(id : &str, version : Option<&str>) -> String {
+    match version {
+        Some(v) => format!("https://hal.science/{id}v{v}/document"),
+        None => format!("https://hal.science/{id}/document"),
+    }
+}
+
+/// Fields asked to the search API.
+const FIELDS : &str = "title_s,subTitle_s,authFullName_s,producedDateY_i,journalTitle_s,conferenceTitle_s,bookTitle_s,page_s,abstract_s,keyword_s,doiId_s,arxivId_s";
+
+/// First string of a field (most fields are lists).
+fn first(value : &Value) -> Option<String> {
+    value.as_str()
+        .or_else(|| value.as_array().and_then(|a| a.iter().find_map(|v| v.as_str())))
+        .map(|s| s.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .filter(|s| !s.is_empty())
+}
+
+/// Strings of a list field.
+fn all(value : &Value) -> Vec<String> {
+    value.as_array()
+        .map(|a| a.iter().filter_map(|v| v.as_str()).map(String::from).collect())
+        .unwrap_or_default()
+}
+
+/// Reads a document of the search API.
+fn parse(doc : &Value) -> Work {
+    let title = first(&doc["title_s"]).map(|title| match first(&doc["subTitle_s"]) {
+        Some(subtitle) => format!("{title}: {subtitle}"),
+        None => title,
+    });
+    let identifiers = first(&doc["doiId_s"]).map(|d| format!("doi:{d}")).into_iter()
+        .chain(first(&doc["arxivId_s"]).map(|a| format!("arxiv:{a}")))
+        .collect();
+    Work {
+        title,
+        authors: all(&doc["authFullName_s"]),
+        orcids: vec![],
+        year: doc["producedDateY_i"].as_u64().and_then(|y| u32::try_from(y).ok()),
+        venue: ["journalTitle_s", "conferenceTitle_s", "bookTitle_s"].iter()
+            .find_map(|field| first(&doc[field])),
+        pages: first(&doc["page_s"]),
+        r#abstract: first(&doc["abstract_s"]),
+        keywords: all(&doc["keyword_s"]),
+        identifiers,
+    }
+}
+
+/// What HAL knows about a deposit, `None` when it does not know it.
+pub fn record(id : &str) -> Result<Option<Work>> {
+    let query = format!("halId_s:\"{id}\"");
+    let query = serde_urlencoded::to_string([("q", query.as_str()), ("fl", FIELDS), ("wt", "json")])?;
+    log::debug!("Querying HAL for {id}");
+    let answer : Value = crate::polite::get(&format!("https://api.archives-ouvertes.fr/search/?{query}"))
+        .send()
+        .and_then(|r| r.error_for_status())
+        .context("Querying the HAL API")?
+        .json()
+        .context("Parsing the HAL API answer")?;
+    Ok(answer["response"]["docs"].as_array()
+        .and_then(|docs| docs.first())
+        .map(parse))
+}
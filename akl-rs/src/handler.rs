@@ -0,0 +1,145 @@
+// Persistent handler of akl:// uris.
+//
+// Starting akl for every click on a link means loading the index
+// (and every cache) again. A handler process keeps everything warm,
+// and receives the uris on a unix socket, one per line. It can
+// either bind the socket itself or be started by systemd using
+// socket activation (see dist/akl-handler.socket).
+//
+// The protocol is line based: the client sends a uri, and the
+// handler answers `ok` or `error: <message>`.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use anyhow::{Result, Context};
+use directories::ProjectDirs;
+
+/// Path of the unix socket of the handler.
+/// Uses the runtime directory when there is one
+/// (`$XDG_RUNTIME_DIR/akl/akl.sock`, as in dist/akl-handler.socket).
+pub fn socket_path() -> PathBuf {
+    let pdirs = ProjectDirs::from("com", "aluminium", "AKL").unwrap();
+    pdirs.runtime_dir()
+         .unwrap_or(pdirs.cache_dir())
+         .join("akl.sock")
+}
+
+/// Serves the requests read from `reader`, one uri per line,
+/// writing one answer line per request to `writer`.
+pub fn serve<R, W, F>(reader : R, mut writer : W, execute : &mut F) -> Result<()>
+    where
+        R : BufRead,
+        W : Write,
+        F : FnMut(&str) -> Result<()>
+{
+    for line in reader.lines() {
+        let line = line.context("Reading a request")?;
+        let uri = line.trim();
+        if uri.is_empty() {
+            continue;
+        }
+        log::info!("Handler received {uri}");
+        match execute(uri) {
+            Ok(()) => { writeln!(writer, "ok")?; }
+            Err(e) => {
+                log::error!("Handler failed on {uri}: {e:?}");
+                writeln!(writer, "error: {e:#}")?;
+            }
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::*;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    /// First file descriptor passed by systemd.
+    const SD_LISTEN_FDS_START : i32 = 3;
+
+    /// The listening socket given by systemd, if any.
+    fn systemd_listener() -> Option<UnixListener> {
+        use std::os::unix::io::FromRawFd;
+
+        let pid : u32 = std::env::var("LISTEN_PID").ok()?.parse().ok()?;
+        let fds : i32 = std::env::var("LISTEN_FDS").ok()?.parse().ok()?;
+        if pid != std::process::id() || fds < 1 {
+            return None;
+        }
+        std::env::remove_var("LISTEN_PID");
+        std::env::remove_var("LISTEN_FDS");
+        // Safety: systemd guarantees that this descriptor is a
+        // listening socket owned by this process.
+        Some(unsafe { UnixListener::from_raw_fd(SD_LISTEN_FDS_START) })
+    }
+
+    /// Listens on the handler socket, either given by systemd
+    /// or created at `socket_path()`.
+    pub fn listen() -> Result<UnixListener> {
+        if let Some(listener) = systemd_listener() {
+            log::info!("Using the socket given by systemd");
+            return Ok(listener);
+        }
+        let path = socket_path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // a socket left behind by a previous handler
+        if path.exists() && UnixStream::connect(&path).is_err() {
+            std::fs::remove_file(&path)?;
+        }
+        log::info!("Listening on {path:?}");
+        UnixListener::bind(&path)
+            .with_context(|| format!("Binding the handler socket {path:?}"))
+    }
+
+    /// Serves the connections of the listener forever.
+    pub fn serve_socket<F>(listener : UnixListener, execute : &mut F) -> Result<()>
+        where
+            F : FnMut(&str) -> Result<()>
+    {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let reader = BufReader::new(stream.try_clone()?);
+                    if let Err(e) = serve(reader, stream, execute) {
+                        log::error!("Handler connection failed: {e:?}");
+                    }
+                }
+                Err(e) => { log::error!("Handler could not accept a connection: {e:?}"); }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a uri to a running handler.
+    ///
+    /// Returns `None` when no handler is running, in which case
+    /// the uri should be executed by the current process.
+    pub fn forward(uri : &str) -> Option<Result<()>> {
+        let mut stream = UnixStream::connect(socket_path()).ok()?;
+        Some((|| {
+            writeln!(stream, "{uri}")?;
+            stream.shutdown(std::net::Shutdown::Write)?;
+            let mut answer = String::new();
+            BufReader::new(stream).read_line(&mut answer)?;
+            match answer.trim().strip_prefix("error: ") {
+                None if answer.trim() == "ok" => Ok(()),
+                None => anyhow::bail!("Invalid answer from the handler: {answer}"),
+                Some(e) => anyhow::bail!("The handler failed: {e}"),
+            }
+        })())
+    }
+}
+
+#[cfg(unix)]
+pub use unix::{listen, serve_socket, forward};
+
+/// Sockets are only supported on unix platforms.
+#[cfg(not(unix))]
+pub fn forward(_uri : &str) -> Option<Result<()>> {
+    None
+}
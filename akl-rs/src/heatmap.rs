@@ -0,0 +1,135 @@
+// Which parts of a document are actually used.
+//
+// Every citation (`akl cite`) and every link followed back to a
+// document (`akl open`) targeting a page or a named destination
+// is recorded in the event log. The heatmap counts them by page,
+// destinations being placed on their page.
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::events::{Event, EventKind};
+use crate::pdflib::NamedDestination;
+
+/// Width of the longest bar.
+const BAR_WIDTH : usize = 40;
+
+/// Shades of the overview, from unused to most used.
+const SHADES : [char; 5] = ['·', '░', '▒', '▓', '█'];
+
+/// Uses of a page.
+#[derive(Clone, Debug, Default)]
+pub struct PageUses {
+    pub cited  : usize,
+    pub opened : usize,
+    /// Destinations of the page that were targeted.
+    pub dests  : BTreeMap<String, usize>,
+}
+
+impl PageUses {
+    pub fn total(&self) -> usize {
+        self.cited + self.opened
+    }
+}
+
+/// Uses of the pages of a document.
+#[derive(Clone, Debug, Default)]
+pub struct Heatmap {
+    /// Uses of every page, the first page first.
+    pub pages    : Vec<PageUses>,
+    /// Targets that are not in the current file of the document
+    /// (destinations that disappeared, pages past the end).
+    pub unplaced : BTreeMap<String, usize>,
+}
+
+/// What an event targets in a document.
+enum Target {
+    Page(u32),
+    /// A destination that is not in the document.
+    Missing(String),
+    /// The document as a whole.
+    Document,
+}
+
+/// The page targeted by an event: the page of its destination, or
+/// its page. Links opened before the page was recorded by itself
+/// only mention it in the details.
+fn target(event : &Event, dests : &[NamedDestination]) -> Target {
+    if let Some(dest) = &event.dest {
+        return dests.iter()
+            .find(|d| &d.name == dest)
+            .map_or_else(|| Target::Missing(dest.clone()), |d| Target::Page(d.page_num));
+    }
+    event.page
+        .or_else(|| event.detail.as_deref()?.strip_prefix("page ")?.parse().ok())
+        .map_or(Target::Document, Target::Page)
+}
+
+/// Counts the citations and opened links of a document by page.
+pub fn collect(events : &[Event], checksum : &str, page_count : usize, dests : &[NamedDestination]) -> Heatmap {
+    let mut map = Heatmap { pages: vec![PageUses::default(); page_count], ..Default::default() };
+    for event in events.iter().filter(|e| e.checksum == checksum) {
+        if !matches!(event.kind, EventKind::Cite | EventKind::Open) {
+            continue;
+        }
+        let page = match target(event, dests) {
+            Target::Page(page) => page,
+            Target::Document => continue,
+            Target::Missing(dest) => {
+                *map.unplaced.entry(dest).or_default() += 1;
+                continue;
+            }
+        };
+        let Some(uses) = (page as usize).checked_sub(1).and_then(|i| map.pages.get_mut(i)) else {
+            *map.unplaced.entry(format!("page {page}")).or_default() += 1;
+            continue;
+        };
+        match event.kind {
+            EventKind::Cite => uses.cited += 1,
+            _ => uses.opened += 1,
+        }
+        if let Some(dest) = &event.dest {
+            *uses.dests.entry(dest.clone()).or_default() += 1;
+        }
+    }
+    map
+}
+
+/// Writes the heatmap as a histogram: an overview of the whole
+/// document (one character per page), then a bar per used page.
+pub fn write<W : Write>(out : &mut W, map : &Heatmap) -> Result<()> {
+    let max = map.pages.iter().map(PageUses::total).max().unwrap_or(0);
+    if max == 0 && map.unplaced.is_empty() {
+        writeln!(out, "No citation or link targets a page of this document")?;
+        return Ok(());
+    }
+    let overview : String = map.pages.iter()
+        .map(|p| match p.total() {
+            0 => SHADES[0],
+            n => SHADES[1 + (n - 1) * (SHADES.len() - 1) / max],
+        })
+        .collect();
+    for (i, line) in overview.chars().collect::<Vec<_>>().chunks(50).enumerate() {
+        writeln!(out, "{:>5}  {}", i * 50 + 1, line.iter().collect::<String>())?;
+    }
+    writeln!(out)?;
+    writeln!(out, "{:>5}  {:>5}  {:>6}", "page", "cited", "opened")?;
+    for (i, uses) in map.pages.iter().enumerate().filter(|(_, p)| p.total() > 0) {
+        let bar = "█".repeat((uses.total() * BAR_WIDTH).div_ceil(max));
+        let dests = uses.dests.iter()
+            .map(|(d, n)| format!("{d}×{n}"))
+            .collect::<Vec<_>>();
+        writeln!(out, "{:>5}  {:>5}  {:>6}  {bar}{}", i + 1, uses.cited, uses.opened,
+                 if dests.is_empty() { String::new() } else { format!("  {}", dests.join(" ")) })?;
+    }
+    if !map.unplaced.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "Not found in the document:")?;
+        for (target, n) in &map.unplaced {
+            writeln!(out, "  {target}×{n}")?;
+        }
+    }
+    Ok(())
+}
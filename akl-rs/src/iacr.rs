@@ -0,0 +1,87 @@
+// Preprints of the IACR Cryptology ePrint archive.
+//
+// A report of the ePrint archive is known by its year and number
+// (`2023/123`), its page is `https://eprint.iacr.org/2023/123` and
+// its latest pdf file `https://eprint.iacr.org/2023/123.pdf`. Like the
+// versions of arxiv, the revisions of a report stay available in the
+// archive (`https://eprint.iacr.org/archive/2023/123/1676543210.pdf`).
+// Reports are identified as `iacr:2023/123`, or `iacr:2023/123/<rev>`
+// for a revision, and their pages carry the usual citation tags.
+
+use anyhow::{Result, Context};
+use url::Url;
+
+use crate::crossref::Work;
+
+const HOST : &str = "eprint.iacr.org";
+
+/// Splits an ePrint id (`2023/123`, or `2023/123/<rev>` for a
+/// revision) into the id of the report and its revision.
+pub fn parse_id(id : &str) -> Option<(String, Option<String>)> {
+    let id = id.trim_matches('/').trim_end_matches(".pdf");
+    let mut parts = id.split('/');
+    let (year, number) = (parts.next()?, parts.next()?);
+    let revision = parts.next().filter(|r| !r.is_empty()).map(String::from);
+    if parts.next().is_some() ||
+       year.len() != 4 || !year.chars().all(|c| c.is_ascii_digit()) ||
+       number.len() < 3 || !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((format!("{year}/{number}"), revision))
+}
+
+/// The report of an ePrint url: its page, its pdf file,
+/// or a revision in the archive.
+pub fn from_url(url : &Url) -> Option<(String, Option<String>)> {
+    if url.host_str() != Some(HOST) {
+        return None;
+    }
+    let path = url.path();
+    match path.strip_prefix("/archive/") {
+        Some(archived) => parse_id(archived),
+        None => parse_id(path).filter(|(_, revision)| revision.is_none()),
+    }
+}
+
+/// The identifier of a report.
+pub fn identifier(id : &str, revision : Option<&str>) -> String {
+    match revision {
+        Some(r) => format!("iacr:{id}/{r}"),
+        None => format!("iacr:{id}"),
+    }
+}
+
+/// Url of the pdf file of a report, the latest revision by default.
+pub fn pdf_url(id : &str, revision : Option<&str>) -> String {
+    match revision {
+        Some(r) => format!("https://{HOST}/archive/{id}/{r}.pdf"),
+        None => format!("https://{HOST}/{id}.pdf"),
+    }
+}
+
+/// What the page of a report says about it.
+pub fn record(id : &str) -> Result<Option<Work>> {
+    log::debug!("Reading the ePrint page of {id}");
+    let answer = crate::polite::get(&format!("https://{HOST}/{id}"))
+        .send()
+        .context("Reading the ePrint page")?;
+    if answer.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let page = answer.error_for_status()
+        .context("Reading the ePrint page")?
+        .bytes()
+        .context("Reading the ePrint page")?;
+    let meta = crate::landing::metadata(&page);
+    Ok(meta.title.is_some().then_some(Work {
+        title: meta.title,
+        authors: meta.authors,
+        orcids: vec![],
+        year: meta.year,
+        venue: None,
+        pages: None,
+        r#abstract: meta.r#abstract,
+        keywords: meta.keywords,
+        identifiers: meta.identifiers,
+    }))
+}
@@ -0,0 +1,413 @@
+// Kinds of document identifiers, and their priority.
+//
+// A document has several identifiers (a DOI, an arxiv id, the
+// urls it was downloaded from, local paths…). The first one, in
+// the order of priority of the configuration, is the canonical
+// identifier used in the rewritten links.
+//
+// The same identifier can be written in several ways (`doi:10.1/ABC`
+// and `https://doi.org/10.1/abc`, the abstract and the pdf pages of an
+// arxiv paper…): identifiers are stored in their canonical form, so
+// that two ways of writing an identifier are the same identifier.
+
+use serde::{Serialize, Deserialize};
+use url::Url;
+
+/// Kinds of identifiers.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum IdentifierKind {
+    /// `doi:…` or a doi.org url.
+    Doi,
+    /// `arxiv:…` or an arxiv.org url.
+    Arxiv,
+    /// `biorxiv:…`, `medrxiv:…` or the url of a preprint
+    /// of these servers.
+    Biorxiv,
+    /// `pubmed:…`, `pmc:…` or the url of an article
+    /// of PubMed or PubMed Central.
+    Pubmed,
+    /// `hal:…` or the url of a HAL deposit.
+    Hal,
+    /// `iacr:…` or the url of an IACR ePrint report.
+    Iacr,
+    /// `eccc:…` or the url of an ECCC report.
+    Eccc,
+    /// `isbn:…` (an ISBN-13) or an openlibrary.org isbn url.
+    Isbn,
+    /// Any other web url.
+    Url,
+    /// `dblp:…` (a DBLP key) or a dblp.org record url.
+    Dblp,
+    /// A path on the local system.
+    Path,
+    /// Anything else.
+    Other,
+}
+
+/// The default priority: from the most general identifier
+/// to the most local one.
+pub const DEFAULT_PRIORITY : [IdentifierKind; 12] = [
+    IdentifierKind::Doi,
+    IdentifierKind::Arxiv,
+    IdentifierKind::Biorxiv,
+    IdentifierKind::Pubmed,
+    IdentifierKind::Hal,
+    IdentifierKind::Iacr,
+    IdentifierKind::Eccc,
+    IdentifierKind::Isbn,
+    IdentifierKind::Url,
+    IdentifierKind::Dblp,
+    IdentifierKind::Path,
+    IdentifierKind::Other,
+];
+
+/// Kind of an identifier.
+pub fn kind_of(ident : &str) -> IdentifierKind {
+    match Url::parse(ident) {
+        Ok(url) => match (url.scheme(), url.host_str()) {
+            ("doi", _) => IdentifierKind::Doi,
+            ("arxiv", _) => IdentifierKind::Arxiv,
+            ("dblp", _) => IdentifierKind::Dblp,
+            ("isbn", _) => IdentifierKind::Isbn,
+            ("biorxiv" | "medrxiv", _) => IdentifierKind::Biorxiv,
+            ("pubmed" | "pmc", _) => IdentifierKind::Pubmed,
+            ("hal", _) => IdentifierKind::Hal,
+            ("iacr", _) => IdentifierKind::Iacr,
+            ("eccc", _) => IdentifierKind::Eccc,
+            ("http" | "https", Some("doi.org" | "dx.doi.org")) => IdentifierKind::Doi,
+            ("http" | "https", Some("arxiv.org")) => IdentifierKind::Arxiv,
+            ("http" | "https", Some("dblp.org")) if url.path().starts_with("/rec/") => IdentifierKind::Dblp,
+            ("http" | "https", Some("openlibrary.org")) if url.path().starts_with("/isbn/") => IdentifierKind::Isbn,
+            ("http" | "https", _) if crate::rxiv::from_url(&url).is_some() => IdentifierKind::Biorxiv,
+            ("http" | "https", _) if crate::pubmed::from_url(&url).is_some() => IdentifierKind::Pubmed,
+            ("http" | "https", _) if crate::hal::from_url(&url).is_some() => IdentifierKind::Hal,
+            ("http" | "https", _) if crate::iacr::from_url(&url).is_some() => IdentifierKind::Iacr,
+            ("http" | "https", _) if crate::eccc::from_url(&url).is_some() => IdentifierKind::Eccc,
+            ("http" | "https", _) => IdentifierKind::Url,
+            ("file", _) => IdentifierKind::Path,
+            _ => IdentifierKind::Other,
+        },
+        Err(_) if ident.starts_with(['/', '.', '~']) => IdentifierKind::Path,
+        Err(_) => IdentifierKind::Other,
+    }
+}
+
+/// Does the url carry session dependent parts (query, fragment)?
+/// Such urls are less stable than their canonical version.
+fn is_decorated(ident : &str) -> bool {
+    Url::parse(ident).is_ok_and(|u| u.query().is_some() || u.fragment().is_some())
+}
+
+/// Sorting key of an identifier given a priority list:
+/// kinds missing from the list come last, and among
+/// identifiers of the same kind, canonical urls come first.
+fn key<'a>(priority : &[IdentifierKind], ident : &'a str) -> (usize, bool, &'a str) {
+    let kind = kind_of(ident);
+    let rank = priority.iter()
+        .position(|k| *k == kind)
+        .unwrap_or(priority.len());
+    (rank, is_decorated(ident), ident)
+}
+
+/// An identifier, read from any of the ways of writing it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Identifier {
+    /// A doi, in lowercase (dois are case insensitive).
+    Doi(String),
+    /// An arxiv id, with its version if given.
+    Arxiv { id : String, version : Option<String> },
+    /// A web url, over https and without trailing slash.
+    Url(Url),
+    /// Any other identifier: the ids of the other providers,
+    /// paths, and identifiers of unknown schemes.
+    Other(String),
+}
+
+impl Identifier {
+    /// Reads an identifier.
+    pub fn parse(ident : &str) -> Identifier {
+        let ident = ident.trim();
+        let Ok(mut url) = Url::parse(ident) else {
+            return Identifier::Other(ident.to_string());
+        };
+        let host = url.host_str().map(|h| h.trim_start_matches("www.").to_string());
+        match (url.scheme(), host.as_deref()) {
+            ("doi", _) | ("http" | "https", Some("doi.org" | "dx.doi.org")) => {
+                let doi = url.path().trim_start_matches('/');
+                return Identifier::Doi(doi.to_lowercase());
+            }
+            _ => {}
+        }
+        if let Some(paper) = crate::arxiv::from_identifier(&url) {
+            return Identifier::Arxiv { id: paper.id, version: paper.version };
+        }
+        // the urls of the other providers are their ids
+        let normalized = normalize(ident);
+        if normalized != ident {
+            return Identifier::parse(&normalized);
+        }
+        if !matches!(url.scheme(), "http" | "https") {
+            return Identifier::Other(url.to_string());
+        }
+        if url.scheme() == "http" {
+            let _ = url.set_scheme("https");
+        }
+        if url.path().len() > 1 && url.path().ends_with('/') {
+            let path = url.path().trim_end_matches('/').to_string();
+            url.set_path(&path);
+        }
+        Identifier::Url(url)
+    }
+}
+
+impl std::fmt::Display for Identifier {
+    fn fmt(&self, f : &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Identifier::Doi(doi) => write!(f, "doi:{doi}"),
+            Identifier::Arxiv { id, version: Some(v) } => write!(f, "arxiv:{id}v{v}"),
+            Identifier::Arxiv { id, version: None } => write!(f, "arxiv:{id}"),
+            Identifier::Url(url) => write!(f, "{url}"),
+            Identifier::Other(ident) => write!(f, "{ident}"),
+        }
+    }
+}
+
+/// The canonical form of an identifier.
+pub fn canonicalize(ident : &str) -> String {
+    Identifier::parse(ident).to_string()
+}
+
+/// Sorts identifiers by priority, then alphabetically, and
+/// removes the duplicates (once in their canonical form).
+pub fn sort(priority : &[IdentifierKind], idents : &mut Vec<String>) {
+    for ident in idents.iter_mut() {
+        *ident = canonicalize(ident);
+    }
+    idents.sort_by(|a, b| key(priority, a).cmp(&key(priority, b)));
+    idents.dedup();
+}
+
+/// The most stable identifier: the one used in the links
+/// generated for the document.
+pub fn canonical<'a>(priority : &[IdentifierKind], idents : &'a [String]) -> Option<&'a String> {
+    idents.iter().min_by(|a, b| key(priority, a).cmp(&key(priority, b)))
+}
+
+/// The doi among identifiers: a `doi:` identifier or a doi.org url.
+pub fn doi(idents : &[String]) -> Option<String> {
+    idents.iter().find_map(|i| {
+        let url = Url::parse(i).ok()?;
+        match (url.scheme(), url.host_str()) {
+            ("doi", _) => Some(url.path().to_string()),
+            ("http" | "https", Some("doi.org" | "dx.doi.org")) => Some(url.path().trim_start_matches('/').to_string()),
+            _ => None,
+        }
+    })
+}
+
+/// The arxiv id among identifiers, with its version if any:
+/// an `arxiv:` identifier or the url of an arxiv paper.
+pub fn arxiv_id(idents : &[String]) -> Option<String> {
+    idents.iter().find_map(|i| {
+        let paper = crate::arxiv::from_identifier(&Url::parse(i).ok()?)?;
+        Some(match paper.version {
+            Some(v) => format!("{}v{v}", paper.id),
+            None => paper.id,
+        })
+    })
+}
+
+/// The DBLP key among identifiers: a `dblp:` identifier
+/// or a dblp.org record url (`https://dblp.org/rec/<key>.html`).
+pub fn dblp_key(idents : &[String]) -> Option<String> {
+    idents.iter().find_map(|i| {
+        let url = Url::parse(i).ok()?;
+        match (url.scheme(), url.host_str()) {
+            ("dblp", _) => Some(url.path().to_string()),
+            ("http" | "https", Some("dblp.org")) => {
+                let path = url.path().strip_prefix("/rec/")?;
+                Some(path.trim_end_matches(".html").trim_end_matches(".bib").to_string())
+            }
+            _ => None,
+        }
+    })
+}
+
+/// The bioRxiv or medRxiv preprint among identifiers: a `biorxiv:`
+/// or `medrxiv:` identifier, the url of a preprint, or else a doi
+/// of a preprint (of an unknown server and version).
+pub fn rxiv_preprint(idents : &[String]) -> Option<crate::rxiv::Preprint> {
+    let urls : Vec<Url> = idents.iter().filter_map(|i| Url::parse(i).ok()).collect();
+    urls.iter().find_map(|u| crate::rxiv::parse_id(u.scheme(), u.path()))
+        .or_else(|| urls.iter().find_map(crate::rxiv::from_url))
+        .or_else(|| {
+            let doi = doi(idents).filter(|d| crate::rxiv::is_preprint_doi(d))?;
+            Some(crate::rxiv::Preprint { server: None, doi, version: None })
+        })
+}
+
+/// The PubMed or PMC article among identifiers: a `pubmed:`
+/// or `pmc:` identifier, or the url of an article.
+pub fn pubmed_id(idents : &[String]) -> Option<crate::pubmed::Id> {
+    idents.iter().find_map(|i| {
+        let url = Url::parse(i).ok()?;
+        crate::pubmed::Id::parse(url.scheme(), url.path())
+            .or_else(|| crate::pubmed::from_url(&url))
+    })
+}
+
+/// The HAL id among identifiers, with its version if any:
+/// a `hal:` identifier or the url of a HAL deposit.
+pub fn hal_id(idents : &[String]) -> Option<String> {
+    idents.iter().find_map(|i| {
+        let url = Url::parse(i).ok()?;
+        let (id, version) = match url.scheme() {
+            "hal" => crate::hal::parse_id(url.path())?,
+            _ => crate::hal::from_url(&url)?,
+        };
+        Some(match version {
+            Some(v) => format!("{id}v{v}"),
+            None => id,
+        })
+    })
+}
+
+/// The ePrint id among identifiers, with its revision if any:
+/// an `iacr:` identifier or the url of an IACR ePrint report.
+pub fn iacr_id(idents : &[String]) -> Option<String> {
+    idents.iter().find_map(|i| {
+        let url = Url::parse(i).ok()?;
+        let (id, revision) = match url.scheme() {
+            "iacr" => crate::iacr::parse_id(url.path())?,
+            _ => crate::iacr::from_url(&url)?,
+        };
+        Some(match revision {
+            Some(r) => format!("{id}/{r}"),
+            None => id,
+        })
+    })
+}
+
+/// The ECCC id among identifiers, with its revision if any:
+/// an `eccc:` identifier or the url of an ECCC report.
+pub fn eccc_id(idents : &[String]) -> Option<String> {
+    idents.iter().find_map(|i| {
+        let url = Url::parse(i).ok()?;
+        let (id, revision) = match url.scheme() {
+            "eccc" => crate::eccc::parse_id(url.path())?,
+            _ => crate::eccc::from_url(&url)?,
+        };
+        Some(match revision {
+            Some(r) => format!("{id}/{r}"),
+            None => id,
+        })
+    })
+}
+
+/// An isbn written as an ISBN-13 without dashes, if it is a valid
+/// ISBN-10 or ISBN-13 (`0-262-13472-2` becomes `9780262134729`).
+pub fn normalize_isbn(isbn : &str) -> Option<String> {
+    let chars : Vec<char> = isbn.chars().filter(|c| !matches!(c, '-' | ' ')).collect();
+    let digit = |c : &char| c.to_digit(10);
+    let isbn13 = match chars.len() {
+        10 => {
+            let mut sum = 0;
+            for (i, c) in chars.iter().enumerate() {
+                let d = match (i, c) {
+                    (9, 'X' | 'x') => 10,
+                    _ => digit(c)?,
+                };
+                sum += (10 - i as u32) * d;
+            }
+            if !sum.is_multiple_of(11) {
+                return None;
+            }
+            let mut digits : Vec<u32> = vec![9, 7, 8];
+            digits.extend(chars[..9].iter().filter_map(digit));
+            let sum : u32 = digits.iter().enumerate().map(|(i, d)| if i % 2 == 0 { *d } else { 3 * d }).sum();
+            digits.push((10 - sum % 10) % 10);
+            digits
+        }
+        13 => chars.iter().map(digit).collect::<Option<Vec<u32>>>()?,
+        _ => return None,
+    };
+    let sum : u32 = isbn13.iter().enumerate().map(|(i, d)| if i % 2 == 0 { *d } else { 3 * d }).sum();
+    sum.is_multiple_of(10).then(|| isbn13.iter().map(|d| d.to_string()).collect())
+}
+
+/// The isbn among identifiers: an `isbn:` identifier
+/// or an openlibrary.org isbn url.
+pub fn isbn(idents : &[String]) -> Option<String> {
+    idents.iter().find_map(|i| {
+        let url = Url::parse(i).ok()?;
+        match (url.scheme(), url.host_str()) {
+            ("isbn", _) => normalize_isbn(url.path()),
+            ("http" | "https", Some("openlibrary.org")) => normalize_isbn(url.path().strip_prefix("/isbn/")?),
+            _ => None,
+        }
+    })
+}
+
+/// The usual form of the identifiers of the providers: isbns as
+/// `isbn:` ISBN-13, the urls of bioRxiv and medRxiv preprints as
+/// versions (or dois), and the urls of PubMed and PMC articles,
+/// HAL deposits, ePrint and ECCC reports as `pubmed:`, `pmc:`,
+/// `hal:`, `iacr:` and `eccc:` ids.
+fn normalize(ident : &str) -> String {
+    let idents = [ident.to_string()];
+    let preprint = Url::parse(ident).ok().as_ref().and_then(crate::rxiv::from_url);
+    isbn(&idents).map(|i| format!("isbn:{i}"))
+        .or_else(|| preprint.map(|p| match (p.server, p.version) {
+            (Some(server), Some(version)) => crate::rxiv::identifier(&server, &p.doi, &version),
+            _ => format!("doi:{}", p.doi),
+        }))
+        .or_else(|| pubmed_id(&idents).map(|p| p.identifier()))
+        .or_else(|| hal_id(&idents).map(|h| format!("hal:{h}")))
+        .or_else(|| iacr_id(&idents).map(|e| format!("iacr:{e}")))
+        .or_else(|| eccc_id(&idents).map(|e| format!("eccc:{e}")))
+        .unwrap_or_else(|| ident.to_string())
+}
+
+/// A web url among identifiers, other than a doi or arxiv page.
+pub fn web_url(idents : &[String]) -> Option<&String> {
+    idents.iter().find(|i| {
+        Url::parse(i).is_ok_and(|u| {
+            matches!(u.scheme(), "http" | "https") &&
+            !matches!(u.host_str(), Some("doi.org" | "dx.doi.org" | "arxiv.org" | "www.arxiv.org"))
+        })
+    })
+}
+
+/// Alphabet of the short ids (lowercase RFC 4648 base32).
+const BASE32 : &[u8] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// Length of the short ids, unless two documents collide.
+pub const SHORT_ID_LEN : usize = 8;
+
+/// Short id of a document: the base32 encoding of the
+/// start of its (hexadecimal) checksum.
+pub fn short_id(checksum : &str, len : usize) -> String {
+    let mut id = String::with_capacity(len);
+    let mut bits : u32 = 0;
+    let mut nbits = 0;
+    for c in checksum.chars() {
+        let Some(v) = c.to_digit(16) else { break };
+        if id.len() == len {
+            break;
+        }
+        bits = (bits << 4) | v;
+        nbits += 4;
+        if nbits >= 5 {
+            nbits -= 5;
+            id.push(BASE32[(bits >> nbits & 31) as usize] as char);
+        }
+        bits &= (1 << nbits) - 1;
+    }
+    id
+}
+
+/// Does the string look like a short id?
+pub fn is_short_id(s : &str) -> bool {
+    s.len() >= SHORT_ID_LEN && s.len() <= 51 && s.bytes().all(|c| BASE32.contains(&c))
+}
@@ -0,0 +1,244 @@
+// Inbox of the new papers.
+//
+// `akl subscribe cs.LO` subscribes to the new submissions of an arXiv
+// category, `akl follow "Wim Martens"` to the new preprints of an
+// author (found by their ORCID or DBLP pid when given, or else by
+// name), and `akl inbox fetch` asks arXiv (and DBLP) for the papers
+// submitted since the previous fetch of each subscription: the ones
+// missing from the library land in the inbox, with their metadata
+// only (the first fetch of a subscription keeps the papers of the
+// last month). The inbox is a triage list, numbered from the newest paper:
+// `akl inbox import <n>` imports a paper (downloading its pdf file),
+// and `akl inbox drop <n>` discards it. The subscriptions and the
+// inbox are stored in the inbox.yaml file next to the index.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, Context};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+
+use crate::arxiv::{self, Submission};
+
+/// Age of the oldest papers kept by the first fetch of a subscription.
+const FIRST_FETCH : chrono::TimeDelta = chrono::TimeDelta::days(30);
+
+/// A paper of the inbox.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Entry {
+    /// The arxiv id, with the version found.
+    pub arxiv     : String,
+    pub title     : String,
+    pub authors   : Vec<String>,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub r#abstract : String,
+    pub published : DateTime<Utc>,
+    /// The subscriptions that found the paper.
+    pub reasons   : Vec<String>,
+}
+
+/// An author whose new preprints come to the inbox.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Followed {
+    pub name : String,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub orcid : Option<String>,
+
+    /// The pid of the author in DBLP (`m/WimMartens`).
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub dblp : Option<String>,
+}
+
+impl Followed {
+    /// The subscription to the author, as a reason
+    /// of the papers of the inbox.
+    pub fn subscription(&self) -> String {
+        format!("author:{}", self.name)
+    }
+
+    /// The latest preprints of the author (at most `max` of them),
+    /// from the arXiv feed of their ORCID, or from their DBLP page,
+    /// or else by a search of their name.
+    pub fn papers(&self, max : usize) -> Result<Vec<Submission>> {
+        let mut found = if let Some(orcid) = &self.orcid {
+            arxiv::by_orcid(orcid)?
+        } else if let Some(pid) = &self.dblp {
+            let ids = crate::dblp::arxiv_papers(pid)?;
+            let ids = &ids[..ids.len().min(max).min(arxiv::BATCH)];
+            if ids.is_empty() { vec![] } else { arxiv::submissions(ids)? }
+        } else {
+            arxiv::by_author(&self.name, max)?
+        };
+        found.sort_by_key(|p| std::cmp::Reverse(p.published));
+        found.truncate(max);
+        Ok(found)
+    }
+}
+
+/// The subscriptions and the papers of the inbox.
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct Contents {
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    subscriptions : Vec<String>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    followed : Vec<Followed>,
+
+    /// Submission date of the newest paper seen,
+    /// by subscription.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    fetched : BTreeMap<String, DateTime<Utc>>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    entries : Vec<Entry>,
+}
+
+/// The inbox of the library.
+#[derive(Debug)]
+pub struct Inbox {
+    path     : PathBuf,
+    contents : Contents,
+}
+
+/// Is this the name of an arXiv category (`cs.LO`,
+/// `math.AG`, `quant-ph`)?
+pub fn is_category(name : &str) -> bool {
+    let (archive, subject) = name.split_once('.').unwrap_or((name, "A"));
+    !archive.is_empty() && archive.chars().all(|c| c.is_ascii_lowercase() || c == '-') &&
+        !subject.is_empty() && subject.chars().all(|c| c.is_ascii_alphabetic() || c == '-')
+}
+
+impl Inbox {
+    /// Loads the inbox from a yaml file.
+    /// A missing file means an empty inbox.
+    pub fn load(path : &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path).or_else(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound { Ok(String::new()) } else { Err(e) }
+        }).context("Reading the inbox")?;
+        let contents = if content.trim().is_empty() {
+            Contents::default()
+        } else {
+            serde_yaml::from_str(&content)
+                .with_context(|| format!("Parsing the inbox {path:?}"))?
+        };
+        Ok(Inbox { path: path.into(), contents })
+    }
+
+    /// Saves the inbox to its yaml file.
+    pub fn save(&self) -> Result<()> {
+        let file = std::fs::File::create(&self.path)
+            .context("Opening the inbox file")?;
+        serde_yaml::to_writer(file, &self.contents)
+            .context("Writing the inbox file")
+    }
+
+    /// The subscribed arXiv categories.
+    pub fn subscriptions(&self) -> &[String] {
+        &self.contents.subscriptions
+    }
+
+    /// Subscribes to the new papers of an arXiv category.
+    pub fn subscribe(&mut self, category : &str) -> Result<()> {
+        if !is_category(category) {
+            anyhow::bail!("{category} is not an arXiv category (e.g. cs.LO, math.AG, quant-ph)");
+        }
+        if self.contents.subscriptions.iter().any(|s| s == category) {
+            anyhow::bail!("Already subscribed to {category}");
+        }
+        self.contents.subscriptions.push(category.into());
+        Ok(())
+    }
+
+    /// Stops a subscription (its papers stay in the inbox).
+    pub fn unsubscribe(&mut self, category : &str) -> Result<()> {
+        let before = self.contents.subscriptions.len();
+        self.contents.subscriptions.retain(|s| s != category);
+        if self.contents.subscriptions.len() == before {
+            anyhow::bail!("Not subscribed to {category}");
+        }
+        self.contents.fetched.remove(category);
+        Ok(())
+    }
+
+    /// The followed authors.
+    pub fn followed(&self) -> &[Followed] {
+        &self.contents.followed
+    }
+
+    /// Follows the new preprints of an author.
+    pub fn follow(&mut self, followed : Followed) -> Result<()> {
+        if let Some(f) = self.contents.followed.iter().find(|f| f.name == followed.name) {
+            anyhow::bail!("Already following {}", f.name);
+        }
+        self.contents.followed.push(followed);
+        Ok(())
+    }
+
+    /// Stops following an author (their papers stay in the inbox).
+    pub fn unfollow(&mut self, name : &str) -> Result<()> {
+        let Some(i) = self.contents.followed.iter().position(|f| f.name == name) else {
+            anyhow::bail!("Not following {name}");
+        };
+        let followed = self.contents.followed.remove(i);
+        self.contents.fetched.remove(&followed.subscription());
+        Ok(())
+    }
+
+    /// The submission date of the newest paper
+    /// seen by a subscription, if any.
+    pub fn fetched(&self, subscription : &str) -> Option<DateTime<Utc>> {
+        self.contents.fetched.get(subscription).copied()
+    }
+
+    /// Adds the papers found by a subscription, newer than the ones
+    /// it found before. Returns the number of papers new to the inbox.
+    pub fn add(&mut self, subscription : &str, found : &[Submission]) -> usize {
+        let since = self.fetched(subscription).unwrap_or_else(|| Utc::now() - FIRST_FETCH);
+        let mut added = 0;
+        for paper in found.iter().filter(|p| p.published > since) {
+            match self.contents.entries.iter_mut().find(|e| e.arxiv.starts_with(&format!("{}v", paper.id.id))) {
+                Some(entry) => {
+                    if !entry.reasons.iter().any(|r| r == subscription) {
+                        entry.reasons.push(subscription.into());
+                    }
+                }
+                None => {
+                    self.contents.entries.push(Entry {
+                        arxiv: format!("{}v{}", paper.id.id, paper.id.version.as_deref().unwrap_or("1")),
+                        title: paper.title.clone(),
+                        authors: paper.authors.clone(),
+                        r#abstract: paper.summary.clone(),
+                        published: paper.published,
+                        reasons: vec![subscription.into()],
+                    });
+                    added += 1;
+                }
+            }
+        }
+        if let Some(newest) = found.iter().map(|p| p.published).max() {
+            let fetched = self.contents.fetched.entry(subscription.into()).or_insert(newest);
+            *fetched = newest.max(*fetched);
+        }
+        self.contents.entries.sort_by_key(|e| std::cmp::Reverse(e.published));
+        added
+    }
+
+    /// The papers of the inbox, the newest first.
+    pub fn entries(&self) -> &[Entry] {
+        &self.contents.entries
+    }
+
+    /// The paper numbered `n` (from 1) in the inbox.
+    pub fn get(&self, n : usize) -> Result<&Entry> {
+        n.checked_sub(1)
+            .and_then(|i| self.contents.entries.get(i))
+            .with_context(|| format!("There is no paper {n} in the inbox ({} papers)", self.contents.entries.len()))
+    }
+
+    /// Removes papers from the inbox.
+    pub fn remove(&mut self, arxiv : &[String]) {
+        self.contents.entries.retain(|e| !arxiv.contains(&e.arxiv));
+    }
+}
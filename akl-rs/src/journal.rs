@@ -0,0 +1,271 @@
+// Journal of the operations on the library.
+//
+// Every change to the index (imports, removals, edits and renames,
+// conversions) is appended to a json-lines journal, with the entry
+// of the document before and after the change, and a copy of the
+// files that the change destroys, so that `akl undo` can revert it.
+// The operations of one invocation of akl form a group, undone
+// together: a re-import with --force is a removal, an import and
+// an edit, and undoing it brings the previous entry back.
+//
+// Unlike the event log, the journal is not meant to be read by
+// the user, but `akl history` lists the groups that can be undone.
+//
+// Layout: journal.jsonl, journal/<group>/<n>/{raw.pdf, mod.pdf}
+
+use std::cell::Cell;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+use anyhow::{Result, Context};
+use chrono::{DateTime, Utc};
+
+use crate::Document;
+
+/// Kinds of operations.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OpKind {
+    /// A document entered the library.
+    Import,
+    /// A document left the library (to the trash).
+    Remove,
+    /// The entry of a document changed, and maybe its filename.
+    Edit,
+    /// The modified file of a document was written again.
+    Reconvert,
+}
+
+/// An operation on the library.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Operation {
+    pub time : DateTime<Utc>,
+
+    /// The invocation of akl that did it.
+    pub group : String,
+
+    /// The command line of that invocation.
+    pub command : String,
+
+    /// Position of the operation in its group.
+    pub seq : usize,
+
+    pub kind : OpKind,
+
+    /// The entry of the document before the operation.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub before : Option<Document>,
+
+    /// The entry of the document after the operation.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub after : Option<Document>,
+}
+
+impl Operation {
+    /// One line description of the operation.
+    pub fn describe(&self) -> String {
+        match (&self.before, &self.after) {
+            (Some(b), Some(a)) if self.kind == OpKind::Edit && a.filename != b.filename => {
+                format!("rename {} -> {}", b.filename, a.filename)
+            }
+            (_, Some(d)) | (Some(d), None) => {
+                let kind = serde_json::to_value(self.kind).ok()
+                    .and_then(|k| k.as_str().map(String::from))
+                    .unwrap_or_default();
+                format!("{kind} {}", d.filename)
+            }
+            (None, None) => String::new(),
+        }
+    }
+}
+
+/// A line of the journal.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "entry", rename_all = "lowercase")]
+enum Line {
+    Operation(Box<Operation>),
+    /// The operations of a group were undone.
+    Undo { time : DateTime<Utc>, group : String },
+}
+
+/// The operations of an invocation of akl.
+#[derive(Debug)]
+pub struct Group {
+    pub id : String,
+    pub command : String,
+    pub time : DateTime<Utc>,
+    pub operations : Vec<Operation>,
+    pub undone : bool,
+}
+
+/// The append-only journal.
+#[derive(Debug)]
+pub struct Journal {
+    path : PathBuf,
+
+    /// Directory of the copies of the files.
+    files : PathBuf,
+
+    /// Group of the operations of this invocation.
+    group : String,
+
+    /// Command line of this invocation.
+    command : String,
+
+    /// Number of operations recorded by this invocation.
+    seq : Cell<usize>,
+
+    /// When set, the operations are not recorded
+    /// (while undoing other operations).
+    pub paused : bool,
+}
+
+/// Are two entries of the index the same?
+pub fn same(a : &Document, b : &Document) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+/// A new group id, unique to this invocation.
+fn group_id() -> String {
+    format!("{}-{}", Utc::now().format("%Y%m%d-%H%M%S%.6f"), std::process::id())
+}
+
+impl Journal {
+    pub fn new(path : &Path, files : &Path) -> Self {
+        Journal {
+            path: path.into(),
+            files: files.into(),
+            group: group_id(),
+            command: std::env::args().skip(1).collect::<Vec<String>>().join(" "),
+            seq: Cell::new(0),
+            paused: false,
+        }
+    }
+
+    /// Starts a new group of operations, for processes
+    /// executing several commands (see `handler`).
+    pub fn start(&mut self, command : &str) {
+        self.group = group_id();
+        self.command = command.into();
+        self.seq.set(0);
+    }
+
+    /// Directory of the copies of the files of an operation.
+    pub fn files_of(&self, op : &Operation) -> PathBuf {
+        self.files.join(&op.group).join(op.seq.to_string())
+    }
+
+    /// Copies files that the next operation will destroy,
+    /// under the given names.
+    pub fn keep(&self, files : &[(&str, &Path)]) -> Result<()> {
+        if self.paused {
+            return Ok(());
+        }
+        let dir = self.files.join(&self.group).join(self.seq.get().to_string());
+        std::fs::create_dir_all(&dir)
+            .context("Creating the journal directory")?;
+        for (name, path) in files {
+            if path.exists() {
+                std::fs::copy(path, dir.join(name))
+                    .with_context(|| format!("Copying {path:?} to the journal"))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn append(&self, line : &Line) -> Result<()> {
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)
+            .context("Opening the journal")?;
+        let line = format!("{}\n", serde_json::to_string(line)?);
+        file.write_all(line.as_bytes())
+            .context("Writing to the journal")
+    }
+
+    /// Appends an operation to the journal.
+    pub fn record(&self, kind : OpKind, before : Option<&Document>, after : Option<&Document>) -> Result<()> {
+        if self.paused {
+            return Ok(());
+        }
+        let seq = self.seq.get();
+        self.seq.set(seq + 1);
+        self.append(&Line::Operation(Box::new(Operation {
+            time: Utc::now(),
+            group: self.group.clone(),
+            command: self.command.clone(),
+            seq,
+            kind,
+            before: before.cloned(),
+            after: after.cloned(),
+        })))
+    }
+
+    /// Records that the operations of a group were undone.
+    pub fn undone(&self, group : &str) -> Result<()> {
+        self.append(&Line::Undo { time: Utc::now(), group: group.into() })
+    }
+
+    /// The operations recorded by this invocation.
+    pub fn current(&self) -> Result<Vec<Operation>> {
+        Ok(self.groups()?.into_iter()
+            .find(|g| g.id == self.group)
+            .map(|g| g.operations)
+            .unwrap_or_default())
+    }
+
+    /// The command line of this invocation.
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    /// All the groups of operations, oldest first.
+    pub fn groups(&self) -> Result<Vec<Group>> {
+        let mut groups : Vec<Group> = vec![];
+        if !self.path.exists() {
+            return Ok(groups);
+        }
+        let file = std::fs::File::open(&self.path)
+            .context("Opening the journal")?;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            match serde_json::from_str::<Line>(&line) {
+                Ok(Line::Operation(op)) => {
+                    match groups.iter_mut().find(|g| g.id == op.group) {
+                        Some(g) => g.operations.push(*op),
+                        None => groups.push(Group {
+                            id: op.group.clone(),
+                            command: op.command.clone(),
+                            time: op.time,
+                            operations: vec![*op],
+                            undone: false,
+                        }),
+                    }
+                }
+                Ok(Line::Undo { group, .. }) => {
+                    if let Some(g) = groups.iter_mut().find(|g| g.id == group) {
+                        g.undone = true;
+                    }
+                }
+                Err(e) => { log::warn!("Ignoring invalid journal entry {line}: {e}"); }
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Deletes the copies of the files of the operations
+    /// done before the given date: they cannot be undone
+    /// anymore.
+    pub fn purge(&self, before : DateTime<Utc>) -> Result<()> {
+        for group in self.groups()?.iter().filter(|g| g.time < before) {
+            let dir = self.files.join(&group.id);
+            if dir.exists() {
+                std::fs::remove_dir_all(&dir)
+                    .with_context(|| format!("Removing {dir:?}"))?;
+            }
+        }
+        Ok(())
+    }
+}
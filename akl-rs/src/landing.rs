@@ -0,0 +1,104 @@
+// Landing pages of the documents.
+//
+// Many urls of papers lead to an html page describing the paper
+// rather than to its pdf file. Most publishers and repositories
+// announce the pdf file and the metadata of the paper to indexers
+// (Google Scholar, Zotero) with `<meta name="citation_…">` tags,
+// and the social networks with Open Graph `<meta property="og:…">`
+// tags: the download follows the first, and the import takes the
+// metadata of both, which are better than the Info dictionary of
+// most pdf files.
+
+use crate::pdflib::PdfMetaData;
+
+/// Tags giving the pdf file, by order of preference.
+const PDF_TAGS : &[&str] = &["citation_pdf_url", "bepress_citation_pdf_url", "eprints.document_url"];
+
+/// The `content` attributes of the `<meta>` tags of an html page
+/// with the given `name` (or `property`, for Open Graph).
+fn meta_contents(html : &str, name : &str) -> Vec<String> {
+    let lower = html.to_ascii_lowercase();
+    let mut contents = vec![];
+    let mut from = 0;
+    while let Some(i) = lower[from..].find("<meta") {
+        let start = from + i;
+        let Some(end) = lower[start..].find('>').map(|e| start + e) else {
+            break;
+        };
+        let tag = &html[start..end];
+        let attr = |attr : &str| -> Option<String> {
+            let lower = tag.to_ascii_lowercase();
+            let i = lower.find(&format!(" {attr}="))? + attr.len() + 2;
+            let quote = tag[i..].chars().next()?;
+            let value = if quote == '"' || quote == '\'' {
+                tag[i + 1..].split(quote).next()?
+            } else {
+                tag[i..].split_whitespace().next()?
+            };
+            Some(unescape(value))
+        };
+        let named = attr("name").or_else(|| attr("property"));
+        if named.is_some_and(|n| n.eq_ignore_ascii_case(name)) {
+            contents.extend(attr("content").filter(|c| !c.trim().is_empty()));
+        }
+        from = end;
+    }
+    contents
+}
+
+/// The first `content` of the `<meta>` tags of the given names.
+pub fn meta_content(html : &str, names : &[&str]) -> Option<String> {
+    names.iter().find_map(|n| meta_contents(html, n).into_iter().next())
+}
+
+/// Decodes the usual html entities of an attribute.
+pub fn unescape(value : &str) -> String {
+    value.replace("&quot;", "\"")
+         .replace("&#39;", "'")
+         .replace("&apos;", "'")
+         .replace("&lt;", "<")
+         .replace("&gt;", ">")
+         .replace("&amp;", "&")
+}
+
+/// The pdf file a landing page (downloaded from `origin`) links to.
+pub fn pdf_url(origin : &str, html : &[u8]) -> Option<String> {
+    let link = meta_content(&String::from_utf8_lossy(html), PDF_TAGS)?;
+    match url::Url::parse(origin).and_then(|base| base.join(&link)) {
+        Ok(url) => Some(url.to_string()),
+        Err(_) => Some(link),
+    }
+}
+
+/// The metadata that a landing page gives about its document.
+pub fn metadata(html : &[u8]) -> PdfMetaData {
+    let html = String::from_utf8_lossy(html);
+    let year = meta_content(&html, &["citation_publication_date", "citation_date", "citation_online_date", "citation_year"])
+        .and_then(|d| d.trim().get(..4).and_then(|y| y.parse().ok()));
+    let mut identifiers = vec![];
+    if let Some(doi) = meta_content(&html, &["citation_doi", "dc.identifier.doi"]) {
+        let doi = doi.trim()
+            .trim_start_matches("doi:")
+            .trim_start_matches("https://doi.org/")
+            .trim_start_matches("http://dx.doi.org/");
+        identifiers.push(format!("doi:{doi}"));
+    }
+    if let Some(arxiv) = meta_content(&html, &["citation_arxiv_id"]) {
+        identifiers.push(format!("arxiv:{}", arxiv.trim()));
+    }
+    PdfMetaData {
+        title: meta_content(&html, &["citation_title", "dc.title", "og:title"]),
+        context: meta_content(&html, &["citation_journal_title", "citation_conference_title", "citation_inbook_title"])
+            .into_iter()
+            .collect(),
+        authors: meta_contents(&html, "citation_author").iter()
+            .map(|a| crate::authors::display_name(a))
+            .collect(),
+        year,
+        identifiers,
+        r#abstract: meta_content(&html, &["citation_abstract"]),
+        keywords: meta_contents(&html, "citation_keywords").iter()
+            .flat_map(|k| crate::xmp::split_keywords(k))
+            .collect(),
+    }
+}
@@ -0,0 +1,343 @@
+// LaTeX-aware text handling.
+//
+// Metadata fetched from pdf files and remote services is plain Unicode,
+// sometimes with inline math (`$\omega$-regular`). BibTeX fields need
+// to be escaped and have their capitalization protected, while
+// filenames need plain ASCII without any markup.
+
+/// Accented letters, grouped by the LaTeX accent command
+/// that produces them: (command, accented letters, base letters).
+const ACCENTS : &[(&str, &str, &str)] = &[
+    ("'",  "áéíóúýćńśźÁÉÍÓÚÝĆŃŚŹ", "aeiouycnszAEIOUYCNSZ"),
+    ("`",  "àèìòùÀÈÌÒÙ",           "aeiouAEIOU"),
+    ("^",  "âêîôûÂÊÎÔÛ",           "aeiouAEIOU"),
+    ("\"", "äëïöüÿÄËÏÖÜ",          "aeiouyAEIOU"),
+    ("~",  "ãñõÃÑÕ",               "anoANO"),
+    ("c",  "çşÇŞ",                 "csCS"),
+    ("v",  "čďěňřšťžČĎĚŇŘŠŤŽ",     "cdenrstzCDENRSTZ"),
+    ("H",  "őűŐŰ",                 "ouOU"),
+    ("u",  "ăğĂĞ",                 "agAG"),
+    ("k",  "ąęĄĘ",                 "aeAE"),
+    (".",  "żŻ",                   "zZ"),
+    ("r",  "åůÅŮ",                 "auAU"),
+];
+
+/// Letters that are not accents but standalone LaTeX commands:
+/// (letter, LaTeX command, ASCII transliteration).
+const SPECIAL_LETTERS : &[(char, &str, &str)] = &[
+    ('ß', "\\ss",  "ss"),
+    ('æ', "\\ae",  "ae"), ('Æ', "\\AE", "AE"),
+    ('œ', "\\oe",  "oe"), ('Œ', "\\OE", "OE"),
+    ('ø', "\\o",   "o"),  ('Ø', "\\O",  "O"),
+    ('ł', "\\l",   "l"),  ('Ł', "\\L",  "L"),
+    ('ı', "\\i",   "i"),
+];
+
+/// Greek letters and common symbols, typeset in math mode.
+const MATH_SYMBOLS : &[(char, &str, &str)] = &[
+    ('α', "\\alpha", "alpha"), ('β', "\\beta", "beta"),
+    ('γ', "\\gamma", "gamma"), ('δ', "\\delta", "delta"),
+    ('ε', "\\varepsilon", "epsilon"), ('λ', "\\lambda", "lambda"),
+    ('μ', "\\mu", "mu"), ('π', "\\pi", "pi"),
+    ('σ', "\\sigma", "sigma"), ('φ', "\\varphi", "phi"),
+    ('ω', "\\omega", "omega"), ('Σ', "\\Sigma", "Sigma"),
+    ('Π', "\\Pi", "Pi"), ('Δ', "\\Delta", "Delta"),
+    ('Ω', "\\Omega", "Omega"), ('∞', "\\infty", "infinity"),
+    ('≤', "\\leq", "leq"), ('≥', "\\geq", "geq"),
+    ('→', "\\to", "to"), ('∀', "\\forall", "forall"),
+    ('∃', "\\exists", "exists"),
+];
+
+/// Finds the LaTeX accent command and base letter of an accented letter.
+fn accent_of(c : char) -> Option<(&'static str, char)> {
+    ACCENTS.iter().find_map(|(cmd, accented, base)| {
+        accented.chars()
+                .position(|a| a == c)
+                .and_then(|i| base.chars().nth(i))
+                .map(|b| (*cmd, b))
+    })
+}
+
+/// Splits a string into alternating text and math segments,
+/// math segments being delimited by `$`. An unbalanced `$`
+/// is considered to be text.
+///
+/// Returns pairs (is_math, segment), where math segments
+/// do not contain their delimiters.
+fn math_segments(s : &str) -> Vec<(bool, &str)> {
+    let mut result = vec![];
+    let mut rest = s;
+    while let Some(start) = rest.find('$') {
+        match rest[start+1..].find('$') {
+            Some(len) => {
+                if start > 0 {
+                    result.push((false, &rest[..start]));
+                }
+                result.push((true, &rest[start+1..start+1+len]));
+                rest = &rest[start+2+len..];
+            }
+            None => { break; }
+        }
+    }
+    if !rest.is_empty() {
+        result.push((false, rest));
+    }
+    result
+}
+
+/// Escapes a plain text segment (not math) for LaTeX.
+///
+/// Backslashes and braces are kept as is, since they usually
+/// are already LaTeX markup when they appear in metadata.
+fn escape_text(s : &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' | '%' | '#' | '_' | '$' => { out.push('\\'); out.push(c); }
+            c if c.is_ascii() => { out.push(c); }
+            c => {
+                if let Some((cmd, base)) = accent_of(c) {
+                    let base = if base == 'i' { "\\i".to_string() } else { base.to_string() };
+                    if cmd.chars().all(char::is_alphabetic) {
+                        out.push_str(&format!("{{\\{cmd}{{{base}}}}}"));
+                    } else {
+                        out.push_str(&format!("{{\\{cmd}{base}}}"));
+                    }
+                } else if let Some((_, cmd, _)) = SPECIAL_LETTERS.iter().find(|(l,_,_)| *l == c) {
+                    out.push_str(&format!("{{{cmd}}}"));
+                } else if let Some((_, cmd, _)) = MATH_SYMBOLS.iter().find(|(l,_,_)| *l == c) {
+                    out.push_str(&format!("${cmd}$"));
+                } else {
+                    out.push(c);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Escapes a string to be used inside a BibTeX field,
+/// keeping inline math untouched.
+pub fn escape(s : &str) -> String {
+    math_segments(s).into_iter()
+        .map(|(math, seg)| if math { format!("${seg}$") } else { escape_text(seg) })
+        .collect()
+}
+
+/// Reads the letter following an accent command, either
+/// alone (`\'e`), braced (`\'{e}`) or dotless (`\'{\i}`).
+fn accent_argument(chars : &mut std::iter::Peekable<std::str::Chars>) -> Option<char> {
+    while chars.next_if(|c| *c == ' ').is_some() {}
+    let braced = chars.next_if_eq(&'{').is_some();
+    let letter = match chars.next()? {
+        '\\' => { chars.next_if(|c| *c == 'i' || *c == 'j') }
+        c => Some(c),
+    };
+    if braced {
+        chars.next_if_eq(&'}');
+    }
+    letter
+}
+
+/// Decodes a plain text segment (not math) of a BibTeX field.
+fn unescape_text(s : &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' => {}
+            '~' => { out.push(' '); }
+            '\\' => {
+                let mut cmd : String = chars.next_if(|n| !n.is_ascii_alphabetic())
+                    .map(String::from)
+                    .unwrap_or_default();
+                if cmd.is_empty() {
+                    while let Some(n) = chars.next_if(char::is_ascii_alphabetic) {
+                        cmd.push(n);
+                    }
+                }
+                if let Some((_, accented, base)) = ACCENTS.iter().find(|(a,_,_)| *a == cmd) {
+                    if let Some(l) = accent_argument(&mut chars) {
+                        match base.chars().position(|b| b == l) {
+                            Some(i) => out.extend(accented.chars().nth(i)),
+                            None => out.push(l),
+                        }
+                    }
+                } else if let Some((l,_,_)) = SPECIAL_LETTERS.iter().find(|(_,m,_)| m[1..] == cmd) {
+                    out.push(*l);
+                    chars.next_if_eq(&' ');
+                } else if matches!(cmd.as_str(), "&" | "%" | "#" | "_" | "$" | "{" | "}") {
+                    out.push_str(&cmd);
+                } else {
+                    out.push('\\');
+                    out.push_str(&cmd);
+                }
+            }
+            c => { out.push(c); }
+        }
+    }
+    out
+}
+
+/// Decodes the markup of a BibTeX field into plain Unicode
+/// (`{\'E}cole {\o}` becomes `École ø`), keeping inline math
+/// untouched. Unknown commands are kept as is.
+pub fn to_unicode(s : &str) -> String {
+    math_segments(s).into_iter()
+        .map(|(math, seg)| if math { format!("${seg}$") } else { unescape_text(seg) })
+        .collect()
+}
+
+/// Should this word be wrapped in braces to keep its capitalization?
+///
+/// BibTeX styles lowercase titles, except for the first letter.
+/// Every word containing a capital letter is protected, except
+/// for the first word when it is simply capitalized.
+fn needs_protection(word : &str, first : bool) -> bool {
+    let mut uppers = word.chars()
+                         .enumerate()
+                         .filter(|(_,c)| c.is_uppercase())
+                         .map(|(i,_)| i);
+    match uppers.next() {
+        None => false,
+        Some(0) if first => uppers.next().is_some(),
+        Some(_) => true,
+    }
+}
+
+/// Produces a BibTeX title field: escaped, with inline math kept
+/// and capitalized words protected using braces.
+pub fn protect_title(title : &str) -> String {
+    let mut first = true;
+    math_segments(title).into_iter().map(|(math, seg)| {
+        if math {
+            first = false;
+            // math in titles must be protected too, otherwise
+            // BibTeX lowercases the letters inside
+            format!("{{${seg}$}}")
+        } else {
+            seg.split(' ').map(|word| {
+                let protect = needs_protection(word, first);
+                if !word.is_empty() {
+                    first = false;
+                }
+                let esc = escape_text(word);
+                if protect && !esc.starts_with('{') {
+                    format!("{{{esc}}}")
+                } else {
+                    esc
+                }
+            }).collect::<Vec<String>>().join(" ")
+        }
+    }).collect()
+}
+
+/// Lowercase particles of family names (von, de, van der, etc.).
+const NAME_PARTICLES : &[&str] = &[
+    "von", "van", "der", "den", "de", "du", "des", "di", "da", "del", "della", "la", "le",
+];
+
+/// Splits an author name, written `First Last` or `Last, First`,
+/// into its given and family names. Particles (`van der`) belong
+/// to the family name; a single word is a family name.
+pub fn split_name(name : &str) -> (String, String) {
+    let name = name.trim();
+    if let Some((last, first)) = name.split_once(',') {
+        return (first.trim().to_string(), last.trim().to_string());
+    }
+    let words : Vec<&str> = name.split_whitespace().collect();
+    if words.len() < 2 {
+        return (String::new(), name.to_string());
+    }
+    let last_start = words.iter()
+        .position(|w| NAME_PARTICLES.contains(w))
+        .filter(|&i| i > 0)
+        .unwrap_or(words.len() - 1);
+    (words[..last_start].join(" "), words[last_start..].join(" "))
+}
+
+/// Formats an author name as `Last, First` with escaped characters,
+/// so that BibTeX correctly splits the name (in particular for
+/// family names with particles like "van der Waals").
+pub fn format_author(name : &str) -> String {
+    let name = name.trim();
+    if name.contains(',') {
+        return escape(name);
+    }
+    match split_name(name) {
+        (first, last) if !first.is_empty() => escape(&format!("{last}, {first}")),
+        _ => escape(name),
+    }
+}
+
+/// Formats a list of authors as a BibTeX `author` field.
+pub fn format_authors(authors : &[String]) -> String {
+    authors.iter()
+           .map(|a| format_author(a))
+           .collect::<Vec<String>>()
+           .join(" and ")
+}
+
+/// Removes the math formulas (`$…$`, `\(…\)`) of a string.
+/// An unclosed formula runs to the end of the string.
+pub fn strip_math(s : &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    loop {
+        let dollar = rest.find('$').map(|i| (i, 1, "$"));
+        let paren = rest.find("\\(").map(|i| (i, 2, "\\)"));
+        let start = match (dollar, paren) {
+            (Some(d), Some(p)) => if d.0 < p.0 { d } else { p },
+            (Some(m), None) | (None, Some(m)) => m,
+            (None, None) => break,
+        };
+        let (i, len, end) = start;
+        out.push_str(&rest[..i]);
+        let after = &rest[i + len..];
+        match after.find(end) {
+            Some(j) => rest = &after[j + end.len()..],
+            None => return out,
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Transliterates a string into plain ASCII, removing math markup,
+/// LaTeX commands and braces (`$\omega$-régulier` becomes `omega-regulier`).
+/// Characters without a known transliteration are dropped.
+pub fn to_ascii(s : &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '$' | '{' | '}' => {}
+            // LaTeX command: keep its name for greek letters and
+            // symbols (\omega -> omega), otherwise drop it
+            '\\' => {
+                let mut cmd = String::new();
+                while let Some(&n) = chars.peek() {
+                    if !n.is_ascii_alphabetic() { break; }
+                    cmd.push(n);
+                    chars.next();
+                }
+                if MATH_SYMBOLS.iter().any(|(_,m,_)| m[1..] == cmd) {
+                    out.push_str(&cmd);
+                }
+            }
+            c if c.is_ascii() => { out.push(c); }
+            c => {
+                if let Some((_, base)) = accent_of(c) {
+                    out.push(base);
+                } else if let Some((_,_,t)) = SPECIAL_LETTERS.iter().find(|(l,_,_)| *l == c) {
+                    out.push_str(t);
+                } else if let Some((_,_,t)) = MATH_SYMBOLS.iter().find(|(l,_,_)| *l == c) {
+                    out.push_str(t);
+                } else if c.is_whitespace() {
+                    out.push(' ');
+                }
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,129 @@
+// Deep-link map of a document.
+//
+// Lists the anchors (named destinations) and the external links
+// of a document, either as text or as a standalone html page
+// where every page is drawn with clickable overlays, so that the
+// anchors can be browsed without akl nor a pdf viewer.
+//
+// Page images are rendered with `pdftoppm` (poppler) when it is
+// installed; otherwise the overlays are drawn on blank pages.
+
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Result, Context};
+
+use crate::pdflib::LinkMap;
+
+/// Resolution of the page images.
+const DPI : f32 = 96.0;
+
+/// Writes the anchors and links, one per line.
+pub fn write_text<W : Write>(out : &mut W, map : &LinkMap) -> Result<()> {
+    for (page, name, _, _) in &map.anchors {
+        writeln!(out, "anchor\t{page}\t{name}")?;
+    }
+    for link in &map.links {
+        writeln!(out, "link\t{}\t{}", link.page_num, link.uri)?;
+    }
+    Ok(())
+}
+
+/// Renders a page to a png image using pdftoppm.
+/// Returns `None` when pdftoppm is not available.
+fn render_page(pdf : &Path, page : u32, dir : &Path) -> Result<Option<Vec<u8>>> {
+    let prefix = dir.join(format!("page-{page}"));
+    let status = std::process::Command::new("pdftoppm")
+        .args(["-png", "-singlefile", "-r", &DPI.to_string()])
+        .args(["-f", &page.to_string(), "-l", &page.to_string()])
+        .arg(pdf)
+        .arg(&prefix)
+        .status();
+    match status {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).context("Running pdftoppm"),
+        Ok(s) if !s.success() => anyhow::bail!("pdftoppm failed on page {page}"),
+        Ok(_) => Ok(Some(std::fs::read(prefix.with_extension("png"))?)),
+    }
+}
+
+/// Standard base64 encoding, for the images embedded in the page.
+fn base64(bytes : &[u8]) -> String {
+    const ALPHABET : &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Escapes text for html content and attributes.
+fn escape(s : &str) -> String {
+    s.replace('&', "&amp;")
+     .replace('<', "&lt;")
+     .replace('>', "&gt;")
+     .replace('"', "&quot;")
+}
+
+/// Writes a standalone html page showing the document,
+/// with overlays on its anchors and links.
+pub fn write_html<W : Write>(out : &mut W, map : &LinkMap, title : &str, pdf : &Path) -> Result<()> {
+    let scale = DPI / 72.0;
+    let dir = tempfile::tempdir()?;
+    let mut images = true;
+
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html><head><meta charset=\"utf-8\"><title>{}</title>", escape(title))?;
+    writeln!(out, "<style>
+body {{ background: #eceff4; font-family: sans-serif; }}
+.page {{ position: relative; margin: 1em auto; background: white; box-shadow: 0 0 4px #4c566a; }}
+.page img {{ position: absolute; width: 100%; height: 100%; }}
+.anchor {{ position: absolute; width: 10px; height: 10px; background: #8fbcbb; border-radius: 2px; }}
+.anchor:target {{ outline: 3px solid #bf616a; }}
+.link {{ position: absolute; background: rgba(129, 161, 193, 0.3); }}
+</style></head><body>")?;
+    writeln!(out, "<h1>{}</h1>", escape(title))?;
+
+    for (i, mb) in map.pages.iter().enumerate() {
+        let page = i as u32 + 1;
+        let (width, height) = ((mb[2] - mb[0]) * scale, (mb[3] - mb[1]) * scale);
+        // coordinates of the pdf go upwards from the bottom left corner
+        let x = |v : f32| (v - mb[0]) * scale;
+        let y = |v : f32| (mb[3] - v) * scale;
+
+        writeln!(out, "<div class=\"page\" id=\"page-{page}\" style=\"width: {width:.0}px; height: {height:.0}px\">")?;
+        if images {
+            match render_page(pdf, page, dir.path())? {
+                Some(png) => {
+                    writeln!(out, "<img alt=\"page {page}\" src=\"data:image/png;base64,{}\">", base64(&png))?;
+                }
+                None => {
+                    log::warn!("pdftoppm is not installed, the pages are drawn blank");
+                    images = false;
+                }
+            }
+        }
+        for link in map.links.iter().filter(|l| l.page_num == page) {
+            let [x_ll, y_ll, x_ur, y_ur] = link.rect;
+            writeln!(out, "<a class=\"link\" href=\"{uri}\" title=\"{uri}\" style=\"left: {:.1}px; top: {:.1}px; width: {:.1}px; height: {:.1}px\"></a>",
+                     x(x_ll.min(x_ur)), y(y_ll.max(y_ur)),
+                     (x_ur - x_ll).abs() * scale, (y_ur - y_ll).abs() * scale,
+                     uri = escape(&link.uri))?;
+        }
+        for (_, name, left, top) in map.anchors.iter().filter(|a| a.0 == page) {
+            writeln!(out, "<a class=\"anchor\" id=\"{name}\" href=\"#{name}\" title=\"{name}\" style=\"left: {:.1}px; top: {:.1}px\"></a>",
+                     x(*left) - 12.0, y(*top), name = escape(name))?;
+        }
+        writeln!(out, "</div>")?;
+    }
+    writeln!(out, "</body></html>")?;
+    Ok(())
+}
@@ -0,0 +1,143 @@
+// Listing the library, with filters and machine-readable outputs.
+
+use std::io::Write;
+
+use serde::{Serialize, Deserialize};
+use anyhow::Result;
+
+use crate::Document;
+use crate::reading::ReadingStatus;
+use crate::doctype::DocType;
+
+/// Output formats of the list command.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ListFormat {
+    /// Aligned columns, for humans.
+    #[default]
+    Table,
+    /// A json array of documents.
+    Json,
+    /// A yaml list of documents (same format as the index).
+    Yaml,
+    /// Tab separated values, one document per line.
+    Tsv,
+}
+
+/// Filters on the documents of the library.
+/// All the given filters must match.
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct DocumentFilter {
+    /// Only documents with an author containing this string
+    #[arg(long)]
+    pub author: Option<String>,
+
+    /// Only documents published this year
+    #[arg(long)]
+    pub year: Option<u32>,
+
+    /// Only documents with a context containing this string
+    #[arg(long)]
+    pub context: Option<String>,
+
+    /// Only documents with an identifier containing this string
+    #[arg(long)]
+    pub identifier: Option<String>,
+
+    /// Only documents with this tag
+    #[arg(long)]
+    pub tag: Option<String>,
+
+    /// Only documents with this reading status
+    #[arg(long, value_enum)]
+    pub status: Option<ReadingStatus>,
+
+    /// Only documents of this type
+    #[arg(long = "type", value_enum)]
+    pub doc_type: Option<DocType>,
+}
+
+/// Case insensitive substring search.
+fn contains(haystack : &str, needle : &str) -> bool {
+    haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+impl DocumentFilter {
+    /// Does the document pass the filter?
+    pub fn matches(&self, doc : &Document) -> bool {
+        self.author.as_ref().is_none_or(|a| doc.authors.iter().any(|x| contains(x, a))) &&
+        self.year.is_none_or(|y| doc.year == y) &&
+        self.context.as_ref().is_none_or(|c| doc.context.iter().any(|x| contains(x, c))) &&
+        self.identifier.as_ref().is_none_or(|i| doc.identifiers.iter().any(|x| contains(x, i))) &&
+        self.tag.as_ref().is_none_or(|t| doc.tags.contains(t)) &&
+        self.status.is_none_or(|s| doc.status == Some(s)) &&
+        self.doc_type.is_none_or(|t| doc.doc_type == Some(t))
+    }
+}
+
+/// Short description of the authors of a document.
+fn short_authors(doc : &Document) -> String {
+    match doc.authors.as_slice() {
+        []      => String::new(),
+        [a]     => a.clone(),
+        [a, b]  => format!("{a} and {b}"),
+        [a, ..] => format!("{a} et al."),
+    }
+}
+
+/// Cuts a string to a given number of characters.
+fn cut(s : &str, width : usize) -> String {
+    if s.chars().count() > width {
+        let mut r : String = s.chars().take(width - 1).collect();
+        r.push('…');
+        r
+    } else {
+        s.into()
+    }
+}
+
+/// Prints the documents in the given format.
+pub fn print_documents<W : Write>(out : &mut W, docs : &[Document], format : ListFormat) -> Result<()> {
+    match format {
+        ListFormat::Table => {
+            for doc in docs {
+                writeln!(out, "{:<8}  {:<4}  {:<30}  {:<60}  {}",
+                         doc.id,
+                         doc.year,
+                         cut(&short_authors(doc), 30),
+                         cut(&doc.title, 60),
+                         doc.context.join(", "))?;
+            }
+        }
+        ListFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, docs)?;
+            writeln!(out)?;
+        }
+        ListFormat::Yaml => {
+            serde_yaml::to_writer(&mut *out, docs)?;
+        }
+        ListFormat::Tsv => {
+            for doc in docs {
+                let fields = [
+                    doc.checksum.clone(),
+                    doc.year.to_string(),
+                    doc.authors.join("; "),
+                    doc.title.clone(),
+                    doc.context.join("; "),
+                    doc.identifiers.join("; "),
+                    doc.filename.clone(),
+                    // the later columns are appended, so that
+                    // the scripts reading the first ones keep working
+                    doc.tags.join("; "),
+                    doc.id.clone(),
+                ];
+                let line = fields.iter()
+                    .map(|f| f.replace(['\t', '\n'], " "))
+                    .collect::<Vec<String>>()
+                    .join("\t");
+                writeln!(out, "{line}")?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+// Advisory locks shared by the akl processes.
+//
+// Several akl processes may run at the same time (a link clicked
+// twice, an import while the picker opens a document). The files
+// they all rewrite are protected by an advisory lock on a separate
+// `<file>.lock` file, which stays in place when the file itself is
+// replaced. Readers take a shared lock, writers an exclusive one.
+
+use std::fs::{File, TryLockError};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, Context};
+
+/// A lock, released when dropped.
+#[derive(Debug)]
+pub struct Lock {
+    _file : File,
+}
+
+/// Path of the lock file of a file.
+fn lock_path(path : &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    path.with_file_name(name)
+}
+
+fn lock(path : &Path, exclusive : bool) -> Result<Lock> {
+    let path = lock_path(path);
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .with_context(|| format!("Opening the lock file {path:?}"))?;
+    let attempt = if exclusive { file.try_lock() } else { file.try_lock_shared() };
+    match attempt {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            log::info!("Waiting for another akl process to release {path:?}");
+            if exclusive { file.lock() } else { file.lock_shared() }
+                .with_context(|| format!("Locking {path:?}"))?;
+        }
+        Err(TryLockError::Error(e)) => {
+            return Err(e).with_context(|| format!("Locking {path:?}"));
+        }
+    }
+    Ok(Lock { _file: file })
+}
+
+/// Waits until no process writes the file.
+pub fn shared(path : &Path) -> Result<Lock> {
+    lock(path, false)
+}
+
+/// Waits until no other process reads or writes the file.
+pub fn exclusive(path : &Path) -> Result<Lock> {
+    lock(path, true)
+}
@@ -1,18 +1,16 @@
 // local directories (cross platform)
 use directories::ProjectDirs;
 // path handling
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 // hashmap 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 // command line argument parsing
 use clap::{Parser, Subcommand, Args};
 
 use url::Url;
 
-// clipboard management
-// with extra x11 trickery to get the clipboard to work
-use copypasta_ext::prelude::*;
-use copypasta_ext::x11_fork::ClipboardContext;
+// checksums
+use sha2::{Digest, Sha256};
 
 
 // serialisation  and deserialisation 
@@ -22,6 +20,86 @@ use serde::{Serialize, Deserialize};
 use anyhow::{Result, Context};
 
 mod pdflib;
+mod events;
+mod journal;
+mod lock;
+mod git;
+mod browse;
+mod remote;
+mod sync;
+mod trash;
+mod list;
+mod handler;
+mod storage;
+mod desktop;
+mod config;
+mod secrets;
+mod latex;
+mod venues;
+mod collections;
+mod quality;
+mod identifiers;
+mod gc;
+mod verify;
+mod naming;
+mod linkmap;
+mod duplicates;
+mod bench;
+mod report;
+mod stopwords;
+mod template;
+mod authors;
+mod doctype;
+mod xmp;
+mod abstracts;
+mod crossref;
+mod dblp;
+mod published;
+mod enrich;
+mod scholar;
+mod unpaywall;
+mod landing;
+mod negotiation;
+mod openlibrary;
+mod hal;
+mod iacr;
+mod eccc;
+mod arxiv;
+mod rxiv;
+mod pubmed;
+mod providers;
+mod plugins;
+mod schema;
+mod bibtex;
+mod conflicts;
+mod export;
+mod csl;
+mod picker;
+mod backup;
+mod session;
+mod heatmap;
+mod zotero;
+mod papis;
+mod project;
+mod query;
+mod reading;
+mod searches;
+mod inbox;
+mod scan;
+mod anchors;
+mod filetype;
+mod format;
+mod snapshot;
+mod download;
+mod proxy;
+mod cookies;
+mod polite;
+mod progress;
+mod store;
+
+#[cfg(feature = "count-allocations")]
+#[global_allocator]
+static ALLOCATOR : bench::CountingAllocator = bench::CountingAllocator;
 //mod view;
 //mod document;
 //mod commands;
@@ -47,6 +125,12 @@ struct CiteArgs {
     /// has been written (url / uid)
     #[arg(short, long)]
     from: Option<String>,
+
+    /// When opening, go back to where the document was left
+    /// (unless a page or destination is given)
+    #[arg(long)]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    resume: bool,
 }
 
 /// Arguments given to the import command.
@@ -59,7 +143,7 @@ struct CiteArgs {
 #[derive(Clone,Args,Debug,Serialize,Deserialize)]
 struct ImportArgs {
     /// URI to the document
-    #[arg(short, long)]
+    #[arg(short, long, required_unless_present_any = ["batch", "bibtex", "zotero", "papis", "pubs", "stdin"], default_value = "")]
     uri: String,
 
     /// title of the document
@@ -82,6 +166,17 @@ struct ImportArgs {
     #[arg(short, long)]
     year: Option<u32>,
 
+    /// Type of the document (by default, read from the BibTeX
+    /// or Zotero metadata, or a preprint for arxiv uris)
+    #[arg(long = "type", value_enum)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    doc_type: Option<doctype::DocType>,
+
+    /// Tags of the document
+    #[arg(long = "tag")]
+    #[serde(default)]
+    tags: Vec<String>,
+
     /// View after import?
     #[arg(short, long, default_value="false")]
     view: bool,
@@ -89,738 +184,5684 @@ struct ImportArgs {
     /// Force re-import even if the pdf is in the library?
     #[arg(short, long, default_value="false")]
     force: bool,
-}
 
-/// Arguments given to the resolve command.
-#[derive(Args,Debug,Serialize,Deserialize,Clone)]
-struct ResolveArgs {
-    /// URI to the document
-    #[arg(short, long)]
-    uri: String,
-}
+    /// Import every pdf file of a directory (and its
+    /// subdirectories) instead, skipping the files already
+    /// in the library; the other options apply to all of them
+    #[arg(long, conflicts_with_all = ["uri", "title", "authors", "identifiers", "year", "view"])]
+    #[serde(skip)]
+    batch: Option<PathBuf>,
 
+    /// Import the entries of a BibTeX file instead, downloading
+    /// them from their arxiv id, url or doi; the fields of the
+    /// entries are used as metadata, and the context and tags
+    /// options apply to all of them
+    #[arg(long, conflicts_with_all = ["uri", "title", "authors", "identifiers", "year", "view", "batch"])]
+    #[serde(skip)]
+    bibtex: Option<PathBuf>,
 
-/// Arguments given to the convert command.
-/// The URI must be a valid filepath to a pdf document.
-///
-/// This command typically is used when opening
-/// a "working document".
-///
-/// TODO: also allow urls to be downloaded?
-#[derive(Args,Debug,Serialize,Deserialize,Clone)]
-struct ConvertArgs {
-    /// URI to the document
-    #[arg(short, long)]
-    uri: String,
+    /// Import a Zotero library instead, from a Zotero RDF export
+    /// (with its files) or a BibTeX export of BetterBibTeX: the
+    /// attached pdf files are imported, and the Zotero tags and
+    /// collections become tags and collections of the library
+    #[arg(long, conflicts_with_all = ["uri", "title", "authors", "identifiers", "year", "view", "batch", "bibtex"])]
+    #[serde(skip)]
+    zotero: Option<PathBuf>,
 
-    /// Output file name
-    #[arg(short, long)]
-    output: PathBuf,
+    /// Import a papis library instead, from its directory: the
+    /// attached pdf files are imported, the papis tags become tags,
+    /// and the citation keys are kept as `citekey:…` identifiers
+    #[arg(long, conflicts_with_all = ["uri", "title", "authors", "identifiers", "year", "view", "batch", "bibtex", "zotero"])]
+    #[serde(skip)]
+    papis: Option<PathBuf>,
+
+    /// Import a pubs repository instead (`~/.pubs` by default),
+    /// like a papis library
+    #[arg(long, conflicts_with_all = ["uri", "title", "authors", "identifiers", "year", "view", "batch", "bibtex", "zotero", "papis"])]
+    #[serde(skip)]
+    pubs: Option<PathBuf>,
+
+    /// Import the uris read from the standard input instead, one
+    /// per line, printing the result of each import as a line of
+    /// json; the other options apply to all of them
+    #[arg(long, default_value="false",
+          conflicts_with_all = ["uri", "title", "authors", "identifiers", "year", "view", "batch", "bibtex", "zotero", "papis", "pubs"])]
+    #[serde(skip)]
+    stdin: bool,
+
+    /// When re-importing with --force, keep these fields of the
+    /// library instead of the ones of the new import (in interactive
+    /// mode, the other fields that changed are asked for)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    #[serde(default)]
+    keep_local: Vec<conflicts::Field>,
+
+    /// Import into the project library (the `.akl` directory of the
+    /// working directory or of its parents) instead of the global
+    /// library; documents of the global library are copied
+    #[arg(long, default_value="false")]
+    #[serde(default)]
+    local: bool,
+
+    /// Import only the metadata of a doi (from Crossref or doi.org)
+    /// or of an isbn (from OpenLibrary), without its pdf file,
+    /// which `akl attach` adds later
+    #[arg(long, default_value="false", conflicts_with_all = ["batch", "bibtex", "zotero", "papis", "pubs", "stdin"])]
+    #[serde(default)]
+    metadata_only: bool,
+
+    /// Import a snapshot of the web page at the uri (a single html
+    /// file, with its stylesheets and images) instead of a pdf file
+    #[arg(long, default_value="false", conflicts_with_all = ["batch", "bibtex", "zotero", "papis", "pubs", "stdin", "metadata_only"])]
+    #[serde(default)]
+    snapshot: bool,
 }
 
+/// Actions of the credentials command.
+///
+/// Passwords are read from the terminal (or the standard input)
+/// and stored in the system keyring, the configuration file
+/// only refers to the keyring entries.
+#[derive(Subcommand,Debug,Clone)]
+enum CredentialsCommands {
+    /// Store the credentials used to download documents from a host
+    Site {
+        /// Host name (e.g. www.example.org)
+        #[arg(long)]
+        host: String,
 
-/// A document in the library.
-#[derive(Serialize, Deserialize,Clone,Debug)]
-struct Document {
-    /// The SHA256 checksum of the original document
-    /// seen as a string
-    checksum : String,
+        /// User name on this host
+        #[arg(long)]
+        username: String,
+    },
 
-    /// The filename of the document on the system.
-    filename : String,
+    /// Store the password used to decrypt documents
+    /// whose URI starts with the given prefix
+    Document {
+        /// URI (or prefix of URIs) of the documents
+        #[arg(long)]
+        uri: String,
+    },
 
-    /// Strings that identify this document. Typically
-    /// a download URI, but it can also be a DOI or an Arxiv Link.
-    ///
-    /// a. Non empty vector
-    /// b. Sorted by generality (DOI > Arxiv > URL > filepath)
-    identifiers : Vec<String>,
+    /// Forget the credentials of a host or a document prefix
+    Remove {
+        /// Host name or URI prefix
+        #[arg(long)]
+        name: String,
+    },
 
-    /// Understandable name of the document
-    /// usually the title of a paper or a blog post.
-    title : String,
+    /// Store the password (WebDAV) or the secret key (S3)
+    /// of the remote of the sync command
+    Sync,
 
-    /// Authors of the document.
-    authors : Vec<String>,
+    /// List the configured credentials (without the passwords)
+    List,
+}
 
-    /// Publication year of the document.
-    year : u32,
+/// Actions of the sync command.
+#[derive(Subcommand,Debug,Clone)]
+enum SyncCommands {
+    /// Show the documents changed in the library and on
+    /// the remote since the last synchronization
+    Status,
 
-    /// Additional context.
-    /// Typically a conference name, a website name, or
-    /// a working group.
-    #[serde(skip_serializing_if = "Vec::is_empty", default)]
-    context : Vec<String>,
+    /// Apply the changes of the remote to the library
+    Pull {
+        /// Side whose values are kept for the fields
+        /// changed on both sides
+        #[arg(long, value_enum, default_value = "local")]
+        prefer: sync::Side,
+    },
 
-    /// Named destinations of the document.
-    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
-    destinations : HashMap<String,Vec<String>>,
+    /// Send the library to the remote, which must not
+    /// have changed since the last pull
+    Push,
 }
 
+/// Arguments given to the sync command.
+#[derive(Args,Debug,Clone)]
+struct SyncArgs {
+    #[command(subcommand)]
+    action: SyncCommands,
+}
 
-/// The main application state.
-#[derive(Serialize, Deserialize,Clone,Debug)]
-struct AppState {
-    /// File path to the index.yaml file 
-    /// containing the catalog of available documents.
-    index_path : PathBuf,
+/// Arguments given to the credentials command.
+#[derive(Args,Debug,Clone)]
+struct CredentialsArgs {
+    #[command(subcommand)]
+    action: CredentialsCommands,
+}
 
-    /// File path to the directory containing
-    /// the "raw" version of the documents. 
-    raw_path   : PathBuf,
+/// Arguments given to the migrate command.
+#[derive(Args,Debug,Clone)]
+struct MigrateArgs {
+    /// Storage backend to migrate the index to
+    #[arg(short, long)]
+    to: storage::Backend,
+}
 
-    /// File path to the directory containing
-    /// the "modified" version of the documents. 
-    mod_path   : PathBuf,
+/// Arguments given to the log command.
+#[derive(Args,Debug,Clone)]
+struct LogArgs {
+    /// Number of commits to show
+    #[arg(short = 'n', long, default_value_t = 20)]
+    limit: usize,
+}
 
-    /// Path to the logs.
-    log_path   : PathBuf,
+/// Arguments given to the revert-to command.
+#[derive(Args,Debug,Clone)]
+struct RevertToArgs {
+    /// Commit of the history to go back to (see akl log)
+    commit: String,
+}
 
-    /// Content of the index.yaml file, parsed.
-    index : Vec<Document>,
+/// Arguments given to the handler command.
+#[derive(Args,Debug,Clone)]
+struct HandlerArgs {
+    /// Listen on a unix socket (possibly given by systemd)
+    /// instead of reading the uris on the standard input
+    #[arg(short, long, default_value="false")]
+    socket: bool,
 }
 
-//// COMMAND LINE INTERFACE /////
+/// Arguments given to the list command.
+#[derive(Args,Debug,Clone)]
+struct ListArgs {
+    #[command(flatten)]
+    filter: list::DocumentFilter,
 
-#[derive(Parser)]
-#[derive(Debug)]
-#[command(author, version, about, long_about = None)]
-#[command(propagate_version = true)]
-struct Cli {
-    /// Optional URI argument to execute.
-    execute_uri: Option<String>,
+    /// Output format
+    #[arg(long, value_enum, default_value_t)]
+    format: list::ListFormat,
+}
 
-    /// Turn debugging information on
-    #[arg(short, long, action = clap::ArgAction::Count)]
-    debug: u8,
+/// Arguments given to the export command.
+#[derive(Args,Debug,Clone)]
+struct ExportArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value_t)]
+    format: export::ExportFormat,
 
-    /// Interactive flag.
-    /// Uses a temporary file and the default editor to
-    /// allow the user to fill out metadata.
-    #[arg(short, long, default_value = "false")]
-    interactive: bool,
+    /// URI, checksum or title of a document to export
+    /// (the whole library by default)
+    #[arg(short, long)]
+    uri: Vec<String>,
 
-    #[command(subcommand)]
-    command: Option<Commands>,
+    /// Output file (standard output by default)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
 }
 
+/// Arguments given to the info command.
+#[derive(Args,Debug,Clone)]
+struct InfoArgs {
+    /// URI, checksum or title of the document
+    uri: String,
 
-#[derive(Subcommand)]
-#[derive(Debug,Clone)]
-enum Commands {
-    /// Cite a document (typically put a nice citation in the clipboard)
-    Cite(CiteArgs),
+    /// Show the report of the import of the document instead
+    #[arg(long)]
+    report: bool,
+}
 
-    /// Build a "converted" document from a pdf, without storing
-    /// it in the library.
-    Convert(ConvertArgs),
+/// Arguments given to the remove command.
+#[derive(Args,Debug,Clone)]
+struct RemoveArgs {
+    /// URI, checksum or title of the document to remove
+    #[arg(short, long)]
+    uri: String,
+}
 
-    /// Resolve a URI to a potential filepath in the library.
-    Resolve(ResolveArgs),
+/// Actions of the trash command.
+#[derive(Subcommand,Debug,Clone)]
+enum TrashCommands {
+    /// Put a removed document back into the library
+    Restore {
+        /// Checksum, title or identifier of the document
+        #[arg(short, long)]
+        uri: String,
+    },
 
-    /// Open a pdf document using the appropriated viewer
-    /// on the system.
-    ///
-    /// (it turns out that the arguments are isomorphic to
-    /// the cite command for now)
-    View(CiteArgs),
+    /// List the removed documents
+    List,
 
-    /// Open a document, similar to resolve followed by View.
-    ///
-    /// (it turns out that the arguments are isomorphic to
-    /// the cite command for now)
-    Open(CiteArgs),
+    /// Definitively delete all the removed documents
+    Empty,
+}
 
-    /// Find a document by searching current metadata.
-    ///
-    /// Currently only provides a list of the current pdfs
-    /// suitable to be used with ROFI/FZF/Dmenu.
-    Find,
+/// Arguments given to the browse command.
+#[derive(Args,Debug,Clone)]
+struct BrowseArgs {
+    /// Directory of the views (by default, browse_dir of the configuration)
+    #[arg(short, long)]
+    dir: Option<PathBuf>,
+}
 
-    /// Imports a document into the library.
-    /// (does perform a conversion)
-    Import(ImportArgs),
+/// Arguments given to the related command.
+#[derive(Args,Debug,Clone)]
+struct RelatedArgs {
+    /// URI, checksum or title of the document
+    uri: String,
 }
 
-#[derive(Debug,Clone)]
-enum ParsedURI {
-    HttpURL (String),
-    DOI (String),
-    Arxiv { arxiv_id : String, arxiv_version : String },
-    AklCommand (Commands),
-    FilePath (PathBuf),
+/// Arguments given to the attach command.
+#[derive(Args,Debug,Clone)]
+struct AttachArgs {
+    /// URI, checksum or title of the metadata-only document
+    uri: String,
+
+    /// Path, url or arxiv id of the pdf file
+    file: String,
 }
 
-/// Serialize from a command to a suitable uri
-/// of the form `akl://command-name/?query-params`.
-fn command_to_query(cmd : Commands) -> Result<String> {
-    match cmd {
-        Commands::Cite(a) => {
-            let name = "cite-document";
-            let params = serde_urlencoded::to_string(a)?;
-            Ok(format!("akl://{name}/?{params}"))
-        }
-        Commands::Convert(a) => {
-            let name = "convert-document";
-            let params = serde_urlencoded::to_string(a)?;
-            Ok(format!("akl://{name}/?{params}"))
-        }
-        Commands::View(a) => {
-            let name = "view-document";
-            let params = serde_urlencoded::to_string(a)?;
-            Ok(format!("akl://{name}/?{params}"))
-        }
-        Commands::Open(a) => {
-            let name = "open-document";
-            let params = serde_urlencoded::to_string(a)?;
-            Ok(format!("akl://{name}/?{params}"))
-        }
-        Commands::Resolve(a) => {
-            let name = "resolve-document";
-            let params = serde_urlencoded::to_string(a)?;
-            Ok(format!("akl://{name}/?{params}"))
-        }
-        Commands::Import(a) => {
-            let name = "import-document";
-            let params = serde_urlencoded::to_string(a)?;
-            Ok(format!("akl://{name}/?{params}"))
-        }
-        Commands::Find => {
-            let name = "find-document";
-            Ok(format!("akl://{name}/"))
-        }
-    }
+/// Arguments given to the update command.
+#[derive(Args,Debug,Clone)]
+struct UpdateArgs {
+    /// URI, checksum or title of the document imported from arXiv
+    uri: String,
 }
 
-/// Converts from a query string and command name
-/// to a parsed command result.
-fn query_to_command(name : &str, query : &str) -> Result<Commands> {
-    match name {
-        "import-document" => {
-            let mut keys = serde_urlencoded::from_str::<HashMap<String,String>>(query)
-                .context("Decoding the import url")?;
+/// Arguments given to the dblp command.
+#[derive(Args,Debug,Clone)]
+struct DblpArgs {
+    /// URI, checksum or title of the document to look up
+    /// (by default, every document without a DBLP key)
+    #[arg(short, long)]
+    uri: Option<String>,
 
-            let payload = keys.remove("payload")
-                .context("Searching for the payload of import args")?;
+    /// Only look up the documents matching these filters
+    #[command(flatten)]
+    filter: list::DocumentFilter,
+}
 
-            let import_args = serde_json::from_str(&payload)
-                .context("Parsing the payload of the import args")?;
-            Ok(Commands::Import(import_args))
-        }
-        "cite-document" => {
-            Ok(Commands::Cite(serde_urlencoded::from_str(query)?))
-        }
-        "view-document" => {
-            Ok(Commands::View(serde_urlencoded::from_str(query)?))
-        }
-        "open-document" => {
-            Ok(Commands::Open(serde_urlencoded::from_str(query)?))
-        }
-        "resolve-document" => {
-            Ok(Commands::Resolve(serde_urlencoded::from_str(query)?))
-        }
-        "convert-document" => {
-            Ok(Commands::Convert(serde_urlencoded::from_str(query)?))
-        }
-        "find-document" => {
-            Ok(Commands::Find)
-        }
-        _ => {
-            anyhow::bail!("Invalid command name {name}")
-        }
-    }
+/// Arguments given to the published command.
+#[derive(Args,Debug,Clone)]
+struct PublishedArgs {
+    /// URI, checksum or title of the preprint to look up
+    /// (by default, every preprint without a published version)
+    #[arg(short, long)]
+    uri: Option<String>,
+
+    /// Only look up the documents matching these filters
+    #[command(flatten)]
+    filter: list::DocumentFilter,
 
+    /// Print the publications found without recording them
+    #[arg(long)]
+    dry_run: bool,
 }
 
-fn parse_arxiv (url : Url) -> Result<ParsedURI> {
-    let arxiv   = url.path();
-    let version = arxiv.find("v");
-    let start : Option<usize>  = 
-        if &arxiv[..5] == "/abs/" ||
-           &arxiv[..5] == "/pdf/" {
-               Some(4)
-        } else { 
-               None
-        };
-    match (start,version) {
-        (Some(s), Some(v)) => {
-            Ok(ParsedURI::Arxiv { arxiv_version: arxiv[v+1..].into(),
-                                  arxiv_id:  arxiv[s+1..v].into() })
-        }
-        (Some(s), None) => {
-            Ok(ParsedURI::Arxiv { arxiv_version: "1".into(),
-                                  arxiv_id:  arxiv[s+1..].into() })
-        }
-        (None, Some(v)) => {
-            Ok(ParsedURI::Arxiv { arxiv_version: arxiv[v+1..].into(),
-                                  arxiv_id:  arxiv[..v].into() })
-        }
-        (None,None) => {
-            Ok(ParsedURI::Arxiv { arxiv_version: "1".into(),
-                                  arxiv_id:  arxiv.into() })
-        }
-    }
+/// Arguments given to the subscribe command.
+#[derive(Args,Debug,Clone)]
+struct SubscribeArgs {
+    /// arXiv category (cs.LO, math.AG…); without
+    /// one, the subscriptions are listed
+    category: Option<String>,
+
+    /// Stop the subscription instead
+    #[arg(long, requires = "category")]
+    remove: bool,
 }
 
-fn parse_doi(url : Url) -> Result<ParsedURI> {
-    let doi = url.path();
-    match doi.chars().nth(0) {
-        Some('/') => {
-            Ok(ParsedURI::DOI(doi[1..].into()))
-        } 
-        _ => {
-            Ok(ParsedURI::DOI(doi.into()))
-        }
-    }
+/// Arguments given to the follow command.
+#[derive(Args,Debug,Clone)]
+struct FollowArgs {
+    /// Name of the author; without one, the
+    /// followed authors are listed
+    name: Option<String>,
+
+    /// ORCID of the author, to find their preprints
+    /// by the arXiv feed of their ORCID
+    #[arg(long, requires = "name", conflicts_with = "remove")]
+    orcid: Option<String>,
+
+    /// Pid of the author in DBLP (m/WimMartens), to find
+    /// their preprints by their DBLP page
+    #[arg(long, requires = "name", conflicts_with = "remove")]
+    dblp: Option<String>,
+
+    /// Stop following the author instead
+    #[arg(long, requires = "name")]
+    remove: bool,
 }
 
-/// URI parser
-fn uri_dispatch(uri : &str) -> Result<ParsedURI> {
-    let nice_url = Url::parse(uri)
-        .context("URL parsing")?;
+/// Actions of the inbox command.
+#[derive(Subcommand,Debug,Clone)]
+enum InboxCommands {
+    /// Add the papers of the subscriptions and of the followed
+    /// authors submitted since the previous fetch to the inbox,
+    /// unless they are in the library
+    Fetch,
 
-    match nice_url.scheme()  {
-        "https" | "http" => {
-            match nice_url.host_str() {
-                Some("arxiv.org") => {
-                    parse_arxiv(nice_url)
-                }
-                Some("doi.org") | Some("dx.doi.org") => {
-                    parse_doi(nice_url)
-                }
-                _ => {
-                    Ok(ParsedURI::HttpURL(uri.into()))
-                }
-            }
+    /// List the papers of the inbox, the newest first
+    List,
+
+    /// Show a paper of the inbox, with its abstract
+    Show {
+        /// Number of the paper in the inbox
+        n: usize,
+    },
+
+    /// Import papers of the inbox into the library
+    Import {
+        /// Numbers of the papers in the inbox
+        #[arg(required = true)]
+        n: Vec<usize>,
+    },
+
+    /// Remove papers from the inbox
+    Drop {
+        /// Numbers of the papers in the inbox
+        #[arg(required = true, conflicts_with = "all")]
+        n: Vec<usize>,
+
+        /// Empty the inbox
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Import, one keystroke each, the pdf files of the
+    /// downloads folder that are not in the library
+    Scan {
+        /// Folder to scan instead of the downloads folder
+        dir: Option<PathBuf>,
+    },
+}
+
+/// Arguments given to the inbox command.
+#[derive(Args,Debug,Clone)]
+struct InboxArgs {
+    #[command(subcommand)]
+    action: InboxCommands,
+}
+
+/// Arguments given to the enrich command.
+#[derive(Args,Debug,Clone)]
+struct EnrichArgs {
+    /// URI, checksum or title of the document to enrich
+    #[arg(short, long)]
+    uri: Option<String>,
+
+    /// Enrich every document of the library
+    /// (matching the filters)
+    #[arg(short, long, required_unless_present = "uri", conflicts_with = "uri")]
+    all: bool,
+
+    /// Only enrich the documents matching these filters
+    #[command(flatten)]
+    filter: list::DocumentFilter,
+
+    /// Print the proposed changes without recording them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// Arguments given to the outdated command.
+#[derive(Args,Debug,Clone)]
+struct OutdatedArgs {
+    /// Only check the documents matching these filters
+    #[command(flatten)]
+    filter: list::DocumentFilter,
+
+    /// Print a json array (for scripts, e.g. a weekly cron job)
+    #[arg(long)]
+    json: bool,
+}
+
+/// Arguments given to the relocate command.
+#[derive(Args,Debug,Clone)]
+struct RelocateArgs {
+    /// New directory of the index (and of the collections,
+    /// authors… but not of the configuration file)
+    #[arg(long)]
+    index: Option<PathBuf>,
+
+    /// New directory of the original files
+    #[arg(long)]
+    raw: Option<PathBuf>,
+
+    /// New directory of the modified files
+    #[arg(long = "mod")]
+    modified: Option<PathBuf>,
+
+    /// New directory of the logs
+    #[arg(long)]
+    log: Option<PathBuf>,
+
+    /// Copy the files instead of moving them
+    #[arg(long)]
+    copy: bool,
+}
+
+/// Arguments given to the trash command.
+#[derive(Args,Debug,Clone)]
+struct TrashArgs {
+    #[command(subcommand)]
+    action: TrashCommands,
+}
+
+/// Actions of the anchors command.
+#[derive(Subcommand,Debug,Clone)]
+enum AnchorsCommands {
+    /// Name a destination of a document
+    Set {
+        /// Checksum, title or identifier of the document
+        #[arg(short, long)]
+        uri: String,
+
+        /// Kind of name
+        #[arg(short, long, value_enum, default_value = "alias")]
+        kind: anchors::AnchorKind,
+
+        /// Alias, label, or destination of a previous version
+        name: String,
+
+        /// Named destination of the document
+        dest: String,
+    },
+
+    /// Forget a name of a destination
+    Remove {
+        /// Checksum, title or identifier of the document
+        #[arg(short, long)]
+        uri: String,
+
+        /// Kind of name
+        #[arg(short, long, value_enum, default_value = "alias")]
+        kind: anchors::AnchorKind,
+
+        /// Alias, label, or destination of a previous version
+        name: String,
+    },
+
+    /// List the names of the destinations of a document
+    List {
+        /// Checksum, title or identifier of the document
+        #[arg(short, long)]
+        uri: String,
+    },
+
+    /// Write the names of the destinations of a document
+    /// to a standalone yaml file, to share them
+    Export {
+        /// Checksum, title or identifier of the document
+        #[arg(short, long)]
+        uri: String,
+
+        /// Output file (standard output by default)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Add the names of an exported file to the document
+    /// it describes, keeping the names already defined
+    Import {
+        /// File written by the export action
+        path: PathBuf,
+    },
+}
+
+/// Arguments given to the anchors command.
+#[derive(Args,Debug,Clone)]
+struct AnchorsArgs {
+    #[command(subcommand)]
+    action: AnchorsCommands,
+}
+
+/// Actions of the session command.
+#[derive(Subcommand,Debug,Clone)]
+enum SessionCommands {
+    /// Save the documents currently open under a name
+    Save {
+        /// Name of the session
+        name: String,
+    },
+
+    /// Reopen the documents of a session
+    Restore {
+        /// Name of the session
+        name: String,
+    },
+
+    /// List the saved sessions
+    List,
+
+    /// Delete a saved session
+    Delete {
+        /// Name of the session
+        name: String,
+    },
+}
+
+/// Arguments given to the session command.
+#[derive(Args,Debug,Clone)]
+struct SessionArgs {
+    #[command(subcommand)]
+    action: SessionCommands,
+}
+
+/// Actions of the backup command.
+#[derive(Subcommand,Debug,Clone)]
+enum BackupCommands {
+    /// Write the whole library to a single archive (.tar.gz)
+    Create {
+        /// Path of the archive
+        path: PathBuf,
+
+        /// Leave out the modified files (they are converted
+        /// again on restore, losing their annotations)
+        #[arg(long)]
+        no_mod: bool,
+    },
+
+    /// Add the documents of an archive to the library,
+    /// after checking the integrity of the archive
+    Restore {
+        /// Path of the archive
+        path: PathBuf,
+    },
+
+    /// Check the integrity of an archive
+    Verify {
+        /// Path of the archive
+        path: PathBuf,
+    },
+}
+
+/// Arguments given to the backup command.
+#[derive(Args,Debug,Clone)]
+struct BackupArgs {
+    #[command(subcommand)]
+    action: BackupCommands,
+}
+
+/// Arguments given to the activity command.
+#[derive(Args,Debug,Clone)]
+struct ActivityArgs {
+    /// Only show the events since this date (2023-05-01)
+    /// or this duration (12h, 3d, 2w)
+    #[arg(short, long)]
+    since: Option<String>,
+}
+
+/// Arguments given to the history command.
+#[derive(Args,Debug,Clone)]
+struct HistoryArgs {
+    /// Number of commands to show
+    #[arg(short = 'n', long, default_value_t = 10)]
+    limit: usize,
+}
+
+/// Arguments given to the undo command.
+#[derive(Args,Debug,Clone)]
+struct UndoArgs {
+    /// Command to undo, by the id shown by the history command
+    /// (or a prefix of it). By default, the last one not undone.
+    id: Option<String>,
+}
+
+/// Arguments given to the review command.
+#[derive(Args,Debug,Clone)]
+struct ReviewArgs {
+    /// Review the documents scoring below this value (0-100)
+    #[arg(short, long, default_value_t = 100)]
+    threshold: u32,
+
+    /// Only print the review queue, without editing
+    #[arg(short, long)]
+    list: bool,
+}
+
+/// Arguments given to the merge command.
+#[derive(Args,Debug,Clone)]
+struct MergeArgs {
+    /// URI, checksum or title of the document to keep,
+    /// followed by its duplicates. Without documents,
+    /// lists the probable duplicates of the library.
+    uris: Vec<String>,
+}
+
+/// Arguments given to the reconvert command.
+#[derive(Args,Debug,Clone)]
+struct ReconvertArgs {
+    /// URI, checksum or title of the document to convert again
+    #[arg(short, long)]
+    uri: Option<String>,
+
+    /// Convert every document again, not only the ones
+    /// whose modified file is outdated
+    #[arg(short, long)]
+    all: bool,
+
+    /// Only convert the documents matching these filters
+    #[command(flatten)]
+    filter: list::DocumentFilter,
+}
+
+/// Arguments given to the linkmap command.
+#[derive(Args,Debug,Clone)]
+struct LinkmapArgs {
+    /// URI, checksum or title of a document of the library,
+    /// or path to a pdf file
+    uri: String,
+
+    /// Generate an html page with the pages and clickable overlays
+    #[arg(long)]
+    html: bool,
+
+    /// Output file (standard output by default)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+/// Arguments given to the heatmap command.
+#[derive(Args,Debug,Clone)]
+struct HeatmapArgs {
+    /// URI, checksum or title of a document of the library
+    uri: String,
+}
+
+/// Arguments given to the bench command.
+#[derive(Args,Debug,Clone)]
+struct BenchArgs {
+    /// Path to a pdf file
+    file: PathBuf,
+
+    /// Number of runs, the fastest one is kept
+    #[arg(short, long, default_value_t = 1)]
+    runs: usize,
+}
+
+/// Arguments given to the rename command.
+#[derive(Args,Debug,Clone)]
+struct RenameArgs {
+    /// Only print the new names
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+}
+
+/// Arguments given to the gc command.
+#[derive(Args,Debug,Clone)]
+struct GcArgs {
+    /// Only report what would be removed
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+
+    /// Delete the files without index entry instead
+    /// of moving them to the trash
+    #[arg(long)]
+    delete: bool,
+}
+
+/// Arguments given to the edit command.
+#[derive(Args,Debug,Clone)]
+struct EditArgs {
+    /// URI, checksum or title of the document to edit
+    #[arg(short, long)]
+    uri: String,
+}
+
+/// Arguments given to the find command.
+#[derive(Args,Debug,Serialize,Deserialize,Clone,Default)]
+struct FindArgs {
+    /// Only documents matching this query, e.g. `author:razborov
+    /// (tag:circuits OR title:~bounds?) NOT year:<2000`: terms
+    /// `author:`, `author-orcid:`, `title:`, `context:`, `abstract:`,
+    /// `keyword:`, `ident:`, `dest:`, `tag:`, `status:`, `type:`, `format:`, `year:`
+    /// (with >, >=, <, <=), `field:~regex`, free text, combined
+    /// with AND (implicit), OR, NOT and parentheses
+    #[serde(default, skip_serializing_if = "Vec::is_empty", with = "query_words")]
+    query: Vec<String>,
+
+    /// Print the documents in this format, instead of
+    /// the paths of their files (for rofi, fzf…)
+    #[arg(long, value_enum, conflicts_with = "template")]
+    format: Option<list::ListFormat>,
+
+    /// Print each document with this template, e.g. `{title} —
+    /// {authors} ({year})\t{mod_path}`, with the placeholders {title},
+    /// {authors}, {first_author}, {year}, {venue}, {context}, {tags},
+    /// {keywords}, {identifiers}, {status}, {type}, {id}, {checksum},
+    /// {filename}, {mod_path} and {raw_path}
+    #[arg(long)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    template: Option<String>,
+
+    /// End each document with a null character instead
+    /// of a newline (for `fzf --read0`, `xargs -0`…)
+    #[arg(long, conflicts_with = "format")]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    print0: bool,
+
+    /// Only documents with this tag
+    #[arg(long)]
+    tag: Option<String>,
+
+    /// Only documents with this reading status
+    #[arg(long, value_enum)]
+    status: Option<reading::ReadingStatus>,
+
+    /// Only documents of this type
+    #[arg(long = "type", value_enum)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    doc_type: Option<doctype::DocType>,
+
+    /// Only documents of this collection
+    #[arg(long)]
+    collection: Option<String>,
+
+    /// Only the documents opened (or the last N opened),
+    /// the last opened first
+    #[arg(long, value_name = "N")]
+    #[serde(skip)]
+    recent: Option<Option<usize>>,
+}
+
+/// The words of a query, as a single string in the akl uris.
+mod query_words {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S : Serializer>(words : &[String], s : S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&words.join(" "))
+    }
+
+    pub fn deserialize<'de, D : Deserializer<'de>>(d : D) -> Result<Vec<String>, D::Error> {
+        Ok(String::deserialize(d)?.split_whitespace().map(String::from).collect())
+    }
+}
+
+/// A document found by the find command.
+struct Found {
+    doc    : Document,
+    /// Path to its modified file.
+    path   : PathBuf,
+    /// Path to its original file.
+    raw    : PathBuf,
+    /// When it was last opened.
+    opened : Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Arguments given to the pick command.
+#[derive(Args,Debug,Clone)]
+struct PickArgs {
+    /// Then pick one of the named destinations of the document
+    #[arg(long)]
+    two_stage: bool,
+
+    /// Only documents with this tag
+    #[arg(long)]
+    tag: Option<String>,
+
+    /// Picker command, reading the choices on its standard input
+    /// (overrides the configuration)
+    #[arg(long)]
+    picker: Option<String>,
+}
+
+/// Actions of the collection command.
+#[derive(Subcommand,Debug,Clone)]
+enum CollectionCommands {
+    /// Create a new collection
+    Create {
+        /// Name of the collection
+        name: String,
+
+        /// Query defining a smart collection (e.g. `author:martens year:>2020`)
+        #[arg(long)]
+        query: Option<String>,
+    },
+
+    /// Add documents to a collection
+    Add {
+        /// Name of the collection
+        name: String,
+
+        /// URIs, checksums or titles of the documents
+        #[arg(required = true)]
+        uris: Vec<String>,
+    },
+
+    /// Remove documents from a collection
+    Rm {
+        /// Name of the collection
+        name: String,
+
+        /// URIs, checksums or titles of the documents
+        #[arg(required = true)]
+        uris: Vec<String>,
+    },
+
+    /// Delete a collection (its documents stay in the library)
+    Delete {
+        /// Name of the collection
+        name: String,
+    },
+
+    /// List the collections
+    List,
+}
+
+/// Arguments given to the collection command.
+#[derive(Args,Debug,Clone)]
+struct CollectionArgs {
+    #[command(subcommand)]
+    action: CollectionCommands,
+}
+
+/// Actions of the search command.
+#[derive(Subcommand,Debug,Clone)]
+enum SearchCommands {
+    /// Save a query of the find command under a name
+    Save {
+        name: String,
+
+        /// The query, e.g. "author:razborov tag:circuits"
+        query: String,
+    },
+
+    /// Run a saved search
+    Run {
+        name: String,
+
+        /// Print the documents in this format, instead of
+        /// the paths of their files (for rofi, fzf…)
+        #[arg(long, value_enum, conflicts_with = "template")]
+        format: Option<list::ListFormat>,
+
+        /// Print each document with this template
+        /// (see `akl find --help`)
+        #[arg(long)]
+        template: Option<String>,
+
+        /// End each document with a null character
+        /// instead of a newline
+        #[arg(long, conflicts_with = "format")]
+        print0: bool,
+    },
+
+    /// List the saved searches
+    List,
+
+    /// Delete a saved search
+    Delete {
+        name: String,
+    },
+}
+
+/// Arguments given to the search command.
+#[derive(Args,Debug,Clone)]
+struct SearchArgs {
+    #[command(subcommand)]
+    action: SearchCommands,
+}
+
+/// Actions of the authors command.
+#[derive(Subcommand,Debug,Clone)]
+enum AuthorsCommands {
+    /// List the authors of the registry, with their aliases
+    List,
+
+    /// Add an author to the registry
+    Add {
+        /// Canonical name of the author
+        name: String,
+
+        /// Other spellings of the name
+        #[arg(long)]
+        alias: Vec<String>,
+
+        /// ORCID of the author
+        #[arg(long)]
+        orcid: Option<String>,
+    },
+
+    /// Add another spelling of the name of an author
+    Alias {
+        name: String,
+        alias: String,
+    },
+
+    /// Merge names into an author, and rewrite the
+    /// authors of the documents of the library
+    Merge {
+        /// Canonical name of the author
+        into: String,
+
+        /// Names (or registered authors) to merge
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+
+    /// List the names of the library that may be
+    /// the same person
+    Suggest,
+
+    /// Remove an author from the registry
+    Remove {
+        name: String,
+    },
+}
+
+/// Arguments given to the authors command.
+#[derive(Args,Debug,Clone)]
+struct AuthorsArgs {
+    #[command(subcommand)]
+    action: AuthorsCommands,
+}
+
+/// Actions of the tag command.
+#[derive(Subcommand,Debug,Clone)]
+enum TagCommands {
+    /// Add tags to a document
+    Add {
+        /// URI, checksum or title of the document
+        uri: String,
+
+        /// Tags to add
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+
+    /// Remove tags from a document
+    Rm {
+        /// URI, checksum or title of the document
+        uri: String,
+
+        /// Tags to remove
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+
+    /// List the tags of a document, or of the whole library
+    List {
+        /// URI, checksum or title of the document
+        uri: Option<String>,
+    },
+}
+
+/// Arguments given to the tag command.
+#[derive(Args,Debug,Clone)]
+struct TagArgs {
+    #[command(subcommand)]
+    action: TagCommands,
+}
+
+/// Actions of the status command.
+#[derive(Subcommand,Debug,Clone)]
+enum StatusCommands {
+    /// Set the reading status of a document
+    Set {
+        /// URI, checksum or title of the document
+        uri: String,
+
+        #[arg(value_enum)]
+        status: reading::ReadingStatus,
+    },
+
+    /// Take a document off the reading list
+    Clear {
+        /// URI, checksum or title of the document
+        uri: String,
+    },
+
+    /// List the reading list, by status
+    List {
+        /// Only the documents with this status
+        #[arg(value_enum)]
+        status: Option<reading::ReadingStatus>,
+    },
+}
+
+/// Arguments given to the status command.
+#[derive(Args,Debug,Clone)]
+struct StatusArgs {
+    #[command(subcommand)]
+    action: StatusCommands,
+}
+
+/// Arguments given to the resolve command.
+#[derive(Args,Debug,Serialize,Deserialize,Clone)]
+struct ResolveArgs {
+    /// URI to the document
+    #[arg(short, long)]
+    uri: String,
+}
+
+
+/// Arguments given to the convert command.
+/// The URI must be a valid filepath to a pdf document.
+///
+/// This command typically is used when opening
+/// a "working document".
+///
+/// TODO: also allow urls to be downloaded?
+#[derive(Args,Debug,Serialize,Deserialize,Clone)]
+struct ConvertArgs {
+    /// URI to the document
+    #[arg(short, long)]
+    uri: String,
+
+    /// Output file name
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+
+/// A document in the library.
+#[derive(Serialize, Deserialize,Clone,Debug)]
+struct Document {
+    /// The SHA256 checksum of the original document
+    /// seen as a string
+    checksum : String,
+
+    /// Short immutable id of the document, derived from
+    /// its checksum (see `identifiers::short_id`).
+    /// Documents imported before ids existed have none,
+    /// and use the default derived id.
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    id : String,
+
+    /// The filename of the document on the system.
+    filename : String,
+
+    /// Strings that identify this document. Typically
+    /// a download URI, but it can also be a DOI or an Arxiv Link.
+    ///
+    /// a. Non empty vector
+    /// b. Sorted by the configured identifier priority,
+    ///    by default (DOI > Arxiv > URL > filepath)
+    identifiers : Vec<String>,
+
+    /// Understandable name of the document
+    /// usually the title of a paper or a blog post.
+    title : String,
+
+    /// Authors of the document.
+    authors : Vec<String>,
+
+    /// ORCIDs of the authors, by author, when
+    /// the sources of the metadata give them.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    orcids : BTreeMap<String, String>,
+
+    /// Publication year of the document.
+    year : u32,
+
+    /// Additional context.
+    /// Typically a conference name, a website name, or
+    /// a working group.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    context : Vec<String>,
+
+    /// Pages of the document in its venue (`123-145`).
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pages : Option<String>,
+
+    /// Named destinations of the document.
+    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
+    destinations : HashMap<String,Vec<String>>,
+
+    /// Tags given by the user.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    tags : Vec<String>,
+
+    /// Abstract of the document.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    r#abstract : Option<String>,
+
+    /// Keywords of the document, from its metadata
+    /// (unlike the tags, given by the user).
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    keywords : Vec<String>,
+
+    /// Identifier used in the links of the modified file.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    linked_as : Option<String>,
+
+    /// SHA256 hash of the original file as stored in the library.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    raw_checksum : Option<String>,
+
+    /// Previous filenames of the document, so that links to
+    /// the files keep working after a rename.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    former_filenames : Vec<String>,
+
+    /// Version of the conversion that wrote the modified file
+    /// (see `CONVERSION_VERSION`).
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    converted_with : Option<u32>,
+
+    /// Reading status, for the documents on the reading list.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    status : Option<reading::ReadingStatus>,
+
+    /// Type of the document (article, thesis, slides…).
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    doc_type : Option<doctype::DocType>,
+
+    /// Number of citations of the document, according to
+    /// Semantic Scholar when it was last asked.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    citations : Option<u32>,
+
+    /// The document has no pdf file yet (see `akl attach`), and
+    /// its checksum is the one of its doi or isbn (see `metadata_checksum`).
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    metadata_only : bool,
+
+    /// Format of the file of the document (see `format`):
+    /// only the pdf files have a modified copy with rewritten links.
+    #[serde(skip_serializing_if = "format::Format::is_pdf", default)]
+    format : format::Format,
+}
+
+/// Version of the conversion of the documents. It is increased
+/// when the modified files change (new kinds of links, markers,
+/// outlines…), so that `akl reconvert` can update the old ones.
+///
+/// 2: outlines added to the documents without bookmarks, and links
+///    of the named destinations and citations using the canonical
+///    identifier.
+const CONVERSION_VERSION : u32 = 2;
+
+
+/// The main application state.
+#[derive(Debug)]
+struct AppState {
+    /// File path to the directory containing
+    /// the index of available documents.
+    index_dir : PathBuf,
+
+    /// File path to the directory containing
+    /// the "raw" version of the documents. 
+    raw_path   : PathBuf,
+
+    /// File path to the directory containing
+    /// the "modified" version of the documents. 
+    mod_path   : PathBuf,
+
+    /// File path to the directory containing the rest of the
+    /// library (trash, event log, reports…).
+    data_path  : PathBuf,
+
+    /// Path to the logs.
+    log_path   : PathBuf,
+
+    /// Documents removed from the library.
+    trash : trash::Trash,
+
+    /// Log of the changes to the library.
+    events : events::EventLog,
+
+    /// Journal of the operations, to undo them.
+    journal : journal::Journal,
+
+    /// Reports of the imports.
+    reports : report::Reports,
+
+    /// Documents open in a viewer, and saved sessions.
+    sessions : session::Sessions,
+
+    /// Path to the cache directory
+    /// (answers of remote services).
+    cache_path : PathBuf,
+
+    /// File path to the config.yaml file.
+    config_path : PathBuf,
+
+    /// Content of the config.yaml file, parsed.
+    config : config::Config,
+
+    /// Desktop environment in which akl runs.
+    desktop : desktop::Desktop,
+
+    /// Catalog of available documents.
+    storage : Box<dyn storage::Storage>,
+
+    /// The global library, for a project library (see `project`).
+    global : Option<Box<AppState>>,
+}
+
+// COMMAND LINE INTERFACE //
+
+#[derive(Parser)]
+#[derive(Debug)]
+#[command(author, version, about, long_about = None)]
+#[command(propagate_version = true)]
+struct Cli {
+    /// Optional URI argument to execute.
+    execute_uri: Option<String>,
+
+    /// Turn debugging information on
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    debug: u8,
+
+    /// Interactive flag.
+    /// Uses a temporary file and the default editor to
+    /// allow the user to fill out metadata.
+    #[arg(short, long, default_value = "false")]
+    interactive: bool,
+
+    /// Headless flag.
+    /// Disables notifications, clipboard and viewers,
+    /// and prints everything on the standard output.
+    /// Automatically set when no display is available.
+    #[arg(long, default_value = "false")]
+    headless: bool,
+
+    /// Ignore the project library of the working directory
+    /// (a `.akl` directory, see `akl import --local`).
+    #[arg(long, default_value = "false")]
+    global: bool,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+
+#[derive(Subcommand)]
+#[derive(Debug,Clone)]
+enum Commands {
+    /// Cite a document (typically put a nice citation in the clipboard)
+    Cite(CiteArgs),
+
+    /// Build a "converted" document from a pdf, without storing
+    /// it in the library.
+    Convert(ConvertArgs),
+
+    /// Resolve a URI to a potential filepath in the library.
+    Resolve(ResolveArgs),
+
+    /// Open a pdf document using the appropriated viewer
+    /// on the system.
+    ///
+    /// (it turns out that the arguments are isomorphic to
+    /// the cite command for now)
+    View(CiteArgs),
+
+    /// Open a document, similar to resolve followed by View.
+    ///
+    /// (it turns out that the arguments are isomorphic to
+    /// the cite command for now)
+    Open(CiteArgs),
+
+    /// Find a document by searching current metadata.
+    ///
+    /// Currently only provides a list of the current pdfs
+    /// suitable to be used with ROFI/FZF/Dmenu.
+    Find(FindArgs),
+
+    /// Pick a document (and one of its named destinations)
+    /// with rofi or fzf, and print a link to it.
+    Pick(PickArgs),
+
+    /// List the documents of the library, possibly filtered,
+    /// in a human or machine-readable format.
+    List(ListArgs),
+
+    /// Export documents of the library as a BibTeX
+    /// or CSL-JSON bibliography.
+    Export(ExportArgs),
+
+    /// Imports a document into the library.
+    /// (does perform a conversion)
+    Import(ImportArgs),
+
+    /// Edit the metadata of a document of the library
+    /// using the editor from $EDITOR.
+    Edit(EditArgs),
+
+    /// Manage the tags of the documents.
+    Tag(TagArgs),
+
+    /// Manage the reading status of the documents
+    /// (to-read, reading, read, archived).
+    Status(StatusArgs),
+
+    /// Walk through the documents with incomplete metadata,
+    /// lowest quality score first.
+    Review(ReviewArgs),
+
+    /// Merge duplicate entries of the same document,
+    /// or list the probable duplicates.
+    Merge(MergeArgs),
+
+    /// Manage collections of documents.
+    Collection(CollectionArgs),
+
+    /// Save queries of the find command under a name,
+    /// and run them again.
+    Search(SearchArgs),
+
+    /// Manage the registry of the authors, mapping
+    /// the spellings of their names to a canonical name.
+    Authors(AuthorsArgs),
+
+    /// Remove a document from the library,
+    /// moving its files to the trash.
+    Remove(RemoveArgs),
+
+    /// Show everything known about a document.
+    Info(InfoArgs),
+
+    /// List the documents of the library that a document cites,
+    /// or that cite it, according to Semantic Scholar.
+    Related(RelatedArgs),
+
+    /// List the documents imported from arXiv of which
+    /// a newer version is out.
+    Outdated(OutdatedArgs),
+
+    /// Replace a document imported from arXiv by the latest
+    /// version of the paper, keeping its tags, reading status
+    /// and annotations, and archiving the previous file.
+    Update(UpdateArgs),
+
+    /// Attach a pdf file to a document imported without
+    /// one (see `akl import --metadata-only`).
+    Attach(AttachArgs),
+
+    /// Rebuild the outdated modified files (or all of them),
+    /// keeping the annotations made on the modified files.
+    Reconvert(ReconvertArgs),
+
+    /// Find the documents in DBLP and record their DBLP keys,
+    /// used as citation keys by the exports.
+    Dblp(DblpArgs),
+
+    /// Find the published versions of the preprints in DBLP and
+    /// Crossref, and record their dois and venues (e.g. from a
+    /// weekly cron job).
+    Published(PublishedArgs),
+
+    /// Fill the missing identifiers (doi of the arXiv papers, arxiv
+    /// id of the published ones), abstracts and venues of documents
+    /// from Semantic Scholar, arXiv, Crossref and DBLP.
+    Enrich(EnrichArgs),
+
+    /// Subscribe to the new papers of an arXiv category,
+    /// which `akl inbox fetch` brings to the inbox.
+    Subscribe(SubscribeArgs),
+
+    /// Follow the new preprints of an author,
+    /// which `akl inbox fetch` brings to the inbox.
+    Follow(FollowArgs),
+
+    /// Triage the new papers of the subscriptions:
+    /// fetch them, list them, import or drop them.
+    Inbox(InboxArgs),
+
+    /// List the anchors and external links of a document,
+    /// optionally as a browsable html page.
+    Linkmap(LinkmapArgs),
+
+    /// Name the destinations of a document (aliases, labels of
+    /// the source, destinations of previous versions), and share
+    /// these names with co-authors.
+    Anchors(AnchorsArgs),
+
+    /// Show which pages of a document are cited
+    /// and reached through links, as a histogram.
+    Heatmap(HeatmapArgs),
+
+    /// Time the passes of the conversion of a pdf file,
+    /// with the memory they allocate.
+    Bench(BenchArgs),
+
+    /// Rename the files of the documents according to
+    /// the filename template of the configuration.
+    Rename(RenameArgs),
+
+    /// Check that the files of the documents exist, are not
+    /// corrupted, and match the checksums of the index. Moves
+    /// the original files of older libraries to the store.
+    Verify,
+
+    /// Move the files without index entry and the index entries
+    /// without file to the trash, and remove the stale temporary files.
+    Gc(GcArgs),
+
+    /// Manage the documents removed from the library.
+    Trash(TrashArgs),
+
+    /// Save the documents currently open as a named session,
+    /// and reopen them later.
+    Session(SessionArgs),
+
+    /// Save the library to an archive, or restore it
+    /// (e.g. on another machine).
+    Backup(BackupArgs),
+
+    /// Review the recent changes to the library.
+    Activity(ActivityArgs),
+
+    /// List the last commands that changed the library,
+    /// with their operations.
+    History(HistoryArgs),
+
+    /// Revert the changes of a command (by default the last one):
+    /// imports, removals, edits, renames and conversions.
+    Undo(UndoArgs),
+
+    /// Show the git history of the index (see git_history
+    /// in the configuration).
+    Log(LogArgs),
+
+    /// Bring the index back to a commit of its git history.
+    /// The files of the documents are left untouched.
+    RevertTo(RevertToArgs),
+
+    /// Manage the passwords used to download and decrypt documents.
+    Credentials(CredentialsArgs),
+
+    /// Synchronize the index and the documents with a remote
+    /// (WebDAV, S3 or rclone, see sync in the configuration).
+    Sync(SyncArgs),
+
+    /// Move the index of the library to another storage backend.
+    Migrate(MigrateArgs),
+
+    /// Move the index, the documents or the logs to other
+    /// directories (e.g. an external drive or a synced folder).
+    Relocate(RelocateArgs),
+
+    /// Build the browse views: symbolic links to the documents
+    /// by year, by author and by tag.
+    Browse(BrowseArgs),
+
+    /// Run a persistent process executing akl uris,
+    /// one per line, keeping the library loaded.
+    Handler(HandlerArgs),
+}
+
+#[derive(Debug,Clone)]
+#[allow(clippy::upper_case_acronyms)]
+enum ParsedURI {
+    HttpURL (String),
+    DOI (String),
+    Arxiv { arxiv_id : String, arxiv_version : String },
+    Isbn (String),
+    Rxiv (rxiv::Preprint),
+    Pubmed (pubmed::Id),
+    Hal { hal_id : String, hal_version : Option<String> },
+    Iacr { iacr_id : String, iacr_revision : Option<String> },
+    Eccc { eccc_id : String, eccc_revision : Option<String> },
+    Provider (providers::Match),
+    Plugin { program : PathBuf, uri : String },
+    AklCommand (Commands),
+    FilePath (PathBuf),
+}
+
+/// Serialize from a command to a suitable uri
+/// of the form `akl://command-name/?query-params`.
+fn command_to_query(cmd : Commands) -> Result<String> {
+    match cmd {
+        Commands::Cite(a) => {
+            let name = "cite-document";
+            let params = serde_urlencoded::to_string(a)?;
+            Ok(format!("akl://{name}/?{params}"))
+        }
+        Commands::Convert(a) => {
+            let name = "convert-document";
+            let params = serde_urlencoded::to_string(a)?;
+            Ok(format!("akl://{name}/?{params}"))
+        }
+        Commands::View(a) => {
+            let name = "view-document";
+            let params = serde_urlencoded::to_string(a)?;
+            Ok(format!("akl://{name}/?{params}"))
+        }
+        Commands::Open(a) => {
+            let name = "open-document";
+            let params = serde_urlencoded::to_string(a)?;
+            Ok(format!("akl://{name}/?{params}"))
+        }
+        Commands::Resolve(a) => {
+            let name = "resolve-document";
+            let params = serde_urlencoded::to_string(a)?;
+            Ok(format!("akl://{name}/?{params}"))
+        }
+        Commands::Import(a) => {
+            let name = "import-document";
+            let params = serde_urlencoded::to_string(a)?;
+            Ok(format!("akl://{name}/?{params}"))
+        }
+        Commands::Find(a) => {
+            let name = "find-document";
+            let params = serde_urlencoded::to_string(a)?;
+            Ok(format!("akl://{name}/?{params}"))
+        }
+        Commands::List(_) => {
+            anyhow::bail!("The library cannot be listed through an akl uri")
+        }
+        Commands::Export(_) => {
+            anyhow::bail!("The library cannot be exported through an akl uri")
+        }
+        Commands::Pick(_) => {
+            anyhow::bail!("Documents cannot be picked through an akl uri")
+        }
+        Commands::Search(_) => {
+            anyhow::bail!("Saved searches cannot be run through an akl uri")
+        }
+        Commands::Subscribe(_) | Commands::Follow(_) | Commands::Inbox(_) => {
+            anyhow::bail!("The inbox cannot be managed through an akl uri")
+        }
+        Commands::Authors(_) => {
+            anyhow::bail!("The authors cannot be managed through an akl uri")
+        }
+        Commands::Activity(_) => {
+            anyhow::bail!("The activity cannot be shown through an akl uri")
+        }
+        Commands::History(_) | Commands::Undo(_) | Commands::Log(_) | Commands::RevertTo(_) => {
+            anyhow::bail!("The journal cannot be used through an akl uri")
+        }
+        Commands::Info(_) | Commands::Related(_) | Commands::Outdated(_) => {
+            anyhow::bail!("Documents cannot be inspected through an akl uri")
+        }
+        Commands::Edit(_) | Commands::Tag(_) | Commands::Status(_) | Commands::Collection(_) | Commands::Review(_) | Commands::Merge(_) => {
+            anyhow::bail!("Documents cannot be edited through an akl uri")
+        }
+        Commands::Remove(_) | Commands::Trash(_) | Commands::Gc(_) => {
+            anyhow::bail!("Documents cannot be removed through an akl uri")
+        }
+        Commands::Credentials(_) => {
+            anyhow::bail!("Credentials cannot be managed through an akl uri")
+        }
+        Commands::Sync(_) => {
+            anyhow::bail!("The library cannot be synchronized through an akl uri")
+        }
+        Commands::Backup(_) => {
+            anyhow::bail!("Backups cannot be made through an akl uri")
+        }
+        Commands::Session(_) => {
+            anyhow::bail!("Sessions cannot be managed through an akl uri")
+        }
+        Commands::Migrate(_) | Commands::Relocate(_) | Commands::Browse(_) | Commands::Reconvert(_) | Commands::Verify => {
+            anyhow::bail!("The library cannot be migrated through an akl uri")
+        }
+        Commands::Rename(_) => {
+            anyhow::bail!("The files of the library cannot be renamed through an akl uri")
+        }
+        Commands::Anchors(_) | Commands::Dblp(_) | Commands::Published(_) | Commands::Enrich(_) | Commands::Attach(_) | Commands::Update(_) => {
+            anyhow::bail!("Documents cannot be edited through an akl uri")
+        }
+        Commands::Linkmap(_) | Commands::Heatmap(_) => {
+            anyhow::bail!("Link maps cannot be generated through an akl uri")
+        }
+        Commands::Bench(_) => {
+            anyhow::bail!("Benchmarks cannot be run through an akl uri")
+        }
+        Commands::Handler(_) => {
+            anyhow::bail!("The handler cannot be started through an akl uri")
+        }
+    }
+}
+
+/// Converts from a query string and command name
+/// to a parsed command result.
+fn query_to_command(name : &str, query : &str) -> Result<Commands> {
+    match name {
+        "import-document" => {
+            let mut keys = serde_urlencoded::from_str::<HashMap<String,String>>(query)
+                .context("Decoding the import url")?;
+
+            let payload = keys.remove("payload")
+                .context("Searching for the payload of import args")?;
+
+            let import_args = serde_json::from_str(&payload)
+                .context("Parsing the payload of the import args")?;
+            Ok(Commands::Import(import_args))
+        }
+        "cite-document" => {
+            Ok(Commands::Cite(serde_urlencoded::from_str(query)?))
+        }
+        "view-document" => {
+            Ok(Commands::View(serde_urlencoded::from_str(query)?))
+        }
+        "open-document" => {
+            Ok(Commands::Open(serde_urlencoded::from_str(query)?))
+        }
+        "resolve-document" => {
+            Ok(Commands::Resolve(serde_urlencoded::from_str(query)?))
+        }
+        "convert-document" => {
+            Ok(Commands::Convert(serde_urlencoded::from_str(query)?))
+        }
+        "find-document" => {
+            Ok(Commands::Find(serde_urlencoded::from_str(query)?))
+        }
+        _ => {
+            anyhow::bail!("Invalid command name {name}")
+        }
+    }
+
+}
+
+/// Arxiv papers, as `arxiv:…` or the url of a paper (the first
+/// version by default). Other pages of arxiv.org (listings,
+/// searches) are plain urls.
+fn parse_arxiv (url : Url) -> Result<ParsedURI> {
+    let paper = match url.scheme() {
+        "arxiv" => arxiv::parse_id(url.path())
+            .with_context(|| format!("{} is not an arxiv id", url.path()))?,
+        _ => match arxiv::from_url(&url) {
+            Some(paper) => paper,
+            None => return Ok(ParsedURI::HttpURL(url.into())),
+        },
+    };
+    Ok(ParsedURI::Arxiv { arxiv_id: paper.id, arxiv_version: paper.version.unwrap_or_else(|| "1".into()) })
+}
+
+fn parse_doi(url : Url) -> Result<ParsedURI> {
+    let doi = url.path();
+    let doi = doi.strip_prefix('/').unwrap_or(doi);
+    // the preprints of bioRxiv and medRxiv have their own provider
+    if rxiv::is_preprint_doi(doi) {
+        return Ok(ParsedURI::Rxiv(rxiv::Preprint { server: None, doi: doi.into(), version: None }));
+    }
+    Ok(ParsedURI::DOI(doi.into()))
+}
+
+/// Preprints of bioRxiv and medRxiv, as `biorxiv:…`, `medrxiv:…`
+/// or their urls. Other urls of the servers are plain urls.
+fn parse_rxiv(url : Url) -> Result<ParsedURI> {
+    let preprint = match url.scheme() {
+        "http" | "https" => match rxiv::from_url(&url) {
+            Some(preprint) => preprint,
+            None => return Ok(ParsedURI::HttpURL(url.into())),
+        },
+        server => rxiv::parse_id(server, url.path())
+            .with_context(|| format!("{} is not a {server} preprint", url.path()))?,
+    };
+    Ok(ParsedURI::Rxiv(preprint))
+}
+
+/// Articles of PubMed and PMC, as `pubmed:…`, `pmc:…` or their
+/// urls. Other urls of NCBI are plain urls.
+fn parse_pubmed(url : Url) -> Result<ParsedURI> {
+    let id = match url.scheme() {
+        "http" | "https" => match pubmed::from_url(&url) {
+            Some(id) => id,
+            None => return Ok(ParsedURI::HttpURL(url.into())),
+        },
+        scheme => pubmed::Id::parse(scheme, url.path())
+            .with_context(|| format!("{} is not a {scheme} id", url.path()))?,
+    };
+    Ok(ParsedURI::Pubmed(id))
+}
+
+/// Isbns, as `isbn:…` or `https://openlibrary.org/isbn/…`.
+fn parse_isbn(url : Url) -> Result<ParsedURI> {
+    let isbn = url.path().trim_start_matches("/isbn/");
+    let isbn = identifiers::normalize_isbn(isbn)
+        .with_context(|| format!("{isbn} is not a valid isbn"))?;
+    Ok(ParsedURI::Isbn(isbn))
+}
+
+/// HAL deposits, as `hal:…` or the url of a deposit. Other
+/// urls of HAL (searches, pages of authors) are plain urls.
+fn parse_hal(url : Url) -> Result<ParsedURI> {
+    let deposit = match url.scheme() {
+        "hal" => hal::parse_id(url.path())
+            .with_context(|| format!("{} is not a HAL id", url.path()))?,
+        _ => match hal::from_url(&url) {
+            Some(deposit) => deposit,
+            None => return Ok(ParsedURI::HttpURL(url.into())),
+        },
+    };
+    let (hal_id, hal_version) = deposit;
+    Ok(ParsedURI::Hal { hal_id, hal_version })
+}
+
+/// IACR ePrint reports, as `iacr:…` or the url of a report.
+/// Other urls of the archive are plain urls.
+fn parse_iacr(url : Url) -> Result<ParsedURI> {
+    let report = match url.scheme() {
+        "iacr" => iacr::parse_id(url.path())
+            .with_context(|| format!("{} is not an ePrint id", url.path()))?,
+        _ => match iacr::from_url(&url) {
+            Some(report) => report,
+            None => return Ok(ParsedURI::HttpURL(url.into())),
+        },
+    };
+    let (iacr_id, iacr_revision) = report;
+    Ok(ParsedURI::Iacr { iacr_id, iacr_revision })
+}
+
+/// ECCC reports, as `eccc:…` or the url of a report.
+/// Other urls of the colloquium are plain urls.
+fn parse_eccc(url : Url) -> Result<ParsedURI> {
+    let report = match url.scheme() {
+        "eccc" => eccc::parse_id(url.path())
+            .with_context(|| format!("{} is not an ECCC id", url.path()))?,
+        _ => match eccc::from_url(&url) {
+            Some(report) => report,
+            None => return Ok(ParsedURI::HttpURL(url.into())),
+        },
+    };
+    let (eccc_id, eccc_revision) = report;
+    Ok(ParsedURI::Eccc { eccc_id, eccc_revision })
+}
+
+/// URI parser: the providers of the configuration,
+/// the provider programs, then the built-in providers.
+fn uri_dispatch(uri : &str, rules : &[providers::ProviderRule]) -> Result<ParsedURI> {
+    if let Some(found) = providers::resolve(rules, uri)? {
+        return Ok(ParsedURI::Provider(found));
+    }
+    let nice_url = Url::parse(uri)
+        .context("URL parsing")?;
+    if let Some(program) = plugins::find(&nice_url) {
+        log::debug!("The program {program:?} provides {uri}");
+        return Ok(ParsedURI::Plugin { program, uri: uri.into() });
+    }
+
+    match nice_url.scheme()  {
+        "https" | "http" => {
+            match nice_url.host_str() {
+                Some("arxiv.org" | "www.arxiv.org" | "export.arxiv.org") => {
+                    parse_arxiv(nice_url)
+                }
+                Some("doi.org") | Some("dx.doi.org") => {
+                    parse_doi(nice_url)
+                }
+                Some("openlibrary.org") if nice_url.path().starts_with("/isbn/") => {
+                    parse_isbn(nice_url)
+                }
+                Some("biorxiv.org" | "www.biorxiv.org" | "medrxiv.org" | "www.medrxiv.org") => {
+                    parse_rxiv(nice_url)
+                }
+                Some("pubmed.ncbi.nlm.nih.gov" | "pmc.ncbi.nlm.nih.gov" | "www.ncbi.nlm.nih.gov") => {
+                    parse_pubmed(nice_url)
+                }
+                Some(host) if hal::is_host(host) => {
+                    parse_hal(nice_url)
+                }
+                Some("eprint.iacr.org") => {
+                    parse_iacr(nice_url)
+                }
+                Some("eccc.weizmann.ac.il") => {
+                    parse_eccc(nice_url)
+                }
+                _ => {
+                    Ok(ParsedURI::HttpURL(uri.into()))
+                }
+            }
+        }
+        "arxiv" => {
+            parse_arxiv(nice_url)
+        }
+        "doi" => {
+            parse_doi(nice_url)
+        }
+        "isbn" => {
+            parse_isbn(nice_url)
+        }
+        "biorxiv" | "medrxiv" => {
+            parse_rxiv(nice_url)
+        }
+        "pubmed" | "pmc" => {
+            parse_pubmed(nice_url)
+        }
+        "hal" => {
+            parse_hal(nice_url)
+        }
+        "iacr" => {
+            parse_iacr(nice_url)
+        }
+        "eccc" => {
+            parse_eccc(nice_url)
+        }
+        "akl" => {
+            let name = nice_url.host_str()
+                               .unwrap_or("");
+            let query = nice_url.query().unwrap_or("");
+            Ok(ParsedURI::AklCommand(query_to_command(name, query)?))
+        }
+        x => {
+            log::info!("No provider attached to scheme {x}");
+            anyhow::bail!("No provider attached to scheme {x}")
+        }
+    }
+}
+
+/// Process URI or a filepath
+fn uri_or_filepath_dispatch (uri : &str, rules : &[providers::ProviderRule]) -> Result<ParsedURI> {
+    match uri_dispatch (uri, rules) {
+        Ok(r) => { Ok(r) }
+        Err(e) => {
+            let s : String = uri.into();
+            let p = PathBuf::from(s);
+            if p.exists() {
+                Ok(ParsedURI::FilePath(p))
+            } else {
+                log::error!("Error when parsing the uri {e:?}");
+                log::error!("The url {uri} is neither a valid scheme nor a path on the system");
+                anyhow::bail!("I don't know how to handle {uri}")
+            }
+        }
+    }
+}
+
+
+
+impl Document {
+    /// The short id of the document.
+    fn short_id(&self) -> String {
+        if self.id.is_empty() {
+            identifiers::short_id(&self.checksum, identifiers::SHORT_ID_LEN)
+        } else {
+            self.id.clone()
+        }
+    }
+
+    /// Adds tags to the document, without duplicates.
+    fn add_tags(&mut self, tags : &[String]) {
+        for t in tags {
+            let t = t.trim();
+            if !t.is_empty() && !self.tags.iter().any(|x| x == t) {
+                self.tags.push(t.to_string());
+            }
+        }
+        self.tags.sort();
+    }
+
+    /// Checks that the document has the minimal
+    /// metadata needed to be part of the library.
+    fn validate(&self) -> Result<()> {
+        if self.title.trim().is_empty() {
+            anyhow::bail!("The title cannot be empty");
+        }
+        if self.identifiers.is_empty() {
+            anyhow::bail!("A document needs at least one identifier");
+        }
+        Ok(())
+    }
+
+    /// Document name generation, from the filename template
+    /// of the configuration (by default `authors year title hash`,
+    /// the historical names).
+    ///
+    /// Words are lowercase and dash separated, to simplify
+    /// exploration using fzf, find or other tools.
+    /// Accented letters are transliterated to plain ASCII, and the
+    /// other templates remove the math and stop words of the title.
+    fn generate_name(&self, config : &config::Config) -> Result<String> {
+        naming::render(config, self)
+    }
+}
+
+#[derive(Serialize,Deserialize,Debug,Clone)]
+struct PageArgs {
+    page: Option<u32>,
+    dest: Option<String>,
+}
+fn get_page_number(uri : &str, args : &mut CiteArgs) -> Result<()>{
+    let url = Url::parse(uri).context("Parsing URL inside document")?;
+    let que = url.query().context("No query to parse")?;
+    let PageArgs { page, dest } : PageArgs = serde_urlencoded::from_str(que).context("Parsing URL query")?;
+    args.page = page;
+    args.dest = dest;
+    Ok(())
+}
+
+
+fn update_document_links(pdoc : &mut pdflib::PdfDocument, ident: Option<String>) {
+    // TODO: allow an optional argument
+    // to set a "from" path!
+    // TODO forward the dest and page from
+    // the link to the citation command
+    pdoc.update_links(&|e| {
+        let mut args = CiteArgs { uri: e.clone(),
+                                  dest: None,
+                                  page: None,
+                                  from: ident.clone(),
+                                  resume: false,
+        };
+        get_page_number(&e, &mut args).unwrap_or(());
+        command_to_query(Commands::Open(args)).unwrap_or(e)
+    }).unwrap();
+
+}
+
+fn update_document_dests(id : &str, pdoc : &mut pdflib::PdfDocument) {
+    pdoc.add_destinations_links(|e : pdflib::NamedDestination| {
+        command_to_query(Commands::Cite(CiteArgs {
+            uri: id.into(),
+            dest: Some(e.name),
+            page: Some(e.page_num),
+            from: None,
+            resume: false,
+        })).unwrap_or("".into())
+    }).unwrap();
+}
+
+/// Adds bookmarks to documents without any, so that
+/// the converted copy is easier to navigate.
+/// Returns whether an outline was added.
+fn add_document_outline(pdoc : &mut pdflib::PdfDocument) -> bool {
+    match pdoc.add_outline() {
+        Ok(true) => { log::info!("Added an outline to the document"); true }
+        Ok(false) => false,
+        Err(e) => { log::warn!("Could not add an outline to the document: {e:?}"); false }
+    }
+}
+
+/// Parses a pdf document from a file (read from `uri`, with the http
+/// status `status` for downloaded files). Encrypted documents are
+/// decrypted using the password configured for their uri.
+fn parse_pdf_file(uri : &str, path : &Path, status : Option<reqwest::StatusCode>, config : &config::Config) -> Result<pdflib::PdfDocument> {
+    let head = filetype::head(path).with_context(|| format!("Reading {path:?}"))?;
+    if filetype::sniff(&head) != filetype::FileKind::Pdf {
+        // not a pdf file, read to explain what it is
+        let bytes = std::fs::read(path).with_context(|| format!("Reading {path:?}"))?;
+        filetype::ensure_pdf(uri, &bytes, status)?;
+    }
+    let mut pdf = lopdf::Document::load(path)
+        .context("parsing the pdf document using lopdf")?;
+
+    if pdf.is_encrypted() {
+        log::debug!("Pdf Document is encrypted");
+        let entry = config.password_for(uri)
+            .with_context(|| format!("The document {uri} is encrypted, but no password is configured for it"))?;
+        let password = secrets::get(&entry.keyring)?;
+        let bytes = std::fs::read(path).with_context(|| format!("Reading {path:?}"))?;
+        let clear = secrets::decrypt_pdf(&bytes, &password)
+            .context("decrypting the pdf document")?;
+        pdf = lopdf::Document::load_mem(&clear)
+            .context("parsing the decrypted pdf document using lopdf")?;
+    }
+
+    log::debug!("Pdf Document parsed !");
+
+    let doc = pdflib::PdfDocument::try_from(pdf)
+        .context("turning the parsed pdf into a fully fledged document")?;
+
+    log::debug!("Pdf Document explored !");
+
+    Ok(doc)
+}
+
+/// Downloads a url to a temporary file of the download directory,
+/// giving the answer and the file (see `download` for the retries
+/// and the maximal size, and `proxy` for the proxies).
+fn fetch(url : &str, config : &config::Config) -> Result<(download::Answer, tempfile::NamedTempFile)> {
+    log::debug!("Loading document from {url}");
+    let url = &proxy::rewrite(&config.institutional_access, url);
+    let client = download::client(config.download_timeout, proxy::proxy_for(&config.proxies, url)?)?;
+    let mut up = Url::parse(url)?;
+    up.set_query(None);
+    let orig = up.to_string();
+    log::debug!("Using {orig} as an origin");
+    let password = match up.host_str().and_then(|h| config.credential_for(h)) {
+        Some(cred) => {
+            log::debug!("Using the credentials of {} for {}", cred.username, cred.host);
+            Some((cred.username.clone(), secrets::get(&cred.keyring)?))
+        }
+        None => None,
+    };
+    let cookie = cookies::header(&config.cookies, url)
+        .and_then(|c| reqwest::header::HeaderValue::from_str(&c).ok())
+        .map(|mut c| { c.set_sensitive(true); c });
+    let request = || {
+        let request = client.get(url)
+          .header(reqwest::header::USER_AGENT, 
+                  "Rust")
+          .header(reqwest::header::ACCEPT, "*/*")
+          .header(reqwest::header::ACCEPT_ENCODING,
+                  "Accept-Encoding: gzip, deflate, br")
+          .header(reqwest::header::ACCEPT_LANGUAGE,
+                  "fr,fr-FR;q=0.8,en-US;q=0.5,en;q=0.3")
+          .header(reqwest::header::REFERER, &orig)
+          .header(reqwest::header::CONNECTION, "keep-alive")
+          .header(reqwest::header::DNT, "1")
+          .header(reqwest::header::ORIGIN, &orig);
+        let request = match &cookie {
+            Some(cookie) => request.header(reqwest::header::COOKIE, cookie.clone()),
+            None => request,
+        };
+        match &password {
+            Some((username, password)) => request.basic_auth(username, Some(password)),
+            None => request,
+        }
+    };
+
+    let dir = config.download_dir.clone().unwrap_or_else(std::env::temp_dir);
+    std::fs::create_dir_all(&dir).with_context(|| format!("Creating {dir:?}"))?;
+    let mut file = tempfile::Builder::new().suffix(".part").tempfile_in(&dir)
+        .context("Creating a temporary file for the download")?;
+    let answer = download::fetch(url, &request, config.download_retries, file.as_file_mut(),
+                                 config.max_download_size.saturating_mul(1_000_000))?;
+    log::debug!("Pdf Document downloaded !");
+    Ok((answer, file))
+}
+
+/// Delay before downloading again a pdf file being generated.
+const GENERATION_DELAY : std::time::Duration = std::time::Duration::from_secs(20);
+
+/// Downloads a url, again while the server answers that the pdf
+/// file is being generated (see `filetype::ErrorPage`).
+fn fetch_document(url : &str, config : &config::Config) -> Result<(download::Answer, tempfile::NamedTempFile, filetype::FileKind)> {
+    let mut tries = 0;
+    loop {
+        let (answer, file) = fetch(url, config)?;
+        let head = filetype::head(file.path()).context("Reading the downloaded file")?;
+        let kind = filetype::sniff(&head);
+        if kind == filetype::FileKind::Html && tries < config.download_retries {
+            let bytes = std::fs::read(file.path()).context("Reading the downloaded file")?;
+            if filetype::error_page(&bytes) == Some(filetype::ErrorPage::Generating) {
+                log::warn!("{url} is being generated, trying again in {}s", GENERATION_DELAY.as_secs());
+                std::thread::sleep(GENERATION_DELAY);
+                tries += 1;
+                continue;
+            }
+        }
+        return Ok((answer, file, kind));
+    }
+}
+
+/// Parses a downloaded pdf file. When it is not one, or cannot be
+/// parsed, the answer is kept in `failed/` of the download directory
+/// for debugging, and the error says where.
+fn parse_download(url : &str, answer : &download::Answer, file : tempfile::NamedTempFile, kind : filetype::FileKind, config : &config::Config) -> Result<pdflib::PdfDocument> {
+    let announced = answer.content_type.as_deref().unwrap_or("no type");
+    if kind == filetype::FileKind::Pdf && !announced.contains("pdf") {
+        log::debug!("{url} sent a pdf file announced as {announced}");
+    }
+    let error = match parse_pdf_file(url, file.path(), Some(answer.status), config) {
+        Ok(pdf) => return Ok(pdf),
+        Err(e) => e,
+    };
+    let error = if announced.contains("pdf") && kind != filetype::FileKind::Pdf {
+        error.context(format!("{url} was announced as a pdf file ({announced}), but is {kind}"))
+    } else {
+        error
+    };
+    let host = Url::parse(url).ok().and_then(|u| u.host_str().map(String::from)).unwrap_or_default();
+    let extension = match kind {
+        filetype::FileKind::Pdf => "pdf",
+        filetype::FileKind::Html => "html",
+        _ => "bin",
+    };
+    let dir = file.path().parent().map(|d| d.join("failed")).unwrap_or_default();
+    let saved = dir.join(format!("{}-{host}.{extension}", chrono::Local::now().format("%Y%m%d-%H%M%S")));
+    match std::fs::create_dir_all(&dir).map_err(anyhow::Error::from).and_then(|_| Ok(file.persist(&saved)?)) {
+        Ok(_) => Err(error.context(format!("The answer of the server is saved in {}", saved.display()))),
+        Err(e) => {
+            log::warn!("Could not keep the answer of {url}: {e:#}");
+            Err(error)
+        }
+    }
+}
+
+/// Downloads the pdf file of a url. When the url is a landing page
+/// announcing its pdf file, the pdf file is downloaded instead, and
+/// the metadata of the landing page are returned with it.
+fn download_pdf_document(url : &str, config : &config::Config) -> Result<(pdflib::PdfDocument, Option<pdflib::PdfMetaData>)> {
+    let (answer, file, kind) = fetch_document(url, config)?;
+    if answer.status.is_success() && kind == filetype::FileKind::Html {
+        let bytes = std::fs::read(file.path()).context("Reading the downloaded file")?;
+        if let Some(pdf_url) = landing::pdf_url(url, &bytes) {
+            log::info!("Following the landing page {url} to its pdf file {pdf_url}");
+            let page = landing::metadata(&bytes);
+            let (answer, file, kind) = fetch_document(&pdf_url, config)?;
+            return Ok((parse_download(&pdf_url, &answer, file, kind, config)?, Some(page)));
+        }
+    }
+    Ok((parse_download(url, &answer, file, kind, config)?, None))
+}
+
+
+/// Url of the pdf of an arxiv paper.
+fn arxiv_pdf_url(arxiv_id : &str, arxiv_version : &str) -> String {
+    format!("https://arxiv.org/pdf/{arxiv_id}v{arxiv_version}.pdf")
+}
+
+/// Url resolving a doi, which leads to the pdf file
+/// for some publishers (and to a landing page for others).
+fn doi_url(doi : &str) -> String {
+    format!("https://doi.org/{doi}")
+}
+
+/// The url from which `load_pdf_document` downloads
+/// a document, if it is not a local file.
+fn download_url(uri : &str, config : &config::Config) -> Option<String> {
+    match uri_or_filepath_dispatch(uri, &config.providers).ok()? {
+        ParsedURI::Arxiv { arxiv_id, arxiv_version } => Some(arxiv_pdf_url(&arxiv_id, &arxiv_version)),
+        ParsedURI::HttpURL(url) => Some(url),
+        ParsedURI::DOI(doi) => Some(doi_url(&doi)),
+        ParsedURI::Rxiv(rxiv::Preprint { server: Some(server), doi, version: Some(version) }) => Some(rxiv::pdf_url(&server, &doi, &version)),
+        ParsedURI::Rxiv(preprint) => Some(doi_url(&preprint.doi)),
+        ParsedURI::Hal { hal_id, hal_version } => Some(hal::pdf_url(&hal_id, hal_version.as_deref())),
+        ParsedURI::Iacr { iacr_id, iacr_revision } => Some(iacr::pdf_url(&iacr_id, iacr_revision.as_deref())),
+        ParsedURI::Eccc { eccc_id, eccc_revision } => Some(eccc::pdf_url(&eccc_id, eccc_revision.as_deref())),
+        ParsedURI::Provider(found) => Some(found.pdf_url),
+        _ => None,
+    }
+}
+
+/// What loading a document found out besides its pdf file.
+#[derive(Default)]
+struct Loaded {
+    /// The identifiers given by the uri.
+    identifiers : Vec<String>,
+    /// The metadata of the landing page the pdf file was found from.
+    landing     : Option<pdflib::PdfMetaData>,
+    /// The metadata given by a provider of the configuration.
+    registered  : Option<crossref::Work>,
+    /// The file of the document, when it is not a pdf file.
+    file        : Option<OtherFile>,
+}
+
+/// The file of a document that is not a pdf file,
+/// stored as it is (see `format`).
+struct OtherFile {
+    format   : format::Format,
+    path     : PathBuf,
+    /// The metadata read from the file: the ones of a web
+    /// page, or else the name of the file as title.
+    metadata : pdflib::PdfMetaData,
+    /// The temporary file of a snapshot, removed after the import.
+    _temp    : Option<tempfile::TempPath>,
+}
+
+/// The local file at `path`, when it is a document of
+/// another format than pdf.
+fn other_file(path : &Path) -> Result<Option<OtherFile>> {
+    let head = filetype::head(path).with_context(|| format!("Reading {path:?}"))?;
+    let Some(format) = format::Format::of_kind(filetype::sniff(&head)).filter(|f| !f.is_pdf()) else {
+        return Ok(None);
+    };
+    let mut metadata = match format {
+        format::Format::Html => snapshot::metadata(&std::fs::read(path).with_context(|| format!("Reading {path:?}"))?),
+        _ => pdflib::PdfMetaData::default(),
+    };
+    if metadata.title.is_none() {
+        metadata.title = path.file_stem().map(|s| s.to_string_lossy().replace(['_', '-'], " "));
+    }
+    Ok(Some(OtherFile { format, path: path.into(), metadata, _temp: None }))
+}
+
+/// Takes a snapshot of the web page at `url` (see `snapshot`),
+/// in a temporary file of the download directory.
+fn snapshot_page(url : &str, config : &config::Config) -> Result<OtherFile> {
+    let (answer, file, kind) = fetch_document(url, config)?;
+    if !answer.status.is_success() {
+        anyhow::bail!("{url} answered with the http status {}", answer.status);
+    }
+    if kind != filetype::FileKind::Html {
+        anyhow::bail!("{url} is not a web page but {kind}: import it without --snapshot");
+    }
+    let html = std::fs::read(file.path()).context("Reading the downloaded page")?;
+    let page = Url::parse(url)?;
+    let client = download::client(config.download_timeout, proxy::proxy_for(&config.proxies, url)?)?;
+    let taken = snapshot::take(&String::from_utf8_lossy(&html), &page, &client);
+    let dir = config.download_dir.clone().unwrap_or_else(std::env::temp_dir);
+    let mut temp = tempfile::Builder::new().suffix(".html").tempfile_in(&dir)
+        .context("Creating a temporary file for the snapshot")?;
+    std::io::Write::write_all(&mut temp, taken.as_bytes()).context("Writing the snapshot")?;
+    let temp = temp.into_temp_path();
+    Ok(OtherFile {
+        format: format::Format::Html,
+        path: temp.to_path_buf(),
+        metadata: snapshot::metadata(&html),
+        _temp: Some(temp),
+    })
+}
+
+/// Loads a pdf document. 
+/// Either from a url to download, an arxiv format,
+/// or simply from a valid filepath.
+fn load_pdf_document(uri : &str, loaded : Option<&mut Loaded>, config : &config::Config) -> Result<pdflib::PdfDocument> {
+    match uri_or_filepath_dispatch(uri, &config.providers)? {
+        ParsedURI::FilePath(p) => {
+            log::debug!("Found a direct path to import!");
+            parse_pdf_file(uri, Path::new(&p), None, config)
+        }
+        ParsedURI::Arxiv { arxiv_id, arxiv_version } => {
+            log::debug!("Found a valid arixv link to import {arxiv_id} / {arxiv_version}!");
+            if let Some(loaded) = loaded {
+                loaded.identifiers.push(format!("arxiv:{}v{}", arxiv_id, arxiv_version));
+            }
+            download_pdf_document(&arxiv_pdf_url(&arxiv_id, &arxiv_version), config).map(|(pdf, _)| pdf)
+
+        }
+        ParsedURI::HttpURL(url) => {
+            log::debug!("This is a direct http request");
+            let (pdf, page) = download_pdf_document(&url, config)?;
+            if let Some(loaded) = loaded {
+                loaded.landing = page;
+            }
+            Ok(pdf)
+        }
+        ParsedURI::DOI(doi) => {
+            let mut loaded = loaded;
+            if let Some(loaded) = loaded.as_deref_mut() {
+                loaded.identifiers.push(format!("doi:{doi}"));
+            }
+            // the open access copies, then the site of the publisher
+            let mut urls = match &config.contact_email {
+                Some(email) => unpaywall::pdf_urls(&doi, email).unwrap_or_else(|e| {
+                    log::warn!("Could not query Unpaywall for {doi}: {e:#}");
+                    vec![]
+                }),
+                None => {
+                    log::info!("Set contact_email in the configuration to look for open access copies of {doi}");
+                    vec![]
+                }
+            };
+            urls.push(doi_url(&doi));
+            let mut errors = vec![];
+            for url in &urls {
+                log::debug!("Following the doi {doi} to {url}, hoping for a pdf file");
+                match download_pdf_document(url, config) {
+                    Ok((pdf, page)) => {
+                        if let Some(loaded) = loaded {
+                            loaded.landing = page;
+                        }
+                        return Ok(pdf);
+                    }
+                    Err(e) => {
+                        log::info!("Could not download {doi} from {url}: {e:#}");
+                        errors.push(format!("{url}: {e:#}"));
+                    }
+                }
+            }
+            anyhow::bail!("Could not download {doi} (--metadata-only imports it without its pdf file): {}", errors.join("; "))
+        }
+        ParsedURI::Rxiv(preprint) => {
+            log::debug!("Found a preprint to import {preprint:?}");
+            // the server and the latest version, when the uri does not tell
+            let preprint = match rxiv::details(&preprint) {
+                Ok(Some((found, _))) => found,
+                Ok(None) => { log::info!("The servers do not know the preprint {}", preprint.doi); preprint }
+                Err(e) => { log::info!("Could not query the servers for {}: {e:#}", preprint.doi); preprint }
+            };
+            let rxiv::Preprint { server, doi, version } = preprint;
+            let url = match (&server, &version) {
+                (Some(server), Some(version)) => rxiv::pdf_url(server, &doi, version),
+                // the page of the doi announces the pdf file
+                _ => doi_url(&doi),
+            };
+            if let Some(loaded) = loaded {
+                loaded.identifiers.push(format!("doi:{doi}"));
+                if let (Some(server), Some(version)) = (&server, &version) {
+                    loaded.identifiers.push(rxiv::identifier(server, &doi, version));
+                }
+            }
+            download_pdf_document(&url, config).map(|(pdf, _)| pdf)
+        }
+        ParsedURI::Pubmed(id) => {
+            log::debug!("Found a PubMed article to import {id:?}");
+            // the other ids of the article: its PMCID for the
+            // open access pdf file, its doi for the publisher
+            let mut ids = match pubmed::summary(&id) {
+                Ok(Some(work)) => work.identifiers,
+                Ok(None) => anyhow::bail!("PubMed does not know {}", id.identifier()),
+                Err(e) => {
+                    log::warn!("Could not query the E-utilities for {}: {e:#}", id.identifier());
+                    vec![]
+                }
+            };
+            if !ids.contains(&id.identifier()) {
+                ids.insert(0, id.identifier());
+            }
+            let doi = identifiers::doi(&ids);
+            let open_access = match ids.iter().find_map(|i| i.strip_prefix("pmc:")) {
+                Some(pmcid) => pubmed::pdf_url(pmcid).unwrap_or_else(|e| {
+                    log::warn!("Could not query the open access service of PMC for {pmcid}: {e:#}");
+                    None
+                }),
+                None => None,
+            };
+            if let Some(loaded) = loaded {
+                loaded.identifiers.extend(ids);
+            }
+            match (open_access, doi) {
+                (Some(url), _) => download_pdf_document(&url, config).map(|(pdf, _)| pdf),
+                (None, Some(doi)) => {
+                    log::info!("{} is not in the open access subset of PMC, following its doi", id.identifier());
+                    load_pdf_document(&format!("doi:{doi}"), None, config)
+                }
+                (None, None) => anyhow::bail!("{} has no open access pdf file (--metadata-only imports it without)", id.identifier()),
+            }
+        }
+        ParsedURI::Hal { hal_id, hal_version } => {
+            log::debug!("Found a HAL deposit to import {hal_id} / {hal_version:?}");
+            if let Some(loaded) = loaded {
+                loaded.identifiers.push(hal::identifier(&hal_id, hal_version.as_deref()));
+            }
+            download_pdf_document(&hal::pdf_url(&hal_id, hal_version.as_deref()), config).map(|(pdf, _)| pdf)
+        }
+        ParsedURI::Iacr { iacr_id, iacr_revision } => {
+            log::debug!("Found an ePrint report to import {iacr_id} / {iacr_revision:?}");
+            if let Some(loaded) = loaded {
+                loaded.identifiers.push(iacr::identifier(&iacr_id, iacr_revision.as_deref()));
+            }
+            download_pdf_document(&iacr::pdf_url(&iacr_id, iacr_revision.as_deref()), config).map(|(pdf, _)| pdf)
+        }
+        ParsedURI::Eccc { eccc_id, eccc_revision } => {
+            log::debug!("Found an ECCC report to import {eccc_id} / {eccc_revision:?}");
+            if let Some(loaded) = loaded {
+                loaded.identifiers.push(eccc::identifier(&eccc_id, eccc_revision.as_deref()));
+            }
+            download_pdf_document(&eccc::pdf_url(&eccc_id, eccc_revision.as_deref()), config).map(|(pdf, _)| pdf)
+        }
+        ParsedURI::Provider(found) => {
+            log::debug!("Found a document of the provider {} to import: {}", found.name, found.identifier);
+            if let Some(loaded) = loaded {
+                loaded.identifiers.push(found.identifier.clone());
+                if let Some((url, fields)) = &found.metadata {
+                    loaded.registered = providers::metadata(&found.name, url, fields)
+                        .map_err(|e| log::warn!("Could not describe {}: {e:#}", found.identifier))
+                        .ok();
+                }
+            }
+            download_pdf_document(&found.pdf_url, config).map(|(pdf, _)| pdf)
+        }
+        ParsedURI::Plugin { program, uri } => {
+            let answer = plugins::ask(&program, &uri)?;
+            let pdf = match (&answer.pdf_path, &answer.pdf_url) {
+                (Some(path), _) => {
+                    parse_pdf_file(&uri, path, None, config)
+                        .with_context(|| format!("Reading the pdf file {path:?} given by {program:?}"))?
+                }
+                (None, Some(url)) => download_pdf_document(url, config)?.0,
+                (None, None) => anyhow::bail!("{program:?} gave no pdf file for {uri}"),
+            };
+            if let Some(loaded) = loaded {
+                loaded.registered = answer.work();
+                loaded.identifiers.extend(answer.identifiers);
+            }
+            Ok(pdf)
+        }
+        ParsedURI::Isbn(isbn) => {
+            anyhow::bail!("Books cannot be downloaded: import the pdf file of isbn:{isbn} with -i isbn:{isbn}, or use --metadata-only")
+        }
+        _ => {
+            anyhow::bail!("Cannot automatically download uri {}", &uri);
+        }
+    }
+}
+
+/// Forward the opening of a document to the operating system.
+/// In headless mode, the uri is printed instead.
+fn forward_open(desktop : &desktop::Desktop, uri : &str) -> Result<()> {
+    if desktop.headless {
+        println!("{uri}");
+        return Ok(());
+    }
+    log::debug!("Opening {uri} using the system's default");
+    log::debug!("Potential openers {:?}", open::commands(uri));
+
+    open::commands(uri)[0].spawn()?;
+    //open::that(uri).unwrap();
+    Ok(())
+}
+
+/// View a pdf file using the "best" available
+/// options depending on the system.
+///
+/// 1. Skim / Evince / Adobe reader
+/// 2. Zathura / Mupdf / Okular
+/// 3. xdg-open / open / etc ...
+///
+/// TODO: allow this to be configured by an environment variable.
+/// -> a program 
+/// -> a name for the argument of destinations
+/// -> a name for the argument of pages
+///
+/// In headless mode, the path and position are printed instead.
+fn view_pdf_file(desktop : &desktop::Desktop, path : &PathBuf, page : Option<u32>, dest: Option<String>) {
+    log::info!("Opening pdf file {path:?} at {page:?} {dest:?}");
+    if desktop.headless {
+        match (page, dest) {
+            (_, Some(dest_name)) => println!("{}\t{dest_name}", path.to_string_lossy()),
+            (Some(page_num), _)  => println!("{}\t{page_num}", path.to_string_lossy()),
+            (None, None)         => println!("{}", path.to_string_lossy()),
+        }
+        return;
+    }
+    if let Some(mut viewer) = spawn_viewer(path, page, dest) {
+        let _ = viewer.wait();
+    }
+}
+
+/// Starts the viewer of a file of another format than pdf: the
+/// command configured for its format, or else the default
+/// application of the system. Does not wait for it.
+fn spawn_format_viewer(config : &config::Config, format : format::Format, path : &Path) -> Result<()> {
+    log::info!("Opening {format} file {path:?}");
+    let mut cmd = match config.viewers.get(&format) {
+        Some(command) => {
+            let mut words = command.split_whitespace();
+            let mut cmd = std::process::Command::new(words.next()
+                .with_context(|| format!("The viewer of the {format} files is empty"))?);
+            cmd.args(words).arg(path);
+            cmd
+        }
+        None => open::commands(path).into_iter().next()
+            .with_context(|| format!("No application opens {path:?}"))?,
+    };
+    cmd.spawn().with_context(|| format!("Opening {path:?}"))?;
+    Ok(())
+}
+
+/// Starts a viewer on a pdf file (see `view_pdf_file`). Returns
+/// its process, or `None` when the file was handed to the system.
+fn spawn_viewer(path : &PathBuf, page : Option<u32>, dest : Option<String>) -> Option<std::process::Child> {
+    //open::that(path).unwrap();
+    let mut cmd = std::process::Command::new("evince");
+    cmd.arg(path);
+
+    if let Some(dest_name) =  dest {
+        cmd.arg(format!("--named-dest={dest_name}"));
+    } else if let Some(page_name) = page {
+        cmd.arg(format!("--page-index={page_name}"));
+    } 
+
+    println!("args {:?}", cmd.get_args().collect::<Vec<&std::ffi::OsStr>>());
+
+    match cmd.spawn() {
+        Ok(child) => Some(child),
+        Err(_) => {
+            // handed to the system, which detaches it: nothing to wait for
+            if let Err(e) = open::commands(path)[0].spawn() {
+                log::error!("Could not open {path:?}: {e}");
+            }
+            None
+        }
+    }
+}
+
+impl AppState {
+    fn new() -> Self {
+        // find the correct path for the application stored state.
+        // this uses ProjectDirs (cross-plateform)
+        let pdirs = ProjectDirs::from("com", "aluminium", "AKL").unwrap();
+        let conf_path = pdirs.config_dir();
+        let mut app = Self::with_dirs(conf_path, pdirs.data_dir(), pdirs.cache_dir(), &conf_path.join("config.yaml"));
+        // the library may have been moved elsewhere (see `akl relocate`)
+        let config = &app.config;
+        if config.index_dir.is_some() || config.raw_dir.is_some() || config.mod_dir.is_some() || config.log_dir.is_some() {
+            app.index_dir = config.index_dir.clone().unwrap_or(app.index_dir);
+            app.raw_path = config.raw_dir.clone().unwrap_or(app.raw_path);
+            app.mod_path = config.mod_dir.clone().unwrap_or(app.mod_path);
+            app.log_path = config.log_dir.clone().unwrap_or(app.log_path);
+            for dir in [&app.index_dir, &app.raw_path, &app.mod_path, &app.log_path] {
+                std::fs::create_dir_all(dir).unwrap();
+            }
+            app.storage = storage::open(app.config.backend, &app.index_dir).unwrap();
+        }
+        app.start_history();
+        app
+    }
+
+    /// Starts the git history of the index, if it is enabled and
+    /// not started yet, before the first command changes anything.
+    fn start_history(&self) {
+        if self.config.git_history {
+            if let Err(e) = git::init(&self.index_dir) {
+                log::warn!("Could not start the git history of the index: {e:#}");
+            }
+        }
+    }
+
+    /// The library of a project (its `.akl` directory), falling
+    /// back to the global library.
+    fn project(root : &Path) -> Self {
+        let global = Self::new();
+        let config_path = Some(root.join("config.yaml"))
+            .filter(|p| p.exists())
+            .unwrap_or_else(|| global.config_path.clone());
+        let mut app = Self::with_dirs(root, root, &global.cache_path, &config_path);
+        app.global = Some(Box::new(global));
+        app.start_history();
+        app
+    }
+
+    fn with_dirs(conf_path : &Path, data_path : &Path, cache_path : &Path, config_path : &Path) -> Self {
+        let raw_path   = data_path.join("raw");
+        let mod_path   = data_path.join("mod");
+        // TODO: in modern XDG, there is XDG_STATE_DIR
+        // but this is not cross platform
+        let index_dir = conf_path.to_path_buf();
+        let config_path = config_path.to_path_buf();
+        let log_path   = cache_path.join("logs");
+        let trash      = trash::Trash::new(&data_path.join("trash"));
+        let events     = events::EventLog::new(&data_path.join("events.jsonl"));
+        let journal    = journal::Journal::new(&data_path.join("journal.jsonl"), &data_path.join("journal"));
+        let reports    = report::Reports::new(&data_path.join("reports"));
+        let sessions   = session::Sessions::new(&data_path.join("sessions"));
+        let cache_path = cache_path.to_path_buf();
+
+        // ensures that the paths exists
+        // TODO: postpone this check to times we actually need
+        // to open the files.
+        std::fs::create_dir_all(conf_path).unwrap();
+        std::fs::create_dir_all(&raw_path).unwrap();
+        std::fs::create_dir_all(&mod_path).unwrap();
+        std::fs::create_dir_all(&log_path).unwrap();
+
+        // TODO: gracefully handle failure to parse the config
+        let mut config = config::Config::load(&config_path).unwrap();
+        config.download_dir = Some(data_path.join("downloads"));
+        polite::configure(&config);
+        let storage = storage::open(config.backend, &index_dir).unwrap();
+
+        AppState {
+            index_dir,
+            raw_path,
+            mod_path,
+            data_path: data_path.to_path_buf(),
+            log_path,
+            trash,
+            events,
+            journal,
+            reports,
+            sessions,
+            cache_path,
+            config_path,
+            config,
+            desktop: desktop::Desktop::default(),
+            storage,
+            global: None,
+        }
+    }
+
+    /// Copies a document of another library (with its files
+    /// and the names of its destinations) to this one.
+    fn copy_document(&mut self, from : &AppState, doc : &Document) -> Result<()> {
+        // metadata-only documents have no files
+        if !doc.metadata_only {
+            std::fs::copy(from.raw_file(doc), store::target(&self.raw_path, doc)?)
+                .context("Copying the original file")?;
+            std::fs::copy(from.mod_path.join(&doc.filename), self.mod_path.join(&doc.filename))
+                .context("Copying the modified file")?;
+        }
+        let table = from.anchors()?.table(doc);
+        if !table.is_empty() {
+            let mut anchors = self.anchors()?;
+            *anchors.table_mut(doc) = table;
+            anchors.save()?;
+        }
+        self.storage.insert(doc)?;
+        self.journal(journal::OpKind::Import, None, Some(doc));
+        self.record(events::EventKind::Import, doc, Some(format!("copied from {}", from.index_dir.display())));
+        Ok(())
+    }
+
+    /// Opens a document of the library in a viewer. The viewer is
+    /// registered as showing the document until it exits (see
+    /// `wait_for_viewers`). In headless mode, nothing is started.
+    fn open_in_viewer(&self, doc : &Document, page : Option<u32>, dest : Option<String>) -> Result<Option<std::process::Child>> {
+        if doc.metadata_only {
+            anyhow::bail!("{} has no pdf file yet, see akl attach", doc.filename);
+        }
+        self.record_at(events::EventKind::Open, doc, page, dest.clone(), None);
+        if page.is_some() || dest.is_some() {
+            self.sessions.left_at(&doc.checksum, page, dest.clone())?;
+        }
+        let dest = match dest {
+            Some(d) => Some(self.anchors()?.table(doc).resolve(&d)),
+            None => None,
+        };
+        let path = self.mod_path.join(&doc.filename);
+        if self.desktop.headless {
+            view_pdf_file(&self.desktop, &path, page, dest);
+            return Ok(None);
+        }
+        // the pages and destinations are the ones of pdf files
+        if !doc.format.is_pdf() {
+            spawn_format_viewer(&self.config, doc.format, &path)?;
+            return Ok(None);
+        }
+        let viewer = spawn_viewer(&path, page, dest.clone());
+        if let Some(viewer) = &viewer {
+            self.sessions.opened(session::OpenDocument {
+                checksum: doc.checksum.clone(),
+                title: doc.title.clone(),
+                page,
+                dest,
+                opened: chrono::Utc::now(),
+                pid: Some(viewer.id()),
+            })?;
+        }
+        Ok(viewer)
+    }
+
+    /// Waits for viewers to exit, and forgets about the documents
+    /// they were showing, remembering the page they were left at
+    /// when the viewer tells.
+    fn wait_for_viewers(&self, viewers : Vec<std::process::Child>) {
+        for mut viewer in viewers {
+            let _ = viewer.wait();
+            if let Err(e) = self.viewer_closed(viewer.id()) {
+                log::warn!("Could not find where the viewer {} was left: {e:?}", viewer.id());
+            }
+            if let Err(e) = self.sessions.closed(viewer.id()) {
+                log::warn!("Could not unregister the viewer {}: {e:?}", viewer.id());
+            }
+        }
+    }
+
+    /// Remembers the page the document shown by
+    /// a viewer that exited was left at.
+    fn viewer_closed(&self, pid : u32) -> Result<()> {
+        let Some(shown) = self.sessions.shown_by(pid)? else {
+            return Ok(());
+        };
+        let Some(doc) = self.storage.find_by_checksum(&shown.checksum)? else {
+            return Ok(());
+        };
+        if let Some(page) = desktop::last_viewed_page(&self.mod_path.join(&doc.filename)) {
+            self.sessions.left_at(&doc.checksum, Some(page), None)?;
+        }
+        Ok(())
+    }
+
+    /// Delete a document from the library
+    fn delete(&mut self, doc : &Document) -> Result<()> {
+        self.storage.remove(doc)
+    }
+
+
+    /// Remove a document from the library,
+    /// moving its files to the trash.
+    fn remove_to_trash(&mut self, doc : &Document) -> Result<()> {
+        let raw = self.raw_file(doc);
+        let modified = self.mod_path.join(&doc.filename);
+        self.journal.keep(&[("raw.pdf", &raw), ("mod.pdf", &modified)])?;
+        self.trash.put(doc, &raw, &modified)?;
+        self.delete(doc)?;
+        self.journal(journal::OpKind::Remove, Some(doc), None);
+        self.record(events::EventKind::Remove, doc, None);
+        self.purge_trash()
+    }
+
+    /// Puts a removed document back into the library.
+    fn restore_from_trash(&mut self, entry : &trash::TrashEntry) -> Result<()> {
+        let doc = &entry.document;
+        if self.storage.find_by_checksum(&doc.checksum)?.is_some() {
+            anyhow::bail!("The document {} is already in the library", doc.filename);
+        }
+        self.trash.restore(entry,
+                           &store::target(&self.raw_path, doc)?,
+                           &self.mod_path.join(&doc.filename))?;
+        self.storage.insert(doc)?;
+        self.journal(journal::OpKind::Import, None, Some(doc));
+        self.record(events::EventKind::Restore, doc, None);
+        Ok(())
+    }
+
+    /// Deletes the documents that stayed in the trash
+    /// longer than the retention period.
+    fn purge_trash(&self) -> Result<()> {
+        let days = self.config.trash_retention_days;
+        if days == 0 {
+            return Ok(());
+        }
+        let before = chrono::Utc::now() - chrono::Duration::days(days.into());
+        for entry in self.trash.purge(before)? {
+            log::info!("Deleted {} from the trash", entry.document.filename);
+        }
+        self.journal.purge(before)
+    }
+
+    /// Loads the collections of the library.
+    fn collections(&self) -> Result<collections::Collections> {
+        collections::Collections::load(&self.index_dir.join("collections.yaml"))
+    }
+
+    /// Loads the saved searches.
+    fn searches(&self) -> Result<searches::Searches> {
+        searches::Searches::load(&self.index_dir.join("searches.yaml"))
+    }
+
+    /// Loads the inbox and its subscriptions.
+    fn inbox(&self) -> Result<inbox::Inbox> {
+        inbox::Inbox::load(&self.index_dir.join("inbox.yaml"))
+    }
+
+    /// Loads the author registry.
+    fn authors(&self) -> Result<authors::Authors> {
+        authors::Authors::load(&self.index_dir.join("authors.yaml"))
+    }
+
+    /// Loads the names of the destinations of the documents.
+    fn anchors(&self) -> Result<anchors::Anchors> {
+        anchors::Anchors::load(&self.index_dir.join("anchors.yaml"))
+    }
+
+    /// The original file of a document (see `store`).
+    fn raw_file(&self, doc : &Document) -> PathBuf {
+        store::locate(&self.raw_path, doc)
+    }
+
+    /// Renames the modified file of a document, and its original
+    /// file when it is not in the store yet.
+    /// Either both files are renamed, or none.
+    fn rename_files(&self, doc : &Document, filename : &str) -> Result<()> {
+        if filename == doc.filename {
+            return Ok(());
+        }
+        log::info!("Renaming {} to {filename}", doc.filename);
+        let moves : Vec<(PathBuf, PathBuf)> = [&self.raw_path, &self.mod_path].iter()
+            .map(|dir| (dir.join(&doc.filename), dir.join(filename)))
+            .filter(|(old, _)| old.exists())
+            .collect();
+        if let Some((_, new)) = moves.iter().find(|(_, new)| new.exists()) {
+            anyhow::bail!("Cannot rename {} to {new:?}, the file already exists", doc.filename);
+        }
+        for (i, (old, new)) in moves.iter().enumerate() {
+            if let Err(e) = std::fs::rename(old, new) {
+                // put back the files already renamed
+                for (old, new) in &moves[..i] {
+                    if let Err(e) = std::fs::rename(new, old) {
+                        log::error!("Could not rename {new:?} back to {old:?}: {e:?}");
+                    }
+                }
+                return Err(e).with_context(|| format!("Renaming {old:?}"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces a document by a new version, renaming its files
+    /// when the filename changed. The old filename is kept, so
+    /// that the links to the old files can still be resolved.
+    fn move_document(&mut self, old : &Document, mut new : Document) -> Result<Document> {
+        if new.filename != old.filename {
+            self.rename_files(old, &new.filename)?;
+            new.former_filenames.push(old.filename.clone());
+            new.former_filenames.retain(|f| *f != new.filename);
+            new.former_filenames.dedup();
+            self.record(events::EventKind::Rename, &new,
+                        Some(format!("{} -> {}", old.filename, new.filename)));
+        }
+        self.update_document(old, &new)?;
+        Ok(new)
+    }
+
+    /// Replaces the entry of a document in the index.
+    fn update_document(&mut self, old : &Document, new : &Document) -> Result<()> {
+        self.storage.update(old, new)?;
+        self.journal(journal::OpKind::Edit, Some(old), Some(new));
+        Ok(())
+    }
+
+    /// Finds a document by its short id.
+    fn find_by_short_id(&self, id : &str) -> Result<Option<Document>> {
+        if !identifiers::is_short_id(id) {
+            return Ok(None);
+        }
+        Ok(self.storage.documents()?.into_iter()
+               .find(|d| d.short_id() == id))
+    }
+
+    /// A short id for a new document, longer than usual
+    /// when it collides with the id of another document.
+    fn fresh_id(&self, checksum : &str) -> Result<String> {
+        let taken : Vec<String> = self.storage.documents()?.iter()
+            .filter(|d| d.checksum != checksum)
+            .map(|d| d.short_id())
+            .collect();
+        let mut len = identifiers::SHORT_ID_LEN;
+        loop {
+            let id = identifiers::short_id(checksum, len);
+            if !taken.iter().any(|t| t.starts_with(&id) || id.starts_with(t.as_str())) || len >= 51 {
+                return Ok(id);
+            }
+            len += 4;
+        }
+    }
+
+    /// Finds a document from the name of one of its files,
+    /// current or former.
+    fn find_by_filename(&self, path : &str) -> Result<Option<Document>> {
+        let Some(name) = std::path::Path::new(path).file_name() else { return Ok(None) };
+        let name = name.to_string_lossy();
+        Ok(self.storage.documents()?.into_iter()
+               .find(|d| d.filename == name || d.former_filenames.iter().any(|f| *f == name)))
+    }
+
+    /// Records an operation in the journal. Failing
+    /// to do so does not make the command fail.
+    fn journal(&self, kind : journal::OpKind, before : Option<&Document>, after : Option<&Document>) {
+        if let Err(e) = self.journal.record(kind, before, after) {
+            log::warn!("Could not record the operation {kind:?} in the journal: {e:?}");
+        }
+    }
+
+    /// Records an event in the log of the library.
+    /// Failing to do so does not make the command fail.
+    fn record(&self, kind : events::EventKind, doc : &Document, detail : Option<String>) {
+        self.record_at(kind, doc, None, None, detail)
+    }
+
+    /// Records an event concerning a page or a destination of a document.
+    fn record_at(&self, kind : events::EventKind, doc : &Document, page : Option<u32>, dest : Option<String>, detail : Option<String>) {
+        if let Err(e) = self.events.record_at(kind, doc, page, dest, detail) {
+            log::warn!("Could not record the event {kind:?}: {e:?}");
+        }
+    }
+
+    /// Finds a document in the library.
+    /// This can be quite complex, but we do the bare minimum here:
+    /// a checksum, an identifier, or as a last resort the exact title.
+    fn find_document(&self, uri : &str) -> Result<Document> {
+        let is_checksum = uri.len() == 64 && uri.chars().all(|c| c.is_ascii_hexdigit());
+        let id = uri.strip_prefix("id:").unwrap_or(uri);
+        let search_result = if is_checksum {
+            self.storage.find_by_checksum(uri)?
+        } else if let Some(doc) = self.find_by_short_id(id)? {
+            Some(doc)
+        } else if let Some(doc) = self.storage.find_by_identifier(&identifiers::canonicalize(uri))? {
+            // the identifiers are stored in their canonical form
+            Some(doc)
+        } else {
+            match uri_or_filepath_dispatch(uri, &self.config.providers) {
+                Ok(ParsedURI::DOI(doi)) => {
+                    self.storage.find_by_identifier(&format!("doi:{doi}"))?
+                }
+                Ok(ParsedURI::Arxiv { arxiv_version, arxiv_id }) => {
+                    self.storage.find_by_identifier(&format!("arxiv:{arxiv_id}v{arxiv_version}"))?
+                }
+                Ok(ParsedURI::HttpURL(url)) => {
+                    self.storage.find_by_identifier(&url)?
+                }
+                Ok(ParsedURI::Isbn(isbn)) => {
+                    self.storage.find_by_identifier(&format!("isbn:{isbn}"))?
+                }
+                Ok(ParsedURI::Rxiv(preprint)) => {
+                    self.storage.find_by_identifier(&format!("doi:{}", preprint.doi))?
+                }
+                Ok(ParsedURI::Pubmed(id)) => {
+                    self.storage.find_by_identifier(&id.identifier())?
+                }
+                Ok(ParsedURI::Provider(found)) => {
+                    self.storage.find_by_identifier(&found.identifier)?
+                }
+                Ok(ParsedURI::Plugin { uri, .. }) => {
+                    self.storage.find_by_identifier(&uri)?
+                }
+                Ok(ParsedURI::Hal { hal_id, hal_version }) => {
+                    self.storage.find_by_identifier(&hal::identifier(&hal_id, hal_version.as_deref()))?
+                }
+                Ok(ParsedURI::Iacr { iacr_id, iacr_revision }) => {
+                    self.storage.find_by_identifier(&iacr::identifier(&iacr_id, iacr_revision.as_deref()))?
+                }
+                Ok(ParsedURI::Eccc { eccc_id, eccc_revision }) => {
+                    self.storage.find_by_identifier(&eccc::identifier(&eccc_id, eccc_revision.as_deref()))?
+                }
+                Ok(ParsedURI::FilePath(_)) => {
+                    match self.storage.find_by_identifier(uri)? {
+                        Some(d) => Some(d),
+                        None => self.find_by_filename(uri)?,
+                    }
+                }
+                Ok(_) => {
+                    None
+                }
+                Err(_) => {
+                    // other identifiers (`citekey:…`), then titles
+                    match self.storage.find_by_identifier(uri)? {
+                        Some(d) => Some(d),
+                        None => match self.storage.find_by_title(uri)?.into_iter().next() {
+                            Some(d) => Some(d),
+                            // a file of the library renamed since
+                            None => self.find_by_filename(uri)?,
+                        },
+                    }
+                }
+            }
+        };
+
+        match search_result {
+            Some(r) => { Ok(r) }
+            None    => { anyhow::bail!("Could not find {uri} in the library.") }
+        }
+    }
+
+    /// Add a document to the library.
+    /// Assumes that the document is valid
+    /// and is not already in the library.
+    fn add_document(&mut self, doc : &mut Document, mut pdoc : pdflib::PdfDocument) -> Result<report::Conversion> {
+        let r = tempfile::Builder::new().suffix(".tmp").tempfile_in(&self.raw_path)
+            .context("Creating a temporary file in the library")?
+            .into_temp_path();
+        pdoc.save_to(&r).context("Saving the original file to the library")?;
+        doc.raw_checksum = Some(store::put(&self.raw_path, &r, format::Format::Pdf)?);
+        let conversion = self.convert_document(doc, pdoc)?;
+        self.storage.insert(doc)?;
+        self.journal(journal::OpKind::Import, None, Some(doc));
+        Ok(conversion)
+    }
+
+    /// Add a document whose file is not a pdf file to the
+    /// library. Its file is stored as it is, without conversion.
+    fn add_file(&mut self, doc : &mut Document, file : &Path) -> Result<()> {
+        let r = tempfile::Builder::new().suffix(".tmp").tempfile_in(&self.raw_path)
+            .context("Creating a temporary file in the library")?
+            .into_temp_path();
+        std::fs::copy(file, &r).context("Copying the original file to the library")?;
+        doc.raw_checksum = Some(store::put(&self.raw_path, &r, doc.format)?);
+        std::fs::copy(file, self.mod_path.join(&doc.filename))
+            .context("Copying the file to the library")?;
+        self.storage.insert(doc)?;
+        self.journal(journal::OpKind::Import, None, Some(doc));
+        Ok(())
+    }
+
+    /// The identifier that the links of the document should use.
+    fn canonical_identifier(&self, doc : &Document) -> Result<String> {
+        identifiers::canonical(&self.config.identifier_priority, &doc.identifiers)
+            .cloned()
+            .context("A document needs at least one identifier")
+    }
+
+    /// Writes the modified file of the document, with links
+    /// using its canonical identifier.
+    fn convert_document(&self, doc : &mut Document, mut pdoc : pdflib::PdfDocument) -> Result<report::Conversion> {
+        let start = std::time::Instant::now();
+        let ident = self.canonical_identifier(doc)?;
+        let annotations = pdoc.annotation_count();
+        update_document_links(&mut pdoc, Some(ident.clone()));
+        update_document_dests(&ident, &mut pdoc);
+        let outline = add_document_outline(&mut pdoc);
+        pdoc.save_to(&self.mod_path.join(&doc.filename))
+            .context("Saving a modified file to the library")?;
+        doc.linked_as = Some(ident);
+        doc.converted_with = Some(CONVERSION_VERSION);
+        Ok(report::Conversion {
+            destinations: pdoc.destination_count(),
+            annotations,
+            outline,
+            duration_ms: start.elapsed().as_millis(),
+        })
+    }
+
+    /// Is the modified file of the document the one the
+    /// current conversion would write? If not, says why.
+    fn conversion_status(&self, doc : &Document) -> Result<Option<String>> {
+        let ident = self.canonical_identifier(doc)?;
+        if doc.metadata_only || !doc.format.is_pdf() {
+            Ok(None)
+        } else if !self.mod_path.join(&doc.filename).exists() {
+            Ok(Some("the modified file is missing".into()))
+        } else if doc.linked_as.as_ref() != Some(&ident) {
+            Ok(Some(format!("its links do not use the canonical identifier {ident}")))
+        } else if doc.converted_with != Some(CONVERSION_VERSION) {
+            Ok(Some("it was written by an older version of akl".into()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Converts the original file of a document of the library again,
+    /// keeping the annotations made on the previous modified copy.
+    fn reconvert(&mut self, doc : &Document) -> Result<Document> {
+        if doc.metadata_only {
+            anyhow::bail!("{} has no pdf file to convert, see akl attach", doc.filename);
+        }
+        if !doc.format.is_pdf() {
+            anyhow::bail!("{} is not a pdf file, only the pdf files are converted", doc.filename);
+        }
+        let raw = self.raw_file(doc);
+        let mut pdoc = load_pdf_document(&raw.to_string_lossy(), None, &self.config)
+            .with_context(|| format!("Loading the original file of {}", doc.filename))?;
+        // keep the annotations the user made on the previous copy
+        let modified = self.mod_path.join(&doc.filename);
+        if modified.exists() {
+            let previous = lopdf::Document::load(&modified)
+                .map_err(anyhow::Error::from)
+                .and_then(|pdf| Ok(pdflib::PdfDocument::try_from(pdf)?));
+            match previous {
+                Ok(previous) => {
+                    let count = pdoc.import_annotations(&previous)?;
+                    if count > 0 {
+                        log::info!("Kept {count} annotations of the previous copy of {}", doc.filename);
+                    }
+                }
+                Err(e) => { log::warn!("Could not read the previous copy of {}, its annotations are lost: {e:#}", doc.filename); }
+            }
+        }
+        let mut new = doc.clone();
+        self.journal.keep(&[("mod.pdf", &modified)])?;
+        self.convert_document(&mut new, pdoc)?;
+        self.storage.update(doc, &new)?;
+        self.journal(journal::OpKind::Reconvert, Some(doc), Some(&new));
+        Ok(new)
+    }
+
+    /// Actions offered by the notifications about a document:
+    /// opening it, citing it and showing its file.
+    fn document_actions(&self, filename : &str) -> Result<Vec<(String, desktop::Action)>> {
+        let Some(doc) = self.find_by_filename(filename)? else {
+            return Ok(vec![]);
+        };
+        let cite = CiteArgs { uri: self.canonical_identifier(&doc)?, page: None, dest: None, from: None, resume: false };
+        let open = CiteArgs { uri: doc.checksum.clone(), ..cite.clone() };
+        Ok(vec![
+            ("Open".into(), desktop::Action::Uri(command_to_query(Commands::Open(open))?)),
+            ("Cite".into(), desktop::Action::Uri(command_to_query(Commands::Cite(cite))?)),
+            ("Show in library".into(), desktop::Action::Reveal(self.mod_path.join(&doc.filename))),
+        ])
+    }
+
+    /// Converts the document again if its modified file is
+    /// outdated, e.g. when its canonical identifier changed.
+    fn reconvert_if_needed(&mut self, doc : &Document) -> Result<()> {
+        if let Some(reason) = self.conversion_status(doc)? {
+            log::info!("Converting {} again: {reason}", doc.filename);
+            self.reconvert(doc)?;
+        }
+        Ok(())
+    }
+
+
+    /// Saving the changes of the library to the index
+    /// (nothing is written when nothing changed), and
+    /// updating its history and browse views.
+    fn save(&mut self) -> Result<()> {
+        self.storage.save()?;
+        if self.config.git_history {
+            if let Err(e) = self.commit_history() {
+                log::warn!("Could not commit the index to its git history: {e:#}");
+            }
+        }
+        if let Some(dir) = &self.config.browse_dir {
+            if let Err(e) = browse::update(dir, &self.storage.documents()?, &self.mod_path) {
+                log::warn!("Could not update the browse views: {e:#}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Commits the changes of the index to its git history, with
+    /// the command and its operations as message.
+    fn commit_history(&self) -> Result<()> {
+        let mut message = format!("akl {}\n", self.journal.command());
+        let operations = self.journal.current()?;
+        if !operations.is_empty() {
+            message.push('\n');
+        }
+        for op in operations {
+            message.push_str(&format!("{}\n", op.describe()));
+        }
+        git::commit(&self.index_dir, &message)?;
+        Ok(())
+    }
+}
+
+/// Lets the user edit the metadata of a document as yaml,
+/// using the editor from $EDITOR (nvim by default).
+///
+/// The editor is opened again as long as the result is not a
+/// valid document, and the edit is aborted when the editor fails
+/// (`:cq` in vim). The checksum cannot be changed.
+fn edit_document(doc : &Document) -> Result<Document> {
+    let editor = std::env::var("EDITOR").unwrap_or("nvim".into());
+    let file = tempfile::Builder::new().suffix(".yaml").tempfile()?;
+    serde_yaml::to_writer(&file, doc)?;
+    loop {
+        let proc =
+            std::process::Command::new(&editor)
+                .arg(file.path())
+                .status()
+                .with_context(|| format!("Running the editor {editor}"))?;
+        if !proc.success() {
+            anyhow::bail!("The edit was aborted ({editor} exited with {proc})");
+        }
+        // some editors replace the file instead of writing it
+        let content = std::fs::read_to_string(file.path())?;
+        let edited = serde_yaml::from_str::<Document>(&content)
+            .map_err(anyhow::Error::from)
+            .and_then(|d| d.validate().map(|_| d))
+            .and_then(|d| {
+                if d.checksum != doc.checksum {
+                    anyhow::bail!("The checksum of a document cannot be changed");
+                }
+                if d.id != doc.id {
+                    anyhow::bail!("The id of a document cannot be changed");
+                }
+                Ok(d)
+            });
+        match edited {
+            Ok(d) => { return Ok(d); }
+            Err(e) => {
+                eprintln!("Invalid document: {e:#}");
+                eprintln!("Press enter to edit it again");
+                std::io::stdin().read_line(&mut String::new())?;
+            }
+        }
+    }
+}
+
+/// Edits the metadata of a document of the library,
+/// renaming its files if needed.
+fn edit_library_document(app : &mut AppState, uri : &str) -> Result<String> {
+    let doc = app.find_document(uri)?;
+    let mut new = edit_document(&doc)?;
+    new.filename = new.generate_name(&app.config)?;
+
+    let new = app.move_document(&doc, new)?;
+    app.record(events::EventKind::Edit, &new, None);
+    app.reconvert_if_needed(&new)?;
+    Ok(new.filename)
+}
+
+/// Reports the orphaned files, dangling index entries and stale
+/// files of the library, and removes them unless `dry_run` is set.
+/// Orphaned files and dangling entries go to the trash, so that they
+/// can be restored, unless `delete` is set for the orphaned files.
+fn collect_garbage(app : &mut AppState, dry_run : bool, delete : bool) -> Result<()> {
+    let report = gc::collect(&app.storage.documents()?, &app.raw_path, &app.mod_path)?;
+    for path in &report.orphans {
+        println!("orphan    {}", path.display());
+    }
+    for doc in &report.dangling {
+        println!("dangling  {}", doc.filename);
+    }
+    for path in &report.stale {
+        println!("stale     {}", path.display());
+    }
+    if dry_run {
+        return Ok(());
+    }
+    let removed = if delete { &report.orphans[..] } else { &[] };
+    for path in removed.iter().chain(report.stale.iter()) {
+        std::fs::remove_file(path)
+            .with_context(|| format!("Removing {path:?}"))?;
+    }
+    if !delete {
+        let (raw, modified) : (Vec<PathBuf>, Vec<PathBuf>) = report.orphans.iter()
+            .cloned()
+            .partition(|p| p.starts_with(&app.raw_path));
+        let now = chrono::Utc::now();
+        app.trash.put_orphans(&raw, "raw", now)?;
+        app.trash.put_orphans(&modified, "mod", now)?;
+    }
+    for doc in &report.dangling {
+        app.remove_to_trash(doc)?;
+    }
+    if delete {
+        println!("Removed {} files and {} index entries",
+                 report.orphans.len() + report.stale.len(),
+                 report.dangling.len());
+    } else {
+        println!("Moved {} files and {} index entries to the trash, removed {} stale files",
+                 report.orphans.len(),
+                 report.dangling.len(),
+                 report.stale.len());
+    }
+    Ok(())
+}
+
+/// Walks through the documents scoring below the threshold,
+/// offering to edit each of them.
+fn review_documents(app : &mut AppState, threshold : u32, list : bool) -> Result<()> {
+    let mut queue : Vec<(quality::Quality, Document)> = app.storage.documents()?
+        .into_iter()
+        .map(|d| (quality::quality(&d), d))
+        .filter(|(q, _)| q.score < threshold)
+        .collect();
+    queue.sort_by_key(|(q, _)| q.score);
+
+    let total = queue.len();
+    for (i, (q, doc)) in queue.into_iter().enumerate() {
+        println!("{:>3}  {}  (missing {})", q.score, doc.title, q.missing.join(", "));
+        if list {
+            continue;
+        }
+        eprint!("[{}/{total}] Edit this document? [y/N/q] ", i + 1);
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        match answer.trim() {
+            "y" | "Y" => {
+                let name = edit_library_document(app, &doc.checksum)?;
+                println!("Updated {name}");
+            }
+            "q" | "Q" => { break; }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Prints the full record of a document, its files
+/// and the state of its modified copy.
+fn show_document(app : &mut AppState, uri : &str) -> Result<()> {
+    let doc = app.find_document(uri)?;
+    let field = |name : &str, value : &str| println!("{:<18}{value}", format!("{name}:"));
+    let list = |name : &str, values : &[String]| {
+        for (i, v) in values.iter().enumerate() {
+            field(if i == 0 { name } else { "" }, v);
+        }
+    };
+
+    field("title", &doc.title);
+    let authors : Vec<String> = doc.authors.iter()
+        .map(|a| match doc.orcids.get(a) {
+            Some(orcid) => format!("{a} ({orcid})"),
+            None => a.clone(),
+        })
+        .collect();
+    list("authors", &authors);
+    field("year", &doc.year.to_string());
+    list("context", &doc.context);
+    list("tags", &doc.tags);
+    list("keywords", &doc.keywords);
+    if let Some(citations) = doc.citations {
+        field("citations", &citations.to_string());
+    }
+    if let Some(status) = doc.status {
+        field("status", &status.to_string());
+    }
+    if let Some(t) = doc.doc_type {
+        field("type", &t.to_string());
+    }
+    if !doc.format.is_pdf() {
+        field("format", &doc.format.to_string());
+    }
+    field("id", &doc.short_id());
+    field("checksum", &doc.checksum);
+    if let Some(c) = &doc.raw_checksum {
+        field("file checksum", c);
+    }
+    let canonical = app.canonical_identifier(&doc)?;
+    let idents : Vec<String> = doc.identifiers.iter()
+        .map(|i| if *i == canonical { format!("{i} (canonical)") } else { i.clone() })
+        .collect();
+    list("identifiers", &idents);
+
+    let with_state = |path : PathBuf| {
+        let state = if path.exists() { "" } else { " (missing)" };
+        format!("{}{state}", path.display())
+    };
+    field("original file", &with_state(app.raw_file(&doc)));
+    field("modified file", &with_state(app.mod_path.join(&doc.filename)));
+    list("former filenames", &doc.former_filenames);
+
+    let imported = app.events.since(None)?
+        .into_iter()
+        .find(|e| e.kind == events::EventKind::Import && e.checksum == doc.checksum)
+        .map(|e| e.time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or("unknown".into());
+    field("imported", &imported);
+    match app.conversion_status(&doc)? {
+        None => field("conversion", "up to date"),
+        Some(reason) => field("conversion", &format!("outdated, {reason} (akl reconvert --uri {})", doc.short_id())),
+    }
+    let q = quality::quality(&doc);
+    field("quality", &format!("{}{}", q.score,
+        if q.missing.is_empty() { String::new() } else { format!(" (missing {})", q.missing.join(", ")) }));
+
+    let mut dests : Vec<(&String, &Vec<String>)> = doc.destinations.iter().collect();
+    dests.sort();
+    field("destinations", &dests.len().to_string());
+    for (name, values) in dests {
+        println!("    {name}  {}", values.join(", "));
+    }
+    if let Some(a) = &doc.r#abstract {
+        println!("\n{}", a.trim());
+    }
+    Ok(())
+}
+
+/// Lists the references and the citations of a document that are
+/// in the library, recording its S2 id and its number of citations.
+fn show_related(app : &mut AppState, uri : &str) -> Result<()> {
+    let doc = app.find_document(uri)?;
+    let paper = scholar::paper(&doc.identifiers)?
+        .with_context(|| format!("Semantic Scholar does not know {} (it needs a doi or an arxiv id)", doc.title))?;
+    let mut new = doc.clone();
+    if scholar::s2_id(&new.identifiers).is_none() {
+        new.identifiers.push(format!("s2:{}", paper.id));
+        identifiers::sort(&app.config.identifier_priority, &mut new.identifiers);
+    }
+    new.citations = paper.citations.or(new.citations);
+    if !journal::same(&doc, &new) {
+        app.update_document(&doc, &new)?;
+    }
+    let library = app.storage.documents()?;
+    for (label, papers) in [("cites", scholar::references(&paper.id)?), ("cited by", scholar::citations(&paper.id)?)] {
+        let known : Vec<&Document> = library.iter()
+            .filter(|d| d.checksum != doc.checksum && papers.iter().any(|p| p.is(d)))
+            .collect();
+        eprintln!("{label} {} papers, {} in the library", papers.len(), known.len());
+        for d in known {
+            println!("{label:<9} {}  {}  {}", d.short_id(), d.year, d.title);
+        }
+    }
+    Ok(())
+}
+
+/// Lets the user pick a document, then one of its named destinations
+/// with `--two-stage`, and returns the link citing the choice
+/// (`None` when the choice is cancelled).
+fn pick_document(app : &mut AppState, args : PickArgs) -> Result<Option<String>> {
+    let command = args.picker.clone()
+        .or(app.config.picker.clone())
+        .unwrap_or(picker::default_command(!app.desktop.headless).into());
+    let mut docs : Vec<Document> = app.storage.documents()?.into_iter()
+        .filter(|d| args.tag.as_ref().is_none_or(|t| d.tags.contains(t)))
+        .collect();
+    docs.sort_by(|a, b| (&a.authors, a.year, &a.title).cmp(&(&b.authors, b.year, &b.title)));
+    let choices : Vec<String> = docs.iter()
+        .map(|d| format!("{}  {}  {}  {}", d.short_id(), d.year, d.authors.join(", "), d.title))
+        .collect();
+    let Some(i) = picker::pick(&command, &choices)? else {
+        return Ok(None);
+    };
+    let doc = &docs[i];
+    let mut cite = CiteArgs { uri: app.canonical_identifier(doc)?, page: None, dest: None, from: None, resume: false };
+
+    if args.two_stage {
+        let path = app.raw_file(doc);
+        let pdf = load_pdf_document(&path.to_string_lossy(), None, &app.config)?;
+        let mut dests : Vec<&pdflib::NamedDestination> = pdf.destinations().iter().collect();
+        dests.sort_by(|a, b| (a.page_num, &a.name).cmp(&(b.page_num, &b.name)));
+        if dests.is_empty() {
+            log::info!("{} has no named destinations", doc.filename);
+        } else {
+            let choices : Vec<String> = dests.iter()
+                .map(|d| format!("{}  (page {})", d.name, d.page_num))
+                .collect();
+            let Some(j) = picker::pick(&command, &choices)? else {
+                return Ok(None);
+            };
+            cite.dest = Some(dests[j].name.clone());
+            cite.page = Some(dests[j].page_num);
+        }
+    }
+    command_to_query(Commands::Cite(cite)).map(Some)
+}
+
+/// Merges duplicates into the first document, moving
+/// their files to the trash, or lists the probable duplicates
+/// when no document is given.
+fn merge_documents(app : &mut AppState, uris : &[String]) -> Result<()> {
+    let Some((first, others)) = uris.split_first() else {
+        let docs = app.storage.documents()?;
+        for (a, b, reason) in duplicates::find(&docs) {
+            println!("{}\t{}\t{reason}", a.short_id(), b.short_id());
+            println!("    {}\n    {}", a.title, b.title);
+        }
+        return Ok(());
+    };
+    if others.is_empty() {
+        anyhow::bail!("Give the duplicates to merge into {first}");
+    }
+    let doc = app.find_document(first)?;
+    let mut merged = doc.clone();
+    let mut removed = vec![];
+    for uri in others {
+        let other = app.find_document(uri)?;
+        if other.checksum == doc.checksum || removed.iter().any(|d : &Document| d.checksum == other.checksum) {
+            continue;
+        }
+        duplicates::merge(&mut merged, &other);
+        removed.push(other);
+    }
+    identifiers::sort(&app.config.identifier_priority, &mut merged.identifiers);
+
+    // the collections refer to the documents by checksum
+    let mut collections = app.collections()?;
+    for c in &mut collections.collections {
+        let before = c.documents.len();
+        c.documents.retain(|d| !removed.iter().any(|r| r.checksum == *d));
+        if c.documents.len() != before && !c.documents.contains(&merged.checksum) {
+            c.documents.push(merged.checksum.clone());
+        }
+    }
+    collections.save()?;
+
+    app.update_document(&doc, &merged)?;
+    for other in &removed {
+        app.remove_to_trash(other)?;
+        app.record(events::EventKind::Edit, &merged, Some(format!("merged {}", other.filename)));
+        println!("Merged {} into {}", other.filename, merged.filename);
+    }
+    app.reconvert_if_needed(&merged)
+}
+
+/// Saves, deletes or lists the saved searches.
+fn manage_searches(app : &mut AppState, action : SearchCommands) -> Result<()> {
+    let mut searches = app.searches()?;
+    match action {
+        SearchCommands::Save { name, query } => {
+            searches.set(&name, &query)?;
+        }
+        SearchCommands::Run { .. } => {
+            // running a saved search is a find, handled by `execute_command`
+            unreachable!("saved searches are run by execute_command");
+        }
+        SearchCommands::List => {
+            for (name, query) in &searches.searches {
+                println!("{name}\t{query}");
+            }
+        }
+        SearchCommands::Delete { name } => {
+            searches.delete(&name)?;
+        }
+    }
+    searches.save()
+}
+
+/// Number of papers asked to arXiv for each subscription
+/// by a fetch of the inbox.
+const INBOX_RESULTS : usize = 100;
+
+/// The import of a paper of the inbox.
+fn inbox_import_args(entry : &inbox::Entry) -> ImportArgs {
+    ImportArgs {
+        uri: format!("arxiv:{}", entry.arxiv),
+        title: Some(entry.title.clone()),
+        authors: entry.authors.clone(),
+        context: vec![],
+        identifiers: vec![],
+        year: Some(chrono::Datelike::year(&entry.published) as u32),
+        doc_type: Some(doctype::DocType::Preprint),
+        tags: vec![],
+        view: false,
+        force: false,
+        batch: None,
+        bibtex: None,
+        zotero: None,
+        papis: None,
+        pubs: None,
+        stdin: false,
+        keep_local: vec![],
+        local: false,
+        metadata_only: false,
+        snapshot: false,
+    }
+}
+
+/// The arguments of the import of a pdf file of the
+/// downloads folder.
+fn scan_import_args(path : &Path) -> ImportArgs {
+    ImportArgs {
+        uri: path.to_string_lossy().to_string(),
+        title: None,
+        authors: vec![],
+        context: vec![],
+        identifiers: scan::name_identifiers(path),
+        year: None,
+        doc_type: None,
+        tags: vec![],
+        view: false,
+        force: false,
+        batch: None,
+        bibtex: None,
+        zotero: None,
+        papis: None,
+        pubs: None,
+        stdin: false,
+        keep_local: vec![],
+        local: false,
+        metadata_only: false,
+        snapshot: false,
+    }
+}
+
+/// Shows the pdf files of a folder (by default, the downloads
+/// folder) that are not in the library, and imports the ones
+/// the user chooses.
+fn scan_downloads(app : &mut AppState, dir : Option<PathBuf>, interactive : bool) -> Result<()> {
+    let folder = match dir.or_else(|| app.config.downloads_folder.clone()) {
+        Some(folder) => folder,
+        None => scan::default_folder()?,
+    };
+    let mut missing = vec![];
+    for path in scan::pdf_files(&folder)? {
+        let loaded = load_pdf_document(&path.to_string_lossy(), None, &app.config)
+            .and_then(|mut pdf| Ok((pdf.get_checksum()?, pdf.get_meta_data().unwrap_or_default())));
+        match loaded {
+            Ok((checksum, met)) => {
+                if app.storage.find_by_checksum(&checksum)?.is_none() {
+                    missing.push((path, met));
+                }
+            }
+            Err(e) => { log::warn!("Skipping {}: {e:#}", path.display()); }
+        }
+    }
+    if missing.is_empty() {
+        println!("The pdf files of {} are all in the library", folder.display());
+        return Ok(());
+    }
+
+    let total = missing.len();
+    for (i, (path, met)) in missing.into_iter().enumerate() {
+        let mut guessed = scan::name_identifiers(&path);
+        guessed.extend(met.identifiers.into_iter().filter(|i| !guessed.contains(i)).collect::<Vec<String>>());
+        println!("[{}/{total}] {}", i + 1, path.display());
+        println!("    title:       {}", met.title.as_deref().filter(|t| !t.trim().is_empty()).unwrap_or("?"));
+        if !met.authors.is_empty() {
+            println!("    authors:     {}", met.authors.join(", "));
+        }
+        println!("    identifiers: {}", if guessed.is_empty() { "?".into() } else { guessed.join(", ") });
+        match scan::ask_key("Import it? [y/N/q]", &['n', 'y', 'q'])? {
+            'y' => match import_document(app, scan_import_args(&path), interactive) {
+                Ok(name) => println!("Imported {name}"),
+                Err(e) => eprintln!("Could not import {}: {e:#}", path.display()),
+            },
+            'q' => break,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Fetches, lists, imports or drops the papers of the inbox,
+/// or scans the downloads folder.
+fn manage_inbox(app : &mut AppState, action : InboxCommands, interactive : bool) -> Result<()> {
+    let mut inbox = app.inbox()?;
+    match action {
+        InboxCommands::Scan { dir } => {
+            return scan_downloads(app, dir, interactive);
+        }
+        InboxCommands::Fetch => {
+            if inbox.subscriptions().is_empty() && inbox.followed().is_empty() {
+                anyhow::bail!("No subscriptions: subscribe to arXiv categories with akl subscribe, \
+                               or follow authors with akl follow");
+            }
+            // the papers of the library, whatever their version
+            let library : HashSet<String> = app.storage.documents()?.iter()
+                .filter_map(|d| arxiv::parse_id(&identifiers::arxiv_id(&d.identifiers)?))
+                .map(|a| a.id)
+                .collect();
+            let mut added = 0;
+            for category in inbox.subscriptions().to_vec() {
+                match arxiv::search(&format!("cat:{category}"), INBOX_RESULTS) {
+                    Ok(mut found) => {
+                        found.retain(|p| !library.contains(&p.id.id));
+                        let n = inbox.add(&category, &found);
+                        println!("{category}: {n} new papers");
+                        added += n;
+                    }
+                    Err(e) => { eprintln!("Could not fetch the papers of {category}: {e:#}"); }
+                }
+            }
+            for followed in inbox.followed().to_vec() {
+                match followed.papers(INBOX_RESULTS) {
+                    Ok(mut found) => {
+                        found.retain(|p| !library.contains(&p.id.id));
+                        let n = inbox.add(&followed.subscription(), &found);
+                        println!("{}: {n} new papers", followed.name);
+                        added += n;
+                    }
+                    Err(e) => { eprintln!("Could not fetch the papers of {}: {e:#}", followed.name); }
+                }
+            }
+            if added > 0 {
+                app.desktop.notify(&format!("📥 {added} new papers in the inbox"), "akl inbox list")?;
+            }
         }
-        "arxiv" => {
-            parse_arxiv(nice_url)
+        InboxCommands::List => {
+            for (i, entry) in inbox.entries().iter().enumerate() {
+                println!("{}	{}	{}	{}	[{}]", i + 1, entry.published.format("%Y-%m-%d"), entry.title,
+                         entry.authors.join(", "), entry.reasons.join(", "));
+            }
+            return Ok(());
         }
-        "doi" => {
-            parse_doi(nice_url)
+        InboxCommands::Show { n } => {
+            let entry = inbox.get(n)?;
+            println!("{}", entry.title);
+            println!("{}", entry.authors.join(", "));
+            println!("https://arxiv.org/abs/{} ({}, {})", entry.arxiv, entry.published.format("%Y-%m-%d"), entry.reasons.join(", "));
+            if !entry.r#abstract.is_empty() {
+                println!("\n{}", entry.r#abstract);
+            }
+            return Ok(());
         }
-        "akl" => {
-            let name = nice_url.host_str()
-                               .unwrap_or("");
-            let query = nice_url.query().unwrap_or("");
-            Ok(ParsedURI::AklCommand(query_to_command(name, query)?))
+        InboxCommands::Import { n } => {
+            let entries = n.iter().map(|n| inbox.get(*n).cloned()).collect::<Result<Vec<inbox::Entry>>>()?;
+            let mut imported = vec![];
+            for entry in &entries {
+                match import_document(app, inbox_import_args(entry), interactive) {
+                    Ok(name) => {
+                        println!("Imported {name}");
+                        imported.push(entry.arxiv.clone());
+                    }
+                    Err(e) => { eprintln!("Could not import {}: {e:#}", entry.arxiv); }
+                }
+            }
+            inbox.remove(&imported);
         }
-        x => {
-            log::info!("No provider attached to scheme {x}");
-            anyhow::bail!("No provider attached to scheme {x}")
+        InboxCommands::Drop { all: true, .. } => {
+            let every : Vec<String> = inbox.entries().iter().map(|e| e.arxiv.clone()).collect();
+            inbox.remove(&every);
+        }
+        InboxCommands::Drop { n, all: false } => {
+            let dropped = n.iter().map(|n| Ok(inbox.get(*n)?.arxiv.clone())).collect::<Result<Vec<String>>>()?;
+            inbox.remove(&dropped);
         }
     }
+    inbox.save()
 }
 
-/// Process URI or a filepath
-fn uri_or_filepath_dispatch (uri : &str) -> Result<ParsedURI> {
-    match uri_dispatch (uri) {
-        Ok(r) => { Ok(r) }
+/// Rewrites the authors of the documents of the library to
+/// their canonical name in the registry.
+fn apply_author_registry(app : &mut AppState, registry : &authors::Authors) -> Result<()> {
+    for doc in app.storage.documents()? {
+        let mut names : Vec<String> = vec![];
+        let mut orcids = BTreeMap::new();
+        for a in &doc.authors {
+            let orcid = doc.orcids.get(a);
+            let n = match orcid.and_then(|o| registry.with_orcid(o)) {
+                Some(author) => author.name.clone(),
+                None if registry.get(a).is_some() => registry.canonical(a),
+                None => a.clone(),
+            };
+            if !names.contains(&n) {
+                if let Some(orcid) = orcid {
+                    orcids.insert(n.clone(), orcid.clone());
+                }
+                names.push(n);
+            }
+        }
+        if names == doc.authors {
+            continue;
+        }
+        let mut new = Document { authors: names, orcids, ..doc.clone() };
+        new.filename = new.generate_name(&app.config)?;
+        let new = app.move_document(&doc, new)?;
+        app.record(events::EventKind::Edit, &new,
+                   Some(format!("authors {}", new.authors.join(", "))));
+        println!("{}: {}", new.short_id(), new.authors.join(", "));
+    }
+    Ok(())
+}
+
+fn manage_authors(app : &mut AppState, action : AuthorsCommands) -> Result<()> {
+    let mut registry = app.authors()?;
+    match action {
+        AuthorsCommands::List => {
+            for a in &registry.authors {
+                let orcid = a.orcid.as_deref().map(|o| format!(" ({o})")).unwrap_or_default();
+                println!("{}{orcid}", a.name);
+                for alias in &a.aliases {
+                    println!("\t{alias}");
+                }
+            }
+            return Ok(());
+        }
+        AuthorsCommands::Add { name, alias, orcid } => {
+            let orcid = orcid.map(|o| authors::normalize_orcid(&o).with_context(|| format!("{o} is not an ORCID")))
+                .transpose()?;
+            registry.add(&name, orcid)?;
+            for a in alias {
+                registry.add_alias(&name, &a)?;
+            }
+        }
+        AuthorsCommands::Alias { name, alias } => {
+            registry.add_alias(&name, &alias)?;
+        }
+        AuthorsCommands::Merge { into, names } => {
+            registry.merge(&into, &names)?;
+            registry.save()?;
+            return apply_author_registry(app, &registry);
+        }
+        AuthorsCommands::Suggest => {
+            let mut counts : BTreeMap<String, usize> = BTreeMap::new();
+            let mut orcids : BTreeMap<String, String> = BTreeMap::new();
+            for doc in app.storage.documents()? {
+                for a in doc.authors {
+                    *counts.entry(a).or_default() += 1;
+                }
+                for (a, orcid) in doc.orcids {
+                    orcids.entry(a).or_insert(orcid);
+                }
+            }
+            let counts : Vec<(String, usize)> = counts.into_iter().collect();
+            for group in authors::similar_names(&counts, &orcids) {
+                println!("{}", group.join("\t"));
+            }
+            return Ok(());
+        }
+        AuthorsCommands::Remove { name } => {
+            registry.remove(&name)?;
+        }
+    }
+    registry.save()
+}
+
+fn manage_status(app : &mut AppState, action : StatusCommands) -> Result<()> {
+    match action {
+        StatusCommands::Set { uri, status } => {
+            let doc = app.find_document(&uri)?;
+            let new = Document { status: Some(status), ..doc.clone() };
+            app.update_document(&doc, &new)?;
+            app.record(events::EventKind::Edit, &new, Some(format!("status {status}")));
+        }
+        StatusCommands::Clear { uri } => {
+            let doc = app.find_document(&uri)?;
+            let new = Document { status: None, ..doc.clone() };
+            app.update_document(&doc, &new)?;
+            app.record(events::EventKind::Edit, &new, Some("status cleared".into()));
+        }
+        StatusCommands::List { status } => {
+            let mut docs : Vec<(reading::ReadingStatus, Document)> = app.storage.documents()?
+                .into_iter()
+                .filter_map(|d| Some((d.status?, d)))
+                .filter(|(s, _)| status.is_none_or(|status| *s == status))
+                .collect();
+            docs.sort_by(|(s, a), (t, b)| s.cmp(t).then_with(|| a.title.cmp(&b.title)));
+            for (s, doc) in docs {
+                println!("{:<8}  {s:<8}  {}", doc.short_id(), doc.title);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn manage_tags(app : &mut AppState, action : TagCommands) -> Result<()> {
+    match action {
+        TagCommands::Add { uri, tags } => {
+            let doc = app.find_document(&uri)?;
+            let mut new = doc.clone();
+            new.add_tags(&tags);
+            app.update_document(&doc, &new)?;
+            app.record(events::EventKind::Edit, &new, Some(format!("tagged {}", tags.join(", "))));
+        }
+        TagCommands::Rm { uri, tags } => {
+            let doc = app.find_document(&uri)?;
+            let mut new = doc.clone();
+            new.tags.retain(|t| !tags.contains(t));
+            app.update_document(&doc, &new)?;
+            app.record(events::EventKind::Edit, &new, Some(format!("untagged {}", tags.join(", "))));
+        }
+        TagCommands::List { uri: Some(uri) } => {
+            app.find_document(&uri)?.tags.iter()
+                .for_each(|t| println!("{t}"));
+        }
+        TagCommands::List { uri: None } => {
+            let mut counts : std::collections::BTreeMap<String, usize> = Default::default();
+            for doc in app.storage.documents()? {
+                for t in doc.tags {
+                    *counts.entry(t).or_default() += 1;
+                }
+            }
+            counts.iter().for_each(|(t, n)| println!("{t}\t{n}"));
+        }
+    }
+    Ok(())
+}
+
+/// Replaces a document of the library by a new import.
+/// The previous version goes to the trash, and is put back
+/// if the import fails.
+///
+/// The tags and identifiers of the previous version are kept, and
+/// so are the fields listed in `--keep-local`. In interactive mode,
+/// the user chooses between the two values of the other fields that
+/// changed, then edits the result.
+fn reimport_document(app : &mut AppState, doc : &Document, args : ImportArgs, interactive : bool) -> Result<String> {
+    let keep = args.keep_local.clone();
+    app.remove_to_trash(doc)?;
+    let name = import_document(app, args, false).or_else(|e| {
+        log::warn!("Import failed, restoring {}", doc.filename);
+        let entry = app.trash.find(&doc.checksum)?;
+        app.restore_from_trash(&entry)?;
+        Err(e)
+    })?;
+
+    let imported = app.find_by_filename(&name)?
+        .context("Finding the imported document")?;
+    let mut resolved = imported.clone();
+    conflicts::preserve(doc, &mut resolved);
+    identifiers::sort(&app.config.identifier_priority, &mut resolved.identifiers);
+    let mut kept = vec![];
+    for conflict in conflicts::conflicts(doc, &imported) {
+        if keep.contains(&conflict.field) || (interactive && conflicts::ask(&conflict)?) {
+            conflicts::keep_local(conflict.field, doc, &mut resolved);
+            kept.push(conflict.field.to_string());
+        } else {
+            log::info!("The {} of {name} changed from {} to {}", conflict.field, conflict.local, conflict.imported);
+        }
+    }
+    resolved.filename = resolved.generate_name(&app.config)?;
+    let resolved = app.move_document(&imported, resolved)?;
+    // the names of the destinations of the previous version
+    let mut anchors = app.anchors()?;
+    if let Some(table) = anchors.tables.remove(&doc.checksum) {
+        anchors.table_mut(&resolved).merge(&table);
+        anchors.save()?;
+    }
+    if !kept.is_empty() {
+        app.record(events::EventKind::Edit, &resolved, Some(format!("kept the {} of the library", kept.join(", "))));
+    }
+    app.reconvert_if_needed(&resolved)?;
+
+    if interactive {
+        return edit_library_document(app, &resolved.checksum);
+    }
+    Ok(resolved.filename)
+}
+
+/// Attaches a pdf file to a metadata-only document: the document
+/// gets the checksum of the file, and keeps its metadata and its id.
+fn attach_document(app : &mut AppState, doc : &Document, file : &str) -> Result<String> {
+    if !doc.metadata_only {
+        anyhow::bail!("{} already has a pdf file, use akl import --force to replace it", doc.filename);
+    }
+    let mut loaded = Loaded::default();
+    let mut pdf = load_pdf_document(file, Some(&mut loaded), &app.config)?;
+    let checksum = pdf.get_checksum()?;
+    if let Some(existing) = app.storage.find_by_checksum(&checksum)? {
+        anyhow::bail!("{file} is already in the library as {}", existing.filename);
+    }
+    let mut new = doc.clone();
+    new.checksum = checksum;
+    new.metadata_only = false;
+    new.identifiers.extend(loaded.identifiers.into_iter().filter(|i| !doc.identifiers.contains(i)));
+    identifiers::sort(&app.config.identifier_priority, &mut new.identifiers);
+    app.delete(doc)?;
+    app.journal(journal::OpKind::Remove, Some(doc), None);
+    app.add_document(&mut new, pdf)?;
+    app.record(events::EventKind::Import, &new, Some(format!("attached {file}")));
+    Ok(new.filename)
+}
+
+/// Replaces a document imported from arXiv by the latest version of
+/// the paper, if it is newer. The document keeps its id, filename,
+/// tags, reading status, collections and other fields (the metadata
+/// of arXiv rarely changes between versions), the annotations of its
+/// modified file are copied to the new one (see
+/// `import_annotations_of_version`), and its previous original file
+/// is archived as `versions/<id>/v<n>.pdf` in the data directory.
+/// Returns the filename of the updated document, `None` when it
+/// already is the latest version.
+fn update_document(app : &mut AppState, doc : &Document) -> Result<Option<String>> {
+    if doc.metadata_only {
+        anyhow::bail!("{} has no pdf file, see akl attach", doc.filename);
+    }
+    let paper = identifiers::arxiv_id(&doc.identifiers)
+        .and_then(|a| arxiv::parse_id(&a))
+        .with_context(|| format!("{} was not imported from arXiv", doc.filename))?;
+    let version : u32 = paper.version.as_deref().and_then(|v| v.parse().ok())
+        .with_context(|| format!("The arXiv version of {} is unknown", doc.filename))?;
+    let latest : u32 = arxiv::latest_versions(std::slice::from_ref(&paper.id))?
+        .into_iter()
+        .find(|p| p.id == paper.id)
+        .and_then(|p| p.version?.parse().ok())
+        .with_context(|| format!("arXiv does not know {}", paper.id))?;
+    if latest <= version {
+        return Ok(None);
+    }
+
+    let uri = format!("arxiv:{}v{latest}", paper.id);
+    let mut loaded = Loaded::default();
+    let pdoc = load_pdf_document(&uri, Some(&mut loaded), &app.config)?;
+
+    let mut new = doc.clone();
+    new.identifiers.retain(|i| {
+        Url::parse(i).ok().and_then(|u| arxiv::from_identifier(&u)).is_none_or(|p| p.id != paper.id)
+    });
+    new.identifiers.push(uri.clone());
+    for ident in loaded.identifiers.iter().map(|i| identifiers::canonicalize(i)) {
+        if !new.identifiers.contains(&ident) {
+            new.identifiers.push(ident);
+        }
+    }
+    identifiers::sort(&app.config.identifier_priority, &mut new.identifiers);
+
+    replace_version(app, doc, &mut new, pdoc, version)?;
+    app.record(events::EventKind::Import, &new, Some(format!("updated from version {version} to {latest} of arXiv")));
+    Ok(Some(new.filename))
+}
+
+/// Replaces a document by `new`, the metadata of another version of
+/// its paper whose file is `pdoc`: the annotations of the modified
+/// file are copied to the new one, the original file moves out of
+/// the store to `versions/<id>/v<version>.pdf`, and the collections
+/// and the names of destinations follow the new checksum.
+fn replace_version(app : &mut AppState, doc : &Document, new : &mut Document,
+                   mut pdoc : pdflib::PdfDocument, version : u32) -> Result<()> {
+    let checksum = pdoc.get_checksum()?;
+    if let Some(existing) = app.storage.find_by_checksum(&checksum)? {
+        anyhow::bail!("This version is already in the library as {}", existing.filename);
+    }
+    let modified = app.mod_path.join(&doc.filename);
+    if modified.exists() {
+        let previous = lopdf::Document::load(&modified)
+            .map_err(anyhow::Error::from)
+            .and_then(|pdf| Ok(pdflib::PdfDocument::try_from(pdf)?));
+        match previous {
+            Ok(previous) => {
+                let count = pdoc.import_annotations_of_version(&previous)?;
+                if count > 0 {
+                    log::info!("Kept {count} annotations of version {version} of {}", doc.filename);
+                }
+            }
+            Err(e) => { log::warn!("Could not read the previous copy of {}, its annotations are lost: {e:#}", doc.filename); }
+        }
+    }
+
+    let raw = app.raw_file(doc);
+    let archive = app.data_path.join("versions").join(doc.short_id());
+    std::fs::create_dir_all(&archive)
+        .with_context(|| format!("Creating {archive:?}"))?;
+
+    new.id = doc.short_id();
+    new.checksum = checksum;
+    app.journal.keep(&[("raw.pdf", &raw), ("mod.pdf", &modified)])?;
+    app.delete(doc)?;
+    app.journal(journal::OpKind::Remove, Some(doc), None);
+    app.add_document(new, pdoc)?;
+    // the store only keeps the files of the documents of the index
+    std::fs::rename(&raw, archive.join(format!("v{version}.pdf")))
+        .with_context(|| format!("Archiving version {version} of {}", doc.filename))?;
+    if new.filename != doc.filename && modified.exists() {
+        std::fs::remove_file(&modified)
+            .with_context(|| format!("Removing {modified:?}"))?;
+    }
+
+    // the collections and the names of destinations refer to the documents by checksum
+    let mut collections = app.collections()?;
+    for c in &mut collections.collections {
+        for d in c.documents.iter_mut().filter(|d| **d == doc.checksum) {
+            d.clone_from(&new.checksum);
+        }
+    }
+    collections.save()?;
+    let mut anchors = app.anchors()?;
+    if let Some(table) = anchors.tables.remove(&doc.checksum) {
+        anchors.table_mut(new).merge(&table);
+        anchors.save()?;
+    }
+    Ok(())
+}
+
+/// Sends the documents that are not in Zotero yet to the running
+/// Zotero, or writes them to a file that Zotero imports. Documents
+/// given explicitly are sent again.
+fn export_to_zotero(app : &mut AppState, uri : Vec<String>, output : Option<PathBuf>) -> Result<()> {
+    let mut exported = zotero::Exported::load(&app.data_path.join("zotero.yaml"))?;
+    let library = app.storage.documents()?;
+    let docs = if uri.is_empty() {
+        library.iter()
+               .filter(|d| !exported.checksums.contains(&d.checksum))
+               .cloned()
+               .collect()
+    } else {
+        uri.iter()
+           .map(|u| app.find_document(u))
+           .collect::<Result<Vec<Document>>>()?
+    };
+    if docs.is_empty() {
+        println!("Zotero already has every document of the library");
+        return Ok(());
+    }
+
+    match output {
+        Some(o) => {
+            let mut out = std::fs::File::create(&o).with_context(|| format!("Creating {o:?}"))?;
+            export::export(&mut out, &library, &docs, export::ExportFormat::Zotero, &app.mod_path)?;
+            println!("Wrote {} documents to {}, to import in Zotero", docs.len(), o.display());
+        }
+        None => {
+            let keys = bibtex::citation_keys(&library);
+            let items = docs.iter()
+                .map(|doc| {
+                    let open = command_to_query(Commands::Open(CiteArgs {
+                        uri: doc.checksum.clone(), page: None, dest: None, from: None, resume: false,
+                    }))?;
+                    let key = keys.get(&doc.checksum).context("No citation key")?;
+                    Ok(zotero::connector_item(key, doc, &open))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            zotero::push(&items)?;
+            println!("Sent {} documents to Zotero", docs.len());
+        }
+    }
+    exported.checksums.extend(docs.into_iter().map(|d| d.checksum));
+    exported.save()
+}
+
+/// Names the destinations of the documents, and shares these names.
+fn manage_anchors(app : &mut AppState, action : AnchorsCommands) -> Result<()> {
+    let mut anchors = app.anchors()?;
+    match action {
+        AnchorsCommands::Set { uri, kind, name, dest } => {
+            let doc = app.find_document(&uri)?;
+            anchors.table_mut(&doc).set(kind, &name, &dest);
+        }
+        AnchorsCommands::Remove { uri, kind, name } => {
+            let doc = app.find_document(&uri)?;
+            if !anchors.table_mut(&doc).remove(kind, &name) {
+                anyhow::bail!("{name} does not name a destination of {}", doc.title);
+            }
+        }
+        AnchorsCommands::List { uri } => {
+            let doc = app.find_document(&uri)?;
+            for (kind, name, dest) in anchors.table(&doc).entries() {
+                println!("{:<8}  {name}\t{dest}", format!("{kind:?}").to_lowercase());
+            }
+            return Ok(());
+        }
+        AnchorsCommands::Export { uri, output } => {
+            let doc = app.find_document(&uri)?;
+            let shared = anchors::SharedTable::new(&doc, anchors.table(&doc));
+            let out : Box<dyn std::io::Write> = match output {
+                Some(o) => Box::new(std::fs::File::create(&o).with_context(|| format!("Creating {o:?}"))?),
+                None => Box::new(std::io::stdout().lock()),
+            };
+            serde_yaml::to_writer(out, &shared)?;
+            return Ok(());
+        }
+        AnchorsCommands::Import { path } => {
+            let file = std::fs::File::open(&path)
+                .with_context(|| format!("Opening {path:?}"))?;
+            let shared : anchors::SharedTable = serde_yaml::from_reader(file)
+                .with_context(|| format!("Parsing {path:?}"))?;
+            let doc = match app.storage.find_by_checksum(&shared.checksum)? {
+                Some(doc) => doc,
+                None => shared.identifiers.iter()
+                    .find_map(|i| app.find_document(i).ok())
+                    .with_context(|| format!("{} is not in the library", shared.title))?,
+            };
+            let (added, conflicts) = anchors.table_mut(&doc).merge(&shared.table);
+            println!("Added {added} names to {}", doc.title);
+            for (kind, name, kept, ignored) in conflicts {
+                println!("  kept the {} {name} → {kept} (instead of {ignored})",
+                         format!("{kind:?}").to_lowercase());
+            }
+        }
+    }
+    anchors.save()
+}
+
+/// Creates, fills and lists collections.
+fn manage_collections(app : &mut AppState, action : CollectionCommands) -> Result<()> {
+    let mut collections = app.collections()?;
+    match action {
+        CollectionCommands::Create { name, query } => {
+            collections.create(&name, query)?;
+        }
+        CollectionCommands::Add { name, uris } => {
+            let checksums = uris.iter()
+                .map(|u| app.find_document(u).map(|d| d.checksum))
+                .collect::<Result<Vec<String>>>()?;
+            let collection = collections.get_mut(&name)?;
+            for c in checksums {
+                if !collection.documents.contains(&c) {
+                    collection.documents.push(c);
+                }
+            }
+        }
+        CollectionCommands::Rm { name, uris } => {
+            let checksums = uris.iter()
+                .map(|u| app.find_document(u).map(|d| d.checksum))
+                .collect::<Result<Vec<String>>>()?;
+            collections.get_mut(&name)?
+                .documents.retain(|c| !checksums.contains(c));
+        }
+        CollectionCommands::Delete { name } => {
+            collections.delete(&name)?;
+        }
+        CollectionCommands::List => {
+            let docs = app.storage.documents()?;
+            for c in &collections.collections {
+                let mut count = 0;
+                for d in &docs {
+                    if c.contains(d)? {
+                        count += 1;
+                    }
+                }
+                match &c.query {
+                    Some(q) => println!("{}\t{count}\t{q}", c.name),
+                    None => println!("{}\t{count}", c.name),
+                }
+            }
+            return Ok(());
+        }
+    }
+    collections.save()
+}
+
+fn import_document(app : &mut AppState, args : ImportArgs, interactive : bool) -> Result<String> {
+    if args.metadata_only {
+        let ident = match uri_or_filepath_dispatch(&args.uri, &app.config.providers) {
+            Ok(ParsedURI::DOI(doi)) => format!("doi:{doi}"),
+            Ok(ParsedURI::Rxiv(preprint)) => format!("doi:{}", preprint.doi),
+            Ok(ParsedURI::Isbn(isbn)) => format!("isbn:{isbn}"),
+            Ok(ParsedURI::Pubmed(id)) => id.identifier(),
+            _ => anyhow::bail!("Only dois, isbns and PubMed articles can be imported without their pdf file, not {}", args.uri),
+        };
+        let loaded = Loaded { identifiers: vec![ident], ..Loaded::default() };
+        return import_loaded_document(app, args, None, loaded, interactive);
+    }
+    let file = match uri_or_filepath_dispatch(&args.uri, &app.config.providers)? {
+        ParsedURI::HttpURL(url) if args.snapshot => Some(snapshot_page(&url, &app.config)?),
+        _ if args.snapshot => anyhow::bail!("Only web pages have snapshots, not {}", args.uri),
+        ParsedURI::FilePath(p) => other_file(Path::new(&p))?,
+        _ => None,
+    };
+    if let Some(file) = file {
+        let loaded = Loaded { file: Some(file), ..Loaded::default() };
+        return import_loaded_document(app, args, None, loaded, interactive);
+    }
+    let mut loaded = Loaded::default();
+    let pdf = load_pdf_document(&args.uri, Some(&mut loaded), &app.config)?;
+    import_loaded_document(app, args, Some(pdf), loaded, interactive)
+}
+
+/// The metadata registered for a document with the given
+/// identifiers, and where it comes from: for its bioRxiv or
+/// medRxiv preprint, for its doi, or else
+/// for its isbn (OpenLibrary), its HAL deposit, its ePrint or
+/// ECCC report, or its PubMed or PMC article.
+fn registered_metadata(crossref : &crossref::Crossref, idents : &[String]) -> Option<(crossref::Work, report::Source)> {
+    if let Some(preprint) = identifiers::rxiv_preprint(idents) {
+        match rxiv::details(&preprint) {
+            Ok(Some((_, work))) => return Some((work, report::Source::Rxiv)),
+            Ok(None) => log::info!("The servers do not know the preprint {}", preprint.doi),
+            Err(e) => log::warn!("Could not query the servers for {}: {e:#}", preprint.doi),
+        }
+    }
+    if let Some(doi) = identifiers::doi(idents) {
+        return doi_metadata(crossref, &doi);
+    }
+    if let Some(isbn) = identifiers::isbn(idents) {
+        return openlibrary::book(&isbn)
+            .unwrap_or_else(|e| { log::warn!("Could not query OpenLibrary for {isbn}: {e:#}"); None })
+            .map(|book| (book, report::Source::OpenLibrary));
+    }
+    if let Some(id) = identifiers::hal_id(idents) {
+        let (id, _) = hal::parse_id(&id)?;
+        return hal::record(&id)
+            .unwrap_or_else(|e| { log::warn!("Could not query HAL for {id}: {e:#}"); None })
+            .map(|record| (record, report::Source::Hal));
+    }
+    if let Some(id) = identifiers::iacr_id(idents) {
+        let (id, _) = iacr::parse_id(&id)?;
+        return iacr::record(&id)
+            .unwrap_or_else(|e| { log::warn!("Could not read the ePrint page of {id}: {e:#}"); None })
+            .map(|record| (record, report::Source::Iacr));
+    }
+    if let Some(id) = identifiers::eccc_id(idents) {
+        let (id, _) = eccc::parse_id(&id)?;
+        return eccc::record(&id)
+            .unwrap_or_else(|e| { log::warn!("Could not read the ECCC page of {id}: {e:#}"); None })
+            .map(|record| (record, report::Source::Eccc));
+    }
+    if let Some(id) = identifiers::pubmed_id(idents) {
+        return pubmed::summary(&id)
+            .unwrap_or_else(|e| { log::warn!("Could not query the E-utilities for {}: {e:#}", id.identifier()); None })
+            .map(|summary| (summary, report::Source::Pubmed));
+    }
+    None
+}
+
+/// The metadata registered for a doi, and where it comes from:
+/// Crossref, or else the agency of the doi through doi.org.
+fn doi_metadata(crossref : &crossref::Crossref, doi : &str) -> Option<(crossref::Work, report::Source)> {
+    match crossref.work(doi) {
+        Ok(Some(work)) => return Some((work, report::Source::Crossref)),
+        Ok(None) => log::info!("Crossref does not know {doi}, asking doi.org"),
+        Err(e) => log::warn!("Could not query the Crossref API for {doi}: {e:#}"),
+    }
+    match negotiation::work(doi) {
+        Ok(work) => work.map(|w| (w, report::Source::DoiOrg)),
         Err(e) => {
-            let s : String = uri.into();
-            let p = PathBuf::from(s);
-            if p.exists() {
-                Ok(ParsedURI::FilePath(p))
-            } else {
-                log::error!("Error when parsing the uri {e:?}");
-                log::error!("The url {uri} is neither a valid scheme nor a path on the system");
-                anyhow::bail!("I don't know how to handle {uri}")
+            log::warn!("Could not ask doi.org for the metadata of {doi}: {e:#}");
+            None
+        }
+    }
+}
+
+/// The checksum of a metadata-only document, which has no file
+/// to hash: the hash of its doi or isbn identifier (dois are
+/// case insensitive).
+fn metadata_checksum(ident : &str) -> String {
+    let hash = Sha256::digest(ident.to_lowercase().as_bytes());
+    format!("{hash:x}")
+}
+
+/// A document being imported, with the metadata gathered from its
+/// file and from the APIs, before it joins the library.
+struct Draft {
+    /// The document, without id nor filename yet.
+    doc      : Document,
+    /// The file of the document, when it is not a pdf file.
+    file     : Option<OtherFile>,
+    /// The ORCIDs of its authors, by name.
+    orcids   : Vec<(String, String)>,
+    /// Where each field comes from, for the import report.
+    sources  : BTreeMap<String, Vec<report::Source>>,
+    warnings : Vec<String>,
+}
+
+/// Gathers the metadata of a document to import: the ones of its
+/// file, of its landing page and of the metadata APIs. This asks the
+/// network but does not touch the library, so that the workers of a
+/// batch import draft their documents at the same time.
+fn draft_document(args : &ImportArgs,
+                  mut pdf : Option<&mut pdflib::PdfDocument>,
+                  loaded : Loaded,
+                  config : &config::Config,
+                  cache_path : &Path) -> Result<Draft> {
+    let Loaded { identifiers: mut t_identifiers, landing, registered, file } = loaded;
+    let ImportArgs { uri, authors, title, context, identifiers, year, doc_type, .. } = args.clone();
+    let identifiers : Vec<String> = identifiers.iter().map(|i| identifiers::canonicalize(i)).collect();
+    let t_checksum = match (pdf.as_deref_mut(), &file) {
+        (Some(pdf), _) => pdf.get_checksum()?,
+        (None, Some(file)) => verify::file_checksum(&file.path)?,
+        (None, None) => metadata_checksum(t_identifiers.first().context("A metadata-only document needs a doi or an isbn")?),
+    };
+
+    let met = match (pdf.as_deref_mut(), &file) {
+        (Some(pdf), _) => pdf.get_meta_data()?,
+        (None, Some(file)) => file.metadata.clone(),
+        (None, None) => pdflib::PdfMetaData::default(),
+    };
+
+    // where each field comes from, for the import report
+    use report::Source;
+    let mut sources : BTreeMap<String, Vec<Source>> = BTreeMap::new();
+    let mut source = |field : &str, from : Source, present : bool| {
+        if present {
+            sources.entry(field.into()).or_default().push(from);
+        }
+    };
+    let mut warnings = vec![];
+
+    // the metadata registered for the doi, then the one of
+    // the landing page, are better than the Info dictionary
+    // of the pdf
+    let page = landing.unwrap_or_default();
+    let crossref = crossref::Crossref::new(cache_path);
+    let known = [t_identifiers.as_slice(), &page.identifiers, &met.identifiers, &identifiers].concat();
+    let (work, registrar) = match registered {
+        Some(work) => (work, Source::Provider),
+        None => registered_metadata(&crossref, &known)
+            .unwrap_or((crossref::Work::default(), Source::Crossref)),
+    };
+
+    source("authors", Source::CommandLine, !authors.is_empty());
+    source("authors", registrar, authors.is_empty() && !work.authors.is_empty());
+    source("authors", Source::LandingPage, authors.is_empty() && work.authors.is_empty() && !page.authors.is_empty());
+    source("authors", Source::PdfMetadata, authors.is_empty() && work.authors.is_empty() && page.authors.is_empty() && !met.authors.is_empty());
+    source("title", Source::CommandLine, title.is_some());
+    source("title", registrar, title.is_none() && work.title.is_some());
+    source("title", Source::LandingPage, title.is_none() && work.title.is_none() && page.title.is_some());
+    source("title", Source::PdfMetadata, title.is_none() && work.title.is_none() && page.title.is_none() && met.title.is_some());
+    source("year", Source::CommandLine, year.is_some());
+    source("year", registrar, year.is_none() && work.year.is_some());
+    source("year", Source::LandingPage, year.is_none() && work.year.is_none() && page.year.is_some());
+    source("year", Source::PdfMetadata, year.is_none() && work.year.is_none() && page.year.is_none() && met.year.is_some());
+    source("identifiers", Source::Uri, true);
+    source("identifiers", registrar, !work.identifiers.is_empty());
+    source("identifiers", Source::LandingPage, !page.identifiers.is_empty());
+    source("identifiers", Source::PdfMetadata, !met.identifiers.is_empty());
+    source("identifiers", Source::CommandLine, !identifiers.is_empty());
+    source("context", Source::CommandLine, !context.is_empty());
+    source("context", registrar, work.venue.is_some());
+    source("context", Source::LandingPage, work.venue.is_none() && !page.context.is_empty());
+    source("context", Source::PdfMetadata, work.venue.is_none() && page.context.is_empty() && !met.context.is_empty());
+    source("pages", registrar, work.pages.is_some());
+    source("tags", Source::CommandLine, !args.tags.is_empty());
+    let year = year.or(work.year).or(page.year);
+    if year.is_none() && met.year.is_some() {
+        warnings.push("the year is the creation date of the pdf file".to_string());
+    }
+
+    let t_authors  = if !authors.is_empty() { authors }
+                     else if !work.authors.is_empty() { work.authors }
+                     else if !page.authors.is_empty() { page.authors }
+                     else { met.authors };
+    // the ORCIDs of the authors, from the registered metadata, or
+    // else from the doi of the arxiv paper at DataCite
+    let mut t_orcids = work.orcids;
+    if t_orcids.is_empty() {
+        let paper = identifiers::arxiv_id(&[known.as_slice(), std::slice::from_ref(&uri)].concat())
+            .and_then(|a| arxiv::parse_id(&a));
+        if let Some(paper) = paper {
+            t_orcids = arxiv::orcids(&paper.id)
+                .unwrap_or_else(|e| { log::warn!("Could not find the ORCIDs of the authors: {e:#}"); vec![] });
+        }
+    }
+
+    let t_title    = title.or(work.title).or(page.title).or(met.title).context("No title could be found")?;
+    if t_authors.is_empty() {
+        warnings.push("no authors were found".to_string());
+    }
+
+    t_identifiers.extend_from_slice(&work.identifiers);
+    t_identifiers.extend_from_slice(&page.identifiers);
+    t_identifiers.extend_from_slice(&met.identifiers);
+    t_identifiers.extend_from_slice(&identifiers);
+    t_identifiers.push(uri.clone());
+    identifiers::sort(&config.identifier_priority, &mut t_identifiers);
+
+    let mut t_context = vec![];
+    t_context.extend_from_slice(&context);
+    match work.venue {
+        Some(venue) => t_context.push(venue),
+        None if !page.context.is_empty() => t_context.extend_from_slice(&page.context),
+        None => t_context.extend_from_slice(&met.context),
+    }
+
+    // the abstract and the keywords of the landing page or
+    // of the pdf, or else of the apis
+    source("abstract", Source::LandingPage, page.r#abstract.is_some());
+    source("abstract", Source::PdfMetadata, page.r#abstract.is_none() && met.r#abstract.is_some());
+    source("keywords", Source::LandingPage, !page.keywords.is_empty());
+    source("keywords", Source::PdfMetadata, page.keywords.is_empty() && !met.keywords.is_empty());
+    let mut t_abstract = page.r#abstract.or(met.r#abstract);
+    let mut t_keywords = if page.keywords.is_empty() { met.keywords } else { page.keywords };
+    if t_abstract.is_none() || t_keywords.is_empty() {
+        let summary = abstracts::lookup(&t_identifiers, &crossref);
+        source("abstract", Source::Api, t_abstract.is_none() && summary.r#abstract.is_some());
+        source("keywords", Source::Api, t_keywords.is_empty() && !summary.keywords.is_empty());
+        t_abstract = t_abstract.or(summary.r#abstract);
+        if t_keywords.is_empty() {
+            t_keywords = summary.keywords;
+        }
+    }
+
+    // the id and the citations of the paper in Semantic Scholar,
+    // when asked for and not known yet
+    let known_to_scholar = t_identifiers.iter().any(|i| i.starts_with("s2:")) && t_abstract.is_some();
+    let paper = if config.scholar_on_import && !known_to_scholar {
+        scholar::paper(&t_identifiers)
+            .unwrap_or_else(|e| { log::warn!("Could not query the Semantic Scholar API: {e:#}"); None })
+    } else {
+        None
+    };
+    if let Some(paper) = &paper {
+        t_identifiers.push(format!("s2:{}", paper.id));
+        identifiers::sort(&config.identifier_priority, &mut t_identifiers);
+        source("identifiers", Source::SemanticScholar, true);
+        source("abstract", Source::SemanticScholar, t_abstract.is_none() && paper.r#abstract.is_some());
+        t_abstract = t_abstract.or(paper.r#abstract.clone());
+    }
+
+    // arxiv, bioRxiv, medRxiv, ePrint and ECCC downloads
+    // are preprints, unless published somewhere
+    let t_doc_type = doc_type.or_else(|| {
+        identifiers::arxiv_id(&t_identifiers)
+            .or_else(|| identifiers::rxiv_preprint(&t_identifiers).map(|p| p.doi))
+            .or_else(|| identifiers::iacr_id(&t_identifiers))
+            .or_else(|| identifiers::eccc_id(&t_identifiers))
+            .filter(|_| t_context.is_empty())
+            .map(|_| doctype::DocType::Preprint)
+    }).or_else(|| {
+        identifiers::isbn(&t_identifiers).map(|_| doctype::DocType::Book)
+    });
+    source("type", Source::CommandLine, t_doc_type.is_some() && doc_type.is_some());
+    source("type", Source::Uri, t_doc_type.is_some() && doc_type.is_none());
+
+    let t_destinations =  HashMap::new();
+    let t_year = match year.or(met.year) {
+        Some(year) => year,
+        // web pages and books rarely say when they were written
+        None if file.is_some() => {
+            warnings.push("no year was found, the year is the one of the import".to_string());
+            chrono::Datelike::year(&chrono::Local::now()) as u32
+        }
+        None => anyhow::bail!("No year present"),
+    };
+
+    let mut doc = Document {
+        id: String::new(),
+        authors: t_authors, checksum: t_checksum, filename: String::new(),
+        orcids: BTreeMap::new(),
+        identifiers: t_identifiers,
+        title: t_title,
+        year: t_year,
+        context: t_context,
+        pages: work.pages,
+        destinations: t_destinations,
+        tags: vec![],
+        r#abstract: t_abstract,
+        keywords: t_keywords,
+        linked_as: None,
+        raw_checksum: None,
+        former_filenames: vec![],
+        converted_with: None,
+        status: None,
+        doc_type: t_doc_type,
+        citations: paper.and_then(|p| p.citations),
+        metadata_only: pdf.is_none() && file.is_none(),
+        format: file.as_ref().map(|f| f.format).unwrap_or_default(),
+    };
+
+    // the key of DBLP, used as citation key by the exports,
+    // when asked for and the venue is not known yet
+    if config.dblp_on_import && doc.context.is_empty() && identifiers::dblp_key(&doc.identifiers).is_none() {
+        match dblp::lookup(&doc) {
+            Ok(Some(key)) => {
+                doc.identifiers.push(format!("dblp:{key}"));
+                identifiers::sort(&config.identifier_priority, &mut doc.identifiers);
+                source("identifiers", Source::Dblp, true);
+            }
+            Ok(None) => log::info!("DBLP knows no record of {}", doc.title),
+            Err(e) => log::warn!("Could not query DBLP for {}: {e:#}", doc.title),
+        }
+    }
+
+    Ok(Draft { doc, file, orcids: t_orcids, sources, warnings })
+}
+
+/// Imports a document whose pdf is already loaded (or a
+/// metadata-only document, without pdf), given what was
+/// found while loading it.
+fn import_loaded_document(app : &mut AppState,
+                          args : ImportArgs,
+                          mut pdf : Option<pdflib::PdfDocument>,
+                          loaded : Loaded,
+                          interactive : bool) -> Result<String> {
+    let t_checksum = match (&mut pdf, &loaded.file) {
+        (Some(pdf), _) => pdf.get_checksum()?,
+        (None, Some(file)) => verify::file_checksum(&file.path)?,
+        (None, None) => metadata_checksum(loaded.identifiers.first().context("A metadata-only document needs a doi or an isbn")?),
+    };
+
+    // The same file may already be in the library under
+    // other identifiers: we simply record the new ones.
+    if let Some(existing) = app.storage.find_by_checksum(&t_checksum)? {
+        if args.force {
+            log::info!("Document {} has the same checksum as {}, replacing it", args.uri, existing.filename);
+            return reimport_document(app, &existing, ImportArgs {
+                view: false, batch: None, bibtex: None, zotero: None, papis: None, pubs: None, stdin: false,
+                ..args
+            }, interactive);
+        } else {
+            log::info!("Document {} has the same checksum as {}", args.uri, existing.filename);
+            let mut updated = existing.clone();
+            updated.identifiers.extend(loaded.identifiers);
+            updated.identifiers.extend(args.identifiers.iter().map(|i| identifiers::canonicalize(i)));
+            let detail = format!("same file as {}", args.uri);
+            updated.identifiers.push(args.uri);
+            identifiers::sort(&app.config.identifier_priority, &mut updated.identifiers);
+            updated.add_tags(&args.tags);
+            app.update_document(&existing, &updated)?;
+            app.record(events::EventKind::Import, &updated, Some(detail));
+            app.reconvert_if_needed(&updated)?;
+            return Ok(existing.filename);
+        }
+    }
+
+    let draft = draft_document(&args, pdf.as_mut(), loaded, &app.config, &app.cache_path)?;
+    import_draft(app, args, pdf, draft, interactive)
+}
+
+/// Adds a drafted document to the library: its authors and its
+/// venue take their canonical names, it gets an id, and its files
+/// are written.
+fn import_draft(app : &mut AppState,
+                args : ImportArgs,
+                pdf : Option<pdflib::PdfDocument>,
+                draft : Draft,
+                interactive : bool) -> Result<String> {
+    let Draft { mut doc, file, orcids, mut sources, mut warnings } = draft;
+    let uri = args.uri;
+    let download_url = download_url(&uri, &app.config);
+    use report::Source;
+    let mut source = |field : &str, from : Source, present : bool| {
+        if present {
+            sources.entry(field.into()).or_default().push(from);
+        }
+    };
+
+    // an author is matched by ORCID first, then by name or alias:
+    // the name of the registry wins, the other one becomes an alias
+    let mut registry = app.authors()?;
+    let (resolved, t_orcids) = registry.resolve_all(&doc.authors, &orcids);
+    registry.save()?;
+    source("authors", Source::AuthorRegistry, resolved != doc.authors);
+    doc.authors = resolved;
+    doc.orcids = t_orcids;
+
+    // use canonical venue names so that the context
+    // is consistent across imports from different sources
+    let mut venues = venues::VenueNormalizer::new(&app.cache_path.join("venues.yaml"));
+    let normalized = venues.normalize_context(&doc.context);
+    venues.save()?;
+    source("context", Source::VenueNormalizer, normalized != doc.context);
+    doc.context = normalized;
+
+    doc.id = app.fresh_id(&doc.checksum)?;
+    doc.add_tags(&args.tags);
+
+    if interactive {
+        let edited = edit_document(&doc)?;
+        source("title", Source::Editor, edited.title != doc.title);
+        source("authors", Source::Editor, edited.authors != doc.authors);
+        source("year", Source::Editor, edited.year != doc.year);
+        source("identifiers", Source::Editor, edited.identifiers != doc.identifiers);
+        source("context", Source::Editor, edited.context != doc.context);
+        source("tags", Source::Editor, edited.tags != doc.tags);
+        doc = edited;
+    }
+
+    let name = doc.generate_name(&app.config)?;
+    doc.filename = name.clone();
+
+    let conversion = match (pdf, &file) {
+        (Some(pdf), _) => app.add_document(&mut doc, pdf)?,
+        (None, Some(file)) => {
+            app.add_file(&mut doc, &file.path)?;
+            report::Conversion::default()
+        }
+        (None, None) => {
+            app.storage.insert(&doc)?;
+            app.journal(journal::OpKind::Import, None, Some(&doc));
+            warnings.push("the document has no pdf file, see akl attach".to_string());
+            report::Conversion::default()
+        }
+    };
+    app.record(events::EventKind::Import, &doc, None);
+    if doc.raw_checksum.is_some() && doc.format.is_pdf() && conversion.destinations == 0 {
+        warnings.push("the document has no named destinations".to_string());
+    }
+    for other in app.storage.documents()?.iter().filter(|d| d.checksum != doc.checksum) {
+        if let Some(reason) = duplicates::compare(&doc, other) {
+            log::warn!("{name} may be a duplicate of {} ({reason}), see akl merge", other.filename);
+            warnings.push(format!("possible duplicate of {} ({reason})", other.short_id()));
+        }
+    }
+
+    let report = report::ImportReport {
+        time: chrono::Utc::now(),
+        uri,
+        download_url,
+        checksum: doc.checksum.clone(),
+        raw_checksum: doc.raw_checksum.clone(),
+        sources,
+        conversion,
+        warnings,
+    };
+    if let Err(e) = app.reports.save(&report) {
+        log::warn!("Could not save the import report of {name}: {e:#}");
+    }
+    Ok(name)
+}
+
+/// Pdf files of a directory and its subdirectories, sorted.
+fn pdf_files(dir : &std::path::Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir).with_context(|| format!("Listing {dir:?}"))? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("pdf")) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Records the identifiers of a document that it does not have yet
+/// (e.g. the citation keys of a library it is imported from again).
+fn add_identifiers(app : &mut AppState, doc : &Document, idents : &[String]) -> Result<()> {
+    let mut new = doc.clone();
+    new.identifiers.extend(idents.iter().filter(|i| !doc.identifiers.contains(i)).cloned());
+    if new.identifiers.len() == doc.identifiers.len() {
+        return Ok(());
+    }
+    identifiers::sort(&app.config.identifier_priority, &mut new.identifiers);
+    app.update_document(doc, &new)
+}
+
+/// A document of a batch import loaded by a worker, with the
+/// arguments of its import (and the uri it was loaded from).
+struct BatchFile {
+    args   : ImportArgs,
+    pdf    : pdflib::PdfDocument,
+    loaded : Loaded,
+    /// The document drafted by the worker, unless it was
+    /// already in the library.
+    draft  : Option<Draft>,
+}
+
+/// Loads the first of the ways to import a document of a batch
+/// (the uris of a BibTeX entry…) that can be loaded.
+fn load_batch_file(candidates : &[ImportArgs], config : &config::Config) -> Result<BatchFile> {
+    let mut errors = vec![];
+    for args in candidates {
+        let mut loaded = Loaded::default();
+        match load_pdf_document(&args.uri, Some(&mut loaded), config) {
+            Ok(pdf) => return Ok(BatchFile { args: args.clone(), pdf, loaded, draft: None }),
+            Err(e) => {
+                log::info!("Could not load {}: {e:#}", args.uri);
+                errors.push(format!("{}: {e:#}", args.uri));
             }
         }
     }
+    anyhow::bail!("{}", errors.join("; "))
 }
 
+/// Drafts a loaded file of a batch import (see `draft_document`),
+/// unless its checksum is one of the `known` ones of the library.
+fn draft_batch_file(mut file : BatchFile, known : &HashSet<String>,
+                    config : &config::Config, cache_path : &Path) -> Result<BatchFile> {
+    if !known.contains(&file.pdf.get_checksum()?) {
+        let loaded = std::mem::take(&mut file.loaded);
+        file.draft = Some(draft_document(&file.args, Some(&mut file.pdf), loaded, config, cache_path)?);
+    }
+    Ok(file)
+}
 
+/// Imports a loaded file of a batch import, unless it is already in
+/// the library, in which case it only gains the identifiers given.
+/// Returns the name of the imported document.
+fn import_batch_loaded(app : &mut AppState, file : BatchFile, interactive : bool) -> Result<Option<String>> {
+    let BatchFile { args, mut pdf, loaded, draft } = file;
+    match (app.storage.find_by_checksum(&pdf.get_checksum()?)?, draft) {
+        (Some(existing), _) if args.force => reimport_document(app, &existing, args, interactive).map(Some),
+        (Some(existing), _) => add_identifiers(app, &existing, &args.identifiers).map(|()| None),
+        (None, Some(draft)) => import_draft(app, args, Some(pdf), draft, interactive).map(Some),
+        (None, None) => import_loaded_document(app, args, Some(pdf), loaded, interactive).map(Some),
+    }
+}
 
-/// Stupid words that should not be part of a title.
-///
-/// TODO: sort the words to improve binary search.
-const STUPID_WORDS : &[&str] = &[
-    "the", "all", "any", "one", "on", "of",
-    "in", "where", "when", "why", "what",
-    "this", "some", "other", "every"
-];
+/// Imports a file of a batch import, see `import_batch_loaded`.
+fn import_batch_file(app : &mut AppState, args : ImportArgs, interactive : bool) -> Result<Option<String>> {
+    let file = load_batch_file(std::slice::from_ref(&args), &app.config)?;
+    import_batch_loaded(app, file, interactive)
+}
 
-impl Document {
-    /// Document name generation.
-    ///
-    /// The format is
-    ///    authors year title hash
-    /// in lowercase and dash separated words, to simplify
-    /// exploration using fzf, find or other tools.
-    fn generate_name(&self) -> String {
-        let mut authors = self.authors.iter()
-            .map(|author| author.to_ascii_lowercase()
-                                .replace("  ", " ")
-                                .replace(' ', "-")
-                                .replace(',',"-"))
-            .collect::<Vec<String>>()
-            .join("-");
-        let year = self.year;
-        let mut title : String = self.title
-                                 .to_ascii_lowercase()
-                                 .split_whitespace()
-                                 .filter(|x| x.len() > 0 && !STUPID_WORDS.contains(x))
-                                 .collect::<Vec<&str>>()
-                                 .join("-");
-        title.truncate(30); // Cannot fail because we have ascii code points
-        authors.truncate(30); // Cannot fail because we have ascii code points
-        let hash = &self.checksum;
-        format!("{authors} {year} {title} {hash}.pdf")
+/// Loads the documents of a batch import on `import_workers`
+/// threads, which also ask the metadata APIs about them (see
+/// `draft_document`), and imports them as they are drafted, one at
+/// a time, since the index has a single writer. `imported` is called with
+/// the position of each document in the batch and the result of
+/// its import.
+fn import_in_parallel(app : &mut AppState, batch : &[Vec<ImportArgs>], interactive : bool,
+                      mut imported : impl FnMut(&mut AppState, usize, Result<Option<String>>) -> Result<()>) -> Result<()> {
+    use rayon::prelude::*;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(app.config.import_workers.max(1))
+        .build()
+        .context("Starting the import workers")?;
+    let config = app.config.clone();
+    let cache_path = app.cache_path.clone();
+    let known : HashSet<String> = app.storage.documents()?.into_iter().map(|d| d.checksum).collect();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::scope(|scope| {
+        scope.spawn(move || pool.install(|| {
+            (0..batch.len()).into_par_iter().for_each_with(sender, |sender, i| {
+                progress::batch(Some((i + 1, batch.len())));
+                // the receiver is gone when an import failed badly
+                let file = load_batch_file(&batch[i], &config)
+                    .and_then(|file| draft_batch_file(file, &known, &config, &cache_path));
+                let _ = sender.send((i, file));
+            })
+        }));
+        for (i, file) in receiver {
+            let result = file.and_then(|file| import_batch_loaded(app, file, interactive));
+            imported(app, i, result)?;
+        }
+        Ok(())
+    })
+}
+
+/// The summary of a batch import, with its duration.
+fn print_batch_summary(imported : usize, skipped : usize, failures : &[(String, String)], started : std::time::Instant) {
+    println!("\n{imported} imported, {skipped} already in the library, {} failed in {}s",
+             failures.len(), started.elapsed().as_secs());
+    for (label, e) in failures {
+        println!("  {label}: {e}");
     }
 }
 
-#[derive(Serialize,Deserialize,Debug,Clone)]
-struct PageArgs {
-    page: Option<u32>,
-    dest: Option<String>,
+/// Imports all the pdf files of a directory, skipping the ones
+/// already in the library, and prints a summary of the failures.
+fn import_batch(app : &mut AppState, args : ImportArgs, interactive : bool) -> Result<()> {
+    let started = std::time::Instant::now();
+    let dir = args.batch.clone().context("No directory to import")?;
+    let batch : Vec<Vec<ImportArgs>> = pdf_files(&dir)?.into_iter()
+        .map(|path| vec![ImportArgs { uri: path.to_string_lossy().to_string(), batch: None, ..args.clone() }])
+        .collect();
+    let total = batch.len();
+    let (mut imported, mut skipped) = (0, 0);
+    let mut failures = vec![];
+
+    import_in_parallel(app, &batch, interactive, |_, i, result| {
+        let uri = &batch[i][0].uri;
+        match result {
+            Ok(Some(name)) => {
+                imported += 1;
+                println!("[{}/{total}] imported {name}", i + 1);
+            }
+            Ok(None) => {
+                skipped += 1;
+                println!("[{}/{total}] skipped {uri} (already in the library)", i + 1);
+            }
+            Err(e) => {
+                println!("[{}/{total}] failed {uri}", i + 1);
+                failures.push((uri.clone(), format!("{e:#}")));
+            }
+        }
+        Ok(())
+    })?;
+
+    print_batch_summary(imported, skipped, &failures, started);
+    app.desktop.notify("🌍 Converting",
+                       &format!("Imported {imported} of the {total} documents of {}", dir.display()))
+        .context("Notifying the user that the import is done")
 }
-fn get_page_number(uri : &str, args : &mut CiteArgs) -> Result<()>{
-    let url = Url::parse(&uri).context("Parsing URL inside document")?;
-    let que = url.query().context("No query to parse")?;
-    let PageArgs { page, dest } : PageArgs = serde_urlencoded::from_str(que).context("Parsing URL query")?;
-    args.page = page;
-    args.dest = dest;
-    Ok(())
+
+/// The ways to import an entry of a BibTeX file, one per uri of the
+/// entry, or `None` when it is already in the library.
+fn bibtex_entry_imports(app : &AppState, entry : &bibtex::Entry, args : &ImportArgs) -> Result<Option<Vec<ImportArgs>>> {
+    let uris = entry.uris();
+    if uris.is_empty() {
+        anyhow::bail!("No arxiv id, url or doi to download the entry from");
+    }
+    if !args.force && uris.iter().any(|u| app.find_document(u).is_ok()) {
+        return Ok(None);
+    }
+
+    let mut context = args.context.clone();
+    context.extend(entry.venues());
+    Ok(Some(uris.iter()
+        .map(|uri| ImportArgs {
+            uri: uri.clone(),
+            title: entry.title(),
+            authors: entry.authors(),
+            year: entry.year(),
+            doc_type: args.doc_type.or(doctype::DocType::from_bibtex(&entry.kind)),
+            context: context.clone(),
+            identifiers: entry.doi().map(|d| format!("doi:{d}")).into_iter()
+                .chain(entry.arxiv_id().map(|a| format!("arxiv:{a}")))
+                .chain(entry.key.strip_prefix("DBLP:").map(|k| format!("dblp:{k}")))
+                .chain(entry.field("isbn").and_then(|i| identifiers::normalize_isbn(&i)).map(|i| format!("isbn:{i}")))
+                .filter(|i| i != uri)
+                .collect(),
+            bibtex: None,
+            ..args.clone()
+        })
+        .collect()))
 }
 
+/// Imports the entries of a BibTeX file, skipping the ones already
+/// in the library, and prints a summary of the entries that could
+/// not be imported.
+fn import_bibtex(app : &mut AppState, args : ImportArgs, interactive : bool) -> Result<()> {
+    let started = std::time::Instant::now();
+    let path = args.bibtex.clone().context("No BibTeX file to import")?;
+    let src = std::fs::read_to_string(&path)
+        .with_context(|| format!("Reading {path:?}"))?;
+    let entries = bibtex::parse(&src);
+    let total = entries.len();
+    let (mut imported, mut skipped) = (0, 0);
+    let mut failures = vec![];
 
-fn update_document_links(pdoc : &mut pdflib::PdfDocument, ident: Option<String>) {
-    // TODO: allow an optional argument
-    // to set a "from" path!
-    // TODO forward the dest and page from
-    // the link to the citation command
-    pdoc.update_links(&|e| {
-        let mut args = CiteArgs { uri: e.clone(),
-                                  dest: None,
-                                  page: None,
-                                  from: ident.clone()
+    // the entries to download, with their labels
+    let mut batch = vec![];
+    let mut labels = vec![];
+    for (i, entry) in entries.into_iter().enumerate() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                println!("[{}/{total}] failed to parse an entry", i + 1);
+                failures.push((e.to_string(), e.root_cause().to_string()));
+                continue;
+            }
         };
-        get_page_number(&e, &mut args).unwrap_or(());
-        command_to_query(Commands::Open(args)).unwrap_or(e)
-    }).unwrap();
+        match bibtex_entry_imports(app, &entry, &args) {
+            Ok(Some(imports)) => {
+                batch.push(imports);
+                labels.push((i, entry.key.clone(), format!("{} (line {})", entry.key, entry.line)));
+            }
+            Ok(None) => {
+                skipped += 1;
+                println!("[{}/{total}] skipped {} (already in the library)", i + 1, entry.key);
+            }
+            Err(e) => {
+                println!("[{}/{total}] failed {}", i + 1, entry.key);
+                failures.push((format!("{} (line {})", entry.key, entry.line), format!("{e:#}")));
+            }
+        }
+    }
+
+    import_in_parallel(app, &batch, interactive, |_, j, result| {
+        let (i, key, line) = &labels[j];
+        match result {
+            Ok(Some(name)) => {
+                imported += 1;
+                println!("[{}/{total}] imported {key} as {name}", i + 1);
+            }
+            Ok(None) => {
+                skipped += 1;
+                println!("[{}/{total}] skipped {key} (already in the library)", i + 1);
+            }
+            Err(e) => {
+                println!("[{}/{total}] failed {key}", i + 1);
+                failures.push((line.clone(), format!("{e:#}")));
+            }
+        }
+        Ok(())
+    })?;
 
+    print_batch_summary(imported, skipped, &failures, started);
+    app.desktop.notify("🌍 Converting",
+                       &format!("Imported {imported} of the {total} entries of {}", path.display()))
+        .context("Notifying the user that the import is done")
 }
 
-fn update_document_dests(id : &str, pdoc : &mut pdflib::PdfDocument) {
-    pdoc.add_destinations_links(&|e : pdflib::NamedDestination| {
-        command_to_query(Commands::Cite(CiteArgs {
-            uri: id.into(),
-            dest: Some(e.name),
-            page: Some(e.page_num),
-            from: None
-        })).unwrap_or("".into())
-    }).unwrap();
+/// The ways to import an item of a Zotero (papis, pubs) library,
+/// its attached pdf files and then its uris, or `None` when it is
+/// already in the library (it then gains the identifiers of the item).
+fn item_imports(app : &mut AppState, item : &zotero::Item, args : &ImportArgs) -> Result<Option<Vec<ImportArgs>>> {
+    if !args.force {
+        if let Some(existing) = item.identifiers.iter().find_map(|i| app.find_document(i).ok()) {
+            add_identifiers(app, &existing, &item.identifiers)?;
+            return Ok(None);
+        }
+    }
+    let uris : Vec<String> = item.attachments.iter()
+        .filter(|a| a.exists())
+        .map(|a| a.to_string_lossy().to_string())
+        .chain(item.uris.iter().cloned())
+        .collect();
+    if uris.is_empty() {
+        anyhow::bail!("No attached pdf file, url or doi to import the item from");
+    }
+
+    let mut context = args.context.clone();
+    context.extend(item.venues.iter().cloned());
+    let mut tags = args.tags.clone();
+    tags.extend(item.tags.iter().cloned());
+    Ok(Some(uris.iter()
+        .map(|uri| ImportArgs {
+            uri: uri.clone(),
+            title: item.title.clone(),
+            authors: item.authors.clone(),
+            year: item.year,
+            doc_type: args.doc_type.or(item.doc_type),
+            context: context.clone(),
+            identifiers: item.identifiers.iter().filter(|i| *i != uri).cloned().collect(),
+            tags: tags.clone(),
+            zotero: None,
+            papis: None,
+            pubs: None,
+            ..args.clone()
+        })
+        .collect()))
 }
 
-fn download_pdf_document(url : &str) -> Result<pdflib::PdfDocument> {
-    log::debug!("Loading document from {url}");
-    let client = reqwest::blocking::Client::new();
-    let mut up = Url::parse(url)?;
-    up.set_query(None);
-    let orig = up.to_string();
-    log::debug!("Using {orig} as an origin");
-    let body = client.get(url)
-          .header(reqwest::header::USER_AGENT, 
-                  "Rust")
-          .header(reqwest::header::ACCEPT, "*/*")
-          .header(reqwest::header::ACCEPT_ENCODING,
-                  "Accept-Encoding: gzip, deflate, br")
-          .header(reqwest::header::ACCEPT_LANGUAGE,
-                  "fr,fr-FR;q=0.8,en-US;q=0.5,en;q=0.3")
-          .header(reqwest::header::REFERER, &orig)
-          .header(reqwest::header::CONNECTION, "keep-alive")
-          .header(reqwest::header::DNT, "1")
-          .header(reqwest::header::ORIGIN, &orig)
-          .send()?;
+/// Imports the items read from a Zotero export (or a papis, pubs
+/// library) at `path`, skipping the ones already in the library,
+/// adds them to the collections of the library named after their
+/// collections, and prints a summary of the items that could not
+/// be imported.
+fn import_items(app : &mut AppState, args : ImportArgs, items : Vec<zotero::Item>, path : &Path, interactive : bool) -> Result<()> {
+    let started = std::time::Instant::now();
+    let total = items.len();
+    let (mut imported, mut skipped) = (0, 0);
+    let mut failures = vec![];
+    let mut collections = app.collections()?;
 
-    log::debug!("Pdf Document downloaded !");
-    log::debug!("Status {:?}", body.status());
+    // the items to import, with their positions
+    let mut batch = vec![];
+    let mut positions = vec![];
+    for (i, item) in items.iter().enumerate() {
+        let label = item.title.as_deref().unwrap_or(&item.key);
+        match item_imports(app, item, &args) {
+            Ok(Some(imports)) => {
+                batch.push(imports);
+                positions.push(i);
+            }
+            Ok(None) => {
+                skipped += 1;
+                println!("[{}/{total}] skipped {label} (already in the library)", i + 1);
+            }
+            Err(e) => {
+                println!("[{}/{total}] failed {label}", i + 1);
+                failures.push((item.key.clone(), format!("{e:#}")));
+            }
+        }
+    }
+
+    import_in_parallel(app, &batch, interactive, |app, j, result| {
+        let i = positions[j];
+        let item = &items[i];
+        let label = item.title.as_deref().unwrap_or(&item.key);
+        match result {
+            Ok(Some(name)) => {
+                imported += 1;
+                println!("[{}/{total}] imported {label} as {name}", i + 1);
+                let doc = app.find_by_filename(&name)?
+                    .with_context(|| format!("Finding the imported document {name}"))?;
+                for name in &item.collections {
+                    if collections.get(name).is_err() {
+                        collections.create(name, None)?;
+                    }
+                    let collection = collections.get_mut(name)?;
+                    if !collection.documents.contains(&doc.checksum) {
+                        collection.documents.push(doc.checksum.clone());
+                    }
+                }
+            }
+            Ok(None) => {
+                skipped += 1;
+                println!("[{}/{total}] skipped {label} (already in the library)", i + 1);
+            }
+            Err(e) => {
+                println!("[{}/{total}] failed {label}", i + 1);
+                failures.push((item.key.clone(), format!("{e:#}")));
+            }
+        }
+        Ok(())
+    })?;
+    collections.save()?;
 
-    let pdf = lopdf::Document::load_from(body)
-        .context("parsing the pdf document in memory using lopdf")?;
+    print_batch_summary(imported, skipped, &failures, started);
+    app.desktop.notify("🌍 Converting",
+                       &format!("Imported {imported} of the {total} items of {}", path.display()))
+        .context("Notifying the user that the import is done")
+}
 
-    log::debug!("Pdf Document parsed !");
+/// Result of the import of a uri read from the standard input.
+#[derive(Serialize, Debug)]
+struct StdinImport {
+    uri    : String,
 
-    let doc = pdflib::PdfDocument::try_from(pdf)
-        .context("turning the parsed pdf into a fully fledged document")?;
+    /// `imported`, `skipped` (already in the library) or `failed`.
+    status : &'static str,
 
-    log::debug!("Pdf Document explored !");
+    /// Name of the document, when known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name   : Option<String>,
 
-    Ok(doc)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error  : Option<String>,
 }
 
+/// Imports the uris of the standard input, one per line (empty
+/// lines and lines starting with `#` are ignored), and prints the
+/// result of each import as a line of json. The metadata cannot be
+/// edited, since the standard input is not the terminal.
+fn import_stdin(app : &mut AppState, args : ImportArgs) -> Result<()> {
+    use std::io::{BufRead, Write};
+    let mut out = std::io::stdout().lock();
+    for line in std::io::stdin().lock().lines() {
+        let line = line.context("Reading the standard input")?;
+        let uri = line.trim();
+        if uri.is_empty() || uri.starts_with('#') {
+            continue;
+        }
+        log::info!("Importing document {uri}");
+        let result = match app.find_document(uri) {
+            Ok(doc) if !args.force => Ok(("skipped", Some(doc.filename))),
+            _ => {
+                let args = ImportArgs { uri: uri.into(), stdin: false, ..args.clone() };
+                import_batch_file(app, args, false).map(|name| match name {
+                    Some(name) => ("imported", Some(name)),
+                    None => ("skipped", None),
+                })
+            }
+        };
+        let result = match result {
+            Ok((status, name)) => StdinImport { uri: uri.into(), status, name, error: None },
+            Err(e) => StdinImport { uri: uri.into(), status: "failed", name: None, error: Some(format!("{e:#}")) },
+        };
+        writeln!(out, "{}", serde_json::to_string(&result)?)?;
+        out.flush()?;
+    }
+    Ok(())
+}
 
-/// Loads a pdf document. 
-/// Either from a url to download, an arxiv format,
-/// or simply from a valid filepath.
-fn load_pdf_document(uri : &str, identifiers : Option<&mut Vec<String>>) -> Result<pdflib::PdfDocument> {
-    match uri_or_filepath_dispatch(uri)? {
-        ParsedURI::FilePath(p) => {
-            log::debug!("Found a direct path to import!");
-            let pdf = lopdf::Document::load(p)?;
-            let doc = pdflib::PdfDocument::try_from(pdf)?;
-            Ok(doc)
+/// Files of the state of the library saved in the backups,
+/// besides the index and the documents: (name in the archive,
+/// path on the disk). The reports and the sessions are the ones
+/// found in `listed`: the data directory of the library when
+/// saving, the extracted archive when restoring.
+fn backup_state_files(app : &AppState, listed : &Path) -> Result<Vec<(String, PathBuf)>> {
+    let data_dir = &app.data_path;
+    let mut files = vec![
+        ("collections.yaml".to_string(), app.index_dir.join("collections.yaml")),
+        ("anchors.yaml".to_string(), app.index_dir.join("anchors.yaml")),
+        ("searches.yaml".to_string(), app.index_dir.join("searches.yaml")),
+        ("authors.yaml".to_string(), app.index_dir.join("authors.yaml")),
+        ("inbox.yaml".to_string(), app.index_dir.join("inbox.yaml")),
+        ("config.yaml".to_string(), app.config_path.clone()),
+        ("events.jsonl".to_string(), data_dir.join("events.jsonl")),
+    ];
+    for dir in ["reports", "sessions"] {
+        let path = listed.join(dir);
+        if !path.is_dir() {
+            continue;
         }
-        ParsedURI::Arxiv { arxiv_id, arxiv_version } => {
-            log::debug!("Found a valid arixv link to import {arxiv_id} / {arxiv_version}!");
-            if let Some(ids) = identifiers {
-                ids.push(format!("arxiv:{}v{}", arxiv_id, arxiv_version));
+        for entry in std::fs::read_dir(&path).with_context(|| format!("Listing {path:?}"))? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // the viewers open on this machine
+            if dir == "sessions" && name == "open.yaml" {
+                continue;
             }
-            let url = format!("https://arxiv.org/pdf/{}v{}.pdf", &arxiv_id, &arxiv_version);
-            download_pdf_document(&url)
+            files.push((format!("{dir}/{name}"), data_dir.join(dir).join(&name)));
+        }
+    }
+    Ok(files)
+}
 
+/// Saves, reopens and lists the reading sessions.
+fn manage_sessions(app : &mut AppState, action : SessionCommands) -> Result<()> {
+    match action {
+        SessionCommands::Save { name } => {
+            // the pages the open documents show now
+            for open in app.sessions.open_documents()? {
+                let Some(doc) = app.storage.find_by_checksum(&open.checksum)? else {
+                    continue;
+                };
+                if let Some(page) = desktop::last_viewed_page(&app.mod_path.join(&doc.filename)) {
+                    app.sessions.left_at(&doc.checksum, Some(page), None)?;
+                }
+            }
+            let session = app.sessions.save(&name)?;
+            println!("Saved {} open documents as {name}", session.documents.len());
         }
-        ParsedURI::HttpURL(url) => {
-            log::debug!("This is a direct http request");
-            download_pdf_document(&url)
+        SessionCommands::Restore { name } => {
+            let session = app.sessions.load(&name)?;
+            let mut viewers = vec![];
+            for open in session.documents {
+                match app.storage.find_by_checksum(&open.checksum)? {
+                    Some(doc) => {
+                        // read further since the session was saved
+                        let (page, dest) = app.sessions.left_position(&open)?;
+                        viewers.extend(app.open_in_viewer(&doc, page, dest)?);
+                    }
+                    None => {
+                        println!("{} is no longer in the library", open.title);
+                    }
+                }
+            }
+            app.wait_for_viewers(viewers);
         }
-        _ => {
-            anyhow::bail!("Cannot automatically download uri {}", &uri);
+        SessionCommands::List => {
+            for name in app.sessions.list()? {
+                let session = app.sessions.load(&name)?;
+                println!("{name}\t{}\t{} documents",
+                         session.saved.format("%Y-%m-%d %H:%M"),
+                         session.documents.len());
+            }
+        }
+        SessionCommands::Delete { name } => {
+            app.sessions.delete(&name)?;
         }
     }
+    Ok(())
 }
 
-/// Forward the opening of a document to the operating system.
-fn forward_open(uri : &str) -> Result<()> {
-    log::debug!("Opening {uri} using the system's default");
-    log::debug!("Potential openers {:?}", open::commands(uri));
-
-    open::commands(uri)[0].spawn().unwrap();
-    //open::that(uri).unwrap();
+/// Creates, restores and checks backups of the library.
+fn manage_backups(app : &mut AppState, action : BackupCommands) -> Result<()> {
+    match action {
+        BackupCommands::Create { path, no_mod } => {
+            let docs = app.storage.documents()?;
+            let mut writer = backup::Writer::create(&path)?;
+            let mut index = vec![];
+            storage::write_yaml_index(&mut index, &docs)?;
+            writer.add("index.yaml", &index)?;
+            for doc in docs.iter().filter(|d| !d.metadata_only) {
+                writer.add_file(&format!("raw/{}", doc.filename), &app.raw_file(doc))?;
+                let modified = app.mod_path.join(&doc.filename);
+                if !no_mod && modified.exists() {
+                    writer.add_file(&format!("mod/{}", doc.filename), &modified)?;
+                }
+            }
+            for (name, file) in backup_state_files(app, &app.data_path)? {
+                if file.exists() {
+                    writer.add_file(&name, &file)?;
+                }
+            }
+            let manifest = writer.finish(docs.len())?;
+            println!("Saved {} documents ({} files) to {}", docs.len(), manifest.files.len(), path.display());
+        }
+        BackupCommands::Verify { path } => {
+            let dir = tempfile::tempdir()?;
+            let manifest = backup::extract(&path, dir.path())?;
+            println!("{}: {} documents, {} files, made on {}, all intact",
+                     path.display(), manifest.documents, manifest.files.len(),
+                     manifest.created.format("%Y-%m-%d %H:%M"));
+        }
+        BackupCommands::Restore { path } => {
+            restore_backup(app, &path)?;
+        }
+    }
     Ok(())
 }
 
-/// View a pdf file using the "best" available
-/// options depending on the system.
-///
-/// 1. Skim / Evince / Adobe reader
-/// 2. Zathura / Mupdf / Okular
-/// 3. xdg-open / open / etc ...
-///
-/// TODO: allow this to be configured by an environment variable.
-/// -> a program 
-/// -> a name for the argument of destinations
-/// -> a name for the argument of pages
-fn view_pdf_file(path : &PathBuf, page : Option<u32>, dest: Option<String>) {
-    log::info!("Opening pdf file {path:?} at {page:?} {dest:?}");
-    //open::that(path).unwrap();
-    let mut cmd = std::process::Command::new("evince");
-    cmd.arg(path);
-
-    if let Some(dest_name) =  dest {
-        cmd.arg(format!("--named-dest={dest_name}"));
-    } else if let Some(page_name) = page {
-        cmd.arg(format!("--page-index={page_name}"));
-    } 
-
-    println!("args {:?}", cmd.get_args().collect::<Vec<&std::ffi::OsStr>>());
+/// Adds the documents of a backup to the library, skipping the ones
+/// already there. The state files (collections, configuration…) are
+/// only restored when the library has none.
+fn restore_backup(app : &mut AppState, path : &std::path::Path) -> Result<()> {
+    // extracted next to the library, so that files can be moved
+    let dir = tempfile::tempdir_in(&app.data_path)?;
+    let manifest = backup::extract(path, dir.path())?;
+    log::info!("Restoring a backup of {} made on {}", manifest.documents, manifest.created);
 
-    let test = cmd.status();
+    let (_, docs) = storage::parse_yaml_index(
+        &std::fs::read_to_string(dir.path().join("index.yaml")).context("The backup has no index")?)
+        .context("Parsing the index of the backup")?;
+    let (mut restored, mut skipped) = (0, 0);
+    for doc in docs {
+        if app.storage.find_by_checksum(&doc.checksum)?.is_some() {
+            skipped += 1;
+            continue;
+        }
+        if doc.metadata_only {
+            app.storage.insert(&doc)?;
+            app.journal(journal::OpKind::Import, None, Some(&doc));
+            app.record(events::EventKind::Restore, &doc, Some(format!("from {}", path.display())));
+            restored += 1;
+            continue;
+        }
+        let raw = dir.path().join("raw").join(&doc.filename);
+        if !raw.exists() {
+            anyhow::bail!("The original file of {} is missing from the backup", doc.filename);
+        }
+        let raw_target = store::target(&app.raw_path, &doc)?;
+        let mod_target = app.mod_path.join(&doc.filename);
+        if raw_target.exists() || mod_target.exists() {
+            anyhow::bail!("The files of {} already exist in the library", doc.filename);
+        }
+        std::fs::rename(&raw, &raw_target)
+            .with_context(|| format!("Restoring {raw_target:?}"))?;
+        let modified = dir.path().join("mod").join(&doc.filename);
+        let has_mod = modified.exists();
+        if has_mod {
+            std::fs::rename(&modified, &mod_target)
+                .with_context(|| format!("Restoring {mod_target:?}"))?;
+        }
+        app.storage.insert(&doc)?;
+        app.journal(journal::OpKind::Import, None, Some(&doc));
+        app.record(events::EventKind::Restore, &doc, Some(format!("from {}", path.display())));
+        if !has_mod {
+            app.reconvert(&doc)?;
+        }
+        restored += 1;
+    }
 
-    match test {
-        Ok(_) => {}
-        Err(_) => {
-            open::commands(path)[0].spawn().unwrap();
+    for (name, file) in backup_state_files(app, dir.path())? {
+        let saved = dir.path().join(&name);
+        if !saved.exists() {
+            continue;
+        }
+        if file.exists() {
+            log::info!("Keeping the {name} of the library");
+            continue;
         }
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::copy(&saved, &file)
+            .with_context(|| format!("Restoring {file:?}"))?;
     }
+    println!("Restored {restored} documents, {skipped} already in the library");
+    Ok(())
 }
 
-impl AppState {
-    fn new() -> Self {
-        // find the correct path for the application stored state.
-        // this uses ProjectDirs (cross-plateform)
-        let pdirs = ProjectDirs::from("com", "aluminium", "AKL").unwrap();
-
+/// Manage the credentials stored in the keyring
+/// and referenced from the configuration.
+fn manage_credentials(app : &mut AppState, action : CredentialsCommands) -> Result<()> {
+    match action {
+        CredentialsCommands::Site { host, username } => {
+            let keyring = format!("site:{username}@{host}");
+            let password = secrets::prompt(&format!("Password of {username} on {host}: "))?;
+            secrets::set(&keyring, &password)?;
+            app.config.credentials.retain(|c| c.host != host);
+            app.config.credentials.push(config::SiteCredential { host, username, keyring });
+        }
+        CredentialsCommands::Document { uri } => {
+            let keyring = format!("pdf:{uri}");
+            let password = secrets::prompt(&format!("Password of the documents {uri}: "))?;
+            secrets::set(&keyring, &password)?;
+            app.config.passwords.retain(|p| p.uri != uri);
+            app.config.passwords.push(config::DocumentPassword { uri, keyring });
+        }
+        CredentialsCommands::Remove { name } => {
+            for c in app.config.credentials.iter().filter(|c| c.host == name) {
+                secrets::delete(&c.keyring)?;
+            }
+            for p in app.config.passwords.iter().filter(|p| p.uri == name) {
+                secrets::delete(&p.keyring)?;
+            }
+            app.config.credentials.retain(|c| c.host != name);
+            app.config.passwords.retain(|p| p.uri != name);
+        }
+        CredentialsCommands::Sync => {
+            let keyring = match app.config.sync.as_mut() {
+                Some(remote::RemoteConfig::Webdav { keyring, .. }) => keyring.get_or_insert_with(|| "sync".into()).clone(),
+                Some(remote::RemoteConfig::S3 { keyring, .. }) => keyring.clone(),
+                Some(remote::RemoteConfig::Rclone { .. }) => anyhow::bail!("rclone keeps the credentials of its remotes itself"),
+                None => anyhow::bail!("No remote is configured (see sync in the configuration)"),
+            };
+            let password = secrets::prompt("Password or secret key of the remote: ")?;
+            secrets::set(&keyring, &password)?;
+        }
+        CredentialsCommands::List => {
+            for c in &app.config.credentials {
+                println!("site\t{}\t{}", c.host, c.username);
+            }
+            for p in &app.config.passwords {
+                println!("document\t{}", p.uri);
+            }
+            return Ok(());
+        }
+    }
+    app.config.save(&app.config_path)
+}
 
-        let conf_path = pdirs.config_dir();
-        let raw_path   = pdirs.data_dir().join("raw");
-        let mod_path   = pdirs.data_dir().join("mod");
-        // TODO: in modern XDG, there is XDG_STATE_DIR
-        // but this is not cross platform
-        let index_path = conf_path.join("index.yaml");
-        let log_path   = pdirs.cache_dir().join("logs");
+/// The manifest of the library: its documents, and the
+/// hashes of their files.
+fn local_manifest(app : &AppState, state : &mut sync::SyncState) -> Result<sync::Manifest> {
+    let mut manifest = sync::Manifest { documents: app.storage.documents()?, ..Default::default() };
+    for doc in &manifest.documents {
+        manifest.files.insert(doc.checksum.clone(), sync::Files {
+            raw: state.hash(&app.raw_file(doc))?,
+            modified: state.hash(&app.mod_path.join(&doc.filename))?,
+        });
+    }
+    Ok(manifest)
+}
 
-        // ensures that the paths exists
-        // TODO: postpone this check to times we actually need
-        // to open the files.
-        std::fs::create_dir_all(&conf_path).unwrap();
-        std::fs::create_dir_all(&raw_path).unwrap();
-        std::fs::create_dir_all(&mod_path).unwrap();
-        std::fs::create_dir_all(&log_path).unwrap();
+/// Downloads a file of the remote, checking its hash.
+fn download(remote : &dyn remote::Remote, hash : &str, path : &Path) -> Result<()> {
+    let data = remote.get(&sync::file_key(hash))?
+        .with_context(|| format!("The file {hash} is missing from the remote"))?;
+    sync::check_hash(&data, hash)?;
+    std::fs::write(path, data).with_context(|| format!("Writing {path:?}"))
+}
 
-        // TODO: gracefully handle failure to parse the config
-        let index : Vec<Document> =
-            std::fs::OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .open(&index_path)
-                .map(serde_yaml::from_reader)
-                .unwrap()
-                .unwrap();
+/// Brings the library from the state `ours` to the state `merged`,
+/// downloading the files it does not have.
+fn apply_manifest(app : &mut AppState, remote : &dyn remote::Remote, ours : &sync::Manifest, merged : &sync::Manifest) -> Result<()> {
+    for doc in ours.documents.iter().filter(|d| merged.document(&d.checksum).is_none()) {
+        println!("Removing {}", doc.filename);
+        app.remove_to_trash(doc)?;
+    }
+    for doc in &merged.documents {
+        let files = merged.files_of(&doc.checksum);
+        let Some(local) = ours.document(&doc.checksum) else {
+            println!("Adding {}", doc.filename);
+            if doc.metadata_only {
+                app.storage.insert(doc)?;
+                app.journal(journal::OpKind::Import, None, Some(doc));
+                app.record(events::EventKind::Import, doc, Some("from the remote".into()));
+                continue;
+            }
+            let raw = files.raw.as_deref()
+                .with_context(|| format!("The remote has no file for {}", doc.filename))?;
+            download(remote, raw, &store::target(&app.raw_path, doc)?)?;
+            if let Some(modified) = &files.modified {
+                download(remote, modified, &app.mod_path.join(&doc.filename))?;
+            }
+            app.storage.insert(doc)?;
+            app.journal(journal::OpKind::Import, None, Some(doc));
+            app.record(events::EventKind::Import, doc, Some("from the remote".into()));
+            if files.modified.is_none() {
+                app.reconvert(doc)?;
+            }
+            continue;
+        };
+        if !journal::same(local, doc) {
+            println!("Updating {}", doc.filename);
+            app.move_document(local, doc.clone())?;
+            app.record(events::EventKind::Edit, doc, Some("from the remote".into()));
+        }
+        let current = ours.files_of(&doc.checksum);
+        if let Some(raw) = files.raw.as_deref().filter(|h| current.raw.as_deref() != Some(*h)) {
+            download(remote, raw, &store::target(&app.raw_path, doc)?)?;
+        }
+        if let Some(modified) = files.modified.as_deref().filter(|h| current.modified.as_deref() != Some(*h)) {
+            println!("Updating the modified file of {}", doc.filename);
+            let path = app.mod_path.join(&doc.filename);
+            app.journal.keep(&[("mod.pdf", &path)])?;
+            download(remote, modified, &path)?;
+            app.journal(journal::OpKind::Reconvert, Some(doc), Some(doc));
+        }
+    }
+    Ok(())
+}
 
-        AppState {
-            index_path,
-            raw_path,
-            mod_path,
-            log_path,
-            index,
+/// Copies the files of a directory (and of its subdirectories) to
+/// another one, checking that each copy has the hash of its original.
+/// Returns the files copied. The configuration file is not copied.
+fn copy_tree(from : &Path, to : &Path, config : &Path) -> Result<Vec<PathBuf>> {
+    let mut copied = vec![];
+    std::fs::create_dir_all(to).with_context(|| format!("Creating {to:?}"))?;
+    for entry in std::fs::read_dir(from).with_context(|| format!("Listing {from:?}"))? {
+        let path = entry?.path();
+        let target = to.join(path.file_name().context("Listing a directory")?);
+        if path.is_dir() {
+            copied.extend(copy_tree(&path, &target, config)?);
+            continue;
+        }
+        if path == config || path.extension().is_some_and(|e| e == "lock") {
+            continue;
+        }
+        if target.exists() {
+            anyhow::bail!("Cannot copy {path:?}, {target:?} already exists");
         }
+        std::fs::copy(&path, &target).with_context(|| format!("Copying {path:?} to {target:?}"))?;
+        if verify::file_checksum(&path)? != verify::file_checksum(&target)? {
+            anyhow::bail!("The copy {target:?} of {path:?} is corrupted");
+        }
+        copied.push(path);
     }
+    Ok(copied)
+}
 
-    /// Delete a document from the library
-    fn delete(&mut self, doc : &Document) -> Result<()> {
-        let idx = self.index.iter()
-                      .enumerate()
-                      .find_map(|(i,d)| {
-                         if d.filename == doc.filename &&
-                            d.checksum == doc.checksum {
-                                Some(i)
-                         } else { None }
-                      });
-        if let Some(index) = idx {
-            self.index.swap_remove(index);
+/// Removes the empty subdirectories of a directory, and
+/// the directory itself if it ends up empty.
+fn remove_empty_dirs(dir : &Path) {
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.flatten().filter(|e| e.path().is_dir()) {
+            remove_empty_dirs(&entry.path());
         }
-        Ok(())
     }
+    // fails when the directory is not empty
+    let _ = std::fs::remove_dir(dir);
+}
 
+/// Moves (or copies) the directories of the library, and records
+/// their new locations in the configuration.
+fn relocate(app : &mut AppState, args : RelocateArgs) -> Result<()> {
+    if app.global.is_some() {
+        anyhow::bail!("A project library stays in its .akl directory");
+    }
+    let RelocateArgs { index, raw, modified, log, copy } = args;
+    let moves = [
+        ("index", index, app.index_dir.clone()),
+        ("original files", raw, app.raw_path.clone()),
+        ("modified files", modified, app.mod_path.clone()),
+        ("logs", log, app.log_path.clone()),
+    ];
+    if moves.iter().all(|(_, to, _)| to.is_none()) {
+        anyhow::bail!("Nothing to relocate (see akl relocate --help)");
+    }
+    for (what, to, from) in moves {
+        let Some(to) = to else { continue };
+        let to = std::path::absolute(&to).with_context(|| format!("Resolving {to:?}"))?;
+        if to == from {
+            continue;
+        }
+        if to.starts_with(&from) {
+            anyhow::bail!("Cannot move the {what} inside their current directory {from:?}");
+        }
+        let copied = {
+            let _lock = lock::exclusive(&app.index_dir.join("index.yaml"))?;
+            copy_tree(&from, &to, &app.config_path)?
+        };
+        if !copy {
+            for file in &copied {
+                std::fs::remove_file(file).with_context(|| format!("Removing {file:?}"))?;
+            }
+            remove_empty_dirs(&from);
+        }
+        println!("{} {} files of the {what} from {} to {}",
+                 if copy { "Copied" } else { "Moved" }, copied.len(), from.display(), to.display());
+        match what {
+            "index" => { app.config.index_dir = Some(to.clone()); app.index_dir = to; }
+            "original files" => { app.config.raw_dir = Some(to.clone()); app.raw_path = to; }
+            "modified files" => { app.config.mod_dir = Some(to.clone()); app.mod_path = to; }
+            _ => { app.config.log_dir = Some(to.clone()); app.log_path = to; }
+        }
+        app.config.save(&app.config_path)?;
+    }
+    app.storage = storage::open(app.config.backend, &app.index_dir)?;
+    Ok(())
+}
 
-    /// Finds a document in the library.
-    /// This can be quite complex, but we do the bare minimum here.
-    fn find_document(&self, uri : &str) -> Result<&Document> {
-        let search_result = match uri_or_filepath_dispatch(uri)? {
-            ParsedURI::DOI(doi) => {
-                let doi = format!("doi:{doi}");
-                self.index.iter()
-                          .find(|doc| {
-                                    doc.identifiers.contains(&doi) })
-            }
-            ParsedURI::Arxiv { arxiv_version, arxiv_id } => {
-                let arxiv = format!("arxiv:{arxiv_id}v{arxiv_version}");
-                self.index.iter()
-                          .find(|doc| {
-                                    doc.identifiers.contains(&arxiv) })
-            }
-            ParsedURI::HttpURL(url) => {
-                self.index.iter()
-                          .find(|doc| {
-                                    doc.identifiers.contains(&url) })
+/// Synchronizes the library with its remote.
+fn manage_sync(app : &mut AppState, action : SyncCommands) -> Result<()> {
+    let config = app.config.sync.clone()
+        .context("No remote is configured (see sync in the configuration)")?;
+    let remote = remote::open(&config)?;
+    let mut state = sync::SyncState::load(&app.index_dir.join("sync.yaml"))?;
+    let theirs = match remote.get(sync::MANIFEST)? {
+        Some(data) => sync::Manifest::parse(&data)?,
+        None => sync::Manifest::default(),
+    };
+    let mut ours = local_manifest(app, &mut state)?;
+    match action {
+        SyncCommands::Status => {
+            let merge = sync::merge(&state.base, &ours, &theirs, sync::Side::Local);
+            for (checksum, status) in &merge.status {
+                let what = match status {
+                    sync::Status::Unchanged => continue,
+                    sync::Status::Local => "push",
+                    sync::Status::Remote => "pull",
+                    sync::Status::Both => "conflict",
+                };
+                let name = [&ours, &theirs, &state.base].iter()
+                    .find_map(|m| m.document(checksum))
+                    .map(|d| d.filename.clone())
+                    .unwrap_or_default();
+                println!("{what:<8}  {name}");
+            }
+            if theirs.generation != state.base.generation {
+                println!("The remote received {} pushes since the last synchronization",
+                         theirs.generation.saturating_sub(state.base.generation));
+            }
+        }
+        SyncCommands::Pull { prefer } => {
+            let merge = sync::merge(&state.base, &ours, &theirs, prefer);
+            apply_manifest(app, remote.as_ref(), &ours, &merge.manifest)?;
+            let conflicts = merge.status.values().filter(|s| **s == sync::Status::Both).count();
+            if conflicts > 0 {
+                let side = match prefer { sync::Side::Local => "local", sync::Side::Remote => "remote" };
+                println!("Merged {conflicts} documents changed on both sides, preferring the {side} values");
+            }
+            state.base = theirs;
+        }
+        SyncCommands::Push => {
+            if theirs.generation != state.base.generation {
+                anyhow::bail!("The remote changed since the last synchronization, run akl sync pull first");
             }
-            _ => {
-                None
+            let present = theirs.hashes();
+            let mut uploaded = 0;
+            for doc in &ours.documents {
+                let files = ours.files_of(&doc.checksum);
+                for (hash, path) in [(files.raw, app.raw_file(doc)), (files.modified, app.mod_path.join(&doc.filename))] {
+                    let Some(hash) = hash.filter(|h| !present.contains(h.as_str())) else {
+                        continue;
+                    };
+                    let data = std::fs::read(path)
+                        .with_context(|| format!("Reading {}", doc.filename))?;
+                    remote.put(&sync::file_key(&hash), &data)?;
+                    uploaded += 1;
+                }
             }
-        };
-
-        match search_result {
-            Some(r) => { Ok(r) }
-            None    => { anyhow::bail!("Could not find {uri} in the library.") }
+            ours.generation = theirs.generation + 1;
+            remote.put(sync::MANIFEST, &serde_json::to_vec(&ours)?)?;
+            println!("Pushed {} documents ({uploaded} files uploaded)", ours.documents.len());
+            state.base = ours;
         }
     }
+    state.save()
+}
 
-    /// Add a document to the library.
-    /// Assumes that the document is valid
-    /// and is not already in the library.
-    fn add_document(&mut self, doc : Document, mut pdoc : pdflib::PdfDocument) -> Result<()> {
-        let p = self.mod_path.join(&doc.filename);
-        let r = self.raw_path.join(&doc.filename);
-        pdoc.save_to(&r).context("Saving the original file to the library")?;
-
-        update_document_links(&mut pdoc, Some(doc.identifiers[0].clone()));
-        update_document_dests(&doc.identifiers[0], &mut pdoc);
-
-        pdoc.save_to(&p).context("Saving a modified file to the library")?;
-
-        self.index.push(doc);
-        Ok(())
+/// The documents of the library matching the arguments of the find
+/// command (except for the number of recent documents).
+fn find_documents(app : &AppState, args : &FindArgs) -> Result<Vec<Found>> {
+    let query = query::Query::parse(&args.query.join(" "))?;
+    let collections = app.collections()?;
+    let collection = args.collection.as_deref()
+        .map(|c| collections.get(c))
+        .transpose()?;
+    let opened = match args.recent {
+        Some(_) => events::last_opened(&app.events.since(None)?),
+        None => HashMap::new(),
+    };
+    let mut found = vec![];
+    for d in app.storage.documents()? {
+        if query.matches(&d) &&
+           args.tag.as_ref().is_none_or(|t| d.tags.contains(t)) &&
+           args.status.is_none_or(|s| d.status == Some(s)) &&
+           args.doc_type.is_none_or(|t| d.doc_type == Some(t)) &&
+           (args.recent.is_none() || opened.contains_key(&d.checksum)) &&
+           collection.map(|c| c.contains(&d)).transpose()?.unwrap_or(true) {
+            found.push(Found {
+                path: app.mod_path.join(&d.filename),
+                raw: app.raw_file(&d),
+                opened: opened.get(&d.checksum).copied(),
+                doc: d,
+            });
+        }
     }
+    Ok(found)
+}
 
-
-    /// Saving the library to the yaml configuration file.
-    fn save(&self) {
-        let file = std::fs::OpenOptions::new()
-            .write(true)
-            .read(false)
-            .append(false)
-            .open(&self.index_path).unwrap();
-        serde_yaml::to_writer(file, &self.index).unwrap();
+/// The document a command is about, if any.
+fn command_document(cmd : &Commands) -> Option<&str> {
+    match cmd {
+        Commands::Cite(a) | Commands::View(a) | Commands::Open(a) => Some(&a.uri),
+        Commands::Resolve(a) => Some(&a.uri),
+        Commands::Info(a) => Some(&a.uri),
+        Commands::Related(a) => Some(&a.uri),
+        Commands::Attach(a) => Some(&a.uri),
+        Commands::Edit(a) => Some(&a.uri),
+        Commands::Remove(a) => Some(&a.uri),
+        Commands::Linkmap(a) => Some(&a.uri),
+        Commands::Heatmap(a) => Some(&a.uri),
+        Commands::Status(StatusArgs { action: StatusCommands::Set { uri, .. } | StatusCommands::Clear { uri } }) => Some(uri),
+        _ => None,
     }
 }
 
-fn import_document(app : &mut AppState, args : ImportArgs, interactive : bool) -> Result<String> {
-    let ImportArgs { uri, authors, title, context, identifiers, year, view: _, force : _ }
-    = args;
-    // TODO: interactive update of the metadata using a text editor?
-    // (detect if command line?)
-    let mut t_identifiers = vec![];
-    let mut pdf = load_pdf_document(&uri, Some(&mut t_identifiers))?;
-    let met = pdf.get_meta_data()?;
-
-    let t_authors  = if authors.len() > 0 { authors } else { met.authors };
-    let t_title    = title.or(met.title).context("No title could be found")?;
-    let t_checksum = pdf.get_checksum()?;
-    let t_filename = "".into();
-
-    t_identifiers.extend_from_slice(&met.identifiers);
-    t_identifiers.extend_from_slice(&identifiers);
-    t_identifiers.push(uri);
-    t_identifiers.dedup();
-    t_identifiers.sort();
-
-    let mut t_context = vec![];
-    t_context.extend_from_slice(&context);
-
-    let t_destinations =  HashMap::new();
-    let t_year = year.or(met.year).context("No year present")?;
-
-    let mut doc = Document {
-        authors: t_authors, checksum: t_checksum, filename: t_filename,
-        identifiers: t_identifiers,
-        title: t_title,
-        year: t_year,
-        context: t_context,
-        destinations: t_destinations
+/// Should a command of a project library run in the global library?
+/// Imports do, unless --local is given, and so do the commands about
+/// a document that is only in the global library.
+fn runs_in_global(app : &AppState, cmd : &Commands) -> bool {
+    let Some(global) = app.global.as_deref() else {
+        return false;
     };
-
-    if interactive {
-        let file = tempfile::NamedTempFile::new()?;
-        serde_yaml::to_writer(&file, &doc)?;
-        loop {
-            let proc =
-                std::process::Command::new("nvim")
-                    .arg(file.path())
-                    .status()?;
-            if proc.success() {
-                break;
-            }
-        }
-        let newfile = file.reopen()?;
-        doc = serde_yaml::from_reader(&newfile).unwrap();
+    match cmd {
+        Commands::Import(a) => !a.local,
+        cmd => command_document(cmd).is_some_and(|uri| {
+            app.find_document(uri).is_err() && global.find_document(uri).is_ok()
+        }),
     }
+}
 
-    let name = doc.generate_name();
-    doc.filename = name.clone();
-
-    app.add_document(doc, pdf)?;
-    Ok(name)
+/// Copies a document of the global library to the project library.
+fn copy_from_global(app : &mut AppState, uri : &str) -> Result<()> {
+    let global = app.global.take().context("There is no global library")?;
+    let copied = global.find_document(uri).and_then(|doc| {
+        if app.storage.find_by_checksum(&doc.checksum)?.is_some() {
+            println!("{} is already in the project library", doc.filename);
+        } else {
+            app.copy_document(&global, &doc)?;
+            println!("Copied {} to the project library", doc.filename);
+        }
+        Ok(())
+    });
+    app.global = Some(global);
+    copied
 }
 
 fn execute_command(app : &mut AppState, cmd : Commands, interactive : bool) -> Result<()> {
     log::debug!("Executing command {cmd:?} in with interactive = {interactive}");
+    if runs_in_global(app, &cmd) {
+        if let Some(global) = app.global.as_deref_mut() {
+            return execute_command(global, cmd, interactive);
+        }
+    }
     match cmd {
-        Commands::Find => {
-            app.index.iter()
-                .for_each(|d| println!("{}",app.mod_path.join(&d.filename).to_string_lossy()));
+        Commands::Find(args) => {
+            let mut found = find_documents(app, &args);
+            // then the documents of the global library, a collection
+            // being in only one of the libraries
+            if let Some(global) = app.global.as_deref() {
+                found = match (found, find_documents(global, &args)) {
+                    (Err(e), Err(_)) => Err(e),
+                    (local, global) => {
+                        let mut found = local.unwrap_or_default();
+                        for f in global.unwrap_or_default() {
+                            if !found.iter().any(|l| l.doc.checksum == f.doc.checksum) {
+                                found.push(f);
+                            }
+                        }
+                        Ok(found)
+                    }
+                };
+            }
+            let mut found = found?;
+            if let Some(recent) = args.recent {
+                found.sort_by_key(|f| std::cmp::Reverse(f.opened));
+                found.truncate(recent.unwrap_or(found.len()));
+            }
+            let end = if args.print0 { '\0' } else { '\n' };
+            match args.format {
+                None => {
+                    let template = args.template.as_deref()
+                        .map(template::Template::parse)
+                        .transpose()?;
+                    for f in found {
+                        match &template {
+                            Some(t) => print!("{}{end}", t.render(&f.doc, &f.path, &f.raw)),
+                            None => print!("{}{end}", f.path.to_string_lossy()),
+                        }
+                    }
+                }
+                Some(format) => {
+                    let docs : Vec<Document> = found.into_iter()
+                        .map(|f| Document { id: f.doc.short_id(), ..f.doc })
+                        .collect();
+                    list::print_documents(&mut std::io::stdout().lock(), &docs, format)?;
+                }
+            }
+        }
+        Commands::List(ListArgs { filter, format }) => {
+            let docs : Vec<Document> = app.storage.documents()?
+                .into_iter()
+                .filter(|d| filter.matches(d))
+                .map(|d| Document { id: d.short_id(), ..d })
+                .collect();
+            list::print_documents(&mut std::io::stdout().lock(), &docs, format)?;
         }
-        Commands::Cite(CiteArgs { uri, page, dest, .. }) => {
-            let mut ctx = ClipboardContext::new().unwrap();
+        Commands::Pick(args) => {
+            if let Some(link) = pick_document(app, args)? {
+                println!("{link}");
+            }
+        }
+        Commands::Export(ExportArgs { format: export::ExportFormat::Zotero, uri, output }) => {
+            export_to_zotero(app, uri, output)?;
+        }
+        Commands::Export(ExportArgs { format, uri, output }) => {
+            let library = app.storage.documents()?;
+            let docs = if uri.is_empty() {
+                let mut docs = library.clone();
+                docs.sort_by(|a, b| (&a.authors, a.year, &a.title).cmp(&(&b.authors, b.year, &b.title)));
+                docs
+            } else {
+                uri.iter()
+                   .map(|u| app.find_document(u))
+                   .collect::<Result<Vec<Document>>>()?
+            };
+            let mut out : Box<dyn std::io::Write> = match output {
+                Some(o) => Box::new(std::fs::File::create(&o).with_context(|| format!("Creating {o:?}"))?),
+                None => Box::new(std::io::stdout().lock()),
+            };
+            export::export(&mut out, &library, &docs, format, &app.mod_path)?;
+        }
+        Commands::Cite(CiteArgs { uri, page, dest, from, .. }) => {
+            if let Ok(doc) = app.find_document(&uri) {
+                app.record_at(events::EventKind::Cite, &doc, page, dest.clone(),
+                              from.map(|f| format!("from {f}")));
+            }
             let citation = format!("{}?{}", 
                                    uri,
                                    serde_urlencoded::to_string(PageArgs { page, dest })?);
-            ctx.set_contents(citation).unwrap();
-            notifica::notify("🌍 Copied To Clipboard",
-                             &format!("Copied citation of {uri}")
-                            ).unwrap();
+            app.desktop.copy(citation)?;
+            if !app.desktop.headless {
+                app.desktop.notify("🌍 Copied To Clipboard",
+                                   &format!("Copied citation of {uri}")
+                                  )?;
+            }
         }
         Commands::Resolve(ResolveArgs { uri }) => {
             match app.find_document(&uri) {
@@ -833,76 +5874,785 @@ fn execute_command(app : &mut AppState, cmd : Commands, interactive : bool) -> R
             }
         }
         Commands::Convert(ConvertArgs { uri, output }) => {
-            notifica::notify("🌍 Converting",
-                             &format!("Processing {}", &uri)
-                            ).unwrap();
-            let mut doc = load_pdf_document(&uri, None).unwrap();
-            let out_path = PathBuf::from(output);
+            app.desktop.notify("🌍 Converting",
+                               &format!("Processing {}", &uri)
+                              )?;
+            let mut doc = load_pdf_document(&uri, None, &app.config).unwrap();
             update_document_links(&mut doc, None);
-            doc.save_to(&out_path).unwrap();
-            notifica::notify("🌍 Converting",
-                             &format!("Finished processing {}", &uri)
-                            ).unwrap();
+            add_document_outline(&mut doc);
+            doc.save_to(&output).unwrap();
+            app.desktop.notify("🌍 Converting",
+                               &format!("Finished processing {}", &uri)
+                              )?;
         }
-        Commands::Open(CiteArgs { uri ,page, dest, .. }) => {
+        Commands::Open(CiteArgs { uri ,page, dest, resume, .. }) => {
             match app.find_document(&uri) {
                 Ok(doc) => {
                     log::debug!("Document {uri} already exists");
-                    view_pdf_file(&app.mod_path.join(&doc.filename), page, dest);
+                    let (page, dest) = match app.sessions.position(&doc.checksum)? {
+                        Some(p) if resume && page.is_none() && dest.is_none() => (p.page, p.dest),
+                        _ => (page, dest),
+                    };
+                    let viewer = app.open_in_viewer(&doc, page, dest)?;
+                    app.wait_for_viewers(viewer.into_iter().collect());
                 }
                 Err(_) => {
                     log::debug!("Document {uri} was not found");
-                    forward_open(&uri)?;
+                    forward_open(&app.desktop, &uri)?;
                 }
             }
         }
         Commands::View(CiteArgs { uri, page, dest,.. }) => {
-            view_pdf_file(&PathBuf::from(uri), page, dest);
+            let mut path = PathBuf::from(&uri);
+            // links to files of the library renamed since
+            if !path.exists() {
+                if let Some(doc) = app.find_by_filename(&uri)? {
+                    path = app.mod_path.join(&doc.filename);
+                }
+            }
+            // files of the library are opened documents too
+            let mut format = format::Format::Pdf;
+            if path.starts_with(&app.mod_path) || path.starts_with(&app.raw_path) {
+                if let Some(doc) = app.find_by_filename(&path.to_string_lossy())? {
+                    app.record_at(events::EventKind::Open, &doc, page, dest.clone(), None);
+                    format = doc.format;
+                }
+            }
+            if format.is_pdf() || app.desktop.headless {
+                view_pdf_file(&app.desktop, &path, page, dest);
+            } else {
+                spawn_format_viewer(&app.config, format, &path)?;
+            }
+        }
+        Commands::Import(ImportArgs { local: true, .. }) if app.global.is_none() => {
+            anyhow::bail!("There is no project library: create a {} directory at the root of the project", project::DIR);
+        }
+        Commands::Import(import_args) if import_args.local && !import_args.uri.is_empty()
+            && app.global.as_deref().is_some_and(|g| g.find_document(&import_args.uri).is_ok()) => {
+            copy_from_global(app, &import_args.uri)?;
+        }
+        Commands::Import(import_args) if import_args.batch.is_some() => {
+            import_batch(app, import_args, interactive)?;
+        }
+        Commands::Import(import_args) if import_args.bibtex.is_some() => {
+            import_bibtex(app, import_args, interactive)?;
+        }
+        Commands::Import(import_args) if import_args.zotero.is_some() => {
+            let path = import_args.zotero.clone().context("No Zotero export to import")?;
+            let items = zotero::read(&path)?;
+            import_items(app, import_args, items, &path, interactive)?;
+        }
+        Commands::Import(import_args) if import_args.papis.is_some() => {
+            let path = import_args.papis.clone().context("No papis library to import")?;
+            let items = papis::read_papis(&path)?;
+            import_items(app, import_args, items, &path, interactive)?;
+        }
+        Commands::Import(import_args) if import_args.pubs.is_some() => {
+            let path = import_args.pubs.clone().context("No pubs repository to import")?;
+            let items = papis::read_pubs(&path)?;
+            import_items(app, import_args, items, &path, interactive)?;
+        }
+        Commands::Import(import_args) if import_args.stdin => {
+            import_stdin(app, import_args)?;
         }
         Commands::Import(import_args) => {
-            notifica::notify("🌍 Converting",
-                             &format!("Processing {}", import_args.uri)
-                            )
+            app.desktop.notify("🌍 Converting",
+                               &format!("Processing {}", import_args.uri)
+                              )
                 .context("Notifying the user that the conversion started")?;
             log::info!("Importing document {}", import_args.uri);
             let m_doc = app.find_document(&import_args.uri);
             let view = import_args.view;
-            let name : String;
 
-            match (m_doc, import_args.force) {
+            let name : String = match (m_doc, import_args.force) {
                 (Ok(doc), false) => {
                     log::info!("Document {} already in the library, but force set to false", import_args.uri);
-                    name = doc.filename.clone();
+                    doc.filename.clone()
                 }
                 (Ok(doc), true)  => {
                     log::info!("Document {} already in the library, and force set to true", import_args.uri);
-                    app.delete(&doc.clone())?;
-                    name = import_document(app, import_args, interactive)?;
+                    reimport_document(app, &doc, import_args, interactive)?
                 }
                 (Err(_), _)    => {
                     log::info!("Document {} is completely new", import_args.uri);
-                    name = import_document(app, import_args, interactive)?;
+                    match import_document(app, import_args, interactive) {
+                        Ok(name) => name,
+                        Err(e) => {
+                            // imports started from a browser have no terminal
+                            if let Err(n) = app.desktop.notify("❌ Import failed", &format!("{e:#}")) {
+                                log::warn!("Could not notify the failure: {n:?}");
+                            }
+                            return Err(e);
+                        }
+                    }
                 }
             };
 
-            notifica::notify("🌍 Converting",
-                             &format!("Finished processing {name}")
-                            )
+            app.desktop.notify_with_actions("🌍 Converting",
+                                            &format!("Finished processing {name}"),
+                                            app.document_actions(&name)?)
                 .context("Notifying the user that the conversion is done")?;
 
 
             if view {
-                view_pdf_file(&app.mod_path.join(name), None, None)
+                let path = app.mod_path.join(&name);
+                match app.find_by_filename(&name)?.filter(|d| !d.format.is_pdf() && !app.desktop.headless) {
+                    Some(doc) => spawn_format_viewer(&app.config, doc.format, &path)?,
+                    None => view_pdf_file(&app.desktop, &path, None, None),
+                }
+            }
+
+        }
+        Commands::Tag(TagArgs { action }) => {
+            manage_tags(app, action)?;
+        }
+        Commands::Search(SearchArgs { action: SearchCommands::Run { name, format, template, print0 } }) => {
+            let query = app.searches()?.get(&name)?.to_string();
+            let args = FindArgs { query: vec![query], format, template, print0, ..Default::default() };
+            execute_command(app, Commands::Find(args), interactive)?;
+        }
+        Commands::Search(SearchArgs { action }) => {
+            manage_searches(app, action)?;
+        }
+        Commands::Authors(AuthorsArgs { action }) => {
+            manage_authors(app, action)?;
+        }
+        Commands::Status(StatusArgs { action }) => {
+            manage_status(app, action)?;
+        }
+        Commands::Collection(CollectionArgs { action }) => {
+            manage_collections(app, action)?;
+        }
+        Commands::Edit(EditArgs { uri }) => {
+            let name = edit_library_document(app, &uri)?;
+            println!("Updated {name}");
+        }
+        Commands::Dblp(DblpArgs { uri, filter }) => {
+            let docs = match uri {
+                Some(uri) => vec![app.find_document(&uri)?],
+                None => app.storage.documents()?.into_iter()
+                    .filter(|d| filter.matches(d) && identifiers::dblp_key(&d.identifiers).is_none())
+                    .collect(),
+            };
+            for doc in &docs {
+                match dblp::lookup(doc) {
+                    Ok(Some(key)) => {
+                        let mut new = doc.clone();
+                        new.identifiers.retain(|i| identifiers::kind_of(i) != identifiers::IdentifierKind::Dblp);
+                        new.identifiers.push(format!("dblp:{key}"));
+                        identifiers::sort(&app.config.identifier_priority, &mut new.identifiers);
+                        app.update_document(doc, &new)?;
+                        app.reconvert_if_needed(&new)?;
+                        println!("{}\tDBLP:{key}", doc.filename);
+                    }
+                    Ok(None) => { eprintln!("{}: not found in DBLP", doc.filename); }
+                    Err(e) => { eprintln!("Could not query DBLP for {}: {e:#}", doc.filename); }
+                }
+            }
+        }
+        Commands::Published(PublishedArgs { uri, filter, dry_run }) => {
+            let docs = match uri {
+                Some(uri) => vec![app.find_document(&uri)?],
+                None => app.storage.documents()?.into_iter()
+                    .filter(|d| filter.matches(d) && published::is_unpublished(d))
+                    .collect(),
+            };
+            let crossref = crossref::Crossref::new(&app.cache_path);
+            let mut found = vec![];
+            for doc in &docs {
+                match published::lookup(doc, &crossref) {
+                    Ok(Some(publication)) => {
+                        let venue = publication.venue.as_deref().unwrap_or("an unknown venue");
+                        println!("{}	doi:{}	{venue} ({})", doc.filename, publication.doi, publication.source);
+                        if !dry_run {
+                            let mut new = doc.clone();
+                            published::apply(&mut new, &publication);
+                            identifiers::sort(&app.config.identifier_priority, &mut new.identifiers);
+                            app.update_document(doc, &new)?;
+                            app.record(events::EventKind::Edit, &new, Some(format!("published in {venue} (doi:{})", publication.doi)));
+                            app.reconvert_if_needed(&new)?;
+                        }
+                        found.push(doc.title.clone());
+                    }
+                    Ok(None) => { log::info!("{}: no published version found", doc.filename); }
+                    Err(e) => { eprintln!("Could not look up the publication of {}: {e:#}", doc.filename); }
+                }
+            }
+            if !found.is_empty() && !dry_run {
+                app.desktop.notify(&format!("📰 {} preprints were published", found.len()), &found.join("\n"))?;
+            }
+        }
+        Commands::Enrich(EnrichArgs { uri, all: _, filter, dry_run }) => {
+            let docs = match uri {
+                Some(uri) => vec![app.find_document(&uri)?],
+                None => app.storage.documents()?.into_iter().filter(|d| filter.matches(d)).collect(),
+            };
+            let crossref = crossref::Crossref::new(&app.cache_path);
+            for doc in &docs {
+                let changes = enrich::propose(doc, &crossref);
+                for (change, api) in &changes {
+                    println!("{}\t{change}\t({api})", doc.filename);
+                }
+                if dry_run || changes.is_empty() {
+                    continue;
+                }
+                let mut new = doc.clone();
+                enrich::apply(&mut new, &changes);
+                identifiers::sort(&app.config.identifier_priority, &mut new.identifiers);
+                app.update_document(doc, &new)?;
+                let mut apis : Vec<&str> = changes.iter().map(|(_, api)| *api).collect();
+                apis.sort_unstable();
+                apis.dedup();
+                app.record(events::EventKind::Edit, &new, Some(format!("enriched from {}", apis.join(", "))));
+                app.reconvert_if_needed(&new)?;
+            }
+        }
+        Commands::Subscribe(SubscribeArgs { category: None, .. }) => {
+            for subscription in app.inbox()?.subscriptions() {
+                println!("{subscription}");
+            }
+        }
+        Commands::Subscribe(SubscribeArgs { category: Some(category), remove }) => {
+            let mut inbox = app.inbox()?;
+            if remove {
+                inbox.unsubscribe(&category)?;
+            } else {
+                inbox.subscribe(&category)?;
+            }
+            inbox.save()?;
+        }
+        Commands::Follow(FollowArgs { name: None, .. }) => {
+            for f in app.inbox()?.followed() {
+                let ids : Vec<String> = f.orcid.iter().map(|o| format!("orcid:{o}"))
+                    .chain(f.dblp.iter().map(|d| format!("dblp:{d}")))
+                    .collect();
+                println!("{}\t{}", f.name, ids.join(" "));
+            }
+        }
+        Commands::Follow(FollowArgs { name: Some(name), remove: true, .. }) => {
+            let mut inbox = app.inbox()?;
+            inbox.unfollow(&name)?;
+            inbox.save()?;
+        }
+        Commands::Follow(FollowArgs { name: Some(name), orcid, dblp, remove: false }) => {
+            let orcid = orcid.map(|o| authors::normalize_orcid(&o).with_context(|| format!("{o} is not an ORCID")))
+                .transpose()?;
+            let mut inbox = app.inbox()?;
+            inbox.follow(inbox::Followed { name: authors::display_name(&name), orcid, dblp })?;
+            inbox.save()?;
+        }
+        Commands::Inbox(InboxArgs { action }) => {
+            manage_inbox(app, action, interactive)?;
+        }
+        Commands::Reconvert(ReconvertArgs { uri: Some(uri), .. }) => {
+            let doc = app.find_document(&uri)?;
+            app.reconvert(&doc)?;
+            println!("Converted {}", doc.filename);
+        }
+        Commands::Reconvert(ReconvertArgs { uri: None, all, filter }) => {
+            for doc in app.storage.documents()?.into_iter().filter(|d| !d.metadata_only && d.format.is_pdf() && filter.matches(d)) {
+                if all || app.conversion_status(&doc)?.is_some() {
+                    match app.reconvert(&doc) {
+                        Ok(_) => { println!("Converted {}", doc.filename); }
+                        Err(e) => { eprintln!("Could not convert {}: {e:#}", doc.filename); }
+                    }
+                }
+            }
+        }
+        Commands::Rename(RenameArgs { dry_run }) => {
+            naming::validate(&app.config.filename_template)?;
+            for doc in app.storage.documents()? {
+                let name = doc.generate_name(&app.config)?;
+                if name == doc.filename {
+                    continue;
+                }
+                println!("{} -> {name}", doc.filename);
+                if !dry_run {
+                    app.move_document(&doc, Document { filename: name, ..doc.clone() })?;
+                }
+            }
+        }
+        Commands::Linkmap(LinkmapArgs { uri, html, output }) => {
+            let (path, title) = match app.find_document(&uri) {
+                Ok(doc) if !doc.format.is_pdf() => anyhow::bail!("{} is not a pdf file, only the pdf files have links", doc.filename),
+                Ok(doc) => (app.raw_file(&doc), doc.title),
+                Err(_) if std::path::Path::new(&uri).exists() => (PathBuf::from(&uri), uri.clone()),
+                Err(e) => { return Err(e); }
+            };
+            let map = load_pdf_document(&path.to_string_lossy(), None, &app.config)?.link_map();
+            let mut out : Box<dyn std::io::Write> = match output {
+                Some(o) => Box::new(std::fs::File::create(&o).with_context(|| format!("Creating {o:?}"))?),
+                None => Box::new(std::io::stdout().lock()),
+            };
+            if html {
+                linkmap::write_html(&mut out, &map, &title, &path)?;
+            } else {
+                linkmap::write_text(&mut out, &map)?;
+            }
+        }
+        Commands::Anchors(AnchorsArgs { action }) => {
+            manage_anchors(app, action)?;
+        }
+        Commands::Heatmap(HeatmapArgs { uri }) => {
+            let doc = app.find_document(&uri)?;
+            if !doc.format.is_pdf() {
+                anyhow::bail!("{} is not a pdf file, only the pdf files have a heatmap", doc.filename);
             }
+            let pdf = load_pdf_document(&app.raw_file(&doc).to_string_lossy(), None, &app.config)?;
+            let table = app.anchors()?.table(&doc);
+            let mut events = app.events.since(None)?;
+            for e in &mut events {
+                e.dest = e.dest.as_deref().map(|d| table.resolve(d));
+            }
+            let map = heatmap::collect(&events, &doc.checksum,
+                                       pdf.link_map().pages.len(), pdf.destinations());
+            println!("{}", doc.title);
+            heatmap::write(&mut std::io::stdout().lock(), &map)?;
+        }
+        Commands::Bench(BenchArgs { file, runs }) => {
+            println!("{:<18} {:>10} {:>12} {:>12} {:>8}", "pass", "time (ms)", "allocations", "bytes", "items");
+            let count = |n : Option<usize>| n.map_or("-".to_string(), |n| n.to_string());
+            for m in bench::bench(&file, runs.max(1))? {
+                println!("{:<18} {:>10.2} {:>12} {:>12} {:>8}",
+                         m.pass, m.time.as_secs_f64() * 1000.0, count(m.allocations), count(m.bytes), m.items);
+            }
+        }
+        Commands::Verify => {
+            let mut broken = 0;
+            for doc in app.storage.documents()?.into_iter().filter(|d| !d.metadata_only) {
+                let verify::Verification { problems, raw_checksum } =
+                    verify::verify(&doc, &app.raw_file(&doc), &app.mod_path.join(&doc.filename));
+                // documents imported before the hash of the original
+                // file was recorded: trust the current file
+                if doc.raw_checksum.is_none() && raw_checksum.is_some() && problems.is_empty() {
+                    log::info!("Recording the hash of the original file of {}", doc.filename);
+                    let mut new = doc.clone();
+                    new.raw_checksum = raw_checksum.clone();
+                    app.update_document(&doc, &new)?;
+                }
+                // documents imported before the store
+                let legacy = store::legacy_path(&app.raw_path, &doc);
+                if raw_checksum.is_some() && legacy.exists() && (doc.raw_checksum.is_some() || problems.is_empty()) {
+                    log::info!("Moving the original file of {} to the store", doc.filename);
+                    store::put(&app.raw_path, &legacy, doc.format)?;
+                }
+                if problems.is_empty() {
+                    continue;
+                }
+                broken += 1;
+                println!("{}", doc.filename);
+                for p in &problems {
+                    println!("  {p}");
+                    println!("    suggestion: {}", p.suggestion(&doc));
+                }
+            }
+            if broken > 0 {
+                anyhow::bail!("{broken} documents have problems");
+            }
+            println!("All the documents are fine");
+        }
+        Commands::Gc(GcArgs { dry_run, delete }) => {
+            collect_garbage(app, dry_run, delete)?;
+        }
+        Commands::Review(ReviewArgs { threshold, list }) => {
+            review_documents(app, threshold, list)?;
+        }
+        Commands::Merge(MergeArgs { uris }) => {
+            merge_documents(app, &uris)?;
+        }
+        Commands::Remove(RemoveArgs { uri }) => {
+            let doc = app.find_document(&uri)?;
+            app.remove_to_trash(&doc)?;
+            println!("Moved {} to the trash", doc.filename);
+        }
+        Commands::Session(SessionArgs { action }) => {
+            manage_sessions(app, action)?;
+        }
+        Commands::Backup(BackupArgs { action }) => {
+            manage_backups(app, action)?;
+        }
+        Commands::Trash(TrashArgs { action: TrashCommands::Restore { uri } }) => {
+            let entry = app.trash.find(&uri)?;
+            app.restore_from_trash(&entry)?;
+            println!("Restored {}", entry.document.filename);
+        }
+        Commands::Trash(TrashArgs { action: TrashCommands::List }) => {
+            app.purge_trash()?;
+            for entry in app.trash.entries()? {
+                println!("{}  {}  {}",
+                         entry.removed.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+                         entry.document.checksum,
+                         entry.document.title);
+            }
+        }
+        Commands::Related(RelatedArgs { uri }) => {
+            show_related(app, &uri)?;
+        }
+        Commands::Outdated(OutdatedArgs { filter, json }) => {
+            let docs : Vec<Document> = app.storage.documents()?.into_iter()
+                .filter(|d| filter.matches(d))
+                .collect();
+            let outdated = outdated_documents(&docs)?;
+            if json {
+                serde_json::to_writer_pretty(std::io::stdout(), &outdated)?;
+                println!();
+            } else {
+                for o in &outdated {
+                    println!("{}\tv{} → v{}\thttps://arxiv.org/abs/{}v{}", o.filename, o.version, o.latest, o.arxiv, o.latest);
+                }
+            }
+        }
+        Commands::Update(UpdateArgs { uri }) => {
+            let doc = app.find_document(&uri)?;
+            match update_document(app, &doc)? {
+                Some(name) => { println!("{name}"); }
+                None => { println!("{} is the latest version on arXiv", doc.filename); }
+            }
+        }
+        Commands::Attach(AttachArgs { uri, file }) => {
+            let doc = app.find_document(&uri)?;
+            let name = attach_document(app, &doc, &file)?;
+            println!("{name}");
+        }
+        Commands::Info(InfoArgs { uri, report: false }) => {
+            show_document(app, &uri)?;
+        }
+        Commands::Info(InfoArgs { uri, report: true }) => {
+            let doc = app.find_document(&uri)?;
+            match app.reports.load(&doc.checksum)? {
+                Some(r) => { print!("{}", serde_yaml::to_string(&r)?); }
+                None => { println!("No import report for {} (imported before akl wrote them)", doc.filename); }
+            }
+        }
+        Commands::Activity(ActivityArgs { since }) => {
+            let since = since.as_deref().map(events::parse_since).transpose()?;
+            for e in app.events.since(since)? {
+                let details = [e.page.map(|p| format!("page {p}")), e.dest, e.detail]
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>();
+                println!("{}  {:<8}  {}{}",
+                         e.time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+                         format!("{:?}", e.kind).to_lowercase(),
+                         e.title,
+                         if details.is_empty() { String::new() } else { format!(" ({})", details.join(", ")) });
+            }
+        }
+        Commands::History(HistoryArgs { limit }) => {
+            for group in app.journal.groups()?.iter().rev().take(limit) {
+                println!("{}  {}  akl {}{}",
+                         group.id,
+                         group.time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+                         group.command,
+                         if group.undone { "  (undone)" } else { "" });
+                for op in &group.operations {
+                    println!("    {}", op.describe());
+                }
+            }
+        }
+        Commands::Undo(UndoArgs { id }) => {
+            let groups = app.journal.groups()?;
+            let group = groups.iter().rev()
+                .find(|g| match &id {
+                    Some(id) => g.id.starts_with(id.as_str()),
+                    None => !g.undone,
+                })
+                .context("Nothing to undo")?;
+            if group.undone {
+                anyhow::bail!("The command {} was already undone", group.id);
+            }
+            undo_group(app, group)?;
+            println!("Undone: akl {}", group.command);
+        }
+        Commands::Trash(TrashArgs { action: TrashCommands::Empty }) => {
+            let entries = app.trash.entries()?;
+            for entry in &entries {
+                app.trash.delete(entry)?;
+            }
+            println!("Deleted {} documents", entries.len());
+        }
+        Commands::Credentials(CredentialsArgs { action }) => {
+            manage_credentials(app, action)?;
+        }
+        Commands::Sync(SyncArgs { action }) => {
+            manage_sync(app, action)?;
+        }
+        Commands::Log(LogArgs { limit }) => {
+            print!("{}", git::log(&app.index_dir, limit)?);
+        }
+        Commands::RevertTo(RevertToArgs { commit }) => {
+            {
+                let _lock = lock::exclusive(&app.index_dir.join("index.yaml"))?;
+                git::revert_to(&app.index_dir, &commit)?;
+            }
+            // the documents the index knows again may have lost their files
+            let storage = storage::open_backend(app.config.backend, &app.index_dir)?;
+            let missing = storage.documents()?.iter()
+                .filter(|d| !d.metadata_only && !app.raw_file(d).exists())
+                .count();
+            println!("Reverted the index to {commit}");
+            if missing > 0 {
+                println!("{missing} documents have no file anymore: see akl trash list, akl verify and akl gc");
+            }
+            // the index in memory is outdated
+            app.storage = storage;
+        }
+        Commands::Relocate(args) => {
+            relocate(app, args)?;
+        }
+        Commands::Browse(BrowseArgs { dir }) => {
+            let dir = dir.or(app.config.browse_dir.clone())
+                .context("No directory for the views, set browse_dir in the configuration or use --dir")?;
+            let (created, removed) = browse::update(&dir, &app.storage.documents()?, &app.mod_path)?;
+            println!("Created {created} links and removed {removed} in {}", dir.display());
+        }
+        Commands::Migrate(MigrateArgs { to }) => {
+            if to == app.config.backend {
+                println!("The index already uses the {to:?} backend");
+            } else {
+                let mut target = storage::open_backend(to, &app.index_dir)?;
+                let existing = target.documents()?;
+                if !existing.is_empty() {
+                    // a stale copy of the index, like the yaml index left
+                    // by the first versions of the sqlite backend
+                    for doc in &existing {
+                        if app.storage.find_by_checksum(&doc.checksum)?.is_none() {
+                            anyhow::bail!("The {to:?} index already contains documents, refusing to overwrite it");
+                        }
+                    }
+                    log::info!("Moving aside the stale {to:?} index");
+                    drop(target);
+                    storage::retire(to, &app.index_dir)?;
+                    target = storage::open_backend(to, &app.index_dir)?;
+                }
+                let count = storage::copy_documents(app.storage.as_ref(), target.as_mut())?;
+                storage::retire(app.config.backend, &app.index_dir)?;
+                app.config.backend = to;
+                app.config.save(&app.config_path)?;
+                app.storage = target;
+                println!("Migrated {count} documents to the {to:?} backend");
+            }
+        }
+        Commands::Handler(HandlerArgs { socket }) => {
+            app.desktop.persistent = true;
+            let mut loaded = storage::modified(app.config.backend, &app.index_dir);
+            let mut execute = |uri : &str| {
+                // the index is reloaded when another process changed it
+                let modified = storage::modified(app.config.backend, &app.index_dir);
+                if modified != loaded {
+                    log::info!("Reloading the index");
+                    app.storage = storage::open(app.config.backend, &app.index_dir)?;
+                    loaded = modified;
+                }
+                app.journal.start(uri);
+                execute_uri(app, uri, false)
+            };
+            if socket {
+                #[cfg(unix)]
+                handler::serve_socket(handler::listen()?, &mut execute)?;
+                #[cfg(not(unix))]
+                anyhow::bail!("The handler socket is only available on unix systems");
+            } else {
+                let stdin = std::io::stdin();
+                handler::serve(stdin.lock(), std::io::stdout(), &mut execute)?;
+            }
+        }
+    }
+    app.save()
+}
+
+/// Reverts the operations of a group of the journal, last first.
+fn undo_group(app : &mut AppState, group : &journal::Group) -> Result<()> {
+    app.journal.paused = true;
+    let result = group.operations.iter().rev()
+        .try_for_each(|op| undo_operation(app, op).with_context(|| format!("Undoing {}", op.describe())));
+    app.journal.paused = false;
+    result?;
+    app.journal.undone(&group.id)
+}
+
+/// Reverts an operation of the journal. The document must be as
+/// the operation left it: the later operations on the document
+/// must be undone first.
+fn undo_operation(app : &mut AppState, op : &journal::Operation) -> Result<()> {
+    let files = app.journal.files_of(op);
+    let current = |app : &AppState, doc : &Document| -> Result<Document> {
+        let current = app.storage.find_by_checksum(&doc.checksum)?
+            .with_context(|| format!("{} is not in the library anymore", doc.filename))?;
+        if !journal::same(&current, doc) {
+            anyhow::bail!("{} changed since, undo the later commands first", current.filename);
+        }
+        Ok(current)
+    };
+    match (op.kind, &op.before, &op.after) {
+        (journal::OpKind::Import, None, Some(after)) => {
+            let doc = current(app, after)?;
+            app.remove_to_trash(&doc)
+        }
+        (journal::OpKind::Remove, Some(before), None) => {
+            if app.storage.find_by_checksum(&before.checksum)?.is_some() {
+                anyhow::bail!("The document {} is already in the library", before.filename);
+            }
+            if !before.metadata_only && !files.join("raw.pdf").exists() {
+                anyhow::bail!("The files of {} are not in the journal anymore", before.filename);
+            }
+            let targets = [
+                (files.join("raw.pdf"), store::target(&app.raw_path, before)?),
+                (files.join("mod.pdf"), app.mod_path.join(&before.filename)),
+            ];
+            if let Some((_, target)) = targets.iter().find(|(_, t)| t.exists()) {
+                anyhow::bail!("Cannot restore {target:?}, the file already exists");
+            }
+            for (from, to) in targets.iter().filter(|(f, _)| f.exists()) {
+                std::fs::copy(from, to).with_context(|| format!("Restoring {to:?}"))?;
+            }
+            app.storage.insert(before)?;
+            // the same document may still be in the trash
+            if let Ok(entry) = app.trash.find(&before.checksum) {
+                if journal::same(&entry.document, before) {
+                    app.trash.delete(&entry)?;
+                }
+            }
+            app.record(events::EventKind::Restore, before, Some("undo".into()));
+            Ok(())
+        }
+        (journal::OpKind::Edit | journal::OpKind::Reconvert, Some(before), Some(after)) => {
+            let doc = current(app, after)?;
+            if op.kind == journal::OpKind::Reconvert {
+                let previous = files.join("mod.pdf");
+                if !previous.exists() {
+                    anyhow::bail!("The previous modified file of {} is not in the journal anymore", doc.filename);
+                }
+                std::fs::copy(&previous, app.mod_path.join(&doc.filename))
+                    .context("Restoring the previous modified file")?;
+            }
+            app.rename_files(&doc, &before.filename)?;
+            app.storage.update(&doc, before)?;
+            app.record(events::EventKind::Edit, before, Some("undo".into()));
+            Ok(())
+        }
+        _ => anyhow::bail!("Invalid operation in the journal"),
+    }
+}
+
+/// A document of which a newer arxiv version is out.
+#[derive(Serialize, Debug)]
+struct NewerVersion {
+    id       : String,
+    filename : String,
+    title    : String,
+    arxiv    : String,
+    version  : u32,
+    latest   : u32,
+}
 
+/// The documents of which the arxiv version is not the
+/// latest one. The documents of an unknown version (imported
+/// from an `arxiv:` identifier without version) are skipped.
+fn outdated_documents(docs : &[Document]) -> Result<Vec<NewerVersion>> {
+    let papers : Vec<(&Document, arxiv::ArxivId, u32)> = docs.iter()
+        .filter_map(|d| {
+            let paper = arxiv::parse_id(&identifiers::arxiv_id(&d.identifiers)?)?;
+            let version = paper.version.as_deref()?.parse().ok()?;
+            Some((d, paper, version))
+        })
+        .collect();
+    let mut latest : HashMap<String, u32> = HashMap::new();
+    for batch in papers.chunks(arxiv::BATCH) {
+        let ids : Vec<String> = batch.iter().map(|(_, p, _)| p.id.clone()).collect();
+        for found in arxiv::latest_versions(&ids)? {
+            if let Some(v) = found.version.and_then(|v| v.parse().ok()) {
+                latest.insert(found.id, v);
+            }
+        }
+    }
+    Ok(papers.into_iter()
+        .filter_map(|(doc, paper, version)| {
+            let latest = *latest.get(&paper.id).filter(|l| **l > version)?;
+            Some(NewerVersion {
+                id: doc.id.clone(),
+                filename: doc.filename.clone(),
+                title: doc.title.clone(),
+                arxiv: paper.id,
+                version,
+                latest,
+            })
+        })
+        .collect())
+}
+
+/// Executes a uri given on the command line
+/// (typically an akl:// link clicked in a document).
+fn execute_uri(app : &mut AppState, val : &str, interactive : bool) -> Result<()> {
+    log::info!("Custom uri found {val:?}, will parse it.");
+    match uri_or_filepath_dispatch(val, &app.config.providers) {
+        Ok(ParsedURI::DOI(doi)) => {
+            println!("Please add a verb to this doi: {doi}");
+        }
+        Ok(ParsedURI::Arxiv { arxiv_id, arxiv_version }) => {
+            println!("Please add a verb to this arxiv identifier: {arxiv_id} {arxiv_version}");
+        }
+        Ok(ParsedURI::HttpURL(url)) => {
+            println!("Please add a verb to this http url: {url}");
+        }
+        Ok(ParsedURI::Isbn(isbn)) => {
+            println!("Please add a verb to this isbn: {isbn}");
+        }
+        Ok(ParsedURI::Rxiv(preprint)) => {
+            println!("Please add a verb to this preprint: {}", preprint.doi);
+        }
+        Ok(ParsedURI::Pubmed(id)) => {
+            println!("Please add a verb to this PubMed article: {}", id.identifier());
+        }
+        Ok(ParsedURI::Provider(found)) => {
+            println!("Please add a verb to this document of {}: {}", found.name, found.identifier);
+        }
+        Ok(ParsedURI::Plugin { program, uri }) => {
+            println!("Please add a verb to this uri of {program:?}: {uri}");
+        }
+        Ok(ParsedURI::Hal { hal_id, .. }) => {
+            println!("Please add a verb to this HAL deposit: {hal_id}");
+        }
+        Ok(ParsedURI::Iacr { iacr_id, .. }) => {
+            println!("Please add a verb to this ePrint report: {iacr_id}");
+        }
+        Ok(ParsedURI::Eccc { eccc_id, .. }) => {
+            println!("Please add a verb to this ECCC report: {eccc_id}");
+        }
+        Ok(ParsedURI::FilePath(path)) => {
+            println!("Please add a verb to this filepath: {path:?}");
+        }
+        Ok(ParsedURI::AklCommand(cmd)) => {
+            execute_command(app, cmd, interactive)?
+        }
+        Err(e) => {
+            log::error!("Could not parse the argument {e:?}");
+            anyhow::bail!("Invalid argument")
         }
     }
-    app.save();
     Ok(())
 }
 
 fn main() {
-    let mut app = AppState::new();
+    let cli = Cli::parse();
+
+    // a running handler already has everything loaded,
+    // it is faster to let it deal with akl links
+    if let Some(val) = cli.execute_uri.as_ref().filter(|v| v.starts_with("akl://")) {
+        if !cli.interactive {
+            if let Some(res) = handler::forward(val) {
+                if let Err(e) = res {
+                    println!("{e}");
+                }
+                return;
+            }
+        }
+    }
+
+    let mut app = match project::current().filter(|_| !cli.global) {
+        Some(root) => AppState::project(&root),
+        None => AppState::new(),
+    };
 
     let log = file_rotate::FileRotate::new(
         app.log_path.join("akl-rs"),
@@ -922,39 +6672,100 @@ fn main() {
     log::debug!("Parsing CLI");
     //log::debug!("Current app state is {app:?}");
 
-    let cli = Cli::parse();
+    app.desktop = desktop::Desktop::detect(cli.headless);
+    progress::configure(&app.desktop);
+    if let Some(global) = app.global.as_deref_mut() {
+        global.desktop = app.desktop.clone();
+    }
 
     match cli.execute_uri {
         Some(val) => {
-            log::info!("Custom uri found {val:?}, will parse it.");
-            match uri_or_filepath_dispatch(&val) {
-                Ok(ParsedURI::DOI(doi)) => {
-                    println!("Please add a verb to this doi: {doi}");
-                }
-                Ok(ParsedURI::Arxiv { arxiv_id, arxiv_version }) => {
-                    println!("Please add a verb to this arxiv identifier: {arxiv_id} {arxiv_version}");
-                }
-                Ok(ParsedURI::HttpURL(url)) => {
-                    println!("Please add a verb to this http url: {url}");
-                }
-                Ok(ParsedURI::FilePath(path)) => {
-                    println!("Please add a verb to this filepath: {path:?}");
-                }
-                Ok(ParsedURI::AklCommand(cmd)) => {
-                    execute_command(&mut app, cmd, cli.interactive).unwrap()
-                }
-                Err(e) => {
-                    log::error!("Could not parse the argument {e:?}");
-                    println!("Invalid argument");
-                }
+            if let Err(e) = execute_uri(&mut app, &val, cli.interactive) {
+                println!("{e}");
             }
         }
         None => {
             log::info!("Regular command mode");
             match cli.command {
-                Some(cmd) => { execute_command(&mut app, cmd, cli.interactive).unwrap() }
+                Some(cmd) => {
+                    if let Err(e) = execute_command(&mut app, cmd, cli.interactive) {
+                        log::error!("{e:?}");
+                        eprintln!("Error: {e:#}");
+                        std::process::exit(1);
+                    }
+                }
                 None => { println!("Please execute something") } 
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, Object, Stream};
+
+    /// A one page document, whose text tells the versions apart.
+    fn version(text : &str) -> pdflib::PdfDocument {
+        let mut doc = lopdf::Document::with_version("1.7");
+        let pages_id = doc.new_object_id();
+        let content_id = doc.add_object(Stream::new(dictionary! {}, format!("BT ({text}) Tj ET").into_bytes()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            "Contents" => content_id,
+        });
+        doc.objects.insert(pages_id, Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => vec![page_id.into()],
+            "Count" => 1,
+        }));
+        let catalog_id = doc.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        doc.trailer.set("Root", catalog_id);
+        pdflib::PdfDocument::try_from(doc).unwrap()
+    }
+
+    #[test]
+    fn replace_version_archives_the_previous_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config");
+        let mut app = AppState::with_dirs(&config, &dir.path().join("data"),
+                                          &dir.path().join("cache"), &config.join("config.yaml"));
+        let mut first = version("version 1");
+        let mut doc : Document = serde_json::from_value(serde_json::json!({
+            "checksum": first.get_checksum().unwrap(),
+            "id": "abcdefgh",
+            "filename": "paper.pdf",
+            "identifiers": ["arxiv:2101.00001v1"],
+            "title": "A paper",
+            "authors": ["Ada Lovelace"],
+            "year": 2021,
+        })).unwrap();
+        app.add_document(&mut doc, first).unwrap();
+        let raw_checksum = verify::file_checksum(&app.raw_file(&doc)).unwrap();
+
+        let mut new = doc.clone();
+        new.identifiers = vec!["arxiv:2101.00001v2".into()];
+        replace_version(&mut app, &doc, &mut new, version("version 2"), 1).unwrap();
+
+        // the previous version is archived, out of the store
+        let archived = app.data_path.join("versions").join("abcdefgh").join("v1.pdf");
+        assert_eq!(verify::file_checksum(&archived).unwrap(), raw_checksum);
+        let garbage = gc::collect(&app.storage.documents().unwrap(), &app.raw_path, &app.mod_path).unwrap();
+        assert!(garbage.orphans.is_empty(), "orphans {:?}", garbage.orphans);
+        assert!(garbage.dangling.is_empty());
+
+        // the index only knows the new version
+        assert_ne!(new.checksum, doc.checksum);
+        let docs = app.storage.documents().unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].checksum, new.checksum);
+        assert_eq!(docs[0].id, "abcdefgh");
+        assert!(app.storage.find_by_checksum(&doc.checksum).unwrap().is_none());
+        assert!(app.mod_path.join(&new.filename).exists());
+    }
+}
@@ -0,0 +1,231 @@
+// Filenames of the documents of the library.
+//
+// Filenames are generated from a template of the configuration,
+// e.g. `{first_author}-{year}-{short_title}-{hash8}.pdf`, using
+// lowercase ascii words so that the library can be explored with
+// fzf, find or other tools. The result is truncated to fit in the
+// filename length limit of the platform.
+
+use anyhow::Result;
+
+use crate::{Document, latex};
+use crate::config::Config;
+use crate::stopwords::{self, StopWords};
+
+/// The default naming scheme, the one of the libraries made
+/// before the templates (see `historical_name`).
+pub const DEFAULT_TEMPLATE : &str = "{authors} {year} {title} {hash}.pdf";
+
+/// Words left out of the titles by the historical names.
+const HISTORICAL_STOP_WORDS : &[&str] = &[
+    "the", "all", "any", "one", "on", "of",
+    "in", "where", "when", "why", "what",
+    "this", "some", "other", "every"
+];
+
+/// Maximal length of a filename, in bytes. This is the limit of
+/// the usual filesystems on linux and macOS (ext4, btrfs, apfs),
+/// and in utf-16 code units on windows (ntfs); the generated names
+/// are ascii, so both agree.
+pub const MAX_FILENAME_LEN : usize = 255;
+
+/// Fields longer than this are cut before the global truncation.
+const MAX_FIELD_LEN : usize = 30;
+
+/// Placeholders of the templates.
+const PLACEHOLDERS : &[&str] = &[
+    "authors", "first_author", "year", "title", "short_title",
+    "venue", "tags", "type", "id", "hash", "hash8",
+];
+
+/// Placeholders that can be shortened to fit the length limit.
+const SHRINKABLE : &[&str] = &["authors", "title", "venue", "tags", "first_author", "short_title"];
+
+/// Lowercase ascii words of a string, joined by dashes.
+pub fn slug(s : &str) -> String {
+    latex::to_ascii(s)
+        .to_ascii_lowercase()
+        .split(|c : char| c.is_whitespace() || c == ',')
+        .map(|w| w.chars().filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '.').collect::<String>())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+/// Significant words of the title, without math.
+fn title_words(doc : &Document, stop : &StopWords) -> Vec<String> {
+    stop.significant(stopwords::words(&doc.title))
+}
+
+/// Cuts a field to a given length, without dangling dashes.
+fn cut(s : &mut String, len : usize) {
+    // fields are ascii, truncating cannot split a character
+    s.truncate(len);
+    while s.ends_with('-') {
+        s.pop();
+    }
+}
+
+/// Value of a placeholder for a document.
+fn field(doc : &Document, name : &str, config : &Config, stop : &StopWords) -> String {
+    let mut value = match name {
+        "authors" => doc.authors.iter().map(|a| slug(a)).collect::<Vec<String>>().join("-"),
+        "first_author" => doc.authors.first().map(|a| slug(a)).unwrap_or_default(),
+        "year" => doc.year.to_string(),
+        "title" => title_words(doc, stop).join("-"),
+        "short_title" => title_words(doc, stop).into_iter().take(config.short_title_words).collect::<Vec<String>>().join("-"),
+        "venue" => doc.context.first().map(|c| slug(c)).unwrap_or_default(),
+        "tags" => doc.tags.iter().map(|t| slug(t)).collect::<Vec<String>>().join("-"),
+        "type" => doc.doc_type.map(|t| t.to_string()).unwrap_or_default(),
+        "id" => doc.short_id(),
+        "hash" => doc.checksum.clone(),
+        "hash8" => doc.checksum.chars().take(8).collect(),
+        _ => String::new(),
+    };
+    if SHRINKABLE.contains(&name) {
+        cut(&mut value, MAX_FIELD_LEN);
+    }
+    value
+}
+
+/// A parsed template: literal text and placeholders.
+enum Part {
+    Text(String),
+    Field(&'static str),
+}
+
+fn parse(template : &str) -> Result<Vec<Part>> {
+    let mut parts = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        parts.push(Part::Text(rest[..start].into()));
+        let end = rest[start..].find('}')
+            .ok_or_else(|| anyhow::anyhow!("Unclosed placeholder in the filename template {template}"))?;
+        let name = &rest[start + 1..start + end];
+        let field = PLACEHOLDERS.iter()
+            .find(|p| **p == name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown placeholder {{{name}}} in the filename template \
+                                           (expected one of {})", PLACEHOLDERS.join(", ")))?;
+        parts.push(Part::Field(field));
+        rest = &rest[start + end + 1..];
+    }
+    parts.push(Part::Text(rest.into()));
+    if !parts.iter().any(|p| matches!(p, Part::Field("id" | "hash" | "hash8"))) {
+        anyhow::bail!("The filename template {template} must contain {{id}}, {{hash}} or {{hash8}} for names to be unique");
+    }
+    Ok(parts)
+}
+
+/// Checks that a template can be used.
+pub fn validate(template : &str) -> Result<()> {
+    parse(template).map(|_| ())
+}
+
+/// Removes the characters that cannot appear in a filename
+/// on some platform, and the repeated separators left by
+/// empty fields.
+fn sanitize(name : &str) -> String {
+    let cleaned : String = name.chars()
+        .filter(|c| !c.is_control() && !"/\\<>:\"|?*".contains(*c))
+        .collect();
+    let mut name = cleaned.split(' ')
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ");
+    while name.contains("--") {
+        name = name.replace("--", "-");
+    }
+    name.trim_matches(['-', ' ']).to_string()
+}
+
+/// The historical name of a document, given by the default template:
+/// its files keep the name they had before the templates (even the
+/// leading space of the documents without authors), so that editing
+/// them does not rename them. Only the characters that no file could
+/// have in its name are removed.
+fn historical_name(doc : &Document) -> String {
+    let mut authors = doc.authors.iter()
+        .map(|author| latex::to_ascii(author)
+                            .to_ascii_lowercase()
+                            .replace("  ", " ")
+                            .replace([' ', ','], "-"))
+        .collect::<Vec<String>>()
+        .join("-");
+    let year = doc.year;
+    let mut title : String = latex::to_ascii(&doc.title)
+                             .to_ascii_lowercase()
+                             .split_whitespace()
+                             .filter(|x| !HISTORICAL_STOP_WORDS.contains(x))
+                             .collect::<Vec<&str>>()
+                             .join("-");
+    title.truncate(30); // Cannot fail because we have ascii code points
+    authors.truncate(30); // Cannot fail because we have ascii code points
+    let hash = &doc.checksum;
+    format!("{authors} {year} {title} {hash}.pdf")
+        .chars()
+        .filter(|c| *c != '/' && *c != '\0')
+        .collect()
+}
+
+/// The extension of the file of a document, for a name ending
+/// in `.pdf`: the templates name pdf files, the other formats
+/// take their own extension.
+fn with_extension(doc : &Document, name : String) -> String {
+    if doc.format.is_pdf() {
+        name
+    } else {
+        format!("{}.{}", name.strip_suffix(".pdf").unwrap_or(&name), doc.format.extension())
+    }
+}
+
+/// Generates the filename of a document from the
+/// template of the configuration.
+pub fn render(config : &Config, doc : &Document) -> Result<String> {
+    if config.filename_template == DEFAULT_TEMPLATE {
+        let name = with_extension(doc, historical_name(doc));
+        // the first version of the templates trimmed the
+        // leading space, the files named then keep their name
+        if doc.filename == name.trim_start() {
+            return Ok(doc.filename.clone());
+        }
+        return Ok(name);
+    }
+    let parts = parse(&config.filename_template)?;
+    let stop = config.stop_words();
+    let mut fields : Vec<(&'static str, String)> = parts.iter()
+        .filter_map(|p| match p {
+            Part::Field(f) => Some((*f, field(doc, f, config, &stop))),
+            Part::Text(_) => None,
+        })
+        .collect();
+
+    let assemble = |fields : &[(&str, String)]| {
+        let mut values = fields.iter();
+        let name : String = parts.iter()
+            .map(|p| match p {
+                Part::Text(t) => t.clone(),
+                Part::Field(_) => values.next().map(|(_, v)| v.clone()).unwrap_or_default(),
+            })
+            .collect();
+        with_extension(doc, sanitize(&name))
+    };
+
+    // shrink the longest shrinkable field until the name fits
+    loop {
+        let name = assemble(&fields);
+        if name.len() <= MAX_FILENAME_LEN {
+            return Ok(name);
+        }
+        let excess = name.len() - MAX_FILENAME_LEN;
+        let longest = fields.iter_mut()
+            .filter(|(n, v)| SHRINKABLE.contains(n) && !v.is_empty())
+            .max_by_key(|(_, v)| v.len());
+        match longest {
+            Some((_, v)) => {
+                let len = v.len().saturating_sub(excess.min(v.len()));
+                cut(v, len);
+            }
+            None => anyhow::bail!("The filename template produces names longer than {MAX_FILENAME_LEN} bytes"),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+// Metadata of the dois from doi.org, by content negotiation.
+//
+// Crossref only knows the dois it registered, while doi.org resolves
+// the dois of every registration agency (DataCite for arXiv, Zenodo
+// and most theses, mEDRA…). Asked for CSL-JSON or BibTeX rather than
+// html (with the `Accept` header), doi.org answers with the metadata
+// registered for the doi instead of redirecting to the landing page.
+// This gives the metadata of a doi even when its pdf file cannot be
+// downloaded, which is what the metadata-only imports rely on.
+
+use anyhow::{Result, Context};
+use serde_json::Value;
+
+use crate::crossref::Work;
+
+const CSL_JSON : &str = "application/vnd.citationstyles.csl+json";
+const BIBTEX   : &str = "application/x-bibtex";
+
+/// The answer of doi.org for a doi in the given format,
+/// `None` when the doi does not exist.
+fn negotiate(doi : &str, format : &str) -> Result<Option<String>> {
+    log::debug!("Asking doi.org for {doi} as {format}");
+    let answer = crate::polite::get(&format!("https://doi.org/{doi}"))
+        .header(reqwest::header::ACCEPT, format)
+        .send()
+        .context("Querying doi.org")?;
+    if answer.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let answer = answer.error_for_status()
+        .context("Querying doi.org")?
+        .text()
+        .context("Reading the answer of doi.org")?;
+    Ok(Some(answer))
+}
+
+/// A CSL-JSON string, which some agencies give as a list.
+fn text(value : &Value) -> Option<String> {
+    value.as_str()
+        .or_else(|| value.as_array().and_then(|a| a.iter().find_map(|v| v.as_str())))
+        .map(|s| s.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .filter(|s| !s.is_empty())
+}
+
+/// The name of an author of a CSL-JSON item.
+fn author_name(author : &Value) -> Option<String> {
+    let family = author["family"].as_str().or(author["literal"].as_str())?;
+    Some(match author["given"].as_str() {
+        Some(given) => format!("{given} {family}"),
+        None => family.to_string(),
+    })
+}
+
+/// Reads a CSL-JSON item.
+fn parse_csl(item : &Value) -> Work {
+    let authors = item["author"].as_array()
+        .map(|a| a.iter().filter_map(author_name).collect())
+        .unwrap_or_default();
+    Work {
+        title: text(&item["title"]),
+        authors,
+        orcids: crate::crossref::orcids(&item["author"], author_name),
+        year: ["issued", "published-print", "published-online", "created"].iter()
+            .find_map(|field| crate::crossref::year(&item[field])),
+        venue: text(&item["container-title"]),
+        pages: text(&item["page"]),
+        r#abstract: text(&item["abstract"])
+            .map(|a| crate::abstracts::strip_tags(&a))
+            .filter(|a| !a.is_empty()),
+        keywords: text(&item["keyword"])
+            .map(|k| crate::xmp::split_keywords(&k))
+            .unwrap_or_default(),
+        identifiers: vec![],
+    }
+}
+
+/// Reads a BibTeX entry.
+fn parse_bibtex(src : &str) -> Option<Work> {
+    let entry = crate::bibtex::parse(src).into_iter().find_map(Result::ok)?;
+    Some(Work {
+        title: entry.title(),
+        authors: entry.authors(),
+        orcids: vec![],
+        year: entry.year(),
+        venue: entry.venues().into_iter().next(),
+        pages: entry.field("pages").map(|p| p.replace("--", "-")),
+        r#abstract: entry.field("abstract"),
+        keywords: entry.field("keywords")
+            .map(|k| crate::xmp::split_keywords(&k))
+            .unwrap_or_default(),
+        identifiers: vec![],
+    })
+}
+
+/// The metadata registered for a doi, as CSL-JSON or else as
+/// BibTeX (which every agency does not give), `None` when the
+/// doi does not exist.
+pub fn work(doi : &str) -> Result<Option<Work>> {
+    let csl = negotiate(doi, CSL_JSON).and_then(|answer| match answer {
+        Some(answer) => serde_json::from_str::<Value>(&answer)
+            .map(|item| Some(parse_csl(&item)))
+            .context("Parsing the CSL-JSON answer of doi.org"),
+        None => Ok(None),
+    });
+    match csl {
+        Ok(work) => Ok(work),
+        Err(e) => {
+            log::debug!("No CSL-JSON for {doi} ({e:#}), asking for BibTeX");
+            Ok(negotiate(doi, BIBTEX)?.as_deref().and_then(parse_bibtex))
+        }
+    }
+}
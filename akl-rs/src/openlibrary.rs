@@ -0,0 +1,59 @@
+// Metadata of the books from OpenLibrary.
+//
+// Books have an isbn rather than a doi, and no pdf file to download:
+// the pdf files of the books are the ones the user owns, imported
+// with their isbn (`akl import -u book.pdf -i isbn:…`), or nothing
+// at all (`akl import -u isbn:… --metadata-only`). OpenLibrary knows
+// the title, the authors, the publisher and the year of most books
+// by isbn, which are better than the Info dictionary of the pdf.
+
+use anyhow::{Result, Context};
+use serde_json::Value;
+
+use crate::crossref::Work;
+
+/// Names of a list of OpenLibrary objects (`[{"name": …}]`).
+fn names(value : &Value) -> Vec<String> {
+    value.as_array()
+        .map(|a| a.iter().filter_map(|v| v["name"].as_str()).map(String::from).collect())
+        .unwrap_or_default()
+}
+
+/// Reads the data of a book.
+fn parse(book : &Value) -> Work {
+    let title = book["title"].as_str().map(|title| match book["subtitle"].as_str() {
+        Some(subtitle) => format!("{title}: {subtitle}"),
+        None => title.to_string(),
+    });
+    // publication dates are free text (`2004`, `March 2004`, `2004-03-02`)
+    let year = book["publish_date"].as_str().and_then(|date| {
+        date.split(|c : char| !c.is_ascii_digit())
+            .find(|w| w.len() == 4)
+            .and_then(|y| y.parse().ok())
+    });
+    Work {
+        title,
+        authors: names(&book["authors"]),
+        orcids: vec![],
+        year,
+        venue: names(&book["publishers"]).into_iter().next(),
+        pages: None,
+        r#abstract: None,
+        keywords: names(&book["subjects"]),
+        identifiers: vec![],
+    }
+}
+
+/// What OpenLibrary knows about a book, `None` when it does not know it.
+pub fn book(isbn : &str) -> Result<Option<Work>> {
+    let key = format!("ISBN:{isbn}");
+    let query = serde_urlencoded::to_string([("bibkeys", key.as_str()), ("format", "json"), ("jscmd", "data")])?;
+    log::debug!("Querying OpenLibrary for {isbn}");
+    let answer : Value = crate::polite::get(&format!("https://openlibrary.org/api/books?{query}"))
+        .send()
+        .and_then(|r| r.error_for_status())
+        .context("Querying the OpenLibrary API")?
+        .json()
+        .context("Parsing the OpenLibrary API answer")?;
+    Ok(answer.get(&key).map(parse))
+}
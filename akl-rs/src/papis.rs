@@ -0,0 +1,202 @@
+// Importing papis and pubs libraries.
+//
+// Both tools keep their libraries as plain files:
+//
+// - papis: a folder per document, holding an `info.yaml` file (with
+//   BibTeX field names, the citation key in `ref`, the tags and the
+//   attached `files`) next to the attached files,
+// - pubs: `bib/<key>.bib` (the BibTeX entry of the document),
+//   `meta/<key>.yaml` (its tags and `docfile`) and `doc/` (the
+//   attached files, designated as `docsdir://…`).
+//
+// The documents become items, as in Zotero imports, and keep their
+// citation key as a `citekey:…` identifier, so that the documents
+// can still be found by the keys cited in existing papers.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, Context};
+use serde_yaml::Value;
+
+use crate::bibtex;
+use crate::zotero::Item;
+
+/// The identifier of a document cited as `key`.
+pub fn citekey_identifier(key : &str) -> String {
+    format!("citekey:{key}")
+}
+
+/// The string value of a yaml scalar.
+fn scalar(value : &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// The strings of a yaml list, or of a comma (or else
+/// space) separated string.
+fn strings(value : Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::Sequence(values)) => values.iter().filter_map(scalar).collect(),
+        Some(Value::String(s)) if s.contains(',') => s.split(',').map(str::to_string).collect(),
+        Some(Value::String(s)) => s.split_whitespace().map(str::to_string).collect(),
+        _ => vec![],
+    }
+    .into_iter()
+    .map(|s| s.trim().to_string())
+    .filter(|s| !s.is_empty())
+    .collect()
+}
+
+/// Expands the `~` of a path.
+fn expand_home(path : &str) -> PathBuf {
+    match (path.strip_prefix("~/"), directories::BaseDirs::new()) {
+        (Some(rest), Some(dirs)) => dirs.home_dir().join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Keeps the pdf files, warning about the missing ones.
+fn pdf_files(key : &str, files : impl Iterator<Item = PathBuf>) -> Vec<PathBuf> {
+    files.filter(|f| f.extension().is_some_and(|e| e.eq_ignore_ascii_case("pdf")))
+        .filter(|f| {
+            let exists = f.exists();
+            if !exists {
+                log::warn!("The file {f:?} of {key} is missing");
+            }
+            exists
+        })
+        .collect()
+}
+
+// PAPIS //
+
+/// Reads the `info.yaml` file of a papis document.
+fn read_papis_info(path : &Path) -> Result<Item> {
+    let src = std::fs::read_to_string(path)
+        .with_context(|| format!("Reading {path:?}"))?;
+    let info : BTreeMap<String, Value> = serde_yaml::from_str(&src)
+        .with_context(|| format!("Parsing {path:?}"))?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+
+    // the fields are named as in BibTeX
+    let mut fields : BTreeMap<String, String> = info.iter()
+        .filter_map(|(k, v)| Some((k.to_lowercase(), scalar(v)?)))
+        .collect();
+    if let Some(id) = fields.get("arxivid").or_else(|| fields.get("arxiv")).cloned() {
+        fields.entry("eprint".into()).or_insert(id);
+        fields.entry("archiveprefix".into()).or_insert("arXiv".into());
+    }
+    let key = fields.get("ref").cloned()
+        .unwrap_or_else(|| dir.file_name().unwrap_or_default().to_string_lossy().to_string());
+    let kind = fields.get("type").cloned().unwrap_or_default();
+    let entry = bibtex::Entry { kind, key: key.clone(), fields, line: 0 };
+    let mut item = Item::from_bibtex(&entry);
+
+    if let Some(Value::Sequence(people)) = info.get("author_list") {
+        let authors : Vec<String> = people.iter()
+            .filter_map(|p| {
+                let part = |name : &str| p.get(name).and_then(scalar).unwrap_or_default();
+                let name = format!("{} {}", part("given"), part("family"));
+                Some(name.trim().to_string()).filter(|n| !n.is_empty())
+            })
+            .collect();
+        if !authors.is_empty() {
+            item.authors = authors;
+        }
+    }
+    if info.contains_key("ref") {
+        item.identifiers.push(citekey_identifier(&key));
+    }
+    item.tags = strings(info.get("tags"));
+    item.attachments = pdf_files(&key, strings(info.get("files")).iter().map(|f| dir.join(f)));
+    Ok(item)
+}
+
+/// The `info.yaml` files of a papis library.
+fn papis_infos(dir : &Path, infos : &mut Vec<PathBuf>) -> Result<()> {
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Listing {dir:?}"))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            papis_infos(&path, infos)?;
+        } else if path.file_name().is_some_and(|n| n == "info.yaml") {
+            infos.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Reads the documents of a papis library.
+pub fn read_papis(dir : &Path) -> Result<Vec<Item>> {
+    let mut infos = vec![];
+    papis_infos(dir, &mut infos)?;
+    infos.sort();
+    if infos.is_empty() {
+        anyhow::bail!("{dir:?} is not a papis library: no info.yaml file was found");
+    }
+    Ok(infos.iter()
+        .filter_map(|path| match read_papis_info(path) {
+            Ok(item) => Some(item),
+            Err(e) => {
+                log::warn!("Ignoring a document of the library: {e:#}");
+                None
+            }
+        })
+        .collect())
+}
+
+// PUBS //
+
+/// Reads the documents of a pubs repository (`~/.pubs` by default),
+/// whose documents are in its `doc` directory.
+pub fn read_pubs(dir : &Path) -> Result<Vec<Item>> {
+    let bib = dir.join("bib");
+    let entries = std::fs::read_dir(&bib)
+        .with_context(|| format!("{dir:?} is not a pubs repository: cannot list {bib:?}"))?;
+    let mut paths : Vec<PathBuf> = entries
+        .map(|e| e.map(|e| e.path()))
+        .collect::<std::io::Result<_>>()?;
+    paths.retain(|p| p.extension().is_some_and(|e| e == "bib"));
+    paths.sort();
+
+    let mut items = vec![];
+    for path in paths {
+        let src = std::fs::read_to_string(&path)
+            .with_context(|| format!("Reading {path:?}"))?;
+        let Some(entry) = bibtex::parse(&src).into_iter().next() else {
+            log::warn!("Ignoring {path:?}, which has no entry");
+            continue;
+        };
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                log::warn!("Ignoring {path:?}: {e:#}");
+                continue;
+            }
+        };
+        let mut item = Item::from_bibtex(&entry);
+        item.identifiers.push(citekey_identifier(&entry.key));
+
+        let meta_path = dir.join("meta").join(format!("{}.yaml", entry.key));
+        if let Ok(src) = std::fs::read_to_string(&meta_path) {
+            let meta : BTreeMap<String, Value> = serde_yaml::from_str(&src)
+                .with_context(|| format!("Parsing {meta_path:?}"))?;
+            item.tags = strings(meta.get("tags"));
+            let docfile = meta.get("docfile").and_then(scalar).map(|f| {
+                match f.strip_prefix("docsdir://") {
+                    Some(name) => dir.join("doc").join(name),
+                    None => expand_home(&f),
+                }
+            });
+            item.attachments = pdf_files(&entry.key, docfile.into_iter());
+        }
+        items.push(item);
+    }
+    Ok(items)
+}
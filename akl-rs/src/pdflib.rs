@@ -8,12 +8,14 @@ use lopdf::dictionary;
 use lopdf::{Document, Dictionary, Object, ObjectId};
 
 // standard library tools
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use chrono::Datelike;
 
 use sha2::{Digest,Sha256};
 
+use crate::xmp;
+
 /// PdfLibError enumerates all possible errors returned by this library.
 #[derive(Error, Debug)]
 pub enum PdfLibError {
@@ -23,14 +25,6 @@ pub enum PdfLibError {
     #[error("Invalid annotation found in the document")]
     InvalidAnnotation,
 
-    /// Invalid UTF-8 found when parsing a text-string object.
-    #[error("Invalid UTF-8 byte sequence when reading 'text-string' object")]
-    NDConvError { source: std::string::FromUtf8Error },
-
-    /// Invalid UTF-16 found when parsing a text-string object.
-    #[error("Invalid UTF-8 byte sequence when reading a 'text-string' object")]
-    NDConvError16 { source: std::string::FromUtf16Error },
-
     /// Represents all other cases of `lopdf::Error`.
     #[error(transparent)]
     PDFError(#[from] lopdf::Error),
@@ -42,9 +36,6 @@ pub enum PdfLibError {
 
 
 // TODO:
-// (5) Import des annotations depuis une copie du document (utile!!!)
-//      -> this is almost already possible 
-//
 // (6) Compute the hash of the document
 //
 
@@ -76,7 +67,182 @@ struct RectangleObject {
     colour : Rgb
 }
 
-//// Generic Pdf utils 
+/// Approximate bounding box of a piece of text on a page.
+#[derive(Debug,Clone,Copy)]
+struct TextBox {
+    x_ll : f32,
+    y_ll : f32,
+    x_ur : f32,
+    y_ur : f32,
+}
+
+impl TextBox {
+    /// Do the boxes intersect, with some padding around the text?
+    fn overlaps(&self, rect : &RectangleObject) -> bool {
+        const PADDING : f32 = 1.0;
+        rect.x_ll < self.x_ur + PADDING && self.x_ll - PADDING < rect.x_ur &&
+        rect.y_ll < self.y_ur + PADDING && self.y_ll - PADDING < rect.y_ur
+    }
+}
+
+/// An affine transformation `[a b c d e f]` of the pdf coordinates.
+type Matrix = [f32; 6];
+
+const IDENTITY : Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// Product `m × n` (apply `m`, then `n`).
+fn mat_mul(m : &Matrix, n : &Matrix) -> Matrix {
+    [m[0] * n[0] + m[1] * n[2],
+     m[0] * n[1] + m[1] * n[3],
+     m[2] * n[0] + m[3] * n[2],
+     m[2] * n[1] + m[3] * n[3],
+     m[4] * n[0] + m[5] * n[2] + n[4],
+     m[4] * n[1] + m[5] * n[3] + n[5]]
+}
+
+fn translation(tx : f32, ty : f32) -> Matrix {
+    [1.0, 0.0, 0.0, 1.0, tx, ty]
+}
+
+fn apply(m : &Matrix, x : f32, y : f32) -> (f32, f32) {
+    (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5])
+}
+
+/// Reads the numeric operands of an operation into a matrix.
+fn matrix_of_operands(operands : &[Object]) -> Option<Matrix> {
+    let v : Vec<f32> = operands.iter().filter_map(|o| o.as_float().ok()).collect();
+    <[f32; 6]>::try_from(v).ok()
+}
+
+/// Approximates the bounding boxes of the text shown on a page,
+/// by following the text and transformation matrices of its
+/// content stream. Glyph widths are not read from the fonts: every
+/// glyph is half an em wide, which is enough to find whitespace.
+/// Rotated text gets the bounding box of its rotated extent.
+/// Text drawn inside form xobjects is not seen.
+fn page_text_boxes(pdf : &Document, page_id : ObjectId) -> Vec<TextBox> {
+    let Ok(content) = pdf.get_and_decode_page_content(page_id) else { return vec![] };
+    let mut boxes = vec![];
+    let mut ctm = IDENTITY;
+    let mut stack = vec![];
+    let mut tm = IDENTITY;
+    let mut tlm = IDENTITY;
+    let mut size : f32 = 10.0;
+    let mut leading : f32 = 0.0;
+
+    let num = |ops : &[Object], i : usize| ops.get(i).and_then(|o| o.as_float().ok()).unwrap_or(0.0);
+
+    for op in &content.operations {
+        let ops = &op.operands;
+        let mut shown : Vec<f32> = vec![];
+        match op.operator.as_str() {
+            "q" => { stack.push(ctm); }
+            "Q" => { ctm = stack.pop().unwrap_or(IDENTITY); }
+            "cm" => {
+                if let Some(m) = matrix_of_operands(ops) {
+                    ctm = mat_mul(&m, &ctm);
+                }
+            }
+            "BT" => { tm = IDENTITY; tlm = IDENTITY; }
+            "Tf" => { size = num(ops, 1); }
+            "TL" => { leading = num(ops, 0); }
+            "Td" => { tlm = mat_mul(&translation(num(ops, 0), num(ops, 1)), &tlm); tm = tlm; }
+            "TD" => {
+                leading = -num(ops, 1);
+                tlm = mat_mul(&translation(num(ops, 0), num(ops, 1)), &tlm);
+                tm = tlm;
+            }
+            "Tm" => {
+                if let Some(m) = matrix_of_operands(ops) {
+                    tlm = m;
+                    tm = m;
+                }
+            }
+            "T*" => { tlm = mat_mul(&translation(0.0, -leading), &tlm); tm = tlm; }
+            "Tj" => {
+                shown.extend(ops.first().and_then(|o| o.as_str().ok()).map(|s| s.len() as f32));
+            }
+            "'" | "\"" => {
+                tlm = mat_mul(&translation(0.0, -leading), &tlm);
+                tm = tlm;
+                shown.extend(ops.last().and_then(|o| o.as_str().ok()).map(|s| s.len() as f32));
+            }
+            "TJ" => {
+                if let Some(Ok(arr)) = ops.first().map(Object::as_array) {
+                    // kerning numbers are in thousandths of an em,
+                    // counted here in glyphs of half an em
+                    let glyphs : f32 = arr.iter().map(|o| match o {
+                        Object::String(s, _) => s.len() as f32,
+                        o => -o.as_float().unwrap_or(0.0) / 500.0,
+                    }).sum();
+                    shown.push(glyphs);
+                }
+            }
+            _ => {}
+        }
+        for glyphs in shown {
+            let width = glyphs * size * 0.5;
+            let m = mat_mul(&tm, &ctm);
+            let corners = [apply(&m, 0.0, -0.2 * size), apply(&m, width, -0.2 * size),
+                           apply(&m, 0.0, 0.8 * size), apply(&m, width, 0.8 * size)];
+            boxes.push(TextBox {
+                x_ll: corners.iter().map(|c| c.0).fold(f32::INFINITY, f32::min),
+                y_ll: corners.iter().map(|c| c.1).fold(f32::INFINITY, f32::min),
+                x_ur: corners.iter().map(|c| c.0).fold(f32::NEG_INFINITY, f32::max),
+                y_ur: corners.iter().map(|c| c.1).fold(f32::NEG_INFINITY, f32::max),
+            });
+            tm = mat_mul(&translation(width, 0.0), &tm);
+        }
+    }
+    boxes
+}
+
+/// Moves a marker horizontally to the nearest position where it
+/// does not overlap text, so that markers of two-column documents
+/// land in the gutter or the margin instead of over the text.
+/// The marker stays in place when no such position exists.
+fn nudge_marker(rect : &mut RectangleObject, boxes : &[TextBox], page_width : f32) {
+    const STEP : f32 = 2.0;
+    const MAX_SHIFT : f32 = 150.0;
+    let free = |r : &RectangleObject| !boxes.iter().any(|b| b.overlaps(r));
+    if free(rect) {
+        return;
+    }
+    let mut shift = STEP;
+    while shift <= MAX_SHIFT {
+        for dx in [-shift, shift] {
+            let mut moved = rect.clone();
+            moved.x_ll += dx;
+            moved.x_ur += dx;
+            if moved.x_ll >= 0.0 && moved.x_ur <= page_width && free(&moved) {
+                *rect = moved;
+                return;
+            }
+        }
+        shift += STEP;
+    }
+}
+
+// Generic Pdf utils
+
+/// Characters 0x80 to 0xA0 of PDFDocEncoding, which differ from
+/// latin-1 (0x9F is undefined). See Annex D of the pdf reference.
+const PDF_DOC_ENCODING_HIGH : [char; 33] = [
+    '\u{2022}', '\u{2020}', '\u{2021}', '\u{2026}', '\u{2014}', '\u{2013}', '\u{0192}', '\u{2044}',
+    '\u{2039}', '\u{203A}', '\u{2212}', '\u{2030}', '\u{201E}', '\u{201C}', '\u{201D}', '\u{2018}',
+    '\u{2019}', '\u{201A}', '\u{2122}', '\u{FB01}', '\u{FB02}', '\u{0141}', '\u{0152}', '\u{0160}',
+    '\u{0178}', '\u{017D}', '\u{0131}', '\u{0142}', '\u{0153}', '\u{0161}', '\u{017E}', '\u{FFFD}',
+    '\u{20AC}',
+];
+
+/// Decodes utf-16 code units, replacing the invalid
+/// ones; a truncated last code unit is dropped.
+fn decode_utf16(s : &[u8], unit : fn([u8; 2]) -> u16) -> String {
+    let units = s.chunks_exact(2).map(|x| unit([x[0], x[1]]));
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
 
 /// Parses a "text string" object as defined by the PDF standard.
 ///
@@ -86,25 +252,24 @@ struct RectangleObject {
 /// UTF-16_BE -> \x254\x255
 /// UTF-16_LE -> \x255\x254
 /// UTF-8     -> \x239\x187\x191
-fn parse_text_string(s : &[u8]) -> Result<String,PdfLibError> {
-    if s.len() < 2 {
-        String::from_utf8(s.into())
-            .map_err(|e| PdfLibError::NDConvError { source : e })
-    } else if s[0] == 0xfe && s[1] == 0xff { 
-        let t16 : Vec<u16> = s.chunks(2)
-                   .skip(1)
-                   .map(|x| (x[0] as u16) << 8 | x[1] as u16).collect();
-        String::from_utf16(&t16)
-            .map_err(|e| PdfLibError::NDConvError16 { source : e })
-    } else if s[0] == 0xff && s[1] == 0xfe { 
-        let t16 : Vec<u16> = s.chunks(2)
-                   .skip(1)
-                   .map(|x| (x[1] as u16) << 8 | x[0] as u16).collect();
-        String::from_utf16(&t16)
-            .map_err(|e| PdfLibError::NDConvError16 { source : e })
+///
+/// Strings without a BOM that are valid UTF-8 are read as such,
+/// since many producers write them this way. Malformed strings
+/// are decoded as far as possible, never rejected.
+pub fn parse_text_string(s : &[u8]) -> String {
+    if let Some(rest) = s.strip_prefix(&[0xfe, 0xff]) {
+        decode_utf16(rest, u16::from_be_bytes)
+    } else if let Some(rest) = s.strip_prefix(&[0xff, 0xfe]) {
+        decode_utf16(rest, u16::from_le_bytes)
+    } else if let Some(rest) = s.strip_prefix(&[0xef, 0xbb, 0xbf]) {
+        String::from_utf8_lossy(rest).into_owned()
+    } else if let Ok(s) = std::str::from_utf8(s) {
+        s.to_string()
     } else {
-        String::from_utf8(s.into())
-            .map_err(|e| PdfLibError::NDConvError { source : e })
+        s.iter().map(|&c| match c {
+            0x80..=0xa0 => PDF_DOC_ENCODING_HIGH[(c - 0x80) as usize],
+            c => c as char,
+        }).collect()
     }
 }
 
@@ -145,7 +310,7 @@ fn rectangle_link(rect : &RectangleObject, url : String) -> Vec<Object> {
 /// This is useful because PDF named destinations have names that can
 /// either be represented as Strings ore Pdf Names depending on the document
 /// version.
-fn as_name_or_str<'a>(obj : &'a Object) -> Result<&'a [u8], PdfLibError> {
+fn as_name_or_str(obj : &Object) -> Result<&[u8], PdfLibError> {
     match obj {
         Object::Name(ref name) => Ok(name) ,
         Object::String(ref name, _) => Ok(name) ,
@@ -163,7 +328,7 @@ fn array_of_named_dest_obj<'a>(doc : &'a Document,
 {
     Ok(obj.as_dict()
        .and_then(move |d| d.get_deref(b"D", doc))
-       .or_else(|_| Ok(obj))
+       .or(Ok(obj))
        .and_then(Object::as_array)?)
 }
 
@@ -179,7 +344,7 @@ fn named_dest_of_object(doc : &Document,
                         key : &Object,
                         obj : &Object,
 ) -> Result<NamedDestination,PdfLibError> {
-    let name = parse_text_string(as_name_or_str(key)?)?;
+    let name = parse_text_string(as_name_or_str(key)?);
 
     let mut top  : f32 = 10.0;
     let mut left : f32 = 10.0;
@@ -211,64 +376,72 @@ fn named_dest_of_object(doc : &Document,
     })
 }
 
-/// Iterate over a name tree as described
-/// in the PDF documentation
-fn name_tree_iter<'a>(doc : &'a Document, tree: &'a Dictionary)
-    -> Box<dyn Iterator<Item = &'a [Object]> + 'a> {
-    // If we have kids, then there are no names and we recursively iterate
-    if let Ok(kids) = tree.get(b"Kids").and_then(Object::as_array) {
-        Box::new(kids.iter().flat_map(|kid| {
-            if let Ok(kid) = doc.dereference(kid)
-                                .map(|(_,obj)| obj)
-                                .and_then(Object::as_dict) {
-                name_tree_iter(doc, kid)
-            } else {
-                Box::new(std::iter::empty())
+/// Name and number trees deeper than this are malformed.
+const MAX_TREE_DEPTH : usize = 64;
+
+/// At most this many entries are read from a tree, so that
+/// adversarial documents cannot make the conversion explode.
+const MAX_TREE_ENTRIES : usize = 100_000;
+
+/// Collects the `[key value]` pairs of a name or number tree,
+/// stored under `key` (`Names` or `Nums`) in the leaves.
+/// Kids already visited (cyclic trees), trees deeper than
+/// `MAX_TREE_DEPTH` and entries past `MAX_TREE_ENTRIES` are skipped.
+fn tree_entries<'a>(doc : &'a Document, tree : &'a Dictionary, key : &[u8]) -> Vec<&'a [Object]> {
+    let mut entries = vec![];
+    let mut visited = HashSet::new();
+    let mut stack = vec![(tree, 0)];
+    while let Some((node, depth)) = stack.pop() {
+        // If we have kids, then there are no names and we iterate on them
+        if let Ok(kids) = node.get(b"Kids").and_then(Object::as_array) {
+            if depth >= MAX_TREE_DEPTH {
+                log::warn!("Skipping the part of a tree nested deeper than {MAX_TREE_DEPTH} levels");
+                continue;
             }
-        }))
-    // otherwise, we may be a leaf with names, and we produce the correct output
-    } else if let Ok(names) = tree.get(b"Names").and_then(Object::as_array) {
-        Box::new(
-            names.chunks_exact(2)
-        )
-    // this may not be an error according to the spec ...
-    } else {
-        Box::new(std::iter::empty())
+            // in reverse, so that the entries come out in order
+            for kid in kids.iter().rev() {
+                if let Ok(id) = kid.as_reference() {
+                    if !visited.insert(id) {
+                        continue;
+                    }
+                }
+                if let Ok(kid) = doc.dereference(kid)
+                                    .map(|(_,obj)| obj)
+                                    .and_then(Object::as_dict) {
+                    stack.push((kid, depth + 1));
+                }
+            }
+        // otherwise, we may be a leaf with names, and we produce the correct output
+        } else if let Ok(names) = node.get_deref(key, doc).and_then(Object::as_array) {
+            entries.extend(names.chunks_exact(2).take(MAX_TREE_ENTRIES - entries.len()));
+            if entries.len() == MAX_TREE_ENTRIES {
+                log::warn!("Only the first {MAX_TREE_ENTRIES} entries of a tree are read");
+                break;
+            }
+        }
+        // this may not be an error according to the spec ...
     }
+    entries
+}
+
+/// Iterate over a name tree as described
+/// in the PDF documentation
+fn name_tree_iter<'a>(doc : &'a Document, tree: &'a Dictionary) -> Vec<&'a [Object]> {
+    tree_entries(doc, tree, b"Names")
 }
 
 /// Iterate over a number tree as described
 /// in the PDF documentation section 7.9.7
 #[allow(dead_code)]
-fn number_tree_iter<'a>(doc : &'a Document, tree: &'a Dictionary)
-    -> Box<dyn Iterator<Item = &'a [Object]> + 'a> {
-    // If we have kids, then there are no names and we recursively iterate
-    if let Ok(kids) = tree.get(b"Kids").and_then(Object::as_array) {
-        Box::new(kids.iter().flat_map(|kid| {
-            if let Ok(kid) = doc.dereference(kid)
-                                .map(|(_,obj)| obj)
-                                .and_then(Object::as_dict) {
-                name_tree_iter(doc, kid)
-            } else {
-                Box::new(std::iter::empty())
-            }
-        }))
-    // otherwise, we may be a leaf with names, and we produce the correct output
-    } else if let Ok(names) = tree.get(b"Nums").and_then(Object::as_array) {
-        Box::new(
-            names.chunks_exact(2)
-        )
-    // this may not be an error according to the spec ...
-    } else {
-        Box::new(std::iter::empty())
-    }
+fn number_tree_iter<'a>(doc : &'a Document, tree: &'a Dictionary) -> Vec<&'a [Object]> {
+    tree_entries(doc, tree, b"Nums")
 }
 
 /// Fetch the named destinations of a given PDF document.
 ///
 /// FIXME: for pdf 1.1 documents this was directly found as a
 /// reference to a dict located at ``/Root/Dests``. 
-fn collect_named_destinations(pdf : &Document, pnum: &HashMap<ObjectId,u32>)
+pub fn collect_named_destinations(pdf : &Document, pnum: &HashMap<ObjectId,u32>)
     -> Result<Vec<NamedDestination>, PdfLibError> {
     let catalog = pdf.catalog()?;
     // pdf 1.1 named destinations in a simple dict
@@ -280,28 +453,53 @@ fn collect_named_destinations(pdf : &Document, pnum: &HashMap<ObjectId,u32>)
                            .and_then(Object::as_dict);
 
     // prefer the newer versions
-    if let Ok(dests) = new_dests {
-        name_tree_iter(pdf, dests).map(|key_val|
+    let results : Vec<Result<NamedDestination, PdfLibError>> = if let Ok(dests) = new_dests {
+        name_tree_iter(pdf, dests).into_iter().map(|key_val|
             named_dest_of_object(pdf, pnum, &key_val[0], &key_val[1])
         ).collect()
     // fallback for old documents
     } else if let Ok(dests) = old_dests {
-        dests.into_iter().map(|(k,v)| {
+        dests.into_iter().take(MAX_TREE_ENTRIES).map(|(k,v)| {
             named_dest_of_object(pdf, pnum, &Object::Name(k.as_slice().to_vec()), v)
         }).collect()
     // It is not a problem if such a dict does not exist!
     // we should not fail.
     } else {
-        Ok(vec![])
+        vec![]
+    };
+
+    // a broken destination should not prevent using the others
+    let mut dests = vec![];
+    let mut broken = 0;
+    for r in results {
+        match r {
+            Ok(d) => { dests.push(d); }
+            Err(e) => {
+                log::debug!("Skipping a named destination: {e}");
+                broken += 1;
+            }
+        }
     }
+    if broken > 0 {
+        log::warn!("Skipped {broken} malformed named destinations");
+    }
+    Ok(dests)
 }
 
 
 
+/// Numbers of the pages of a document, starting from 1.
+pub fn page_numbers(pdf : &Document) -> HashMap<ObjectId, u32> {
+    pdf.page_iter()
+       .enumerate()
+       .map(|(i, page_id)| (page_id, (i+1) as u32))
+       .collect()
+}
+
 /// Iterate over the annotations that appear in a document
 /// we assume that annotations are always given as indirect objects
 /// (which I think is standard in pdf documents)
-fn page_annotations_iter<'a>(pdf: &'a Document) -> impl Iterator<Item = ObjectId> + 'a {
+pub fn page_annotations_iter<'a>(pdf: &'a Document) -> impl Iterator<Item = ObjectId> + 'a {
     // iterate over the pages to get the arrays of annotations
     pdf.page_iter().flat_map(move |page_id| {
         let page_obj = pdf.get_dictionary(page_id)?;
@@ -313,6 +511,28 @@ fn page_annotations_iter<'a>(pdf: &'a Document) -> impl Iterator<Item = ObjectId
     })
 }
 
+/// Media box `[x_ll y_ll x_ur y_ur]` of a page (possibly inherited).
+fn page_media_box(pdf : &Document, page_id : ObjectId) -> [f32; 4] {
+    let mut node = pdf.get_dictionary(page_id).ok();
+    while let Some(dict) = node {
+        if let Ok(mb) = dict.get_deref(b"MediaBox", pdf).and_then(Object::as_array) {
+            let v : Vec<f32> = mb.iter().filter_map(|o| o.as_float().ok()).collect();
+            if let Ok(mb) = <[f32; 4]>::try_from(v) {
+                return mb;
+            }
+        }
+        node = dict.get_deref(b"Parent", pdf).and_then(Object::as_dict).ok();
+    }
+    // US letter
+    [0.0, 0.0, 612.0, 792.0]
+}
+
+/// Width of a page.
+fn page_width(pdf : &Document, page_id : ObjectId) -> f32 {
+    let mb = page_media_box(pdf, page_id);
+    mb[2] - mb[0]
+}
+
 /// Appends annotation objets to a given page.
 /// The objects should probably be indirect references
 /// to previously added objets.
@@ -330,7 +550,8 @@ fn append_annots_to_page(pdf : &mut Document,
         Object::Array(_) => {
             let arr = page.get_mut(b"Annots")
                 .and_then(Object::as_array_mut)?;
-            Ok(arr.append(elts))
+            arr.append(elts);
+            Ok(())
         }
         // Second case: the array is indirect
         Object::Reference(_) => {
@@ -338,7 +559,8 @@ fn append_annots_to_page(pdf : &mut Document,
                                 .and_then(Object::as_reference)
                                 .and_then(|k| pdf.get_object_mut(k))
                                 .and_then(Object::as_array_mut)?;
-            Ok(arr.append(elts))
+            arr.append(elts);
+            Ok(())
         }
         // otherwise, we do not have a correct annotation array
         _ => {
@@ -347,6 +569,90 @@ fn append_annots_to_page(pdf : &mut Document,
     }
 }
 
+/// Copies an object of another document into a document,
+/// together with the objects it refers to. The map associates
+/// the objects of `from` that were already copied (and the pages,
+/// which are never copied) to their id in `to`.
+fn copy_object(from : &Document,
+               to : &mut Document,
+               obj : &Object,
+               ids : &mut HashMap<ObjectId, ObjectId>) -> Object {
+    match obj {
+        Object::Reference(r) => {
+            if let Some(&id) = ids.get(r) {
+                return Object::Reference(id);
+            }
+            // reserve the id first, annotations refer to each other (/Popup, /Parent)
+            let id = to.new_object_id();
+            ids.insert(*r, id);
+            let copy = from.get_object(*r)
+                           .map(|o| copy_object(from, to, o, ids))
+                           .unwrap_or(Object::Null);
+            to.objects.insert(id, copy);
+            Object::Reference(id)
+        }
+        Object::Array(arr) => {
+            Object::Array(arr.iter().map(|o| copy_object(from, to, o, ids)).collect())
+        }
+        Object::Dictionary(dict) => {
+            Object::Dictionary(copy_dictionary(from, to, dict, ids))
+        }
+        Object::Stream(stream) => {
+            let mut stream = stream.clone();
+            stream.dict = copy_dictionary(from, to, &stream.dict, ids);
+            Object::Stream(stream)
+        }
+        obj => obj.clone(),
+    }
+}
+
+fn copy_dictionary(from : &Document,
+                   to : &mut Document,
+                   dict : &Dictionary,
+                   ids : &mut HashMap<ObjectId, ObjectId>) -> Dictionary {
+    let mut copy = Dictionary::new();
+    for (key, value) in dict.iter() {
+        copy.set(key.clone(), copy_object(from, to, value, ids));
+    }
+    copy
+}
+
+/// Subtype and rectangle of an annotation, rounded so that
+/// annotations survive a round trip through the disk.
+fn annotation_key(pdf : &Document, dict : &Dictionary) -> Option<(Vec<u8>, [i64; 4])> {
+    let subtype = dict.get(b"Subtype").and_then(Object::as_name).ok()?;
+    let rect : Vec<i64> = dict.get_deref(b"Rect", pdf)
+        .and_then(Object::as_array).ok()?
+        .iter()
+        .filter_map(|o| o.as_float().ok())
+        .map(|v| (v * 100.0).round() as i64)
+        .collect();
+    Some((subtype.to_vec(), rect.try_into().ok()?))
+}
+
+/// Is the annotation created by the conversion of a document:
+/// a link rewritten to an akl uri, or the marker drawn under a
+/// destination link (see `rectangle_link`)?
+fn is_generated_annotation(dict : &Dictionary) -> bool {
+    let subtype = dict.get(b"Subtype").and_then(Object::as_name).unwrap_or(b"");
+    match subtype {
+        b"Link" => {
+            dict.get(b"A")
+                .and_then(Object::as_dict)
+                .and_then(|a| a.get(b"URI"))
+                .and_then(Object::as_str)
+                .is_ok_and(|uri| uri.starts_with(b"akl://"))
+        }
+        b"Square" => {
+            !dict.has(b"T") && !dict.has(b"Contents") && !dict.has(b"Popup") &&
+            dict.get(b"Border")
+                .and_then(Object::as_array)
+                .is_ok_and(|b| b.iter().all(|v| v.as_float().is_ok_and(|v| v == 0.0)))
+        }
+        _ => false,
+    }
+}
+
 
 /// Update the URL of one link according to the update function.
 fn update_link<F>(dct : &mut Dictionary, lik : &F) -> Result<(), PdfLibError>
@@ -355,7 +661,7 @@ fn update_link<F>(dct : &mut Dictionary, lik : &F) -> Result<(), PdfLibError>
 {
     let action : &mut Dictionary = dct.get_mut(b"A").and_then(Object::as_dict_mut)?;
     if let Ok(raw_uri) = action.get(b"URI").and_then(Object::as_str) {
-        let old_uri = parse_text_string(raw_uri)?;
+        let old_uri = parse_text_string(raw_uri);
         action.set("URI",
                    lopdf::Object::String(
                         lik(old_uri).into(),
@@ -366,7 +672,107 @@ fn update_link<F>(dct : &mut Dictionary, lik : &F) -> Result<(), PdfLibError>
     Ok(())
 }
 
+/// Kinds of hyperref destinations (`section.2.1`, `theorem.3`…)
+/// that deserve an outline entry, with their label.
+const OUTLINE_KINDS : &[(&str, &str)] = &[
+    ("chapter", "Chapter"),
+    ("section", "Section"),
+    ("subsection", "Section"),
+    ("subsubsection", "Section"),
+    ("appendix", "Appendix"),
+    ("theorem", "Theorem"),
+    ("lemma", "Lemma"),
+    ("proposition", "Proposition"),
+    ("corollary", "Corollary"),
+    ("definition", "Definition"),
+];
+
+/// Theorem-like headings searched in the text of the pages.
+const OUTLINE_HEADINGS : &[&str] = &[
+    "Theorem", "Lemma", "Proposition", "Corollary", "Definition",
+];
+
+/// An entry of a generated outline.
+#[derive(Debug,Clone)]
+struct OutlineEntry {
+    title    : String,
+    page     : ObjectId,
+    page_num : u32,
+    /// position on the page, when known.
+    position : Option<(f32, f32)>,
+}
+
+/// Title of the outline entry of a named destination, if it deserves one.
+///
+/// Hyperref names its destinations `kind.number`: only the
+/// structural kinds are kept. Other names are kept as is, except
+/// for page anchors (names without letters).
+fn outline_title(name : &str) -> Option<String> {
+    match name.split_once('.') {
+        Some((kind, num)) => {
+            OUTLINE_KINDS.iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(kind))
+                .map(|(_, label)| format!("{label} {num}"))
+        }
+        None if name == "Doc-Start" => None,
+        None if !name.chars().any(char::is_alphabetic) => None,
+        None => Some(name.into()),
+    }
+}
+
+/// Finds a theorem-like heading (`Theorem 3.2`) at the start of a line.
+fn heading_of_line(line : &str) -> Option<String> {
+    let mut words = line.split_whitespace();
+    let word = words.next()?;
+    let num = words.next()?.trim_end_matches(['.', ':']);
+    if OUTLINE_HEADINGS.contains(&word) &&
+       num.starts_with(|c : char| c.is_ascii_digit()) &&
+       num.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        Some(format!("{word} {num}"))
+    } else {
+        None
+    }
+}
+
+/// Encodes a string as a pdf text string.
+fn text_string(s : &str) -> Object {
+    if s.is_ascii() {
+        Object::string_literal(s)
+    } else {
+        let mut bytes = vec![0xfe, 0xff];
+        s.encode_utf16().for_each(|c| bytes.extend_from_slice(&c.to_be_bytes()));
+        Object::String(bytes, lopdf::StringFormat::Hexadecimal)
+    }
+}
+
+/// An external link of a page.
+#[derive(Debug,Clone)]
+pub struct PageLink {
+    /// page number, starting at 1.
+    pub page_num : u32,
+    /// clickable area `[x_ll y_ll x_ur y_ur]`.
+    pub rect     : [f32; 4],
+    /// target of the link.
+    pub uri      : String,
+}
+
+/// Anchors and links of a document, to display them
+/// outside of a pdf viewer.
 #[derive(Debug,Clone)]
+pub struct LinkMap {
+    /// media boxes of the pages, in order.
+    pub pages : Vec<[f32; 4]>,
+    /// named destinations: page number, name, position.
+    pub anchors : Vec<(u32, String, f32, f32)>,
+    /// external links.
+    pub links : Vec<PageLink>,
+}
+
+/// Minimal number of words of a description
+/// to be taken as an abstract.
+const MIN_ABSTRACT_WORDS : usize = 20;
+
+#[derive(Debug,Clone,Default)]
 pub struct PdfMetaData {
     /// Potential title of the pdf file.
     pub title       : Option<String>,
@@ -378,11 +784,15 @@ pub struct PdfMetaData {
     pub year        : Option<u32>,
     /// Identifiers found inside the pdf (arxiv, doi, ISBN, etc.)
     pub identifiers : Vec<String>,
+    /// Abstract of the pdf file.
+    pub r#abstract  : Option<String>,
+    /// Keywords of the pdf file.
+    pub keywords    : Vec<String>,
 }
 
 
 
-//// MUTABILITY ////
+// MUTABILITY //
 
 
 #[derive(Debug,Clone)]
@@ -397,18 +807,15 @@ pub struct PdfDocument {
     named_dests : Vec<NamedDestination>,
     /// All the annotations that can be found in the document.
     annotations : Vec<ObjectId>,
+    /// Checksum of the document as loaded, see `get_checksum`.
+    checksum    : Option<String>,
 }
 
 impl TryFrom<Document> for PdfDocument {
     type Error = PdfLibError;
     fn try_from(value: Document) -> Result<Self, Self::Error> {
         // Collect the pages and their respective numbers 
-        let mut page_nums = HashMap::new();
-        value.page_iter()
-             .enumerate()
-             .for_each(|(i, page_id)| {
-                 page_nums.insert(page_id, (i+1) as u32);
-             });
+        let page_nums = page_numbers(&value);
         // Collect the named destinations in some suitable vector
         let named_dests = collect_named_destinations(&value, &page_nums)?;
 
@@ -419,6 +826,7 @@ impl TryFrom<Document> for PdfDocument {
             pdf: value,
             named_dests,
             annotations,
+            checksum: None,
             //page_nums,
         })
     }
@@ -428,12 +836,21 @@ impl TryFrom<Document> for PdfDocument {
 
 impl PdfDocument {
 
-    /// Provides a checksum of the pdf contents
+    /// Provides a checksum of the pdf contents.
+    ///
+    /// Saving updates the document (trailer, cross references), so
+    /// the checksum is computed once, by the first call, and kept:
+    /// otherwise a second call would give another checksum than the
+    /// one of the freshly loaded document.
     pub fn get_checksum(&mut self) -> Result<String, PdfLibError> {
+        if let Some(checksum) = &self.checksum {
+            return Ok(checksum.clone());
+        }
         let mut hasher = Sha256::new();
         self.pdf.save_to(&mut hasher)?;
-        let checksum = hasher.finalize();
-        Ok(format!("{:x}", checksum))
+        let checksum = format!("{:x}", hasher.finalize());
+        self.checksum = Some(checksum.clone());
+        Ok(checksum)
     }
 
 
@@ -455,14 +872,12 @@ impl PdfDocument {
                                .and_then(Object::as_dict)?;
         let title = infos.get(b"Title")
                          .and_then(Object::as_str)
-                         .map_err(|e| PdfLibError::PDFError(e))
-                         .and_then(parse_text_string).ok();
+                         .map(parse_text_string).ok();
         // In the pdf meta-data ... only one author a priori :(
         let authors : Vec<String>
             = infos.get(b"Author")
                    .and_then(Object::as_str)
-                   .map_err(|e| PdfLibError::PDFError(e))
-                   .and_then(parse_text_string)
+                   .map(parse_text_string)
                    .map(|s| s.split(',')
                               .map(|e| e.trim())
                               .map(String::from)
@@ -477,15 +892,63 @@ impl PdfDocument {
         let context = vec![];
         let identifiers = vec![];
 
+        // the XMP metadata, then the Info dictionary, whose subject
+        // is only taken as an abstract when it is long enough
+        let xmp = self.xmp_metadata().map(|x| xmp::parse(&x)).unwrap_or_default();
+        let subject = infos.get(b"Subject")
+                           .and_then(Object::as_str)
+                           .map(parse_text_string)
+                           .ok()
+                           .filter(|s| s.split_whitespace().count() >= MIN_ABSTRACT_WORDS);
+        let r#abstract = xmp.description
+                            .filter(|d| d.split_whitespace().count() >= MIN_ABSTRACT_WORDS)
+                            .or(subject);
+        let mut keywords : Vec<String> = vec![];
+        let info_keywords = infos.get(b"Keywords")
+                                 .and_then(Object::as_str)
+                                 .map(|k| xmp::split_keywords(&parse_text_string(k)))
+                                 .unwrap_or_default();
+        for k in xmp.keywords.into_iter().chain(info_keywords) {
+            if !keywords.contains(&k) {
+                keywords.push(k);
+            }
+        }
+
         Ok(PdfMetaData {
             title,
             authors,
             context,
             year,
             identifiers,
+            r#abstract,
+            keywords,
         })
     }
 
+    /// The XMP packet of the document, if any.
+    fn xmp_metadata(&self) -> Option<String> {
+        let stream = self.pdf.catalog().ok()?
+            .get_deref(b"Metadata", &self.pdf).ok()?
+            .as_stream().ok()?;
+        let content = stream.decompressed_content().unwrap_or_else(|_| stream.content.clone());
+        Some(String::from_utf8_lossy(&content).into())
+    }
+
+
+    /// Number of named destinations of the document.
+    pub fn destination_count(&self) -> usize {
+        self.named_dests.len()
+    }
+
+    /// Named destinations of the document.
+    pub fn destinations(&self) -> &[NamedDestination] {
+        &self.named_dests
+    }
+
+    /// Number of annotations of the document.
+    pub fn annotation_count(&self) -> usize {
+        self.annotations.len()
+    }
 
     /// Save the pdf to a given file.
     pub fn save_to(&mut self, path : &Path) 
@@ -507,6 +970,8 @@ impl PdfDocument {
         };
         // what should be added to the pages
         let mut page_annots : HashMap<ObjectId, Vec<ObjectId>> = HashMap::new();
+        // text of the pages, to place the markers next to it
+        let mut page_boxes : HashMap<ObjectId, (Vec<TextBox>, f32)> = HashMap::new();
 
         // creates all the objects in the pdf document
         self.named_dests.iter().for_each(|destination| {
@@ -515,6 +980,11 @@ impl PdfDocument {
             rect.y_ll = destination.top - 10.0;
             rect.y_ur = destination.top - 5.0;
 
+            let (boxes, width) = page_boxes.entry(destination.page).or_insert_with(|| {
+                (page_text_boxes(&self.pdf, destination.page), page_width(&self.pdf, destination.page))
+            });
+            nudge_marker(&mut rect, boxes, *width);
+
             let mut ids = rectangle_link(&rect, lik(destination.clone()))
                           .iter()
                           .map(|obj| self.pdf.add_object(obj.clone()))
@@ -526,12 +996,207 @@ impl PdfDocument {
         });
 
         // batch addition of the objects to the respective pages
-        page_annots.iter_mut().map(|(k,v)| {
+        page_annots.iter_mut().try_for_each(|(k,v)| {
             let mut objs : Vec<Object> = v.iter()
                 .map(|&x| Object::Reference(x)).collect();
             self.annotations.append(v);
             append_annots_to_page(&mut self.pdf, *k, &mut objs)
-        }).collect()
+        })
+    }
+
+    /// Collects the named destinations and the external
+    /// links of the document.
+    pub fn link_map(&self) -> LinkMap {
+        let pages : Vec<(u32, ObjectId)> = self.pdf.get_pages().into_iter().collect();
+        let mut links = vec![];
+        for (page_num, page_id) in &pages {
+            let annots = self.pdf.get_dictionary(*page_id)
+                .and_then(|p| p.get_deref(b"Annots", &self.pdf))
+                .and_then(Object::as_array);
+            for annot in annots.into_iter().flatten() {
+                let Ok(dict) = self.pdf.dereference(annot).and_then(|(_, o)| o.as_dict()) else { continue };
+                let uri = dict.get_deref(b"A", &self.pdf)
+                    .and_then(Object::as_dict)
+                    .and_then(|a| a.get(b"URI"))
+                    .and_then(Object::as_str)
+                    .map(parse_text_string);
+                let rect : Vec<f32> = dict.get(b"Rect")
+                    .and_then(Object::as_array)
+                    .map(|r| r.iter().filter_map(|o| o.as_float().ok()).collect())
+                    .unwrap_or_default();
+                if let (Ok(uri), Ok(rect)) = (uri, <[f32; 4]>::try_from(rect)) {
+                    links.push(PageLink { page_num: *page_num, rect, uri });
+                }
+            }
+        }
+        LinkMap {
+            pages: pages.iter().map(|(_, id)| page_media_box(&self.pdf, *id)).collect(),
+            anchors: self.named_dests.iter()
+                .map(|d| (d.page_num, d.name.clone(), d.left, d.top))
+                .collect(),
+            links,
+        }
+    }
+
+    /// Does the document already have bookmarks?
+    pub fn has_outline(&self) -> bool {
+        self.pdf.catalog()
+            .and_then(|c| c.get_deref(b"Outlines", &self.pdf))
+            .and_then(Object::as_dict)
+            .is_ok_and(|o| o.has(b"First"))
+    }
+
+    /// Entries of the outline generated from the named
+    /// destinations and the theorem headings, in reading order.
+    fn outline_entries(&self) -> Vec<OutlineEntry> {
+        let mut entries : Vec<OutlineEntry> = self.named_dests.iter()
+            .filter_map(|d| Some(OutlineEntry {
+                title: outline_title(&d.name)?,
+                page: d.page,
+                page_num: d.page_num,
+                position: Some((d.left, d.top)),
+            }))
+            .collect();
+
+        for (&page_num, &page) in &self.pdf.get_pages() {
+            // text extraction fails on some fonts, the
+            // destinations are good enough for these pages
+            let Ok(text) = self.pdf.extract_text(&[page_num]) else { continue };
+            for title in text.lines().filter_map(heading_of_line) {
+                if !entries.iter().any(|e| e.title == title) {
+                    entries.push(OutlineEntry { title, page, page_num, position: None });
+                }
+            }
+        }
+
+        // top of the page first: pdf coordinates go upwards
+        entries.sort_by(|a, b| {
+            let ta = a.position.map_or(f32::INFINITY, |p| p.1);
+            let tb = b.position.map_or(f32::INFINITY, |p| p.1);
+            a.page_num.cmp(&b.page_num).then(tb.total_cmp(&ta))
+        });
+        entries
+    }
+
+    /// Adds an outline (bookmarks) built from the named destinations
+    /// and the theorem headings to documents that have none.
+    ///
+    /// Returns whether an outline was added.
+    pub fn add_outline(&mut self) -> Result<bool, PdfLibError> {
+        if self.has_outline() {
+            return Ok(false);
+        }
+        let entries = self.outline_entries();
+        if entries.is_empty() {
+            return Ok(false);
+        }
+
+        let root = self.pdf.new_object_id();
+        let ids : Vec<ObjectId> = entries.iter()
+            .map(|_| self.pdf.new_object_id())
+            .collect();
+        for (i, entry) in entries.iter().enumerate() {
+            let dest : Vec<Object> = match entry.position {
+                Some((left, top)) => vec![entry.page.into(), "XYZ".into(),
+                                          left.into(), top.into(), Object::Null],
+                None => vec![entry.page.into(), "Fit".into()],
+            };
+            let mut item = dictionary! {
+                "Title"  => text_string(&entry.title),
+                "Parent" => root,
+                "Dest"   => dest,
+            };
+            if i > 0 {
+                item.set("Prev", ids[i - 1]);
+            }
+            if i + 1 < ids.len() {
+                item.set("Next", ids[i + 1]);
+            }
+            self.pdf.objects.insert(ids[i], Object::Dictionary(item));
+        }
+        self.pdf.objects.insert(root, Object::Dictionary(dictionary! {
+            "Type"  => "Outlines",
+            "First" => ids[0],
+            "Last"  => ids[ids.len() - 1],
+            "Count" => ids.len() as i64,
+        }));
+        self.pdf.catalog_mut()?.set("Outlines", root);
+        Ok(true)
+    }
+
+    /// Copies the annotations of another version of the document
+    /// (typically a converted copy annotated by the user) to the
+    /// same pages of this one. The annotations already present in
+    /// this document and the ones generated by the conversion are
+    /// left out. Returns the number of copied annotations.
+    pub fn import_annotations(&mut self, other : &PdfDocument) -> Result<usize, PdfLibError> {
+        self.copy_annotations(other, Some, |_| true)
+    }
+
+    /// Copies the annotations of a converted copy of another version
+    /// of the paper (an earlier arXiv version) to this one. The pages
+    /// do not match exactly anymore: the annotations of a page go to
+    /// the page at the same place in this document (page 10 of 20
+    /// goes to page 11 of 22), and the links, which belong to the
+    /// paper rather than to the user, are left out.
+    pub fn import_annotations_of_version(&mut self, other : &PdfDocument) -> Result<usize, PdfLibError> {
+        let ours = self.pdf.get_pages().len() as u32;
+        let theirs = other.pdf.get_pages().len() as u32;
+        self.copy_annotations(other,
+            |num| (ours > 0 && theirs > 0).then(|| (num * ours).div_ceil(theirs).clamp(1, ours)),
+            |dict| dict.get(b"Subtype").and_then(Object::as_name).is_ok_and(|s| s != b"Link"))
+    }
+
+    /// Copies the annotations of another document kept by `keep`,
+    /// from its page `num` to our page `page_of(num)`.
+    fn copy_annotations<P, K>(&mut self, other : &PdfDocument, page_of : P, keep : K) -> Result<usize, PdfLibError>
+        where
+            P : Fn(u32) -> Option<u32>,
+            K : Fn(&Dictionary) -> bool
+    {
+        let pages = self.pdf.get_pages();
+        let other_pages = other.pdf.get_pages();
+        // pages are shared, not copied
+        let mut ids : HashMap<ObjectId, ObjectId> = other_pages.iter()
+            .filter_map(|(num, id)| Some((*id, *pages.get(&page_of(*num)?)?)))
+            .collect();
+        let mut count = 0;
+
+        for (num, other_page) in &other_pages {
+            let Some(&page) = page_of(*num).and_then(|n| pages.get(&n)) else { continue };
+            let existing : Vec<(Vec<u8>, [i64; 4])> = self.pdf.get_dictionary(page)
+                .and_then(|p| p.get_deref(b"Annots", &self.pdf))
+                .and_then(Object::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|a| self.pdf.dereference(a).and_then(|(_, o)| o.as_dict()).ok())
+                .filter_map(|d| annotation_key(&self.pdf, d))
+                .collect();
+            let annots = other.pdf.get_dictionary(*other_page)
+                .and_then(|p| p.get_deref(b"Annots", &other.pdf))
+                .and_then(Object::as_array);
+
+            let mut objs = vec![];
+            for annot in annots.into_iter().flatten() {
+                let Ok(dict) = other.pdf.dereference(annot).and_then(|(_, o)| o.as_dict()) else { continue };
+                if is_generated_annotation(dict) || !keep(dict) ||
+                   annotation_key(&other.pdf, dict).is_some_and(|k| existing.contains(&k)) {
+                    continue;
+                }
+                let copy = copy_object(&other.pdf, &mut self.pdf, annot, &mut ids);
+                let id = match copy {
+                    Object::Reference(id) => id,
+                    obj => self.pdf.add_object(obj),
+                };
+                self.annotations.push(id);
+                objs.push(Object::Reference(id));
+            }
+            count += objs.len();
+            if !objs.is_empty() {
+                append_annots_to_page(&mut self.pdf, page, &mut objs)?;
+            }
+        }
+        Ok(count)
     }
 
     /// Updates all external URL links inside the pdf document.
@@ -0,0 +1,49 @@
+// Interactive pickers (rofi, fzf, dmenu…).
+//
+// The choices are written on the standard input of the picker,
+// one per line, and the chosen line is read from its standard
+// output. Closing the picker without choosing anything (or a
+// non-zero exit status) cancels the choice.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use anyhow::{Result, Context};
+
+/// The picker used when none is configured.
+pub fn default_command(graphical : bool) -> &'static str {
+    if graphical { "rofi -dmenu -i" } else { "fzf" }
+}
+
+/// Lets the user choose one of the lines using a picker command
+/// (a program and its arguments, separated by spaces). Returns
+/// the index of the chosen line, `None` if the choice is cancelled.
+pub fn pick(command : &str, choices : &[String]) -> Result<Option<usize>> {
+    let mut words = command.split_whitespace();
+    let program = words.next().context("The picker command is empty")?;
+    let mut child = Command::new(program)
+        .args(words)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Running the picker {command}"))?;
+
+    {
+        let mut stdin = child.stdin.take().context("Opening the input of the picker")?;
+        for choice in choices {
+            // the picker may exit before reading everything
+            if writeln!(stdin, "{}", choice.replace('\n', " ")).is_err() {
+                break;
+            }
+        }
+    }
+    let output = child.wait_with_output()
+        .with_context(|| format!("Running the picker {command}"))?;
+    if !output.status.success() {
+        log::info!("The picker exited with {}", output.status);
+        return Ok(None);
+    }
+    let chosen = String::from_utf8_lossy(&output.stdout);
+    let chosen = chosen.trim_end_matches(['\n', '\r']);
+    Ok(choices.iter().position(|c| c.replace('\n', " ") == chosen))
+}
@@ -0,0 +1,113 @@
+// Providers given by external programs.
+//
+// Some sources are too specific for the crate: publishers behind a
+// paywall reached with the cookies of an institution, intranets,
+// archives needing a browser session. A program `akl-provider-<name>`
+// on the PATH provides the uris whose scheme is `<name>` (`acm:…` for
+// `akl-provider-acm`), or whose host is `<name>` or one of its
+// subdomains (`https://dl.acm.org/…` for `akl-provider-acm.org`).
+//
+// The program reads the uri on its standard input and writes on its
+// standard output a JSON object describing the document:
+//
+//     {
+//       "pdf_url": "https://…",         or "pdf_path": "/tmp/….pdf",
+//       "identifiers": ["doi:…"],
+//       "title": "…", "authors": ["…"], "year": 2023,
+//       "venue": "…", "pages": "…", "abstract": "…", "keywords": ["…"]
+//     }
+//
+// where every field is optional but the pdf file. A program failing
+// (with a non-zero exit status) refuses the uri, with its standard
+// error as the reason.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use anyhow::{Result, Context};
+use serde::Deserialize;
+use url::Url;
+
+use crate::crossref::Work;
+
+/// Prefix of the names of the programs.
+const PREFIX : &str = "akl-provider-";
+
+/// What a program says about a uri.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Answer {
+    pub pdf_url     : Option<String>,
+    pub pdf_path    : Option<PathBuf>,
+    pub identifiers : Vec<String>,
+    pub title       : Option<String>,
+    pub authors     : Vec<String>,
+    pub year        : Option<u32>,
+    pub venue       : Option<String>,
+    pub pages       : Option<String>,
+    pub r#abstract  : Option<String>,
+    pub keywords    : Vec<String>,
+}
+
+impl Answer {
+    /// The metadata of the answer, if it has any.
+    pub fn work(&self) -> Option<Work> {
+        let work = Work {
+            title: self.title.clone(),
+            authors: self.authors.clone(),
+            orcids: vec![],
+            year: self.year,
+            venue: self.venue.clone(),
+            pages: self.pages.clone(),
+            r#abstract: self.r#abstract.clone(),
+            keywords: self.keywords.clone(),
+            identifiers: self.identifiers.clone(),
+        };
+        (work.title.is_some() || !work.authors.is_empty() || work.year.is_some()).then_some(work)
+    }
+}
+
+/// The program named `akl-provider-<name>` on the PATH.
+fn program(name : &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(format!("{PREFIX}{name}")))
+        .find(|p| p.is_file())
+}
+
+/// The program providing a uri, if any: the one of its scheme,
+/// or else of its host or of the domains above it.
+pub fn find(url : &Url) -> Option<PathBuf> {
+    match url.scheme() {
+        "http" | "https" => {
+            let host = url.host_str()?;
+            let labels : Vec<&str> = host.split('.').collect();
+            // `dl.acm.org`, then `acm.org`, but not `org`
+            (0..labels.len().saturating_sub(1))
+                .find_map(|i| program(&labels[i..].join(".")))
+        }
+        scheme => program(scheme),
+    }
+}
+
+/// Asks a program about a uri.
+pub fn ask(program : &PathBuf, uri : &str) -> Result<Answer> {
+    log::debug!("Asking {program:?} about {uri}");
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Running {program:?}"))?;
+    child.stdin.take().context("Writing the uri to the provider")?
+        .write_all(format!("{uri}\n").as_bytes())
+        .context("Writing the uri to the provider")?;
+    let output = child.wait_with_output()
+        .with_context(|| format!("Running {program:?}"))?;
+    if !output.status.success() {
+        anyhow::bail!("{program:?} failed on {uri}: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    serde_json::from_slice(&output.stdout)
+        .with_context(|| format!("Parsing the answer of {program:?}"))
+}
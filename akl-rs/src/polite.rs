@@ -0,0 +1,119 @@
+// Polite use of the servers.
+//
+// Batch imports and enrichments send many requests to the same hosts,
+// and the servers ban the clients going too fast (arXiv asks for at
+// most one request every three seconds). Every request to an API and
+// every download waits until the delay of its host has passed since
+// the previous request of the process to that host. The delays have
+// defaults for the known hosts, and can be changed in the configuration
+// (in seconds, for a host and its subdomains):
+//
+//     rate_limits:
+//       arxiv.org: 5
+//       example.org: 0.5
+//
+// The requests to the APIs identify akl by a user agent with the
+// address of the project, and the contact address of the configuration
+// when there is one (Crossref serves such clients better, and arXiv
+// contacts them rather than banning them).
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use reqwest::blocking::RequestBuilder;
+use url::Url;
+
+/// Delay between two requests to a host without a known delay.
+const DEFAULT_DELAY : Duration = Duration::from_millis(500);
+
+/// Delays of the known hosts (and their subdomains).
+const DELAYS : [(&str, Duration); 8] = [
+    ("arxiv.org", crate::arxiv::DELAY),
+    ("api.crossref.org", Duration::from_millis(100)),
+    ("dblp.org", crate::dblp::DELAY),
+    // without API key, Semantic Scholar is shared by every client
+    ("api.semanticscholar.org", Duration::from_secs(3)),
+    // three requests per second without an API key
+    ("eutils.ncbi.nlm.nih.gov", Duration::from_millis(340)),
+    ("api.biorxiv.org", Duration::from_secs(1)),
+    ("api.unpaywall.org", Duration::from_millis(100)),
+    ("doi.org", Duration::from_millis(100)),
+];
+
+/// The state shared by the requests of the process.
+struct State {
+    /// Delays of the configuration, in seconds.
+    overrides  : BTreeMap<String, f64>,
+    user_agent : String,
+    /// When each host was (or will be) last requested.
+    last       : HashMap<String, Instant>,
+}
+
+static STATE : LazyLock<Mutex<State>> = LazyLock::new(|| Mutex::new(State {
+    overrides: BTreeMap::new(),
+    user_agent: user_agent(None),
+    last: HashMap::new(),
+}));
+
+/// The user agent of the requests to the APIs.
+fn user_agent(contact : Option<&str>) -> String {
+    let mailto = contact.map(|c| format!("; mailto:{c}")).unwrap_or_default();
+    format!("akl-rs/{} (https://github.com/AliaumeL/akl{mailto})", env!("CARGO_PKG_VERSION"))
+}
+
+/// Sets the delays and the contact address of the configuration.
+pub fn configure(config : &crate::config::Config) {
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    state.overrides = config.rate_limits.clone();
+    state.user_agent = user_agent(config.contact_email.as_deref());
+}
+
+/// Is `host` the domain `domain` or one of its subdomains?
+fn within(host : &str, domain : &str) -> bool {
+    host == domain || host.strip_suffix(domain).is_some_and(|s| s.ends_with('.'))
+}
+
+/// The delay between two requests to a host. The configured
+/// delays that are not durations (`.inf`) are ignored.
+fn delay(overrides : &BTreeMap<String, f64>, host : &str) -> Duration {
+    overrides.iter()
+        .find(|(domain, _)| within(host, domain))
+        .and_then(|(domain, secs)| match Duration::try_from_secs_f64(secs.max(0.0)) {
+            Ok(d) => Some(d),
+            Err(e) => {
+                log::warn!("Ignoring the delay {secs} of {domain} in the configuration: {e}");
+                None
+            }
+        })
+        .or_else(|| DELAYS.iter().find(|(domain, _)| within(host, domain)).map(|(_, d)| *d))
+        .unwrap_or(DEFAULT_DELAY)
+}
+
+/// Waits until the host of a url can be requested again.
+pub fn wait(url : &str) {
+    let Some(host) = Url::parse(url).ok().and_then(|u| u.host_str().map(String::from)) else {
+        return;
+    };
+    // the request takes the next free slot of the host, so that
+    // the requests of several threads queue up
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let delay = delay(&state.overrides, &host);
+    let now = Instant::now();
+    let slot = state.last.get(&host).map_or(now, |last| (*last + delay).max(now));
+    state.last.insert(host.clone(), slot);
+    drop(state);
+    if slot > now {
+        log::debug!("Waiting {}ms before requesting {host}", (slot - now).as_millis());
+        std::thread::sleep(slot - now);
+    }
+}
+
+/// A GET request to an API, once its host can be requested.
+pub fn get(url : &str) -> RequestBuilder {
+    wait(url);
+    let user_agent = STATE.lock().unwrap_or_else(|e| e.into_inner()).user_agent.clone();
+    reqwest::blocking::Client::new()
+        .get(url)
+        .header(reqwest::header::USER_AGENT, user_agent)
+}
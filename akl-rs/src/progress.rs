@@ -0,0 +1,144 @@
+// Progress of the downloads.
+//
+// A large pdf file from a slow publisher takes a while, and a silent
+// download looks stuck. The downloads lasting more than a second show
+// their progress as the body arrives: a bar on the standard error when
+// it is a terminal, and a desktop notification updated in place, with
+// the percentage as a gauge where the notification server shows the
+// `value` hint. In a batch import, the progress says which document
+// of the batch is downloaded (by the thread of its worker).
+
+use std::cell::Cell;
+use std::io::{IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::desktop::{Desktop, ProgressNotification};
+
+/// Downloads shorter than this show no progress.
+const SHOW_AFTER : Duration = Duration::from_secs(1);
+
+/// Delay between two redraws of the bar.
+const REDRAW : Duration = Duration::from_millis(100);
+
+/// Delay between two updates of the notification.
+const RENOTIFY : Duration = Duration::from_secs(1);
+
+/// Width of the bar, in characters.
+const WIDTH : usize = 30;
+
+/// The desktop showing the notifications.
+static DESKTOP : Mutex<Option<Desktop>> = Mutex::new(None);
+
+thread_local! {
+    /// The position of the document downloaded by the thread
+    /// in the current batch.
+    static BATCH : Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+/// Sets the desktop showing the notifications (none before
+/// it is called, e.g. for the commands run by the handler).
+pub fn configure(desktop : &Desktop) {
+    *DESKTOP.lock().unwrap_or_else(|e| e.into_inner()) = Some(desktop.clone());
+}
+
+/// Sets the position of the next downloads of the thread
+/// in a batch (1-based, with the size of the batch).
+pub fn batch(position : Option<(usize, usize)>) {
+    BATCH.set(position);
+}
+
+/// A size in bytes, for humans.
+fn size(bytes : u64) -> String {
+    match bytes {
+        0..1_000 => format!("{bytes} B"),
+        1_000..1_000_000 => format!("{:.0} kB", bytes as f64 / 1e3),
+        _ => format!("{:.1} MB", bytes as f64 / 1e6),
+    }
+}
+
+/// The progress of a download.
+pub struct Progress {
+    label        : String,
+    started      : Instant,
+    tty          : bool,
+    drawn        : Option<Instant>,
+    desktop      : Option<Desktop>,
+    notification : Option<ProgressNotification>,
+    notified     : Option<Instant>,
+}
+
+impl Progress {
+    /// The progress of the download of a url.
+    pub fn new(url : &str) -> Self {
+        let host = url::Url::parse(url).ok()
+            .and_then(|u| u.host_str().map(String::from))
+            .unwrap_or_else(|| url.to_string());
+        let label = match BATCH.get() {
+            Some((position, total)) => format!("[{position}/{total}] {host}"),
+            None => host,
+        };
+        Progress {
+            label,
+            started: Instant::now(),
+            tty: std::io::stderr().is_terminal(),
+            drawn: None,
+            desktop: DESKTOP.lock().unwrap_or_else(|e| e.into_inner()).clone().filter(|d| !d.headless),
+            notification: None,
+            notified: None,
+        }
+    }
+
+    /// Shows that `received` bytes of `total` (when announced)
+    /// have arrived.
+    pub fn update(&mut self, received : u64, total : Option<u64>) {
+        let now = Instant::now();
+        if now - self.started < SHOW_AFTER {
+            return;
+        }
+        let percent = total.filter(|t| *t > 0).map(|t| (received.min(t) * 100 / t) as u8);
+        let amount = match total {
+            Some(total) => format!("{} / {}", size(received), size(total)),
+            None => size(received),
+        };
+
+        if self.tty && self.drawn.is_none_or(|d| now - d >= REDRAW) {
+            let bar = match percent {
+                Some(percent) => {
+                    let done = WIDTH * percent as usize / 100;
+                    format!(" [{}{}] {percent:>3}%", "#".repeat(done), "-".repeat(WIDTH - done))
+                }
+                None => String::new(),
+            };
+            eprint!("\r\x1b[K{}{bar} {amount}", self.label);
+            let _ = std::io::stderr().flush();
+            self.drawn = Some(now);
+        }
+
+        if let Some(desktop) = &self.desktop {
+            if self.notified.is_none_or(|n| now - n >= RENOTIFY) {
+                let body = match percent {
+                    Some(percent) => format!("{} — {percent}% ({amount})", self.label),
+                    None => format!("{} — {amount}", self.label),
+                };
+                self.notification.get_or_insert_with(|| desktop.progress_notification())
+                    .update("⬇️ Downloading", &body, percent);
+                self.notified = Some(now);
+            }
+        }
+    }
+}
+
+impl Drop for Progress {
+    /// Clears the bar and closes the notification,
+    /// whether the download succeeded or not.
+    fn drop(&mut self) {
+        if self.drawn.is_some() {
+            eprint!("\r\x1b[K");
+            let _ = std::io::stderr().flush();
+        }
+        if let Some(notification) = self.notification.take() {
+            notification.close();
+        }
+    }
+}
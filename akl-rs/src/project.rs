@@ -0,0 +1,38 @@
+// Project libraries.
+//
+// A paper in progress can ship the documents it cites: when the
+// working directory (or one of its parents) contains a `.akl/`
+// directory, it is a library of its own, with the layout of the
+// global library in a single directory:
+//
+//   .akl/index.sqlite, collections.yaml, anchors.yaml, config.yaml
+//   .akl/raw/, mod/, trash/, reports/, sessions/, events.jsonl
+//
+// The configuration is the global one unless `.akl/config.yaml`
+// exists, and the cache and the logs are shared.
+//
+// Resolution order: the commands about a document (cite, open,
+// info…) look for it in the project library first, then in the
+// global library; `find` lists the documents of both. The other
+// commands act on the project library only. Documents are imported
+// into the global library, unless `akl import --local` is used
+// (which copies the documents of the global library). `akl --global`
+// ignores the project library.
+
+use std::path::{Path, PathBuf};
+
+/// Name of the directory of the project libraries.
+pub const DIR : &str = ".akl";
+
+/// The project library of a directory: the closest `.akl/`
+/// directory of the directory or of its parents.
+pub fn find(from : &Path) -> Option<PathBuf> {
+    from.ancestors()
+        .map(|dir| dir.join(DIR))
+        .find(|dir| dir.is_dir())
+}
+
+/// The project library of the working directory.
+pub fn current() -> Option<PathBuf> {
+    find(&std::env::current_dir().ok()?)
+}
@@ -0,0 +1,165 @@
+// Providers declared in the configuration.
+//
+// Institutional repositories and niche archives follow the same
+// pattern as the built-in providers: a uri gives an identifier and the
+// url of a pdf file, and maybe an API describing the document. Such
+// providers are declared as rules of the configuration rather than in
+// the code:
+//
+//     providers:
+//       - name: zenodo
+//         url: '^(?:https://zenodo\.org/records/|zenodo:)(?P<id>\d+)$'
+//         pdf: 'https://zenodo.org/records/${id}/files/paper.pdf'
+//         identifier: 'zenodo:${id}'
+//         metadata:
+//           url: 'https://zenodo.org/api/records/${id}'
+//           fields:
+//             title: /metadata/title
+//             authors: /metadata/creators/*/name
+//             year: /metadata/publication_date
+//
+// The templates refer to the groups of the regular expression (`$1`,
+// `${id}`, `$0` for the whole uri), and the fields of the metadata to
+// the answer of the API by JSON pointers, where `*` stands for every
+// element of a list. The rules are tried in order, before the
+// built-in providers.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Result, Context};
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use crate::crossref::Work;
+
+/// A provider, in the configuration.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProviderRule {
+    /// Name of the provider, in the messages.
+    pub name : String,
+
+    /// Regular expression of the uris of the provider.
+    pub url : String,
+
+    /// Template of the url of the pdf file.
+    pub pdf : String,
+
+    /// Template of the identifier of the document.
+    pub identifier : String,
+
+    /// The API describing the documents.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub metadata : Option<MetadataEndpoint>,
+}
+
+/// An API answering in JSON, in the configuration.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MetadataEndpoint {
+    /// Template of the url of the API.
+    pub url : String,
+
+    /// JSON pointers of the fields (title, authors, year,
+    /// venue, pages, abstract, keywords, identifiers).
+    pub fields : BTreeMap<String, String>,
+}
+
+/// A uri matched by a rule.
+#[derive(Debug, Clone)]
+pub struct Match {
+    /// Name of the provider.
+    pub name       : String,
+    pub identifier : String,
+    pub pdf_url    : String,
+    /// The url of the API and the pointers of the fields.
+    pub metadata   : Option<(String, BTreeMap<String, String>)>,
+}
+
+/// The first rule matching a uri, with its templates filled.
+pub fn resolve(rules : &[ProviderRule], uri : &str) -> Result<Option<Match>> {
+    for rule in rules {
+        let re = Regex::new(&rule.url)
+            .with_context(|| format!("Invalid url of the provider {}", rule.name))?;
+        let Some(captures) = re.captures(uri) else {
+            continue;
+        };
+        let fill = |template : &str| {
+            let mut filled = String::new();
+            captures.expand(template, &mut filled);
+            filled
+        };
+        log::debug!("The provider {} handles {uri}", rule.name);
+        return Ok(Some(Match {
+            name: rule.name.clone(),
+            identifier: fill(&rule.identifier),
+            pdf_url: fill(&rule.pdf),
+            metadata: rule.metadata.as_ref().map(|m| (fill(&m.url), m.fields.clone())),
+        }));
+    }
+    Ok(None)
+}
+
+/// The values a JSON pointer designates, where `*`
+/// stands for every element of a list.
+fn select<'a>(value : &'a Value, pointer : &str) -> Vec<&'a Value> {
+    let mut found = vec![value];
+    for token in pointer.split('/').skip(1) {
+        let token = token.replace("~1", "/").replace("~0", "~");
+        found = found.into_iter()
+            .flat_map(|v| match (token.as_str(), v) {
+                ("*", Value::Array(a)) => a.iter().collect(),
+                (_, Value::Array(a)) => token.parse::<usize>().ok().and_then(|i| a.get(i)).into_iter().collect(),
+                (_, Value::Object(o)) => o.get(&token).into_iter().collect(),
+                _ => vec![],
+            })
+            .collect();
+    }
+    found
+}
+
+/// The strings a JSON pointer designates (lists are flattened).
+fn strings(value : &Value, pointer : &str) -> Vec<String> {
+    select(value, pointer).into_iter()
+        .flat_map(|v| match v {
+            Value::Array(a) => a.iter().collect(),
+            v => vec![v],
+        })
+        .filter_map(|v| match v {
+            Value::String(s) => Some(s.split_whitespace().collect::<Vec<&str>>().join(" ")),
+            Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        })
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Reads the answer of an API with the pointers of the fields.
+fn parse(answer : &Value, fields : &BTreeMap<String, String>) -> Work {
+    let all = |field : &str| fields.get(field).map(|p| strings(answer, p)).unwrap_or_default();
+    let first = |field : &str| all(field).into_iter().next();
+    Work {
+        title: first("title"),
+        authors: all("authors"),
+        orcids: vec![],
+        // dates are often full dates (`2023-04-05`)
+        year: first("year").and_then(|y| y.get(..4)?.parse().ok()),
+        venue: first("venue"),
+        pages: first("pages"),
+        r#abstract: first("abstract").map(|a| crate::abstracts::strip_tags(&a)),
+        keywords: all("keywords"),
+        identifiers: all("identifiers"),
+    }
+}
+
+/// What the API of a provider says about a document.
+pub fn metadata(name : &str, url : &str, fields : &BTreeMap<String, String>) -> Result<Work> {
+    log::debug!("Querying the API of the provider {name}: {url}");
+    let answer : Value = crate::polite::get(url)
+        .header(reqwest::header::ACCEPT, "application/json")
+        .send()
+        .and_then(|r| r.error_for_status())
+        .with_context(|| format!("Querying the API of the provider {name}"))?
+        .json()
+        .with_context(|| format!("Parsing the answer of the provider {name}"))?;
+    Ok(parse(&answer, fields))
+}
@@ -0,0 +1,88 @@
+// Proxies and institutional access.
+//
+// Every request follows the proxies of the environment (`HTTP_PROXY`,
+// `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY`). The downloads of some
+// hosts can use their own proxy, and the urls of the publishers can
+// be rewritten to go through the EZproxy of a university, so that
+// the paywalled documents are fetched with its subscriptions:
+//
+//     proxies:
+//       - hosts: [dl.acm.org, ieeexplore.ieee.org]
+//         proxy: http://proxy.univ.example:3128
+//     institutional_access:
+//       - hosts: [dl.acm.org, link.springer.com]
+//         url: 'https://{dashed_host}.ezproxy.univ.example{path}'
+//
+// The templates of the rewritten urls know `{url}` (the whole url,
+// for the `login?url={url}` style of EZproxy), `{host}`, `{dashed_host}`
+// (`dl-acm-org`, for the host style) and `{path}` (with the query).
+// The credentials of the proxy host (see `credentials`) are sent to
+// it. The hosts of a rule include their subdomains.
+
+use anyhow::{Result, Context};
+use serde::{Serialize, Deserialize};
+use url::Url;
+
+/// A proxy used for some hosts, in the configuration.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProxyRule {
+    /// Hosts downloaded through the proxy.
+    pub hosts : Vec<String>,
+
+    /// Url of the proxy (`http://host:port`).
+    pub proxy : String,
+}
+
+/// A rewriting of the urls of some hosts, in the configuration.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AccessRule {
+    /// Hosts reached through the institution.
+    pub hosts : Vec<String>,
+
+    /// Template of the rewritten urls.
+    pub url : String,
+}
+
+/// Is `host` one of `hosts`, or one of their subdomains?
+fn matches(hosts : &[String], host : &str) -> bool {
+    hosts.iter().any(|h| host == h || host.strip_suffix(h.as_str()).is_some_and(|s| s.ends_with('.')))
+}
+
+/// The url to download instead of `url`, through the
+/// institution of the first rule of its host.
+pub fn rewrite(rules : &[AccessRule], url : &str) -> String {
+    let Ok(parsed) = Url::parse(url) else {
+        return url.to_string();
+    };
+    let Some(host) = parsed.host_str() else {
+        return url.to_string();
+    };
+    let Some(rule) = rules.iter().find(|r| matches(&r.hosts, host)) else {
+        return url.to_string();
+    };
+    let path = match parsed.query() {
+        Some(query) => format!("{}?{query}", parsed.path()),
+        None => parsed.path().to_string(),
+    };
+    let encoded : String = url::form_urlencoded::byte_serialize(url.as_bytes()).collect();
+    let rewritten = rule.url
+        .replace("{url}", &encoded)
+        .replace("{dashed_host}", &host.replace('.', "-"))
+        .replace("{host}", host)
+        .replace("{path}", &path);
+    log::debug!("Downloading {url} through the institution as {rewritten}");
+    rewritten
+}
+
+/// The proxy of the first rule of the host of a url, if any.
+pub fn proxy_for(rules : &[ProxyRule], url : &str) -> Result<Option<reqwest::Proxy>> {
+    let Some(host) = Url::parse(url).ok().and_then(|u| u.host_str().map(String::from)) else {
+        return Ok(None);
+    };
+    let Some(rule) = rules.iter().find(|r| matches(&r.hosts, &host)) else {
+        return Ok(None);
+    };
+    log::debug!("Downloading {url} through the proxy {}", rule.proxy);
+    Ok(Some(reqwest::Proxy::all(&rule.proxy)
+        .with_context(|| format!("Invalid proxy {} (only http proxies are supported)", rule.proxy))?))
+}
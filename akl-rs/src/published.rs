@@ -0,0 +1,89 @@
+// Published versions of the preprints.
+//
+// Papers are often imported from arXiv, bioRxiv or medRxiv before they
+// are published, and cited as preprints long after. DBLP lists the
+// published versions of most computer science papers next to their
+// arXiv (CoRR) records, and Crossref knows the others: a preprint is
+// published when one of them has a work of the same title with a doi,
+// outside of the preprint servers. The doi and the venue are then
+// added to the document, so that its links and citations use the
+// published version.
+
+use anyhow::Result;
+
+use crate::{Document, crossref::Crossref, dblp, doctype::DocType, identifiers, latex, rxiv};
+
+/// The published version of a preprint.
+#[derive(Debug, Clone)]
+pub struct Publication {
+    pub doi         : String,
+    pub venue       : Option<String>,
+    /// Other identifiers of the publication (its DBLP key).
+    pub identifiers : Vec<String>,
+    /// Where it was found, in the messages.
+    pub source      : &'static str,
+}
+
+/// Is this doi the one of a preprint server (`10.48550/arXiv.…`
+/// for arXiv, `10.1101/…` for bioRxiv and medRxiv)?
+pub fn is_preprint_doi(doi : &str) -> bool {
+    doi.to_lowercase().starts_with("10.48550/") || rxiv::is_preprint_doi(doi)
+}
+
+/// Is the document a preprint not known to be published: a document
+/// of arXiv, bioRxiv or medRxiv (or typed as a preprint) without a
+/// doi other than the one of its preprint server?
+pub fn is_unpublished(doc : &Document) -> bool {
+    let preprint = doc.doc_type == Some(DocType::Preprint) ||
+        identifiers::arxiv_id(&doc.identifiers).is_some() ||
+        identifiers::rxiv_preprint(&doc.identifiers).is_some();
+    preprint && !doc.identifiers.iter()
+        .filter_map(|i| identifiers::doi(std::slice::from_ref(i)))
+        .any(|doi| !is_preprint_doi(&doi))
+}
+
+/// The published version of a preprint, according to DBLP or else to Crossref.
+pub fn lookup(doc : &Document, crossref : &Crossref) -> Result<Option<Publication>> {
+    match dblp::publication(doc) {
+        Ok(Some(publication)) => return Ok(Some(publication)),
+        Ok(None) => {}
+        Err(e) => { log::warn!("Could not query DBLP for {}: {e:#}", doc.filename); }
+    }
+    let title = dblp::normalize(&doc.title);
+    let mut query = doc.title.clone();
+    if let Some(author) = doc.authors.first() {
+        query.push(' ');
+        query.push_str(&latex::split_name(author).1);
+    }
+    Ok(crossref.search(&query)?.into_iter()
+        .find(|(doi, kind, work)| {
+            kind != "posted-content" && !is_preprint_doi(doi) &&
+            work.title.as_deref().is_some_and(|t| dblp::normalize(t) == title)
+        })
+        .map(|(doi, _, work)| Publication {
+            doi,
+            venue: work.venue,
+            identifiers: vec![],
+            source: "Crossref",
+        }))
+}
+
+/// Records the publication of a preprint in the document: its doi
+/// and other identifiers, its venue, and its type. The year stays
+/// the one of the preprint, which names the files.
+pub fn apply(doc : &mut Document, publication : &Publication) {
+    let idents = std::iter::once(format!("doi:{}", publication.doi))
+        .chain(publication.identifiers.iter().cloned())
+        .map(|i| identifiers::canonicalize(&i));
+    for ident in idents {
+        if !doc.identifiers.contains(&ident) {
+            doc.identifiers.push(ident);
+        }
+    }
+    if let Some(venue) = &publication.venue {
+        doc.context = vec![venue.clone()];
+    }
+    if doc.doc_type == Some(DocType::Preprint) {
+        doc.doc_type = Some(DocType::Article);
+    }
+}
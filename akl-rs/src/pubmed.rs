@@ -0,0 +1,153 @@
+// Articles of PubMed and PubMed Central.
+//
+// PubMed indexes the biomedical literature by PMID (`12345678`), and
+// PubMed Central (PMC) keeps the full text of part of it, by PMCID
+// (`PMC1234567`). Their pages are `https://pubmed.ncbi.nlm.nih.gov/<pmid>/`
+// and `https://pmc.ncbi.nlm.nih.gov/articles/<pmcid>/` (formerly under
+// `www.ncbi.nlm.nih.gov/pmc/articles/`). The E-utilities of NCBI give
+// the summary of an article in both databases, with its other ids, and
+// the open access service of PMC gives the pdf files of the articles of
+// its open access subset. Articles are identified as `pubmed:<pmid>`
+// and `pmc:<pmcid>`, along with their doi, so that an article imported
+// by one of them is found by the others.
+
+use anyhow::{Result, Context};
+use serde_json::Value;
+use url::Url;
+
+use crate::crossref::Work;
+
+/// An article of PubMed, or of PubMed Central.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Id {
+    Pubmed(String),
+    Pmc(String),
+}
+
+impl Id {
+    /// The article of a `pubmed:` or `pmc:` identifier.
+    pub fn parse(scheme : &str, id : &str) -> Option<Id> {
+        let id = id.trim_matches('/');
+        match scheme {
+            "pubmed" => pmid(id).map(Id::Pubmed),
+            "pmc" => pmcid(id).map(Id::Pmc),
+            _ => None,
+        }
+    }
+
+    /// The identifier of the article.
+    pub fn identifier(&self) -> String {
+        match self {
+            Id::Pubmed(pmid) => format!("pubmed:{pmid}"),
+            Id::Pmc(pmcid) => format!("pmc:{pmcid}"),
+        }
+    }
+}
+
+/// A PMID: digits.
+fn pmid(id : &str) -> Option<String> {
+    (!id.is_empty() && id.chars().all(|c| c.is_ascii_digit())).then(|| id.to_string())
+}
+
+/// A PMCID, written `PMC1234567` (the prefix is optional).
+fn pmcid(id : &str) -> Option<String> {
+    let digits = id.get(..3)
+        .filter(|p| p.eq_ignore_ascii_case("pmc"))
+        .map_or(id, |_| &id[3..]);
+    pmid(digits).map(|d| format!("PMC{d}"))
+}
+
+/// The article of a url of PubMed or PMC.
+pub fn from_url(url : &Url) -> Option<Id> {
+    let mut segments = url.path_segments()?.filter(|s| !s.is_empty());
+    match url.host_str()? {
+        "pubmed.ncbi.nlm.nih.gov" => pmid(segments.next()?).map(Id::Pubmed),
+        "pmc.ncbi.nlm.nih.gov" | "www.ncbi.nlm.nih.gov" | "ncbi.nlm.nih.gov" => {
+            let mut segments = segments.skip_while(|s| *s != "articles");
+            segments.next()?;
+            pmcid(segments.next()?).map(Id::Pmc)
+        }
+        _ => None,
+    }
+}
+
+/// Reads the summary of an article, in either database.
+fn parse(summary : &Value) -> Work {
+    let ids : Vec<(&str, &str)> = summary["articleids"].as_array()
+        .map(|a| a.iter().filter_map(|id| Some((id["idtype"].as_str()?, id["value"].as_str()?))).collect())
+        .unwrap_or_default();
+    let identifiers = ids.iter()
+        .filter_map(|(kind, value)| match *kind {
+            "pubmed" | "pmid" => pmid(value).map(|v| format!("pubmed:{v}")),
+            "pmc" | "pmcid" => pmcid(value).map(|v| format!("pmc:{v}")),
+            "doi" => Some(format!("doi:{value}")),
+            _ => None,
+        })
+        .fold(vec![], |mut identifiers : Vec<String>, ident| {
+            if !identifiers.contains(&ident) {
+                identifiers.push(ident);
+            }
+            identifiers
+        });
+    let text = |field : &str| summary[field].as_str()
+        .map(|s| s.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .filter(|s| !s.is_empty());
+    Work {
+        // titles of PubMed end with a full stop
+        title: text("title").map(|t| t.strip_suffix('.').map(String::from).unwrap_or(t)),
+        authors: summary["authors"].as_array()
+            .map(|a| a.iter().filter_map(|author| author["name"].as_str()).map(String::from).collect())
+            .unwrap_or_default(),
+        orcids: vec![],
+        year: text("pubdate").and_then(|d| d.get(..4)?.parse().ok()),
+        venue: text("fulljournalname").or_else(|| text("source")),
+        pages: text("pages"),
+        r#abstract: None,
+        keywords: vec![],
+        identifiers,
+    }
+}
+
+/// What the E-utilities know about an article, with its other ids,
+/// `None` when it does not exist.
+pub fn summary(id : &Id) -> Result<Option<Work>> {
+    let (db, uid) = match id {
+        Id::Pubmed(pmid) => ("pubmed", pmid.as_str()),
+        Id::Pmc(pmcid) => ("pmc", pmcid.trim_start_matches("PMC")),
+    };
+    let query = serde_urlencoded::to_string([("db", db), ("id", uid), ("retmode", "json"), ("tool", "akl-rs")])?;
+    log::debug!("Querying the E-utilities for {}", id.identifier());
+    let answer : Value = crate::polite::get(&format!("https://eutils.ncbi.nlm.nih.gov/entrez/eutils/esummary.fcgi?{query}"))
+        .send()
+        .and_then(|r| r.error_for_status())
+        .context("Querying the E-utilities")?
+        .json()
+        .context("Parsing the E-utilities answer")?;
+    let summary = &answer["result"][uid];
+    if summary.is_null() || !summary["error"].is_null() {
+        return Ok(None);
+    }
+    Ok(Some(parse(summary)))
+}
+
+/// Url of the pdf file of an article of the open access subset of
+/// PMC, `None` when it is not open access or has no pdf file.
+pub fn pdf_url(pmcid : &str) -> Result<Option<String>> {
+    log::debug!("Asking the open access service of PMC for {pmcid}");
+    let answer = crate::polite::get(&format!("https://www.ncbi.nlm.nih.gov/pmc/utils/oa/oa.fcgi?id={pmcid}"))
+        .send()
+        .and_then(|r| r.error_for_status())
+        .context("Querying the open access service of PMC")?
+        .text()
+        .context("Reading the open access service of PMC")?;
+    // `<link format="pdf" href="ftp://ftp.ncbi.nlm.nih.gov/pub/pmc/…"/>`,
+    // whose files are also served over https
+    Ok(answer.split("<link").skip(1)
+        .map(|link| &link[..link.find('>').unwrap_or(link.len())])
+        .filter(|link| link.contains("format=\"pdf\""))
+        .find_map(|link| {
+            let href = &link[link.find("href=\"")? + 6..];
+            Some(href[..href.find('"')?].to_string())
+        })
+        .map(|href| href.replacen("ftp://", "https://", 1)))
+}
@@ -0,0 +1,53 @@
+// Metadata quality of the documents.
+//
+// Each document gets a completeness score, from the metadata
+// that makes it useful: a DOI to cite it, authors, a venue,
+// an abstract and named destinations to link to. The review
+// command walks through the documents with the lowest scores.
+
+use crate::Document;
+
+/// A metadata check, with its weight in the score.
+struct Check {
+    /// What is missing when the check fails.
+    missing : &'static str,
+
+    /// Weight of the check in the score.
+    weight  : u32,
+
+    /// Does the document pass the check?
+    passes  : fn(&Document) -> bool,
+}
+
+const CHECKS : [Check; 5] = [
+    Check { missing: "doi",          weight: 3, passes: |d| d.identifiers.iter().any(|i| i.starts_with("doi:")) },
+    Check { missing: "authors",      weight: 2, passes: |d| !d.authors.is_empty() },
+    Check { missing: "venue",        weight: 2, passes: |d| !d.context.is_empty() },
+    Check { missing: "abstract",     weight: 1, passes: |d| d.r#abstract.as_ref().is_some_and(|a| !a.trim().is_empty()) },
+    Check { missing: "destinations", weight: 1, passes: |d| !d.destinations.is_empty() || !d.format.is_pdf() },
+];
+
+/// Completeness of the metadata of a document.
+#[derive(Debug, Clone)]
+pub struct Quality {
+    /// Score between 0 and 100.
+    pub score   : u32,
+
+    /// Metadata missing from the document.
+    pub missing : Vec<&'static str>,
+}
+
+/// Computes the completeness score of a document.
+pub fn quality(doc : &Document) -> Quality {
+    let total : u32 = CHECKS.iter().map(|c| c.weight).sum();
+    let mut passed = 0;
+    let mut missing = vec![];
+    for check in &CHECKS {
+        if (check.passes)(doc) {
+            passed += check.weight;
+        } else {
+            missing.push(check.missing);
+        }
+    }
+    Quality { score: passed * 100 / total, missing }
+}
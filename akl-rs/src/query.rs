@@ -0,0 +1,462 @@
+// The query language of find, the saved searches and the smart
+// collections.
+//
+// A query is made of terms, combined by boolean operators:
+//
+//   author:razborov (tag:circuits OR tag:proofs) NOT year:<2000
+//
+// - `field:value` terms search a field of the documents: `author:`,
+//   `author-orcid:` (the ORCIDs of the authors), `title:`, `context:`,
+//   `abstract:`, `keyword:`, `ident:` and
+//   `dest:` (the named destinations) for a substring, `tag:` for a tag, `status:` for
+//   a reading status, `type:` for a type of document, `format:` for
+//   the format of its file (`format:epub`), and `year:`
+//   for a year, optionally preceded by a comparison (`year:>=2020`,
+//   `year:<2010`),
+// - `field:~regex` terms match a (case insensitive) regular
+//   expression instead (`title:~^automata`, `tag:~^logic/`),
+// - terms without field search the title, the authors, the
+//   context, the tags and the keywords of the documents,
+// - values with spaces or parentheses are quoted: `title:"lower
+//   bounds"`, `title:"~(upper|lower) bounds"`.
+//
+// Terms next to each other must all match (`AND` may be written),
+// `OR` matches either side, `NOT` (or `-`, after `--` on the
+// command line) negates a term, and parentheses group terms. The
+// operators are uppercase, `and` and `or` are searched as words.
+// The empty query matches every document.
+
+use std::fmt;
+
+use anyhow::{Result, Context};
+use regex::{Regex, RegexBuilder};
+
+use crate::Document;
+use crate::reading::ReadingStatus;
+use crate::doctype::DocType;
+use crate::format::Format;
+
+/// Comparison of years in a query.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum YearOp {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// How a text field is searched.
+#[derive(Clone, Debug)]
+enum Pattern {
+    /// Case insensitive substring.
+    Contains(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    fn parse(value : &str) -> Result<Self> {
+        match value.strip_prefix('~') {
+            Some(re) => RegexBuilder::new(re)
+                .case_insensitive(true)
+                .build()
+                .map(Pattern::Regex)
+                .with_context(|| format!("Invalid regular expression {re}")),
+            None => Ok(Pattern::Contains(value.to_lowercase())),
+        }
+    }
+
+    fn matches(&self, text : &str) -> bool {
+        match self {
+            Pattern::Contains(s) => text.to_lowercase().contains(s),
+            Pattern::Regex(re) => re.is_match(text),
+        }
+    }
+
+    fn matches_any<'a>(&self, mut texts : impl Iterator<Item = &'a String>) -> bool {
+        texts.any(|t| self.matches(t))
+    }
+}
+
+/// One term of a query.
+#[derive(Clone, Debug)]
+enum Term {
+    Text(Pattern),
+    Author(Pattern),
+    AuthorOrcid(Pattern),
+    Title(Pattern),
+    Context(Pattern),
+    Abstract(Pattern),
+    Keyword(Pattern),
+    Identifier(Pattern),
+    Destination(Pattern),
+    Tag(String),
+    /// Tags matching a regular expression.
+    Tags(Pattern),
+    Status(ReadingStatus),
+    Type(DocType),
+    Format(Format),
+    Year(YearOp, u32),
+}
+
+impl Term {
+    fn parse(word : &str) -> Result<Self> {
+        let Some((field, value)) = word.split_once(':') else {
+            return Ok(Term::Text(Pattern::parse(word)?));
+        };
+        Ok(match field {
+            "author" => Term::Author(Pattern::parse(value)?),
+            "author-orcid" => Term::AuthorOrcid(Pattern::parse(value)?),
+            "title" => Term::Title(Pattern::parse(value)?),
+            "context" => Term::Context(Pattern::parse(value)?),
+            "abstract" => Term::Abstract(Pattern::parse(value)?),
+            "keyword" => Term::Keyword(Pattern::parse(value)?),
+            "ident" => Term::Identifier(Pattern::parse(value)?),
+            "dest" => Term::Destination(Pattern::parse(value)?),
+            "tag" if value.starts_with('~') => Term::Tags(Pattern::parse(value)?),
+            "tag" => Term::Tag(value.into()),
+            "status" => {
+                let status = <ReadingStatus as clap::ValueEnum>::from_str(value, true)
+                    .map_err(|_| anyhow::anyhow!("Unknown reading status {value} in the query term {word}"))?;
+                Term::Status(status)
+            }
+            "type" => {
+                let t = <DocType as clap::ValueEnum>::from_str(value, true)
+                    .map_err(|_| anyhow::anyhow!("Unknown document type {value} in the query term {word}"))?;
+                Term::Type(t)
+            }
+            "format" => {
+                let f = <Format as clap::ValueEnum>::from_str(value, true)
+                    .map_err(|_| anyhow::anyhow!("Unknown format {value} in the query term {word}"))?;
+                Term::Format(f)
+            }
+            "year" => {
+                let (op, num) = if let Some(n) = value.strip_prefix(">=") { (YearOp::Ge, n) }
+                    else if let Some(n) = value.strip_prefix("<=") { (YearOp::Le, n) }
+                    else if let Some(n) = value.strip_prefix('>') { (YearOp::Gt, n) }
+                    else if let Some(n) = value.strip_prefix('<') { (YearOp::Lt, n) }
+                    else { (YearOp::Eq, value.strip_prefix('=').unwrap_or(value)) };
+                let year = num.parse()
+                    .with_context(|| format!("Invalid year in the query term {word}"))?;
+                Term::Year(op, year)
+            }
+            _ => anyhow::bail!("Unknown field {field} in the query term {word}"),
+        })
+    }
+
+    fn matches(&self, doc : &Document) -> bool {
+        match self {
+            Term::Text(p) => p.matches(&doc.title)
+                || p.matches_any(doc.authors.iter())
+                || p.matches_any(doc.context.iter())
+                || p.matches_any(doc.tags.iter())
+                || p.matches_any(doc.keywords.iter()),
+            Term::Author(p) => p.matches_any(doc.authors.iter()),
+            Term::AuthorOrcid(p) => p.matches_any(doc.orcids.values()),
+            Term::Title(p) => p.matches(&doc.title),
+            Term::Context(p) => p.matches_any(doc.context.iter()),
+            Term::Abstract(p) => p.matches_any(doc.r#abstract.iter()),
+            Term::Keyword(p) => p.matches_any(doc.keywords.iter()),
+            Term::Identifier(p) => p.matches_any(doc.identifiers.iter()),
+            Term::Destination(p) => p.matches_any(doc.destinations.keys()),
+            Term::Tag(t) => doc.tags.contains(t),
+            Term::Tags(p) => p.matches_any(doc.tags.iter()),
+            Term::Status(s) => doc.status == Some(*s),
+            Term::Type(t) => doc.doc_type == Some(*t),
+            Term::Format(f) => doc.format == *f,
+            Term::Year(op, y) => match op {
+                YearOp::Eq => doc.year == *y,
+                YearOp::Lt => doc.year < *y,
+                YearOp::Le => doc.year <= *y,
+                YearOp::Gt => doc.year > *y,
+                YearOp::Ge => doc.year >= *y,
+            },
+        }
+    }
+}
+
+/// A query, as a boolean combination of terms.
+#[derive(Clone, Debug)]
+enum Expr {
+    Term(Term),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+impl Expr {
+    fn matches(&self, doc : &Document) -> bool {
+        match self {
+            Expr::Term(t) => t.matches(doc),
+            Expr::Not(e) => !e.matches(doc),
+            Expr::And(es) => es.iter().all(|e| e.matches(doc)),
+            Expr::Or(es) => es.iter().any(|e| e.matches(doc)),
+        }
+    }
+}
+
+/// Tokens of a query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Word(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+            Token::And => write!(f, "AND"),
+            Token::Or => write!(f, "OR"),
+            Token::Not => write!(f, "NOT"),
+            Token::Word(w) => write!(f, "{w}"),
+        }
+    }
+}
+
+/// Splits a query into tokens: parentheses, operators and words,
+/// quotes protecting spaces and parentheses.
+fn tokenize(s : &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => { chars.next(); }
+            '(' => { chars.next(); tokens.push(Token::Open); }
+            ')' => { chars.next(); tokens.push(Token::Close); }
+            '-' => { chars.next(); tokens.push(Token::Not); }
+            _ => {
+                let mut word = String::new();
+                let mut quoted = false;
+                while let Some(&c) = chars.peek() {
+                    if !quoted && (c.is_whitespace() || c == '(' || c == ')') {
+                        break;
+                    }
+                    chars.next();
+                    if c == '"' {
+                        quoted = !quoted;
+                    } else {
+                        word.push(c);
+                    }
+                }
+                if quoted {
+                    anyhow::bail!("Unbalanced quotes in the query {s}");
+                }
+                tokens.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Word(word),
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser of the queries:
+///
+///   or   := and ("OR" and)*
+///   and  := not ("AND"? not)*
+///   not  := ("NOT" | "-") not | "(" or ")" | term
+struct Parser {
+    tokens : Vec<Token>,
+    pos    : usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut es = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            es.push(self.and()?);
+        }
+        Ok(if es.len() == 1 { es.remove(0) } else { Expr::Or(es) })
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut es = vec![self.not()?];
+        loop {
+            match self.peek() {
+                Some(Token::And) => { self.next(); }
+                Some(Token::Or | Token::Close) | None => break,
+                Some(_) => {}
+            }
+            es.push(self.not()?);
+        }
+        Ok(if es.len() == 1 { es.remove(0) } else { Expr::And(es) })
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.not()?))),
+            Some(Token::Open) => {
+                let e = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(e),
+                    _ => anyhow::bail!("Missing closing parenthesis in the query"),
+                }
+            }
+            Some(Token::Word(w)) => Ok(Expr::Term(Term::parse(&w)?)),
+            Some(t) => anyhow::bail!("Unexpected {t} in the query"),
+            None => anyhow::bail!("Unexpected end of the query"),
+        }
+    }
+}
+
+/// A query on documents.
+#[derive(Clone, Debug)]
+pub struct Query {
+    expr : Expr,
+}
+
+impl Query {
+    pub fn parse(s : &str) -> Result<Self> {
+        let mut parser = Parser { tokens: tokenize(s)?, pos: 0 };
+        if parser.tokens.is_empty() {
+            return Ok(Query { expr: Expr::And(vec![]) });
+        }
+        let expr = parser.or()?;
+        if let Some(t) = parser.peek() {
+            anyhow::bail!("Unexpected {t} in the query {s}");
+        }
+        Ok(Query { expr })
+    }
+
+    /// Does the document match the query?
+    pub fn matches(&self, doc : &Document) -> bool {
+        self.expr.matches(doc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(title : &str, authors : &[&str], year : u32, tags : &[&str]) -> Document {
+        serde_json::from_value(serde_json::json!({
+            "checksum": "0000",
+            "filename": "doc.pdf",
+            "identifiers": [],
+            "title": title,
+            "authors": authors,
+            "year": year,
+            "tags": tags,
+        })).unwrap()
+    }
+
+    fn matches(query : &str, doc : &Document) -> bool {
+        Query::parse(query).unwrap().matches(doc)
+    }
+
+    #[test]
+    fn precedence() {
+        let a = doc("A", &[], 2000, &["a"]);
+        let b = doc("B", &[], 2000, &["b"]);
+        let bc = doc("BC", &[], 2000, &["b", "c"]);
+        // AND binds tighter than OR
+        assert!(matches("tag:a OR tag:b tag:c", &a));
+        assert!(!matches("tag:a OR tag:b tag:c", &b));
+        assert!(matches("tag:a OR tag:b AND tag:c", &bc));
+        assert!(!matches("(tag:a OR tag:b) tag:c", &a));
+        assert!(matches("(tag:a OR tag:b) tag:c", &bc));
+        // NOT binds tighter than AND and OR
+        assert!(matches("NOT tag:a tag:b", &b));
+        assert!(!matches("NOT tag:a tag:b", &a));
+        assert!(matches("NOT tag:b OR tag:c", &a));
+        assert!(matches("NOT tag:b OR tag:c", &bc));
+        assert!(!matches("NOT tag:b OR tag:c", &b));
+        assert!(matches("NOT (tag:b tag:c)", &b));
+        assert!(!matches("NOT NOT tag:b", &a));
+    }
+
+    #[test]
+    fn lowercase_operators_are_words() {
+        let d = doc("Sense and Sensibility", &[], 1811, &[]);
+        assert!(matches("sense and sensibility", &d));
+        assert!(!matches("sense or sensibility", &d));
+    }
+
+    #[test]
+    fn quoting() {
+        let lower = doc("Lower bounds for circuits", &[], 2000, &[]);
+        let upper = doc("Upper bounds (revisited)", &[], 2000, &[]);
+        assert!(matches(r#"title:"lower bounds""#, &lower));
+        assert!(!matches(r#"title:"bounds for lower""#, &lower));
+        assert!(matches(r#"title:"~(upper|lower) bounds""#, &lower));
+        assert!(matches(r#"title:"~(upper|lower) bounds""#, &upper));
+        assert!(matches(r#""(revisited)""#, &upper));
+        assert!(!matches(r#""(revisited)""#, &lower));
+    }
+
+    #[test]
+    fn dash_negation() {
+        let a = doc("A", &[], 2000, &["a"]);
+        let b = doc("B", &[], 2000, &["b"]);
+        assert!(!matches("-tag:a", &a));
+        assert!(matches("-tag:a", &b));
+        assert!(matches("year:2000 -tag:a", &b));
+        assert!(!matches("year:2000 -(tag:a OR tag:b)", &b));
+        // only a leading dash negates
+        let d = doc("Co-NP complete problems", &[], 2000, &[]);
+        assert!(matches("co-np", &d));
+    }
+
+    #[test]
+    fn unbalanced() {
+        assert!(Query::parse("(tag:a").is_err());
+        assert!(Query::parse("tag:a)").is_err());
+        assert!(Query::parse("(tag:a OR (tag:b)").is_err());
+        assert!(Query::parse("()").is_err());
+        assert!(Query::parse(r#"title:"lower bounds"#).is_err());
+        assert!(Query::parse(r#""a" "b"#).is_err());
+    }
+
+    #[test]
+    fn dangling_operators() {
+        assert!(Query::parse("tag:a OR").is_err());
+        assert!(Query::parse("NOT").is_err());
+        assert!(Query::parse("AND tag:a").is_err());
+    }
+
+    #[test]
+    fn empty_query() {
+        let d = doc("A", &[], 2000, &[]);
+        assert!(matches("", &d));
+        assert!(matches("   ", &d));
+    }
+
+    #[test]
+    fn bare_terms() {
+        let d = doc("Automata", &["Anca Muscholl"], 2020, &["logic"]);
+        assert!(matches("automata", &d));
+        assert!(matches("muscholl", &d));
+        assert!(matches("logic", &d));
+        assert!(!matches("title:muscholl", &d));
+    }
+
+    #[test]
+    fn years() {
+        let d = doc("A", &[], 2020, &[]);
+        assert!(matches("year:2020", &d));
+        assert!(matches("year:>=2020", &d));
+        assert!(!matches("year:>2020", &d));
+        assert!(matches("year:<2021", &d));
+        assert!(Query::parse("year:twenty").is_err());
+    }
+}
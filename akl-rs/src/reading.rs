@@ -0,0 +1,35 @@
+// Reading status of the documents.
+//
+// Documents can be put on a reading list (to-read), and followed
+// until they are read or archived. Documents without status are
+// not on the reading list.
+
+use std::fmt;
+
+use serde::{Serialize, Deserialize};
+
+/// Where a document is in the reading list.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReadingStatus {
+    /// On the reading list.
+    ToRead,
+    /// Being read.
+    Reading,
+    /// Read.
+    Read,
+    /// No longer relevant.
+    Archived,
+}
+
+impl fmt::Display for ReadingStatus {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ReadingStatus::ToRead => "to-read",
+            ReadingStatus::Reading => "reading",
+            ReadingStatus::Read => "read",
+            ReadingStatus::Archived => "archived",
+        };
+        write!(f, "{name}")
+    }
+}
@@ -0,0 +1,259 @@
+// Remote stores used by `akl sync`.
+//
+// A remote is a flat store of objects addressed by keys (`library.json`,
+// `files/<sha256>`), reached through WebDAV, the S3 API, or any remote
+// known to rclone. Only the secrets are kept in the keyring: the
+// password of the WebDAV user, or the secret key of S3.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use anyhow::{Result, Context};
+use chrono::Utc;
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+
+use crate::secrets;
+
+/// Where the library is synchronized, in the configuration.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "backend", rename_all = "lowercase")]
+pub enum RemoteConfig {
+    /// A WebDAV directory (Nextcloud, ownCloud…).
+    Webdav {
+        /// Url of the directory.
+        url : String,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        username : Option<String>,
+        /// Keyring entry of the password.
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        keyring : Option<String>,
+    },
+    /// A bucket of an S3 compatible service.
+    S3 {
+        /// Url of the service (`https://s3.eu-west-1.amazonaws.com`).
+        endpoint : String,
+        region : String,
+        bucket : String,
+        /// Prefix of the keys of the library in the bucket.
+        #[serde(default)]
+        prefix : String,
+        access_key : String,
+        /// Keyring entry of the secret key.
+        keyring : String,
+    },
+    /// A remote path of rclone (`drive:papers/akl`).
+    Rclone {
+        remote : String,
+    },
+}
+
+/// Operations of a remote store.
+pub trait Remote {
+    /// Reads an object, `None` when it does not exist.
+    fn get(&self, key : &str) -> Result<Option<Vec<u8>>>;
+
+    /// Writes an object, replacing the previous one.
+    fn put(&self, key : &str, data : &[u8]) -> Result<()>;
+}
+
+/// Connects to the remote of the configuration.
+pub fn open(config : &RemoteConfig) -> Result<Box<dyn Remote>> {
+    match config {
+        RemoteConfig::Webdav { url, username, keyring } => {
+            let password = keyring.as_deref().map(secrets::get).transpose()?;
+            Ok(Box::new(WebDav {
+                url: format!("{}/", url.trim_end_matches('/')),
+                username: username.clone(),
+                password,
+                client: reqwest::blocking::Client::new(),
+            }))
+        }
+        RemoteConfig::S3 { endpoint, region, bucket, prefix, access_key, keyring } => {
+            Ok(Box::new(S3 {
+                endpoint: endpoint.trim_end_matches('/').into(),
+                region: region.clone(),
+                bucket: bucket.clone(),
+                prefix: prefix.clone(),
+                access_key: access_key.clone(),
+                secret_key: secrets::get(keyring)?,
+                client: reqwest::blocking::Client::new(),
+            }))
+        }
+        RemoteConfig::Rclone { remote } => {
+            Ok(Box::new(Rclone { remote: remote.trim_end_matches('/').into() }))
+        }
+    }
+}
+
+/// Reads the body of an answer, `None` for a 404.
+fn body(answer : reqwest::blocking::Response, what : &str) -> Result<Option<Vec<u8>>> {
+    if answer.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let answer = answer.error_for_status().with_context(|| format!("Reading {what}"))?;
+    Ok(Some(answer.bytes().with_context(|| format!("Reading {what}"))?.to_vec()))
+}
+
+struct WebDav {
+    url : String,
+    username : Option<String>,
+    password : Option<String>,
+    client : reqwest::blocking::Client,
+}
+
+impl WebDav {
+    fn request(&self, method : reqwest::Method, key : &str) -> reqwest::blocking::RequestBuilder {
+        let request = self.client.request(method, format!("{}{key}", self.url));
+        match &self.username {
+            Some(user) => request.basic_auth(user, self.password.as_ref()),
+            None => request,
+        }
+    }
+}
+
+impl Remote for WebDav {
+    fn get(&self, key : &str) -> Result<Option<Vec<u8>>> {
+        let answer = self.request(reqwest::Method::GET, key).send()
+            .with_context(|| format!("Reading {key} from {}", self.url))?;
+        body(answer, key)
+    }
+
+    fn put(&self, key : &str, data : &[u8]) -> Result<()> {
+        // the collections of the key must exist
+        let mut dir = String::new();
+        for part in key.split('/').collect::<Vec<&str>>().split_last().map(|(_, d)| d).unwrap_or_default() {
+            dir.push_str(part);
+            dir.push('/');
+            let mkcol = reqwest::Method::from_bytes(b"MKCOL")?;
+            let answer = self.request(mkcol, &dir).send()
+                .with_context(|| format!("Creating {dir} on {}", self.url))?;
+            // 405: the collection already exists
+            if !answer.status().is_success() && answer.status() != reqwest::StatusCode::METHOD_NOT_ALLOWED {
+                anyhow::bail!("Creating {dir} on {}: {}", self.url, answer.status());
+            }
+        }
+        self.request(reqwest::Method::PUT, key)
+            .body(data.to_vec())
+            .send()
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("Writing {key} to {}", self.url))?;
+        Ok(())
+    }
+}
+
+struct S3 {
+    endpoint : String,
+    region : String,
+    bucket : String,
+    prefix : String,
+    access_key : String,
+    secret_key : String,
+    client : reqwest::blocking::Client,
+}
+
+/// HMAC-SHA256 of a message.
+fn hmac(key : &[u8], message : &[u8]) -> Vec<u8> {
+    let mut block = [0u8; 64];
+    if key.len() > 64 {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte : u8| block.iter().map(|b| b ^ byte).collect::<Vec<u8>>();
+    let inner = Sha256::new().chain_update(pad(0x36)).chain_update(message).finalize();
+    Sha256::new().chain_update(pad(0x5c)).chain_update(inner).finalize().to_vec()
+}
+
+fn hex(bytes : &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+impl S3 {
+    /// A request signed with AWS signature version 4.
+    fn request(&self, method : reqwest::Method, key : &str, payload : &[u8]) -> Result<reqwest::blocking::RequestBuilder> {
+        let path = format!("/{}/{}{key}", self.bucket, self.prefix);
+        let url = url::Url::parse(&format!("{}{path}", self.endpoint))
+            .with_context(|| format!("Invalid S3 endpoint {}", self.endpoint))?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(h), Some(p)) => format!("{h}:{p}"),
+            (Some(h), None) => h.to_string(),
+            (None, _) => anyhow::bail!("Invalid S3 endpoint {}", self.endpoint),
+        };
+        let now = Utc::now();
+        let date = now.format("%Y%m%d").to_string();
+        let time = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let payload_hash = hex(&Sha256::digest(payload));
+        let canonical = format!("{method}\n{}\n\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{time}\n\n\
+                                 host;x-amz-content-sha256;x-amz-date\n{payload_hash}", url.path());
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let to_sign = format!("AWS4-HMAC-SHA256\n{time}\n{scope}\n{}", hex(&Sha256::digest(canonical)));
+        let key = [date.as_str(), &self.region, "s3", "aws4_request"].iter()
+            .fold(format!("AWS4{}", self.secret_key).into_bytes(), |k, part| hmac(&k, part.as_bytes()));
+        let signature = hex(&hmac(&key, to_sign.as_bytes()));
+        Ok(self.client.request(method, url)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", time)
+            .header(reqwest::header::AUTHORIZATION,
+                    format!("AWS4-HMAC-SHA256 Credential={}/{scope}, \
+                             SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={signature}",
+                            self.access_key)))
+    }
+}
+
+impl Remote for S3 {
+    fn get(&self, key : &str) -> Result<Option<Vec<u8>>> {
+        let answer = self.request(reqwest::Method::GET, key, b"")?.send()
+            .with_context(|| format!("Reading {key} from the bucket {}", self.bucket))?;
+        body(answer, key)
+    }
+
+    fn put(&self, key : &str, data : &[u8]) -> Result<()> {
+        self.request(reqwest::Method::PUT, key, data)?
+            .body(data.to_vec())
+            .send()
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("Writing {key} to the bucket {}", self.bucket))?;
+        Ok(())
+    }
+}
+
+struct Rclone {
+    remote : String,
+}
+
+impl Remote for Rclone {
+    fn get(&self, key : &str) -> Result<Option<Vec<u8>>> {
+        let path = format!("{}/{key}", self.remote);
+        let output = Command::new("rclone")
+            .args(["cat", &path])
+            .output()
+            .context("Running rclone (is it installed?)")?;
+        if output.status.success() {
+            return Ok(Some(output.stdout));
+        }
+        let error = String::from_utf8_lossy(&output.stderr);
+        if error.contains("not found") {
+            return Ok(None);
+        }
+        anyhow::bail!("rclone cat {path} failed: {}", error.trim())
+    }
+
+    fn put(&self, key : &str, data : &[u8]) -> Result<()> {
+        let path = format!("{}/{key}", self.remote);
+        let mut child = Command::new("rclone")
+            .args(["rcat", &path])
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Running rclone (is it installed?)")?;
+        child.stdin.take().context("Writing to rclone")?
+            .write_all(data)
+            .context("Writing to rclone")?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            anyhow::bail!("rclone rcat {path} failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(())
+    }
+}
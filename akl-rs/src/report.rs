@@ -0,0 +1,146 @@
+// Import reports.
+//
+// Every import writes a report recording where each field of the
+// metadata came from, what was downloaded, and how the conversion
+// went, so that an entry looking wrong can be explained long after
+// the import. Reports are stored by checksum, so that they survive
+// renames.
+//
+// Layout: reports/<checksum>.yaml
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+use anyhow::{Result, Context};
+use chrono::{DateTime, Utc};
+
+/// Where a field of the metadata came from.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Source {
+    /// Given on the command line (or in the akl uri).
+    CommandLine,
+    /// Read from the metadata of the pdf file.
+    PdfMetadata,
+    /// Deduced from the uri (e.g. an arxiv id).
+    Uri,
+    /// Changed in the editor during an interactive import.
+    Editor,
+    /// Rewritten to the canonical name of a venue.
+    VenueNormalizer,
+    /// Rewritten to the canonical name of an author.
+    AuthorRegistry,
+    /// Fetched from the arXiv or Crossref API.
+    Api,
+    /// Registered by the publisher of the doi at Crossref.
+    Crossref,
+    /// Registered for the doi at another agency, as given
+    /// by doi.org (content negotiation).
+    DoiOrg,
+    /// Known by OpenLibrary, for the isbn of a book.
+    OpenLibrary,
+    /// Deposited in HAL, as given by the HAL API.
+    Hal,
+    /// Given by the API of bioRxiv or medRxiv.
+    Rxiv,
+    /// Summarized by the E-utilities of NCBI (PubMed, PMC).
+    Pubmed,
+    /// Given by the API of a provider of the configuration,
+    /// or by a provider program.
+    Provider,
+    /// Given by the page of the report in the IACR ePrint archive.
+    Iacr,
+    /// Read from the page of the report on the ECCC site.
+    Eccc,
+    /// Found in DBLP.
+    Dblp,
+    /// Fetched from the Semantic Scholar API.
+    SemanticScholar,
+    /// Announced by the landing page the pdf file was found from.
+    LandingPage,
+}
+
+/// What the conversion did to the document.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Conversion {
+    /// Named destinations, each given a link.
+    pub destinations : usize,
+
+    /// Annotations of the original file (links rewritten).
+    pub annotations  : usize,
+
+    /// Was an outline added?
+    pub outline      : bool,
+
+    /// Duration of the conversion, in milliseconds.
+    pub duration_ms  : u128,
+}
+
+/// The report of an import.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ImportReport {
+    /// When the document was imported.
+    pub time         : DateTime<Utc>,
+
+    /// The uri given to the import.
+    pub uri          : String,
+
+    /// The url the file was downloaded from, if any.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub download_url : Option<String>,
+
+    /// Checksum of the document.
+    pub checksum     : String,
+
+    /// Hash of the original file as stored.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub raw_checksum : Option<String>,
+
+    /// Sources of each field of the metadata.
+    pub sources      : BTreeMap<String, Vec<Source>>,
+
+    pub conversion   : Conversion,
+
+    /// Everything that looked suspicious.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub warnings     : Vec<String>,
+}
+
+/// The directory of the reports.
+#[derive(Debug, Clone)]
+pub struct Reports {
+    path : PathBuf,
+}
+
+impl Reports {
+    pub fn new(path : &Path) -> Self {
+        Reports { path: path.into() }
+    }
+
+    fn report_path(&self, checksum : &str) -> PathBuf {
+        self.path.join(format!("{checksum}.yaml"))
+    }
+
+    /// Writes the report of an import, replacing the
+    /// report of a previous import of the same document.
+    pub fn save(&self, report : &ImportReport) -> Result<()> {
+        std::fs::create_dir_all(&self.path)
+            .context("Creating the reports directory")?;
+        let file = std::fs::File::create(self.report_path(&report.checksum))
+            .context("Creating the import report")?;
+        serde_yaml::to_writer(file, report)
+            .context("Writing the import report")
+    }
+
+    /// The report of the import of a document, if there is one.
+    pub fn load(&self, checksum : &str) -> Result<Option<ImportReport>> {
+        let path = self.report_path(checksum);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file = std::fs::File::open(&path)
+            .with_context(|| format!("Opening {path:?}"))?;
+        Ok(Some(serde_yaml::from_reader(file).with_context(|| format!("Parsing {path:?}"))?))
+    }
+}
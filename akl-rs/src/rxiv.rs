@@ -0,0 +1,139 @@
+// Preprints of bioRxiv and medRxiv.
+//
+// The preprints of both servers have a doi of Cold Spring Harbor
+// (`10.1101/2020.01.01.123456`, or `10.1101/123456` for the oldest
+// ones; the journals of the same prefix have letters in their dois)
+// and versions, like arxiv: `…123456v2`. Their pages are
+// `https://www.biorxiv.org/content/<doi>v<n>` (or medrxiv.org) and
+// their pdf files `…v<n>.full.pdf`. The public API of the servers
+// gives the metadata of every version, and tells which server a doi
+// belongs to. Preprints are identified by their doi, and by
+// `biorxiv:<doi>v<n>` (or `medrxiv:`) for the version imported.
+
+use anyhow::{Result, Context};
+use serde_json::Value;
+use url::Url;
+
+use crate::crossref::Work;
+
+/// The servers, as named in the identifiers.
+pub const SERVERS : [&str; 2] = ["biorxiv", "medrxiv"];
+
+/// A preprint of bioRxiv or medRxiv, maybe of an unknown
+/// server and version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preprint {
+    pub server  : Option<String>,
+    pub doi     : String,
+    pub version : Option<String>,
+}
+
+/// Is this doi the one of a preprint of bioRxiv or medRxiv?
+pub fn is_preprint_doi(doi : &str) -> bool {
+    doi.strip_prefix("10.1101/").is_some_and(|suffix| {
+        !suffix.is_empty() && suffix.chars().all(|c| c.is_ascii_digit() || c == '.')
+    })
+}
+
+/// Reads `10.1101/2020.01.01.123456v2…` (with anything after the version).
+fn parse_doi(server : Option<&str>, s : &str) -> Option<Preprint> {
+    let suffix = s.strip_prefix("10.1101/")?;
+    let number : String = suffix.chars().take_while(|c| c.is_ascii_digit() || *c == '.').collect();
+    let number = number.trim_end_matches('.');
+    let rest = &suffix[number.len()..];
+    let version : String = rest.strip_prefix('v')
+        .map(|v| v.chars().take_while(char::is_ascii_digit).collect())
+        .unwrap_or_default();
+    let doi = format!("10.1101/{number}");
+    is_preprint_doi(&doi).then(|| Preprint {
+        server: server.map(String::from),
+        doi,
+        version: (!version.is_empty()).then_some(version),
+    })
+}
+
+/// The preprint of a `biorxiv:` or `medrxiv:` identifier.
+pub fn parse_id(server : &str, id : &str) -> Option<Preprint> {
+    if !SERVERS.contains(&server) {
+        return None;
+    }
+    parse_doi(Some(server), id)
+}
+
+/// The server of a host.
+fn server_of(host : &str) -> Option<&'static str> {
+    SERVERS.iter().copied().find(|s| {
+        host.strip_prefix("www.").unwrap_or(host) == format!("{s}.org")
+    })
+}
+
+/// The preprint of a url of bioRxiv or medRxiv: its page,
+/// its pdf file, or a page of one of its versions.
+pub fn from_url(url : &Url) -> Option<Preprint> {
+    let server = server_of(url.host_str()?)?;
+    parse_doi(Some(server), url.path().strip_prefix("/content/")?)
+}
+
+/// The identifier of a version of a preprint.
+pub fn identifier(server : &str, doi : &str, version : &str) -> String {
+    format!("{server}:{doi}v{version}")
+}
+
+/// Url of the pdf file of a version of a preprint.
+pub fn pdf_url(server : &str, doi : &str, version : &str) -> String {
+    format!("https://www.{server}.org/content/{doi}v{version}.full.pdf")
+}
+
+/// Authors of the API (`Smith, J.; Doe, A. B.`), as `J. Smith`.
+fn authors(value : &str) -> Vec<String> {
+    value.split(';')
+        .map(str::trim)
+        .filter(|a| !a.is_empty())
+        .map(|a| match a.split_once(',') {
+            Some((last, first)) => format!("{} {}", first.trim(), last.trim()),
+            None => a.to_string(),
+        })
+        .collect()
+}
+
+/// What the API of a server knows about a preprint: its server,
+/// the version asked (the latest by default), and its metadata.
+/// Without server, both servers are asked.
+pub fn details(preprint : &Preprint) -> Result<Option<(Preprint, Work)>> {
+    let servers : Vec<&str> = match &preprint.server {
+        Some(server) => vec![server.as_str()],
+        None => SERVERS.to_vec(),
+    };
+    for server in servers {
+        log::debug!("Querying the {server} API for {}", preprint.doi);
+        let answer : Value = crate::polite::get(&format!("https://api.biorxiv.org/details/{server}/{}/na/json", preprint.doi))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("Querying the {server} API"))?
+            .json()
+            .with_context(|| format!("Parsing the {server} API answer"))?;
+        let versions = answer["collection"].as_array().cloned().unwrap_or_default();
+        let found = match &preprint.version {
+            Some(v) => versions.iter().find(|d| d["version"].as_str() == Some(v)),
+            None => versions.last(),
+        };
+        let Some(found) = found else {
+            continue;
+        };
+        let version = found["version"].as_str().map(String::from);
+        let work = Work {
+            title: found["title"].as_str().map(|t| t.split_whitespace().collect::<Vec<&str>>().join(" ")),
+            authors: found["authors"].as_str().map(authors).unwrap_or_default(),
+            orcids: vec![],
+            year: found["date"].as_str().and_then(|d| d.get(..4)).and_then(|y| y.parse().ok()),
+            venue: None,
+            pages: None,
+            r#abstract: found["abstract"].as_str().map(String::from).filter(|a| !a.trim().is_empty()),
+            keywords: found["category"].as_str().map(String::from).into_iter().collect(),
+            identifiers: version.iter().map(|v| identifier(server, &preprint.doi, v)).collect(),
+        };
+        let preprint = Preprint { server: Some(server.to_string()), doi: preprint.doi.clone(), version };
+        return Ok(Some((preprint, work)));
+    }
+    Ok(None)
+}
@@ -0,0 +1,81 @@
+// The pdf files of the downloads folder.
+//
+// Most papers arrive by the download button of the browser, and wait
+// in the downloads folder to be imported. `akl inbox scan` goes
+// through the pdf files of that folder (`downloads_folder` in the
+// configuration, the downloads folder of the user by default, or the
+// folder given) that are not in the library, by checksum, showing the
+// title and the identifiers guessed from each file: its metadata, and
+// its name, as arXiv names its files by their id (`2210.16580v2.pdf`).
+// A single key imports the file, skips it, or stops the scan.
+
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+
+use anyhow::{Result, Context};
+
+/// The folder scanned when none is given nor configured.
+pub fn default_folder() -> Result<PathBuf> {
+    directories::UserDirs::new()
+        .and_then(|dirs| dirs.download_dir().map(Path::to_path_buf))
+        .context("The downloads folder is unknown: give the folder to scan, \
+                  or set downloads_folder in the configuration")
+}
+
+/// The pdf files of a folder (but not of its subfolders),
+/// the most recent first.
+pub fn pdf_files(folder : &Path) -> Result<Vec<PathBuf>> {
+    let mut files : Vec<(SystemTime, PathBuf)> = vec![];
+    for entry in std::fs::read_dir(folder).with_context(|| format!("Reading the folder {folder:?}"))? {
+        let entry = entry.with_context(|| format!("Reading the folder {folder:?}"))?;
+        let path = entry.path();
+        if path.is_file() && path.extension().is_some_and(|e| e.eq_ignore_ascii_case("pdf")) {
+            let modified = entry.metadata().and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+            files.push((modified, path));
+        }
+    }
+    files.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
+
+/// The identifiers given by the name of a file: the arxiv id of
+/// the files downloaded from arXiv.
+pub fn name_identifiers(path : &Path) -> Vec<String> {
+    let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+        return vec![];
+    };
+    match crate::arxiv::parse_id(stem) {
+        Some(paper) => vec![match paper.version {
+            Some(v) => format!("arxiv:{}v{v}", paper.id),
+            None => format!("arxiv:{}", paper.id),
+        }],
+        None => vec![],
+    }
+}
+
+/// Asks a question answered by one of `keys` (lowercase, the first
+/// being the default), read as a single keystroke when the standard
+/// input is a terminal that `stty` can switch to the non-canonical mode,
+/// and else as a line.
+pub fn ask_key(question : &str, keys : &[char]) -> Result<char> {
+    eprint!("{question} ");
+    std::io::stderr().flush()?;
+    let stty = |args : &[&str]| Command::new("stty").args(args).status().is_ok_and(|s| s.success());
+    let single = std::io::stdin().is_terminal() && stty(&["-icanon", "min", "1"]);
+    let answer = if single {
+        let mut key = [0u8];
+        let read = std::io::stdin().read_exact(&mut key);
+        stty(&["icanon"]);
+        read.context("Reading the answer")?;
+        eprintln!();
+        char::from(key[0])
+    } else {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line).context("Reading the answer")?;
+        line.trim().chars().next().unwrap_or(keys[0])
+    };
+    let answer = answer.to_ascii_lowercase();
+    Ok(if keys.contains(&answer) { answer } else { keys[0] })
+}
@@ -0,0 +1,80 @@
+// Versions of the schema of the index.
+//
+// The index records the version of its schema: the yaml index is a
+// mapping `{schema: N, documents: […]}` (the bare list of documents
+// written before versions existed is version 1), and the sqlite
+// index uses the `user_version` pragma (0 meaning version 1).
+//
+// Old indexes are upgraded when they are opened: each document goes,
+// as a json value, through the migrations of the versions in between.
+// An index written by a newer version of akl can be read, but not
+// written, since the fields this version does not know would be lost.
+//
+// Adding an optional field to `Document` needs no new version; a new
+// version is needed when existing values must be rewritten.
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::identifiers;
+
+/// Version of the schema written by this version of akl.
+pub const VERSION : u32 = 3;
+
+/// Upgrades a document by one version.
+type Migration = fn(&mut Value) -> Result<()>;
+
+/// `MIGRATIONS[i]` upgrades a document from version `i + 1`
+/// to version `i + 2`.
+const MIGRATIONS : &[Migration] = &[
+    store_short_id,
+    canonical_identifiers,
+];
+
+/// Version 2: the short ids, derived from the checksum for
+/// the documents imported before ids existed, are stored.
+fn store_short_id(doc : &mut Value) -> Result<()> {
+    let checksum = doc["checksum"].as_str()
+        .ok_or_else(|| anyhow::anyhow!("A document of the index has no checksum"))?
+        .to_string();
+    let doc = doc.as_object_mut()
+        .ok_or_else(|| anyhow::anyhow!("A document of the index is not a mapping"))?;
+    if doc.get("id").and_then(Value::as_str).is_none_or(str::is_empty) {
+        doc.insert("id".into(), identifiers::short_id(&checksum, identifiers::SHORT_ID_LEN).into());
+    }
+    Ok(())
+}
+
+/// Version 3: the identifiers are stored in their canonical
+/// form, without the duplicates this reveals.
+fn canonical_identifiers(doc : &mut Value) -> Result<()> {
+    let Some(idents) = doc.get_mut("identifiers").and_then(Value::as_array_mut) else {
+        return Ok(());
+    };
+    let mut canonical : Vec<Value> = vec![];
+    for ident in idents.iter().filter_map(Value::as_str) {
+        let ident = Value::from(identifiers::canonicalize(ident));
+        if !canonical.contains(&ident) {
+            canonical.push(ident);
+        }
+    }
+    *idents = canonical;
+    Ok(())
+}
+
+/// Upgrades a document written with the schema `from`.
+pub fn upgrade(doc : &mut Value, from : u32) -> Result<()> {
+    for migration in MIGRATIONS.iter().skip(from.saturating_sub(1) as usize) {
+        migration(doc)?;
+    }
+    Ok(())
+}
+
+/// Refuses to write an index of a newer schema.
+pub fn check_writable(version : u32) -> Result<()> {
+    if version > VERSION {
+        anyhow::bail!("The index was written by a newer version of akl (schema {version}, \
+                       this version knows up to {VERSION}): upgrade akl to modify the library");
+    }
+    Ok(())
+}
@@ -0,0 +1,121 @@
+// Citation graph of the documents from Semantic Scholar.
+//
+// The Graph API of Semantic Scholar knows a paper by its doi, its
+// arxiv id or its own id (the S2 id), and gives its abstract, its
+// number of citations, the papers it cites and the papers citing it.
+// Imports record the S2 id (`s2:<id>`) and the number of citations,
+// and `akl related` lists the references and the citations of a
+// document that are in the library. Without an API key, the API is
+// shared by every client and often busy: failures are not errors.
+
+use anyhow::{Result, Context};
+use serde_json::Value;
+
+use crate::{Document, duplicates, identifiers};
+
+const API : &str = "https://api.semanticscholar.org/graph/v1";
+
+/// Fields asked for each paper.
+const FIELDS : &str = "paperId,title,abstract,citationCount,externalIds";
+
+/// Maximal number of references or citations asked.
+const LIMIT : &str = "1000";
+
+/// What Semantic Scholar knows about a paper.
+#[derive(Debug, Clone)]
+pub struct Paper {
+    /// The S2 id.
+    pub id          : String,
+    pub title       : Option<String>,
+    pub r#abstract  : Option<String>,
+    pub citations   : Option<u32>,
+    /// Its doi and arxiv id, as identifiers of the library.
+    pub identifiers : Vec<String>,
+}
+
+impl Paper {
+    fn parse(value : &Value) -> Option<Self> {
+        let ids = &value["externalIds"];
+        let identifiers = [("DOI", "doi"), ("ArXiv", "arxiv")].iter()
+            .filter_map(|(field, scheme)| ids[field].as_str().map(|id| format!("{scheme}:{id}")))
+            .collect();
+        Some(Paper {
+            id: value["paperId"].as_str()?.to_string(),
+            title: value["title"].as_str().map(String::from),
+            r#abstract: value["abstract"].as_str().map(String::from).filter(|a| !a.trim().is_empty()),
+            citations: value["citationCount"].as_u64().and_then(|c| u32::try_from(c).ok()),
+            identifiers,
+        })
+    }
+
+    /// Is this paper the given document of the library?
+    pub fn is(&self, doc : &Document) -> bool {
+        let ids : Vec<String> = self.identifiers.iter()
+            .chain(std::iter::once(&format!("s2:{}", self.id)))
+            .map(|i| duplicates::normalize(i))
+            .collect();
+        doc.identifiers.iter().any(|i| ids.contains(&duplicates::normalize(i))) ||
+        self.title.as_ref().is_some_and(|t| duplicates::same_title(t, &doc.title))
+    }
+}
+
+/// The S2 id of a document among its identifiers.
+pub fn s2_id(idents : &[String]) -> Option<String> {
+    idents.iter().find_map(|i| i.strip_prefix("s2:").map(String::from))
+}
+
+/// The id of a paper in the API: its S2 id,
+/// or else its doi or its arxiv id.
+fn paper_id(idents : &[String]) -> Option<String> {
+    s2_id(idents)
+        .or_else(|| identifiers::doi(idents).map(|d| format!("DOI:{d}")))
+        .or_else(|| identifiers::arxiv_id(idents).map(|a| format!("ARXIV:{}", duplicates::strip_version(&a))))
+}
+
+/// Reads an answer of the API, `None` for a 404.
+fn get(path : &str, query : &[(&str, &str)]) -> Result<Option<Value>> {
+    let query = serde_urlencoded::to_string(query)?;
+    log::debug!("Querying Semantic Scholar for {path}");
+    let answer = crate::polite::get(&format!("{API}/{path}?{query}"))
+        .send()
+        .context("Querying the Semantic Scholar API")?;
+    if answer.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let answer = answer.error_for_status()
+        .context("Querying the Semantic Scholar API")?
+        .json()
+        .context("Parsing the Semantic Scholar API answer")?;
+    Ok(Some(answer))
+}
+
+/// What Semantic Scholar knows about a document with these
+/// identifiers, `None` when it does not know it.
+pub fn paper(idents : &[String]) -> Result<Option<Paper>> {
+    let Some(id) = paper_id(idents) else {
+        return Ok(None);
+    };
+    Ok(get(&format!("paper/{id}"), &[("fields", FIELDS)])?
+        .as_ref()
+        .and_then(Paper::parse))
+}
+
+/// The papers linked to a paper: `references` (the papers it
+/// cites) or `citations` (the papers citing it).
+fn linked(id : &str, kind : &str, field : &str) -> Result<Vec<Paper>> {
+    let answer = get(&format!("paper/{id}/{kind}"), &[("fields", FIELDS), ("limit", LIMIT)])?
+        .unwrap_or_default();
+    Ok(answer["data"].as_array()
+        .map(|data| data.iter().filter_map(|d| Paper::parse(&d[field])).collect())
+        .unwrap_or_default())
+}
+
+/// The papers cited by a paper.
+pub fn references(id : &str) -> Result<Vec<Paper>> {
+    linked(id, "references", "citedPaper")
+}
+
+/// The papers citing a paper.
+pub fn citations(id : &str) -> Result<Vec<Paper>> {
+    linked(id, "citations", "citingPaper")
+}
@@ -0,0 +1,69 @@
+// Saved searches.
+//
+// A saved search is a query of the find command (see
+// `query::Query`) stored under a name, to run it again later
+// (`akl search run <name>`), e.g. from a picker, with the output
+// formats of the find command. Searches are stored in the
+// searches.yaml file next to the index.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, Context};
+
+use crate::query::Query;
+
+/// The saved searches of the library.
+#[derive(Debug)]
+pub struct Searches {
+    path : PathBuf,
+    /// Queries, by name.
+    pub searches : BTreeMap<String, String>,
+}
+
+impl Searches {
+    /// Loads the searches from a yaml file.
+    /// A missing file means no searches.
+    pub fn load(path : &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path).or_else(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound { Ok(String::new()) } else { Err(e) }
+        }).context("Reading the saved searches")?;
+        let searches = if content.trim().is_empty() {
+            BTreeMap::new()
+        } else {
+            serde_yaml::from_str(&content)
+                .with_context(|| format!("Parsing the saved searches {path:?}"))?
+        };
+        Ok(Searches { path: path.into(), searches })
+    }
+
+    /// Saves the searches to their yaml file.
+    pub fn save(&self) -> Result<()> {
+        let file = std::fs::File::create(&self.path)
+            .context("Opening the saved searches file")?;
+        serde_yaml::to_writer(file, &self.searches)
+            .context("Writing the saved searches file")
+    }
+
+    /// The query of a search.
+    pub fn get(&self, name : &str) -> Result<&str> {
+        self.searches.get(name)
+            .map(String::as_str)
+            .with_context(|| format!("There is no saved search named {name}"))
+    }
+
+    /// Saves a query under a name, replacing the
+    /// previous search of that name.
+    pub fn set(&mut self, name : &str, query : &str) -> Result<()> {
+        Query::parse(query)?;
+        self.searches.insert(name.into(), query.into());
+        Ok(())
+    }
+
+    /// Deletes a search.
+    pub fn delete(&mut self, name : &str) -> Result<()> {
+        self.searches.remove(name)
+            .map(|_| ())
+            .with_context(|| format!("There is no saved search named {name}"))
+    }
+}
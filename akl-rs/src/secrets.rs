@@ -0,0 +1,70 @@
+// Secrets management.
+//
+// Passwords are never stored in the configuration or the index:
+// they live in the system keyring (secret-service, keychain,
+// or the windows credential manager), and the configuration
+// only refers to the name of the keyring entry.
+
+use anyhow::{Result, Context};
+
+/// Service name under which all the entries are stored.
+const SERVICE : &str = "akl-rs";
+
+/// Fetches a secret from the keyring.
+pub fn get(name : &str) -> Result<String> {
+    keyring::Entry::new(SERVICE, name)
+        .and_then(|e| e.get_password())
+        .with_context(|| format!("Reading the keyring entry {name}"))
+}
+
+/// Stores a secret in the keyring, replacing the previous one.
+pub fn set(name : &str, secret : &str) -> Result<()> {
+    keyring::Entry::new(SERVICE, name)
+        .and_then(|e| e.set_password(secret))
+        .with_context(|| format!("Writing the keyring entry {name}"))
+}
+
+/// Removes a secret from the keyring.
+/// Removing a missing entry is not an error.
+pub fn delete(name : &str) -> Result<()> {
+    match keyring::Entry::new(SERVICE, name).and_then(|e| e.delete_password()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Deleting the keyring entry {name}")),
+    }
+}
+
+/// Asks the user for a secret: without echo on a terminal,
+/// and as a single line when stdin is redirected.
+pub fn prompt(message : &str) -> Result<String> {
+    rpassword::prompt_password(message)
+        .context("Reading the password")
+}
+
+/// Decrypts a pdf document using qpdf, the password
+/// being given on the standard input so that it does not
+/// appear in the list of processes.
+pub fn decrypt_pdf(bytes : &[u8], password : &str) -> Result<Vec<u8>> {
+    use std::io::Write;
+
+    let input  = tempfile::NamedTempFile::new()?;
+    let output = tempfile::NamedTempFile::new()?;
+    std::fs::write(input.path(), bytes)?;
+
+    let mut child = std::process::Command::new("qpdf")
+        .arg("--password-file=-")
+        .arg("--decrypt")
+        .arg(input.path())
+        .arg(output.path())
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .context("Running qpdf to decrypt the document")?;
+    child.stdin.take()
+         .context("Opening the standard input of qpdf")?
+         .write_all(password.as_bytes())?;
+    let status = child.wait()?;
+    // qpdf exits with 3 when it succeeds with warnings
+    if !status.success() && status.code() != Some(3) {
+        anyhow::bail!("qpdf could not decrypt the document ({status})");
+    }
+    Ok(std::fs::read(output.path())?)
+}
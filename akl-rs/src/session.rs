@@ -0,0 +1,256 @@
+// Reading sessions.
+//
+// Every viewer started by `akl open` is registered, with the
+// document and the page (or destination) it was opened at, until
+// the viewer exits. A session is a named snapshot of the documents
+// open at some point, that can be reopened later on, e.g. when
+// coming back to a project.
+//
+// The last position of every document is remembered (the page or
+// destination it was opened at, or the page evince shows or was left
+// at), for `akl open --resume`. A session is saved, and reopened,
+// with the documents at the positions they were left at, when known
+// (evince records them), or else at the positions they were opened.
+//
+// Layout: sessions/open.yaml (the open documents),
+//         sessions/positions.yaml (the last positions),
+//         sessions/<name>.yaml (the saved sessions)
+
+use std::collections::BTreeMap;
+
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+use anyhow::{Result, Context};
+use chrono::{DateTime, Utc};
+
+/// Name of the file listing the open documents.
+const OPEN : &str = "open";
+
+/// Name of the file of the last positions.
+const POSITIONS : &str = "positions";
+
+/// A document open in a viewer.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OpenDocument {
+    /// Checksum of the document.
+    pub checksum : String,
+
+    /// Title of the document (at that time).
+    pub title    : String,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub page     : Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub dest     : Option<String>,
+
+    /// When the document was opened.
+    pub opened   : DateTime<Utc>,
+
+    /// Process of the viewer, not saved in the sessions.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub pid      : Option<u32>,
+}
+
+/// A saved session.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Session {
+    /// When the session was saved.
+    pub saved     : DateTime<Utc>,
+
+    pub documents : Vec<OpenDocument>,
+}
+
+/// Where a document was left.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Position {
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub page : Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub dest : Option<String>,
+
+    pub time : DateTime<Utc>,
+}
+
+/// Is the process still running? Only known on linux,
+/// elsewhere the viewers are assumed to be running.
+fn is_running(pid : u32) -> bool {
+    if cfg!(target_os = "linux") {
+        Path::new("/proc").join(pid.to_string()).exists()
+    } else {
+        true
+    }
+}
+
+/// The directory of the sessions.
+#[derive(Debug, Clone)]
+pub struct Sessions {
+    path : PathBuf,
+}
+
+impl Sessions {
+    pub fn new(path : &Path) -> Self {
+        Sessions { path: path.into() }
+    }
+
+    fn file(&self, name : &str) -> PathBuf {
+        self.path.join(format!("{name}.yaml"))
+    }
+
+    fn read(&self, name : &str) -> Result<Option<Session>> {
+        let path = self.file(name);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file = std::fs::File::open(&path)
+            .with_context(|| format!("Opening {path:?}"))?;
+        serde_yaml::from_reader(file)
+            .with_context(|| format!("Parsing the session {path:?}"))
+    }
+
+    fn write(&self, name : &str, session : &Session) -> Result<()> {
+        std::fs::create_dir_all(&self.path)
+            .context("Creating the sessions directory")?;
+        let path = self.file(name);
+        let file = std::fs::File::create(&path)
+            .with_context(|| format!("Creating {path:?}"))?;
+        serde_yaml::to_writer(file, session)
+            .with_context(|| format!("Writing the session {path:?}"))
+    }
+
+    /// The documents currently open in a viewer.
+    pub fn open_documents(&self) -> Result<Vec<OpenDocument>> {
+        Ok(self.read(OPEN)?
+            .map(|s| s.documents)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|d| d.pid.is_some_and(is_running))
+            .collect())
+    }
+
+    /// The document registered as shown by a viewer,
+    /// even if the viewer exited since.
+    pub fn shown_by(&self, pid : u32) -> Result<Option<OpenDocument>> {
+        Ok(self.read(OPEN)?
+            .and_then(|s| s.documents.into_iter().find(|d| d.pid == Some(pid))))
+    }
+
+    /// Registers a document opened in a viewer. A document opened
+    /// again replaces its previous position.
+    pub fn opened(&self, doc : OpenDocument) -> Result<()> {
+        let mut documents = self.open_documents()?;
+        documents.retain(|d| d.checksum != doc.checksum);
+        documents.push(doc);
+        self.write(OPEN, &Session { saved: Utc::now(), documents })
+    }
+
+    /// Unregisters the document shown by a viewer that exited.
+    pub fn closed(&self, pid : u32) -> Result<()> {
+        let mut documents = self.open_documents()?;
+        documents.retain(|d| d.pid != Some(pid));
+        self.write(OPEN, &Session { saved: Utc::now(), documents })
+    }
+
+    fn positions(&self) -> Result<BTreeMap<String, Position>> {
+        let path = self.file(POSITIONS);
+        if !path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        let file = std::fs::File::open(&path)
+            .with_context(|| format!("Opening {path:?}"))?;
+        serde_yaml::from_reader(file)
+            .with_context(|| format!("Parsing the positions {path:?}"))
+    }
+
+    /// The last position of a document, by checksum.
+    pub fn position(&self, checksum : &str) -> Result<Option<Position>> {
+        Ok(self.positions()?.remove(checksum))
+    }
+
+    /// Remembers the last position of a document.
+    pub fn left_at(&self, checksum : &str, page : Option<u32>, dest : Option<String>) -> Result<()> {
+        let mut positions = self.positions()?;
+        positions.insert(checksum.into(), Position { page, dest, time: Utc::now() });
+        std::fs::create_dir_all(&self.path)
+            .context("Creating the sessions directory")?;
+        let path = self.file(POSITIONS);
+        let file = std::fs::File::create(&path)
+            .with_context(|| format!("Creating {path:?}"))?;
+        serde_yaml::to_writer(file, &positions)
+            .with_context(|| format!("Writing the positions {path:?}"))
+    }
+
+    /// Where a document of a session was left: its last position,
+    /// when it was recorded after the document was opened, or else
+    /// the position it was opened at.
+    pub fn left_position(&self, doc : &OpenDocument) -> Result<(Option<u32>, Option<String>)> {
+        Ok(match self.position(&doc.checksum)? {
+            Some(p) if p.time >= doc.opened => (p.page, p.dest),
+            _ => (doc.page, doc.dest.clone()),
+        })
+    }
+
+    /// Saves the open documents under a name, at the positions
+    /// they were left at, replacing a previous session of that name.
+    pub fn save(&self, name : &str) -> Result<Session> {
+        check_name(name)?;
+        let documents = self.open_documents()?
+            .into_iter()
+            .map(|d| {
+                let (page, dest) = self.left_position(&d)?;
+                Ok(OpenDocument { pid: None, page, dest, ..d })
+            })
+            .collect::<Result<_>>()?;
+        let session = Session { saved: Utc::now(), documents };
+        self.write(name, &session)?;
+        Ok(session)
+    }
+
+    /// Loads a saved session.
+    pub fn load(&self, name : &str) -> Result<Session> {
+        check_name(name)?;
+        self.read(name)?
+            .with_context(|| format!("There is no session named {name}"))
+    }
+
+    /// Names of the saved sessions, sorted.
+    pub fn list(&self) -> Result<Vec<String>> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        let mut names = vec![];
+        for entry in std::fs::read_dir(&self.path).context("Listing the sessions")? {
+            let path = entry?.path();
+            match path.file_stem().and_then(|s| s.to_str()) {
+                Some(name) if name != OPEN && name != POSITIONS && path.extension().is_some_and(|e| e == "yaml") => {
+                    names.push(name.to_string());
+                }
+                _ => {}
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Deletes a saved session.
+    pub fn delete(&self, name : &str) -> Result<()> {
+        check_name(name)?;
+        let path = self.file(name);
+        if !path.exists() {
+            anyhow::bail!("There is no session named {name}");
+        }
+        std::fs::remove_file(&path)
+            .with_context(|| format!("Removing {path:?}"))
+    }
+}
+
+/// Session names are file names, and `open` and `positions` are taken.
+fn check_name(name : &str) -> Result<()> {
+    if name.is_empty() || name == OPEN || name == POSITIONS || name.starts_with('.')
+       || name.contains(['/', '\\']) {
+        anyhow::bail!("Invalid session name {name:?}");
+    }
+    Ok(())
+}
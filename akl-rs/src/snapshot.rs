@@ -0,0 +1,231 @@
+// Snapshots of web pages.
+//
+// A web page (a blog post, lecture notes) is imported as a single
+// html file, readable offline and unaffected by the later changes of
+// the page: its stylesheets are inlined in `<style>` elements, and its
+// images, and the images and fonts of its stylesheets, become `data:`
+// urls. The scripts are removed, so that the snapshot shows the page
+// as the server sent it, without fetching anything. The resources that
+// cannot be fetched keep their absolute url. The snapshot ends with a
+// comment saying where and when it was taken.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::LazyLock;
+
+use anyhow::{Result, Context};
+use base64::Engine;
+use regex::{Captures, Regex};
+use reqwest::blocking::Client;
+use url::Url;
+
+use crate::landing;
+use crate::pdflib::PdfMetaData;
+
+/// Resources larger than this are not inlined.
+const MAX_RESOURCE : u64 = 10_000_000;
+
+static SCRIPT : LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<script\b.*?</script\s*>|<script\b[^>]*/>").unwrap());
+static LINK : LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<link\b[^>]*>").unwrap());
+static STYLE : LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)(<style\b[^>]*>)(.*?)(</style\s*>)").unwrap());
+static IMAGE : LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<(?:img|source)\b[^>]*>").unwrap());
+static BASE : LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<base\b[^>]*>").unwrap());
+static TITLE : LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<title\b[^>]*>(.*?)</title\s*>").unwrap());
+static CSS_URL : LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"url\(\s*['"]?([^'")]*)['"]?\s*\)"#).unwrap());
+/// The srcset attributes, which would fetch the images again.
+static SRCSET : LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"(?is)\s(?:data-)?srcset\s*=\s*(?:"[^"]*"|'[^']*'|[^\s>]+)"#).unwrap());
+
+/// The regular expression of an attribute of a tag.
+fn attribute_regex(name : &str) -> Regex {
+    Regex::new(&format!(r#"(?is)\s{name}\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#)).unwrap()
+}
+
+static SRC : LazyLock<Regex> = LazyLock::new(|| attribute_regex("src"));
+static DATA_SRC : LazyLock<Regex> = LazyLock::new(|| attribute_regex("data-src"));
+static HREF : LazyLock<Regex> = LazyLock::new(|| attribute_regex("href"));
+static REL : LazyLock<Regex> = LazyLock::new(|| attribute_regex("rel"));
+
+/// The value of an attribute of a tag.
+fn attribute(tag : &str, regex : &Regex) -> Option<String> {
+    let c = regex.captures(tag)?;
+    let value = c.get(1).or(c.get(2)).or(c.get(3))?;
+    Some(landing::unescape(value.as_str()))
+}
+
+/// The type of a resource from the extension of its url,
+/// when the server does not say.
+fn guess_type(url : &Url) -> &'static str {
+    let path = url.path().to_ascii_lowercase();
+    let extension = path.rsplit_once('.').map(|(_, e)| e).unwrap_or_default();
+    match extension {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "css" => "text/css",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// The resources of a page, fetched once each.
+struct Resources<'a> {
+    client : &'a Client,
+    /// The `data:` urls of the resources, `None` for
+    /// the ones that could not be fetched.
+    fetched : HashMap<Url, Option<String>>,
+}
+
+impl Resources<'_> {
+    /// Fetches a resource, with its announced type.
+    fn fetch(&self, url : &Url) -> Result<(Vec<u8>, Option<String>)> {
+        let answer = self.client.get(url.as_str())
+            .send()
+            .and_then(|r| r.error_for_status())
+            .with_context(|| format!("Fetching {url}"))?;
+        let kind = answer.headers().get(reqwest::header::CONTENT_TYPE)
+            .and_then(|t| t.to_str().ok())
+            .and_then(|t| t.split(';').next())
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty());
+        let mut bytes = vec![];
+        answer.take(MAX_RESOURCE + 1).read_to_end(&mut bytes)
+            .with_context(|| format!("Reading {url}"))?;
+        if bytes.len() as u64 > MAX_RESOURCE {
+            anyhow::bail!("{url} is larger than {} MB", MAX_RESOURCE / 1_000_000);
+        }
+        Ok((bytes, kind))
+    }
+
+    /// The `data:` url of a resource.
+    fn data_url(&mut self, url : &Url) -> Option<String> {
+        if let Some(data) = self.fetched.get(url) {
+            return data.clone();
+        }
+        let data = match self.fetch(url) {
+            Ok((bytes, kind)) => {
+                let kind = kind.unwrap_or_else(|| guess_type(url).to_string());
+                let bytes = if kind == "text/css" {
+                    self.stylesheet(&String::from_utf8_lossy(&bytes), url).into_bytes()
+                } else {
+                    bytes
+                };
+                Some(format!("data:{kind};base64,{}", base64::engine::general_purpose::STANDARD.encode(bytes)))
+            }
+            Err(e) => {
+                log::warn!("Could not inline {url} in the snapshot: {e:#}");
+                None
+            }
+        };
+        self.fetched.insert(url.clone(), data.clone());
+        data
+    }
+
+    /// The inlined resource at `link`, relative to `base`, or its
+    /// absolute url if it cannot be fetched.
+    fn inline(&mut self, link : &str, base : &Url) -> Option<String> {
+        let link = link.trim();
+        if link.is_empty() || link.starts_with("data:") || link.starts_with('#') {
+            return None;
+        }
+        let url = base.join(link).ok()?;
+        Some(self.data_url(&url).unwrap_or_else(|| url.to_string()))
+    }
+
+    /// Inlines the urls of a stylesheet (read from `base`).
+    fn stylesheet(&mut self, css : &str, base : &Url) -> String {
+        CSS_URL.replace_all(css, |c : &Captures| {
+            match self.inline(&c[1], base) {
+                Some(inlined) => format!("url(\"{inlined}\")"),
+                None => c[0].to_string(),
+            }
+        }).into_owned()
+    }
+}
+
+/// Escapes a value to put between double quotes in an attribute.
+fn quote(value : &str) -> String {
+    format!("\"{}\"", value.replace('&', "&amp;").replace('"', "&quot;"))
+}
+
+/// The snapshot of the html of a page, fetched from `page`,
+/// fetching its resources with `client`.
+pub fn take(html : &str, page : &Url, client : &Client) -> String {
+    let base = BASE.find(html)
+        .and_then(|tag| attribute(tag.as_str(), &HREF))
+        .and_then(|href| page.join(&href).ok())
+        .unwrap_or_else(|| page.clone());
+    let mut resources = Resources { client, fetched: HashMap::new() };
+
+    let html = SCRIPT.replace_all(html, "");
+    let html = BASE.replace_all(&html, "");
+    let html = STYLE.replace_all(&html, |c : &Captures| {
+        format!("{}{}{}", &c[1], resources.stylesheet(&c[2], &base), &c[3])
+    });
+    let html = LINK.replace_all(&html, |c : &Captures| {
+        let tag = &c[0];
+        let rel = attribute(tag, &REL).unwrap_or_default().to_ascii_lowercase();
+        let Some(href) = attribute(tag, &HREF) else {
+            return tag.to_string();
+        };
+        if rel.split_whitespace().any(|r| r == "stylesheet") {
+            match base.join(&href).ok().and_then(|url| resources.fetch(&url).ok().map(|(css, _)| (url, css))) {
+                Some((url, css)) => format!("<style>{}</style>", resources.stylesheet(&String::from_utf8_lossy(&css), &url)),
+                None => tag.to_string(),
+            }
+        } else if rel.split_whitespace().any(|r| r == "icon") {
+            match resources.inline(&href, &base) {
+                Some(inlined) => HREF.replace(tag, format!(" href={}", quote(&inlined))).into_owned(),
+                None => tag.to_string(),
+            }
+        } else {
+            tag.to_string()
+        }
+    });
+    let html = IMAGE.replace_all(&html, |c : &Captures| {
+        let tag = SRCSET.replace_all(&c[0], "").into_owned();
+        // lazily loaded images give their source to a script
+        let Some(src) = attribute(&tag, &DATA_SRC).or_else(|| attribute(&tag, &SRC)) else {
+            return tag;
+        };
+        let Some(inlined) = resources.inline(&src, &base) else {
+            return tag;
+        };
+        let src = format!(" src={}", quote(&inlined));
+        if SRC.is_match(&tag) {
+            SRC.replace(&tag, src).into_owned()
+        } else {
+            tag.replacen("<img", &format!("<img{src}"), 1)
+        }
+    });
+    format!("{html}\n<!-- Snapshot of {page} taken by akl on {} -->\n", chrono::Utc::now().to_rfc3339())
+}
+
+/// The metadata of a page: the ones given to the indexers
+/// (see `landing`), and else its title, its `author` and
+/// its date of publication.
+pub fn metadata(html : &[u8]) -> PdfMetaData {
+    let mut met = landing::metadata(html);
+    let text = String::from_utf8_lossy(html);
+    if met.title.is_none() {
+        met.title = TITLE.captures(&text)
+            .map(|c| landing::unescape(&c[1].split_whitespace().collect::<Vec<&str>>().join(" ")))
+            .filter(|t| !t.is_empty());
+    }
+    if met.authors.is_empty() {
+        met.authors = landing::meta_content(&text, &["author", "article:author"])
+            .filter(|a| !a.starts_with("http"))
+            .map(|a| vec![crate::authors::display_name(&a)])
+            .unwrap_or_default();
+    }
+    if met.year.is_none() {
+        met.year = landing::meta_content(&text, &["article:published_time", "dc.date", "date"])
+            .and_then(|d| d.trim().get(..4).and_then(|y| y.parse().ok()));
+    }
+    met
+}
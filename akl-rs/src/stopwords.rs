@@ -0,0 +1,104 @@
+// Stop words of the titles.
+//
+// Words such as `the` or `of` carry no meaning in a filename and
+// are removed from the titles. Each language has its own list;
+// the language of a title is the one whose stop words it uses
+// the most, so that `Die Logik der Forschung` loses `die` and
+// `der` but `Why Die Young` keeps `die`. The lists can be
+// extended, and other languages added, in the configuration.
+//
+// Words are compared in the ascii form of `latex::to_ascii`,
+// hence `fur` and `uber` in the german list.
+
+use std::collections::{BTreeMap, HashSet};
+
+use crate::latex;
+
+const ENGLISH : &[&str] = &[
+    "a", "all", "an", "and", "any", "at", "by", "every", "for",
+    "from", "in", "of", "on", "one", "other", "some", "the", "this",
+    "to", "what", "when", "where", "why", "with",
+];
+
+const FRENCH : &[&str] = &[
+    "a", "au", "aux", "avec", "ce", "ces", "d", "dans", "de", "des",
+    "du", "en", "et", "l", "la", "le", "les", "ou", "par", "pour",
+    "quelques", "sur", "un", "une",
+];
+
+const GERMAN : &[&str] = &[
+    "am", "auf", "aus", "bei", "das", "dem", "den", "der", "des",
+    "die", "ein", "eine", "einem", "einen", "einer", "eines", "fur",
+    "im", "in", "mit", "oder", "uber", "und", "von", "zu", "zum", "zur",
+];
+
+/// Languages with a built-in list.
+const BUILTIN_LANGUAGES : &[(&str, &[&str])] = &[
+    ("en", ENGLISH),
+    ("fr", FRENCH),
+    ("de", GERMAN),
+];
+
+/// Is the word a stop word of any built-in language?
+pub fn is_common(word : &str) -> bool {
+    BUILTIN_LANGUAGES.iter().any(|(_, l)| l.contains(&word))
+}
+
+/// Lowercase ascii words of a title. Math markup is removed
+/// (it makes unreadable filenames), and elisions are split
+/// (`l'automate` gives `l` and `automate`).
+pub fn words(title : &str) -> Vec<String> {
+    let title = latex::strip_math(title).replace(['\'', '\u{2019}'], " ");
+    latex::to_ascii(&title)
+        .to_ascii_lowercase()
+        .split(|c : char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Stop words of the configured languages.
+#[derive(Debug, Clone)]
+pub struct StopWords {
+    /// Stop words of each language, in the configured order.
+    languages : Vec<HashSet<String>>,
+}
+
+impl StopWords {
+    /// Stop words of the given languages, with the additional
+    /// words of the configuration. Languages without a built-in
+    /// list only use the configured words.
+    pub fn new(languages : &[String], extra : &BTreeMap<String, Vec<String>>) -> Self {
+        let languages = languages.iter().map(|lang| {
+            let builtin = BUILTIN_LANGUAGES.iter()
+                .find(|(l, _)| l == lang)
+                .map(|(_, words)| *words)
+                .unwrap_or_default();
+            builtin.iter()
+                .map(|w| w.to_string())
+                .chain(extra.get(lang).into_iter().flatten().map(|w| w.to_lowercase()))
+                .collect()
+        }).collect();
+        StopWords { languages }
+    }
+
+    /// The stop words of the language of a list of words: the
+    /// language with the most stop words, the first configured
+    /// one on ties.
+    fn language_of(&self, words : &[String]) -> Option<&HashSet<String>> {
+        self.languages.iter()
+            .enumerate()
+            .max_by_key(|(i, stop)| {
+                (words.iter().filter(|w| stop.contains(*w)).count(), std::cmp::Reverse(*i))
+            })
+            .map(|(_, stop)| stop)
+    }
+
+    /// Removes the stop words of the language of the words.
+    pub fn significant(&self, words : Vec<String>) -> Vec<String> {
+        match self.language_of(&words) {
+            Some(stop) => words.into_iter().filter(|w| !stop.contains(w)).collect(),
+            None => words,
+        }
+    }
+}
@@ -0,0 +1,527 @@
+// Storage backends for the library index.
+//
+// The historical backend is a single yaml file, which is
+// simple to read and edit by hand, but has to be parsed and
+// rewritten completely on every invocation. The sqlite backend
+// stores the same documents, with indexed lookups on identifiers,
+// checksums and titles.
+//
+// Several akl processes may use the index at the same time. The
+// sqlite database locks itself. The yaml index is read under a
+// shared lock, and its changes are kept aside until it is saved:
+// the index is then read again under an exclusive lock, and the
+// changes applied to it, so that the changes of another process
+// that saved in the meantime are not lost.
+//
+// The yaml index is only written when it changed, to a temporary
+// file renamed over the index, so that a crash never leaves a
+// truncated index. The previous index is kept as `index.yaml.bak`.
+//
+// The index of a backend whose documents were copied to another one
+// (by `akl migrate`, or when a fresh sqlite index imports the yaml
+// index) is moved aside as `index.yaml.migrated` (or
+// `index.sqlite.migrated`), so that migrating back starts from an
+// empty index.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+use anyhow::{Result, Context};
+use rusqlite::OptionalExtension;
+
+use crate::Document;
+use crate::schema;
+use crate::lock;
+
+/// Available storage backends.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// A single index.yaml file.
+    #[default]
+    Yaml,
+    /// A sqlite database index.sqlite.
+    Sqlite,
+}
+
+/// Operations provided by a storage backend.
+pub trait Storage : std::fmt::Debug {
+    /// All the documents of the library.
+    fn documents(&self) -> Result<Vec<Document>>;
+
+    /// Finds a document having the given identifier.
+    fn find_by_identifier(&self, ident : &str) -> Result<Option<Document>>;
+
+    /// Finds a document with the given checksum.
+    fn find_by_checksum(&self, checksum : &str) -> Result<Option<Document>>;
+
+    /// Finds the documents with the given title (case insensitive).
+    fn find_by_title(&self, title : &str) -> Result<Vec<Document>>;
+
+    /// Adds a document to the library.
+    fn insert(&mut self, doc : &Document) -> Result<()>;
+
+    /// Removes a document from the library.
+    /// Removing a missing document is not an error.
+    fn remove(&mut self, doc : &Document) -> Result<()>;
+
+    /// Replaces a document of the library by a new version.
+    fn update(&mut self, old : &Document, new : &Document) -> Result<()> {
+        self.remove(old)?;
+        self.insert(new)
+    }
+
+    /// Persists the pending changes.
+    fn save(&mut self) -> Result<()>;
+}
+
+/// The index file of a backend in the given directory.
+fn index_file(backend : Backend, dir : &Path) -> PathBuf {
+    match backend {
+        Backend::Yaml => dir.join("index.yaml"),
+        Backend::Sqlite => dir.join("index.sqlite"),
+    }
+}
+
+/// Opens the index of the library located in the given directory,
+/// using the given backend.
+pub fn open_backend(backend : Backend, dir : &Path) -> Result<Box<dyn Storage>> {
+    let path = index_file(backend, dir);
+    match backend {
+        Backend::Yaml => {
+            Ok(Box::new(YamlStorage::open(&path)?))
+        }
+        Backend::Sqlite => {
+            Ok(Box::new(SqliteStorage::open(&path)?))
+        }
+    }
+}
+
+/// Last modification of the index of a backend, if it exists.
+pub fn modified(backend : Backend, dir : &Path) -> Option<std::time::SystemTime> {
+    std::fs::metadata(index_file(backend, dir)).and_then(|m| m.modified()).ok()
+}
+
+/// Moves aside the index of a backend whose documents
+/// were copied to another backend.
+pub fn retire(backend : Backend, dir : &Path) -> Result<()> {
+    let path = index_file(backend, dir);
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut aside = path.clone().into_os_string();
+    aside.push(".migrated");
+    std::fs::rename(&path, &aside)
+        .with_context(|| format!("Moving {path:?} aside"))
+}
+
+/// Opens the storage of the library located in the given directory.
+///
+/// When opening a sqlite index for the first time, the content
+/// of an existing yaml index is imported.
+pub fn open(backend : Backend, dir : &Path) -> Result<Box<dyn Storage>> {
+    let fresh = backend == Backend::Sqlite && !index_file(Backend::Sqlite, dir).exists();
+    let mut db = open_backend(backend, dir)?;
+    if fresh && index_file(Backend::Yaml, dir).exists() {
+        log::info!("Importing the yaml index into the sqlite index");
+        copy_documents(open_backend(Backend::Yaml, dir)?.as_ref(), db.as_mut())?;
+        retire(Backend::Yaml, dir)?;
+    }
+    Ok(db)
+}
+
+/// Copies all the documents from a storage to another one.
+pub fn copy_documents(from : &dyn Storage, to : &mut dyn Storage) -> Result<usize> {
+    let docs = from.documents()?;
+    for doc in &docs {
+        to.insert(doc)?;
+    }
+    to.save()?;
+    Ok(docs.len())
+}
+
+/// Are these two values describing the same document?
+fn same_document(a : &Document, b : &Document) -> bool {
+    a.filename == b.filename && a.checksum == b.checksum
+}
+
+/// Layout of the yaml index (see `schema`).
+#[derive(Serialize)]
+struct YamlIndex<'a> {
+    schema    : u32,
+    documents : &'a [Document],
+}
+
+/// Parses a yaml index, of any schema, upgrading its documents
+/// to the current schema. Returns the schema of the file.
+pub fn parse_yaml_index(content : &str) -> Result<(u32, Vec<Document>)> {
+    if content.trim().is_empty() {
+        return Ok((schema::VERSION, vec![]));
+    }
+    let value : serde_yaml::Value = serde_yaml::from_str(content)?;
+    let (version, documents) = match value {
+        serde_yaml::Value::Sequence(docs) => (1, docs),
+        serde_yaml::Value::Mapping(mut m) => {
+            let version = m.get("schema")
+                .and_then(serde_yaml::Value::as_u64)
+                .context("The index has no schema version")?;
+            let docs = match m.remove("documents") {
+                Some(serde_yaml::Value::Sequence(docs)) => docs,
+                None | Some(serde_yaml::Value::Null) => vec![],
+                Some(_) => anyhow::bail!("The documents of the index are not a list"),
+            };
+            (u32::try_from(version)?, docs)
+        }
+        _ => anyhow::bail!("The index is neither a list of documents nor a mapping"),
+    };
+    if version < schema::VERSION {
+        log::info!("Upgrading the index from schema {version} to schema {}", schema::VERSION);
+    }
+    let documents = documents.into_iter()
+        .map(|d| {
+            let mut d = serde_json::to_value(d)?;
+            schema::upgrade(&mut d, version)?;
+            Ok(serde_json::from_value(d)?)
+        })
+        .collect::<Result<Vec<Document>>>()?;
+    Ok((version, documents))
+}
+
+/// Writes a yaml index, with the current schema.
+pub fn write_yaml_index<W : std::io::Write>(out : W, documents : &[Document]) -> Result<()> {
+    serde_yaml::to_writer(out, &YamlIndex { schema: schema::VERSION, documents })?;
+    Ok(())
+}
+
+/// Path of the backup of the previous version of a yaml index.
+pub fn backup_path(path : &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+/// Replaces a yaml index by a new version, keeping the previous
+/// one as a backup. The new version is written to a temporary file
+/// of the same directory, and then renamed over the index.
+fn replace_yaml_index(path : &Path, documents : &[Document]) -> Result<()> {
+    let dir = path.parent().context("Finding the directory of the index")?;
+    let mut file = tempfile::NamedTempFile::new_in(dir)
+        .context("Creating a temporary index")?;
+    write_yaml_index(file.as_file_mut(), documents)
+        .context("Writing the yaml index")?;
+    file.as_file().sync_all()
+        .context("Writing the yaml index")?;
+    if path.exists() {
+        // the backup keeps the file being replaced
+        let backup = backup_path(path);
+        let _ = std::fs::remove_file(&backup);
+        std::fs::hard_link(path, &backup)
+            .or_else(|_| std::fs::copy(path, &backup).map(|_| ()))
+            .context("Backing up the previous index")?;
+    }
+    file.persist(path)
+        .context("Replacing the yaml index")?;
+    Ok(())
+}
+
+/// A change to the yaml index, not saved yet.
+#[derive(Debug)]
+enum Change {
+    Insert(Document),
+    Remove(Document),
+}
+
+/// The yaml index, fully loaded in memory.
+#[derive(Debug)]
+pub struct YamlStorage {
+    /// File path to the index.yaml file.
+    path    : PathBuf,
+
+    /// Content of the index.yaml file, parsed.
+    index   : Vec<Document>,
+
+    /// Schema of the index.yaml file.
+    version : u32,
+
+    /// Changes made since the index was read. The
+    /// index is only written when there are some.
+    pending : Vec<Change>,
+}
+
+/// Reads a yaml index, a missing file being an empty index.
+fn read_yaml_index(path : &Path) -> Result<(u32, Vec<Document>)> {
+    let content = std::fs::read_to_string(path).or_else(|e| {
+        if e.kind() == std::io::ErrorKind::NotFound { Ok(String::new()) } else { Err(e) }
+    }).context("Reading the yaml index")?;
+    parse_yaml_index(&content)
+        .with_context(|| format!("Parsing the yaml index {path:?}"))
+}
+
+impl YamlStorage {
+    pub fn open(path : &Path) -> Result<Self> {
+        let (version, index) = {
+            let _lock = lock::shared(path)?;
+            read_yaml_index(path)?
+        };
+        Ok(YamlStorage { path: path.into(), index, version, pending: vec![] })
+    }
+}
+
+impl Change {
+    fn apply(&self, index : &mut Vec<Document>) {
+        match self {
+            Change::Insert(doc) => {
+                index.retain(|d| !same_document(d, doc));
+                index.push(doc.clone());
+            }
+            Change::Remove(doc) => {
+                if let Some(i) = index.iter().position(|d| same_document(d, doc)) {
+                    index.swap_remove(i);
+                }
+            }
+        }
+    }
+}
+
+impl Storage for YamlStorage {
+    fn documents(&self) -> Result<Vec<Document>> {
+        Ok(self.index.clone())
+    }
+
+    fn find_by_identifier(&self, ident : &str) -> Result<Option<Document>> {
+        Ok(self.index.iter()
+               .find(|doc| doc.identifiers.iter().any(|i| i == ident))
+               .cloned())
+    }
+
+    fn find_by_checksum(&self, checksum : &str) -> Result<Option<Document>> {
+        Ok(self.index.iter()
+               .find(|doc| doc.checksum == checksum)
+               .cloned())
+    }
+
+    fn find_by_title(&self, title : &str) -> Result<Vec<Document>> {
+        let title = title.to_lowercase();
+        Ok(self.index.iter()
+               .filter(|doc| doc.title.to_lowercase() == title)
+               .cloned()
+               .collect())
+    }
+
+    fn insert(&mut self, doc : &Document) -> Result<()> {
+        schema::check_writable(self.version)?;
+        let change = Change::Insert(doc.clone());
+        change.apply(&mut self.index);
+        self.pending.push(change);
+        Ok(())
+    }
+
+    fn remove(&mut self, doc : &Document) -> Result<()> {
+        schema::check_writable(self.version)?;
+        let change = Change::Remove(doc.clone());
+        change.apply(&mut self.index);
+        self.pending.push(change);
+        Ok(())
+    }
+
+    /// Saving the changes to the yaml file, on top of
+    /// the changes saved by other processes.
+    fn save(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let _lock = lock::exclusive(&self.path)?;
+        let (version, mut index) = read_yaml_index(&self.path)?;
+        schema::check_writable(version)?;
+        for change in self.pending.drain(..) {
+            change.apply(&mut index);
+        }
+        replace_yaml_index(&self.path, &index)?;
+        self.index = index;
+        self.version = schema::VERSION;
+        Ok(())
+    }
+}
+
+/// The sqlite index.
+///
+/// Documents are stored as json values, next to indexed columns
+/// used for lookups, so that new document fields do not require
+/// changing the database layout.
+#[derive(Debug)]
+pub struct SqliteStorage {
+    conn    : rusqlite::Connection,
+
+    /// Schema of the database.
+    version : u32,
+}
+
+/// How long to wait for another process writing the database.
+const SQLITE_BUSY_TIMEOUT : std::time::Duration = std::time::Duration::from_secs(30);
+
+const SQLITE_SCHEMA : &str = "
+    CREATE TABLE IF NOT EXISTS documents (
+        id       INTEGER PRIMARY KEY,
+        checksum TEXT NOT NULL,
+        filename TEXT NOT NULL,
+        title    TEXT NOT NULL,
+        data     TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS identifiers (
+        ident    TEXT NOT NULL,
+        document INTEGER NOT NULL REFERENCES documents(id) ON DELETE CASCADE
+    );
+    CREATE INDEX IF NOT EXISTS documents_checksum ON documents(checksum);
+    CREATE INDEX IF NOT EXISTS documents_title ON documents(title COLLATE NOCASE);
+    CREATE INDEX IF NOT EXISTS identifiers_ident ON identifiers(ident);
+    CREATE INDEX IF NOT EXISTS identifiers_document ON identifiers(document);
+";
+
+/// Parses the json value of a document.
+fn document_of_row(row : &rusqlite::Row) -> rusqlite::Result<Document> {
+    let data : String = row.get(0)?;
+    serde_json::from_str(&data).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+impl SqliteStorage {
+    pub fn open(path : &Path) -> Result<Self> {
+        let conn = rusqlite::Connection::open(path)
+            .with_context(|| format!("Opening the sqlite index {path:?}"))?;
+        // wait for the other akl processes instead of failing
+        conn.busy_timeout(SQLITE_BUSY_TIMEOUT)?;
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SQLITE_SCHEMA)
+            .context("Creating the sqlite tables")?;
+        let mut db = SqliteStorage { conn, version: schema::VERSION };
+        db.upgrade().with_context(|| format!("Upgrading the sqlite index {path:?}"))?;
+        Ok(db)
+    }
+
+    /// Upgrades the documents of an older schema. The version 0
+    /// of a new database is the current schema, and the one of a
+    /// database with documents the schema 1.
+    fn upgrade(&mut self) -> Result<()> {
+        let version : u32 = self.conn.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+        let count : u32 = self.conn.query_row("SELECT COUNT(*) FROM documents", [], |r| r.get(0))?;
+        self.version = match version {
+            0 if count == 0 => schema::VERSION,
+            0 => 1,
+            v => v,
+        };
+        if self.version == schema::VERSION && version != 0 {
+            return Ok(());
+        }
+        if self.version < schema::VERSION {
+            log::info!("Upgrading the index from schema {} to schema {}", self.version, schema::VERSION);
+        }
+        let tx = self.conn.transaction()?;
+        if self.version < schema::VERSION {
+            let rows = tx.prepare("SELECT id, data FROM documents")?
+                .query_map([], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?)))?
+                .collect::<rusqlite::Result<Vec<(i64, String)>>>()?;
+            for (id, data) in rows {
+                let mut doc : serde_json::Value = serde_json::from_str(&data)?;
+                schema::upgrade(&mut doc, self.version)?;
+                tx.execute("UPDATE documents SET data = ?1 WHERE id = ?2", (doc.to_string(), id))?;
+                // the migrations may rewrite the identifiers
+                tx.execute("DELETE FROM identifiers WHERE document = ?1", [id])?;
+                for ident in doc["identifiers"].as_array().into_iter().flatten().filter_map(|i| i.as_str()) {
+                    tx.execute("INSERT INTO identifiers (ident, document) VALUES (?1, ?2)", (ident, id))?;
+                }
+            }
+        }
+        if self.version <= schema::VERSION {
+            tx.pragma_update(None, "user_version", schema::VERSION)?;
+            self.version = schema::VERSION;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Runs a query returning documents.
+    fn query(&self, sql : &str, param : &str) -> Result<Vec<Document>> {
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        let docs = stmt.query_map([param], document_of_row)?
+                       .collect::<rusqlite::Result<Vec<Document>>>()?;
+        Ok(docs)
+    }
+}
+
+/// Inserts the rows of a document.
+fn insert_document(conn : &rusqlite::Connection, doc : &Document) -> Result<()> {
+    conn.execute("INSERT INTO documents (checksum, filename, title, data) VALUES (?1, ?2, ?3, ?4)",
+                 (&doc.checksum, &doc.filename, &doc.title, serde_json::to_string(doc)?))?;
+    let id = conn.last_insert_rowid();
+    for ident in &doc.identifiers {
+        conn.execute("INSERT INTO identifiers (ident, document) VALUES (?1, ?2)", (ident, id))?;
+    }
+    Ok(())
+}
+
+/// Deletes the rows of a document, if any.
+fn delete_document(conn : &rusqlite::Connection, doc : &Document) -> Result<()> {
+    conn.execute("DELETE FROM documents WHERE filename = ?1 AND checksum = ?2",
+                 (&doc.filename, &doc.checksum))?;
+    Ok(())
+}
+
+impl Storage for SqliteStorage {
+    fn documents(&self) -> Result<Vec<Document>> {
+        let mut stmt = self.conn.prepare_cached("SELECT data FROM documents ORDER BY id")?;
+        let docs = stmt.query_map([], document_of_row)?
+                       .collect::<rusqlite::Result<Vec<Document>>>()?;
+        Ok(docs)
+    }
+
+    fn find_by_identifier(&self, ident : &str) -> Result<Option<Document>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT d.data FROM documents d
+             JOIN identifiers i ON i.document = d.id
+             WHERE i.ident = ?1 LIMIT 1")?;
+        Ok(stmt.query_row([ident], document_of_row).optional()?)
+    }
+
+    fn find_by_checksum(&self, checksum : &str) -> Result<Option<Document>> {
+        Ok(self.query("SELECT data FROM documents WHERE checksum = ?1 LIMIT 1", checksum)?
+               .into_iter().next())
+    }
+
+    fn find_by_title(&self, title : &str) -> Result<Vec<Document>> {
+        self.query("SELECT data FROM documents WHERE title = ?1 COLLATE NOCASE", title)
+    }
+
+    /// Inserting a document replaces the rows of the same document,
+    /// as in the yaml index.
+    fn insert(&mut self, doc : &Document) -> Result<()> {
+        schema::check_writable(self.version)?;
+        let tx = self.conn.transaction()?;
+        delete_document(&tx, doc)?;
+        insert_document(&tx, doc)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn remove(&mut self, doc : &Document) -> Result<()> {
+        schema::check_writable(self.version)?;
+        delete_document(&self.conn, doc)
+    }
+
+    /// The old version is removed and the new one inserted in a single
+    /// transaction, so that the document is never missing from the index.
+    fn update(&mut self, old : &Document, new : &Document) -> Result<()> {
+        schema::check_writable(self.version)?;
+        let tx = self.conn.transaction()?;
+        delete_document(&tx, old)?;
+        delete_document(&tx, new)?;
+        insert_document(&tx, new)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Every operation is its own transaction, there is nothing left to do.
+    fn save(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+// Content-addressed store of the original files.
+//
+// The original files live under their sha256, `raw/ab/cdef….pdf`
+// (with the extension of their format, see `format`):
+// the filename of a document is only metadata of the index, so it is
+// never too long for the filesystem and renaming a document leaves
+// its original file alone; the same file is stored once; and checking
+// a file is comparing its hash with its name.
+//
+// Documents imported before the store keep their original file under
+// `raw/<filename>` until `akl verify` moves it to the store.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, Context};
+
+use crate::Document;
+use crate::format::Format;
+
+/// Path of the file with the given hash in the store.
+pub fn path(dir : &Path, hash : &str, format : Format) -> PathBuf {
+    let (shard, rest) = hash.split_at(hash.len().min(2));
+    dir.join(shard).join(format!("{rest}.{}", format.extension()))
+}
+
+/// Path of the original file of a document outside of the store.
+pub fn legacy_path(dir : &Path, doc : &Document) -> PathBuf {
+    dir.join(&doc.filename)
+}
+
+/// Where the original file of a document is, or should be.
+pub fn locate(dir : &Path, doc : &Document) -> PathBuf {
+    let legacy = legacy_path(dir, doc);
+    match &doc.raw_checksum {
+        Some(hash) if !legacy.exists() => path(dir, hash, doc.format),
+        _ => legacy,
+    }
+}
+
+/// Where the original file of a document should be written,
+/// creating the directory of its shard.
+pub fn target(dir : &Path, doc : &Document) -> Result<PathBuf> {
+    let path = match &doc.raw_checksum {
+        Some(hash) => path(dir, hash, doc.format),
+        None => legacy_path(dir, doc),
+    };
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Creating {parent:?}"))?;
+    }
+    Ok(path)
+}
+
+/// Moves a file into the store, and returns its hash. When the
+/// store already has the same file, the new copy is deleted.
+pub fn put(dir : &Path, file : &Path, format : Format) -> Result<String> {
+    let hash = crate::verify::file_checksum(file)?;
+    let stored = path(dir, &hash, format);
+    if stored.exists() {
+        log::info!("The store already has {file:?}");
+        std::fs::remove_file(file)
+            .with_context(|| format!("Removing {file:?}"))?;
+        return Ok(hash);
+    }
+    if let Some(parent) = stored.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Creating {parent:?}"))?;
+    }
+    std::fs::rename(file, &stored)
+        .with_context(|| format!("Moving {file:?} to the store"))?;
+    Ok(hash)
+}
+
+/// All the files of the store, and the ones left outside of it.
+pub fn files(dir : &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Listing {dir:?}"))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            for entry in std::fs::read_dir(&path).with_context(|| format!("Listing {path:?}"))? {
+                let path = entry?.path();
+                if path.is_file() {
+                    files.push(path);
+                }
+            }
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
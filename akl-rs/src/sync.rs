@@ -0,0 +1,281 @@
+// Synchronization of the library with a remote (see `remote`).
+//
+// The remote holds a manifest, `library.json`: the index entries of
+// the documents, and the sha256 hashes of their original and modified
+// files, which are stored once under `files/<sha256>`. Each machine
+// keeps in `sync.yaml`, next to the index, the manifest of the remote
+// as of its last synchronization: comparing it with the library and
+// with the current manifest of the remote tells what changed on each
+// side since then.
+//
+// An entry changed on one side only takes the version of that side.
+// An entry removed on one side and edited on the other is kept. An
+// entry edited on both sides is merged field by field: the lists
+// (tags, identifiers, keywords, former filenames) are united, and the
+// other fields take the value of the preferred side (the local one,
+// unless `--prefer remote`). The files follow the same rules.
+//
+// `akl sync pull` applies the changes of the remote to the library,
+// and `akl sync push` sends the library to the remote, provided that
+// nobody pushed since the last pull.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, Context};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+
+use crate::Document;
+
+/// Key of the manifest on the remote.
+pub const MANIFEST : &str = "library.json";
+
+/// Key of a file on the remote.
+pub fn file_key(hash : &str) -> String {
+    format!("files/{hash}")
+}
+
+/// Hashes of the files of a document.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Files {
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub raw : Option<String>,
+
+    #[serde(rename = "mod", skip_serializing_if = "Option::is_none", default)]
+    pub modified : Option<String>,
+}
+
+/// State of a library: its documents and their files.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Manifest {
+    /// Number of pushes the remote received.
+    #[serde(default)]
+    pub generation : u64,
+
+    #[serde(default)]
+    pub documents : Vec<Document>,
+
+    /// Hashes of the files, by checksum of the document.
+    #[serde(default)]
+    pub files : BTreeMap<String, Files>,
+}
+
+impl Manifest {
+    pub fn document(&self, checksum : &str) -> Option<&Document> {
+        self.documents.iter().find(|d| d.checksum == checksum)
+    }
+
+    pub fn files_of(&self, checksum : &str) -> Files {
+        self.files.get(checksum).cloned().unwrap_or_default()
+    }
+
+    /// All the file hashes of the manifest.
+    pub fn hashes(&self) -> BTreeSet<&str> {
+        self.files.values()
+            .flat_map(|f| [f.raw.as_deref(), f.modified.as_deref()])
+            .flatten()
+            .collect()
+    }
+
+    pub fn parse(data : &[u8]) -> Result<Self> {
+        serde_json::from_slice(data).context("Parsing the manifest of the remote")
+    }
+}
+
+/// Side preferred for the fields changed on both sides.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Local,
+    Remote,
+}
+
+/// The hash of a local file, as of its last modification.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct CachedHash {
+    size     : u64,
+    modified : i64,
+    hash     : String,
+}
+
+/// What a library knows of its last synchronization.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SyncState {
+    #[serde(skip)]
+    path : PathBuf,
+
+    /// The manifest of the remote, as of the last synchronization.
+    #[serde(default)]
+    pub base : Manifest,
+
+    /// Hashes of the local files, by path.
+    #[serde(default)]
+    hashes : BTreeMap<PathBuf, CachedHash>,
+}
+
+impl SyncState {
+    pub fn load(path : &Path) -> Result<Self> {
+        let mut state : SyncState = if path.exists() {
+            let file = std::fs::File::open(path)
+                .context("Opening the synchronization state")?;
+            serde_yaml::from_reader(file)
+                .with_context(|| format!("Parsing the synchronization state {path:?}"))?
+        } else {
+            SyncState::default()
+        };
+        state.path = path.into();
+        Ok(state)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let file = std::fs::File::create(&self.path)
+            .context("Opening the synchronization state")?;
+        serde_yaml::to_writer(file, self)
+            .context("Writing the synchronization state")
+    }
+
+    /// The sha256 of a file, computed again only when
+    /// the file changed since the last time.
+    pub fn hash(&mut self, path : &Path) -> Result<Option<String>> {
+        let Ok(meta) = std::fs::metadata(path) else {
+            return Ok(None);
+        };
+        let modified = meta.modified()
+            .map(|t| chrono::DateTime::<chrono::Utc>::from(t).timestamp_nanos_opt().unwrap_or_default())
+            .unwrap_or_default();
+        if let Some(c) = self.hashes.get(path).filter(|c| c.size == meta.len() && c.modified == modified) {
+            return Ok(Some(c.hash.clone()));
+        }
+        let hash = crate::verify::file_checksum(path)?;
+        self.hashes.insert(path.into(), CachedHash { size: meta.len(), modified, hash: hash.clone() });
+        Ok(Some(hash))
+    }
+}
+
+/// Checks that data downloaded from the remote has the expected hash.
+pub fn check_hash(data : &[u8], hash : &str) -> Result<()> {
+    let actual = format!("{:x}", Sha256::digest(data));
+    if actual != hash {
+        anyhow::bail!("The file {hash} of the remote is corrupted (its hash is {actual})");
+    }
+    Ok(())
+}
+
+/// Are two entries of the index the same?
+fn same(a : Option<&Document>, b : Option<&Document>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => crate::journal::same(a, b),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Unites two lists, keeping the order of the first one.
+fn unite(first : &[String], second : &[String]) -> Vec<String> {
+    let mut result = first.to_vec();
+    result.extend(second.iter().filter(|s| !first.contains(s)).cloned());
+    result
+}
+
+/// Merges two versions of an entry edited on both sides.
+fn merge_entries(local : &Document, remote : &Document, prefer : Side) -> Document {
+    let (preferred, other) = match prefer {
+        Side::Local => (local, remote),
+        Side::Remote => (remote, local),
+    };
+    let mut merged = preferred.clone();
+    merged.tags = unite(&preferred.tags, &other.tags);
+    merged.identifiers = unite(&preferred.identifiers, &other.identifiers);
+    merged.keywords = unite(&preferred.keywords, &other.keywords);
+    for (author, orcid) in &other.orcids {
+        if merged.authors.contains(author) {
+            merged.orcids.entry(author.clone()).or_insert_with(|| orcid.clone());
+        }
+    }
+    merged.former_filenames = unite(&preferred.former_filenames, &other.former_filenames);
+    merged.former_filenames.retain(|f| *f != merged.filename);
+    if merged.r#abstract.is_none() {
+        merged.r#abstract = other.r#abstract.clone();
+    }
+    merged
+}
+
+/// Three-way choice of a value changed since `base` on either side.
+fn choose<T : Clone + PartialEq>(base : &T, local : &T, remote : &T, prefer : Side) -> T {
+    if local == remote || remote == base {
+        local.clone()
+    } else if local == base {
+        remote.clone()
+    } else {
+        match prefer {
+            Side::Local => local.clone(),
+            Side::Remote => remote.clone(),
+        }
+    }
+}
+
+/// What changed for a document since the last synchronization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Unchanged,
+    Local,
+    Remote,
+    Both,
+}
+
+/// Result of a three-way merge.
+#[derive(Debug, Default)]
+pub struct Merge {
+    pub manifest : Manifest,
+
+    /// What changed, by checksum.
+    pub status : BTreeMap<String, Status>,
+}
+
+/// Merges the changes of the library and of the remote
+/// since their common state `base`.
+pub fn merge(base : &Manifest, local : &Manifest, remote : &Manifest, prefer : Side) -> Merge {
+    let checksums : BTreeSet<&String> = [base, local, remote].iter()
+        .flat_map(|m| m.documents.iter().map(|d| &d.checksum))
+        .collect();
+    let mut result = Merge {
+        manifest: Manifest { generation: remote.generation, ..Manifest::default() },
+        ..Merge::default()
+    };
+    for checksum in checksums {
+        let (b, l, r) = (base.document(checksum), local.document(checksum), remote.document(checksum));
+        let (bf, lf, rf) = (base.files_of(checksum), local.files_of(checksum), remote.files_of(checksum));
+        let local_changed = !same(b, l) || bf != lf;
+        let remote_changed = !same(b, r) || bf != rf;
+        let entry = if same(l, r) || same(b, r) {
+            l.cloned()
+        } else if same(b, l) {
+            r.cloned()
+        } else {
+            match (l, r) {
+                (Some(l), Some(r)) => Some(merge_entries(l, r, prefer)),
+                // edits win over removals
+                (Some(d), None) | (None, Some(d)) => Some(d.clone()),
+                (None, None) => None,
+            }
+        };
+        let status = match (local_changed, remote_changed) {
+            (false, false) => Status::Unchanged,
+            (true, false) => Status::Local,
+            (false, true) => Status::Remote,
+            (true, true) if same(l, r) && lf == rf => Status::Unchanged,
+            (true, true) => Status::Both,
+        };
+        result.status.insert(checksum.clone(), status);
+        if let Some(entry) = entry {
+            let files = Files {
+                raw: choose(&bf.raw, &lf.raw, &rf.raw, prefer).or(lf.raw).or(rf.raw),
+                modified: choose(&bf.modified, &lf.modified, &rf.modified, prefer).or(lf.modified).or(rf.modified),
+            };
+            result.manifest.files.insert(checksum.clone(), files);
+            result.manifest.documents.push(entry);
+        }
+    }
+    result
+}
@@ -0,0 +1,120 @@
+// Output templates of the find command.
+//
+// `akl find --template "{title} — {authors} ({year})\t{mod_path}"`
+// prints one line per document in the format expected by a picker
+// (rofi, fzf, dmenu, telescope…). The literal text of a template
+// understands the escapes `\t`, `\n`, `\0` and `\\`, and `{{`, `}}`
+// for braces. Tabs and newlines inside the values are replaced by
+// spaces, so that each document stays on its own line.
+
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::Document;
+
+/// Placeholders of the templates.
+const PLACEHOLDERS : &[&str] = &[
+    "title", "authors", "first_author", "year", "venue", "context",
+    "tags", "keywords", "identifiers", "status", "type", "id", "checksum", "filename",
+    "mod_path", "raw_path",
+];
+
+/// A parsed template: literal text and placeholders.
+enum Part {
+    Text(String),
+    Field(&'static str),
+}
+
+/// An output template.
+pub struct Template {
+    parts : Vec<Part>,
+}
+
+/// Replaces the escapes of the literal text of a template.
+fn unescape(text : &str, template : &str) -> Result<String> {
+    let mut result = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            Some('0') => result.push('\0'),
+            Some('\\') => result.push('\\'),
+            Some(c) => anyhow::bail!("Unknown escape \\{c} in the template {template}"),
+            None => anyhow::bail!("Dangling \\ at the end of the template {template}"),
+        }
+    }
+    Ok(result)
+}
+
+impl Template {
+    pub fn parse(template : &str) -> Result<Self> {
+        let mut parts = vec![];
+        let mut text = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find(['{', '}']) {
+            text.push_str(&rest[..start]);
+            rest = &rest[start..];
+            if let Some(r) = rest.strip_prefix("{{").or(rest.strip_prefix("}}")) {
+                text.push_str(&rest[..1]);
+                rest = r;
+                continue;
+            }
+            if rest.starts_with('}') {
+                anyhow::bail!("Unmatched }} in the template {template} (write }}}} for a brace)");
+            }
+            let end = rest.find('}')
+                .ok_or_else(|| anyhow::anyhow!("Unclosed placeholder in the template {template}"))?;
+            let name = &rest[1..end];
+            let field = PLACEHOLDERS.iter()
+                .find(|p| **p == name)
+                .ok_or_else(|| anyhow::anyhow!("Unknown placeholder {{{name}}} in the template \
+                                               (expected one of {})", PLACEHOLDERS.join(", ")))?;
+            parts.push(Part::Text(unescape(&std::mem::take(&mut text), template)?));
+            parts.push(Part::Field(field));
+            rest = &rest[end + 1..];
+        }
+        text.push_str(rest);
+        parts.push(Part::Text(unescape(&text, template)?));
+        Ok(Template { parts })
+    }
+
+    /// Renders the template for a document, whose files are
+    /// at the given paths.
+    pub fn render(&self, doc : &Document, mod_path : &Path, raw_path : &Path) -> String {
+        self.parts.iter()
+            .map(|p| match p {
+                Part::Text(t) => t.clone(),
+                Part::Field(f) => field(doc, f, mod_path, raw_path).replace(['\t', '\n', '\r', '\0'], " "),
+            })
+            .collect()
+    }
+}
+
+/// Value of a placeholder for a document.
+fn field(doc : &Document, name : &str, mod_path : &Path, raw_path : &Path) -> String {
+    match name {
+        "title" => doc.title.clone(),
+        "authors" => doc.authors.join(", "),
+        "first_author" => doc.authors.first().cloned().unwrap_or_default(),
+        "year" => doc.year.to_string(),
+        "venue" => doc.context.first().cloned().unwrap_or_default(),
+        "context" => doc.context.join(", "),
+        "tags" => doc.tags.join(", "),
+        "keywords" => doc.keywords.join(", "),
+        "identifiers" => doc.identifiers.join(", "),
+        "status" => doc.status.map(|s| s.to_string()).unwrap_or_default(),
+        "type" => doc.doc_type.map(|t| t.to_string()).unwrap_or_default(),
+        "id" => doc.short_id(),
+        "checksum" => doc.checksum.clone(),
+        "filename" => doc.filename.clone(),
+        "mod_path" => mod_path.to_string_lossy().into(),
+        "raw_path" => raw_path.to_string_lossy().into(),
+        _ => String::new(),
+    }
+}
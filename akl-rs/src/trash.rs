@@ -0,0 +1,164 @@
+// Trash of the library.
+//
+// Removed documents are not deleted right away: their raw and
+// modified files are moved to a trash directory, next to their
+// index entry, so that an accidental removal can be undone.
+//
+// The files found without index entry by `akl gc` go to the trash
+// as well, grouped by collection, and can be moved back by hand.
+//
+// Layout: trash/<checksum>/{entry.yaml, raw.pdf, mod.pdf}
+//         trash/orphans/<date>/{raw,mod}/<file>
+
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+use anyhow::{Result, Context};
+use chrono::{DateTime, Utc};
+
+use crate::Document;
+
+/// Directory of the orphaned files, which cannot be a checksum.
+const ORPHANS : &str = "orphans";
+
+/// Format of the names of the collections of orphaned files.
+const ORPHANS_DATE : &str = "%Y-%m-%dT%H-%M-%S";
+
+/// A document in the trash.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TrashEntry {
+    /// When the document was removed from the library.
+    pub removed  : DateTime<Utc>,
+
+    /// The index entry of the document.
+    pub document : Document,
+}
+
+/// The trash directory.
+#[derive(Debug, Clone)]
+pub struct Trash {
+    path : PathBuf,
+}
+
+/// Moves a file if it exists.
+fn move_if_exists(from : &Path, to : &Path) -> Result<()> {
+    if from.exists() {
+        std::fs::rename(from, to)
+            .with_context(|| format!("Moving {from:?} to {to:?}"))?;
+    }
+    Ok(())
+}
+
+impl Trash {
+    pub fn new(path : &Path) -> Self {
+        Trash { path: path.into() }
+    }
+
+    /// Directory of the trashed document with the given checksum.
+    fn entry_dir(&self, checksum : &str) -> PathBuf {
+        self.path.join(checksum)
+    }
+
+    /// Moves a document and its files to the trash.
+    pub fn put(&self, doc : &Document, raw : &Path, modified : &Path) -> Result<()> {
+        let dir = self.entry_dir(&doc.checksum);
+        std::fs::create_dir_all(&dir)
+            .context("Creating the trash directory")?;
+        let entry = TrashEntry { removed: Utc::now(), document: doc.clone() };
+        let file = std::fs::File::create(dir.join("entry.yaml"))?;
+        serde_yaml::to_writer(file, &entry)
+            .context("Writing the trash entry")?;
+        move_if_exists(raw, &dir.join("raw.pdf"))?;
+        move_if_exists(modified, &dir.join("mod.pdf"))?;
+        Ok(())
+    }
+
+    /// All the documents in the trash, oldest removal first.
+    pub fn entries(&self) -> Result<Vec<TrashEntry>> {
+        let mut entries = vec![];
+        if !self.path.exists() {
+            return Ok(entries);
+        }
+        for dir in std::fs::read_dir(&self.path)? {
+            let dir = dir?.path();
+            if dir.ends_with(ORPHANS) {
+                continue;
+            }
+            let path = dir.join("entry.yaml");
+            match std::fs::File::open(&path).map(serde_yaml::from_reader) {
+                Ok(Ok(entry)) => { entries.push(entry); }
+                _ => { log::warn!("Ignoring invalid trash entry {path:?}"); }
+            }
+        }
+        entries.sort_by_key(|e : &TrashEntry| e.removed);
+        Ok(entries)
+    }
+
+    /// Finds a trashed document by checksum, filename, title or identifier.
+    pub fn find(&self, key : &str) -> Result<TrashEntry> {
+        self.entries()?.into_iter()
+            .find(|e| {
+                let d = &e.document;
+                d.checksum == key || d.filename == key ||
+                d.title.eq_ignore_ascii_case(key) ||
+                d.identifiers.iter().any(|i| i == key)
+            })
+            .with_context(|| format!("Could not find {key} in the trash"))
+    }
+
+    /// Moves the files of a trashed document back to the given
+    /// locations, and removes it from the trash.
+    pub fn restore(&self, entry : &TrashEntry, raw : &Path, modified : &Path) -> Result<()> {
+        let dir = self.entry_dir(&entry.document.checksum);
+        move_if_exists(&dir.join("raw.pdf"), raw)?;
+        move_if_exists(&dir.join("mod.pdf"), modified)?;
+        std::fs::remove_dir_all(&dir)
+            .context("Removing the trash entry")
+    }
+
+    /// Definitively deletes a trashed document.
+    pub fn delete(&self, entry : &TrashEntry) -> Result<()> {
+        std::fs::remove_dir_all(self.entry_dir(&entry.document.checksum))
+            .context("Removing the trash entry")
+    }
+
+    /// Moves files without index entry to the trash, in the
+    /// `kind` (raw or mod) directory of the collection made at `when`.
+    pub fn put_orphans(&self, files : &[PathBuf], kind : &str, when : DateTime<Utc>) -> Result<()> {
+        let dir = self.path.join(ORPHANS)
+            .join(when.format(ORPHANS_DATE).to_string())
+            .join(kind);
+        for file in files {
+            let name = file.file_name()
+                .with_context(|| format!("{file:?} has no file name"))?;
+            std::fs::create_dir_all(&dir)
+                .context("Creating the trash directory")?;
+            move_if_exists(file, &dir.join(name))?;
+        }
+        Ok(())
+    }
+
+    /// Definitively deletes the documents, and the orphaned files,
+    /// removed before the given date, and returns the documents.
+    pub fn purge(&self, before : DateTime<Utc>) -> Result<Vec<TrashEntry>> {
+        let expired : Vec<TrashEntry> = self.entries()?.into_iter()
+            .filter(|e| e.removed < before)
+            .collect();
+        for entry in &expired {
+            self.delete(entry)?;
+        }
+        let orphans = self.path.join(ORPHANS);
+        if orphans.exists() {
+            for dir in std::fs::read_dir(&orphans)? {
+                let dir = dir?.path();
+                let removed = dir.file_name()
+                    .and_then(|n| chrono::NaiveDateTime::parse_from_str(&n.to_string_lossy(), ORPHANS_DATE).ok());
+                if removed.is_some_and(|r| r.and_utc() < before) {
+                    std::fs::remove_dir_all(&dir)
+                        .with_context(|| format!("Removing {dir:?}"))?;
+                }
+            }
+        }
+        Ok(expired)
+    }
+}
@@ -0,0 +1,37 @@
+// Open access copies of the dois, from Unpaywall.
+//
+// Importing a doi downloads what doi.org leads to, which for most
+// publishers is a landing page or a paywall. Unpaywall knows the open
+// access copies of a doi (on the site of the publisher, an accepted
+// manuscript in a repository, a preprint), and they are tried first,
+// the best one first. Unpaywall asks for the email address of its
+// users: it is `contact_email` of the configuration.
+
+use anyhow::{Result, Context};
+use serde_json::Value;
+
+/// Urls of the pdf files of the open access copies of a doi,
+/// the best copy first.
+pub fn pdf_urls(doi : &str, email : &str) -> Result<Vec<String>> {
+    let query = serde_urlencoded::to_string([("email", email)])?;
+    log::debug!("Querying Unpaywall for {doi}");
+    let answer = crate::polite::get(&format!("https://api.unpaywall.org/v2/{doi}?{query}"))
+        .send()
+        .context("Querying the Unpaywall API")?;
+    if answer.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(vec![]);
+    }
+    let answer : Value = answer.error_for_status()
+        .context("Querying the Unpaywall API")?
+        .json()
+        .context("Parsing the Unpaywall API answer")?;
+    let locations = std::iter::once(&answer["best_oa_location"])
+        .chain(answer["oa_locations"].as_array().into_iter().flatten());
+    let mut urls : Vec<String> = vec![];
+    for url in locations.filter_map(|l| l["url_for_pdf"].as_str()) {
+        if !urls.iter().any(|u| u == url) {
+            urls.push(url.to_string());
+        }
+    }
+    Ok(urls)
+}
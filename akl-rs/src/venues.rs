@@ -0,0 +1,250 @@
+// Venue normalization.
+//
+// The `context` of a document is typically a conference or a journal,
+// but it is written in many different ways depending on where the
+// metadata comes from ("ICALP 2023", "50th International Colloquium on
+// Automata, Languages, and Programming", "icalp"). This module maps
+// these raw strings to a canonical venue, first using a bundled table
+// and then asking DBLP, whose answers are cached on disk.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Deserialize};
+use anyhow::{Result, Context};
+
+/// A canonical venue.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Venue {
+    /// Short name of the venue, used as the canonical
+    /// `context` of a document (ICALP, LICS, JACM).
+    pub acronym : String,
+
+    /// Full name of the venue, suitable for a `booktitle`
+    /// or a `journal` BibTeX field.
+    pub name : String,
+}
+
+/// Bundled table of venues: (acronym, full name, aliases).
+///
+/// The aliases are matched using the same normalization as the full names,
+/// so they only need to be listed when they are not derivable from
+/// the acronym or the name.
+const KNOWN_VENUES : &[(&str, &str, &[&str])] = &[
+    ("ICALP",   "International Colloquium on Automata, Languages and Programming", &[]),
+    ("LICS",    "ACM/IEEE Symposium on Logic in Computer Science",
+                &["Logic in Computer Science", "IEEE Symposium on Logic in Computer Science"]),
+    ("STACS",   "Symposium on Theoretical Aspects of Computer Science", &[]),
+    ("MFCS",    "International Symposium on Mathematical Foundations of Computer Science", &[]),
+    ("CONCUR",  "International Conference on Concurrency Theory", &[]),
+    ("CSL",     "Annual Conference on Computer Science Logic", &["Computer Science Logic"]),
+    ("FoSSaCS", "International Conference on Foundations of Software Science and Computation Structures", &[]),
+    ("FSTTCS",  "Foundations of Software Technology and Theoretical Computer Science", &[]),
+    ("FSCD",    "International Conference on Formal Structures for Computation and Deduction", &[]),
+    ("FOCS",    "IEEE Symposium on Foundations of Computer Science",
+                &["IEEE Annual Symposium on Foundations of Computer Science"]),
+    ("STOC",    "ACM Symposium on Theory of Computing", &["Symposium on Theory of Computing"]),
+    ("SODA",    "ACM-SIAM Symposium on Discrete Algorithms", &["Symposium on Discrete Algorithms"]),
+    ("POPL",    "ACM SIGPLAN Symposium on Principles of Programming Languages",
+                &["Principles of Programming Languages"]),
+    ("ICFP",    "ACM SIGPLAN International Conference on Functional Programming", &[]),
+    ("PODS",    "ACM Symposium on Principles of Database Systems",
+                &["Principles of Database Systems"]),
+    ("ICDT",    "International Conference on Database Theory", &[]),
+    ("DLT",     "International Conference on Developments in Language Theory", &[]),
+    ("CAV",     "International Conference on Computer Aided Verification", &[]),
+    ("TACAS",   "International Conference on Tools and Algorithms for the Construction and Analysis of Systems", &[]),
+    ("ESOP",    "European Symposium on Programming", &[]),
+    ("IJCAR",   "International Joint Conference on Automated Reasoning", &[]),
+    ("CADE",    "International Conference on Automated Deduction", &[]),
+    ("CCC",     "Computational Complexity Conference", &[]),
+    ("JACM",    "Journal of the ACM", &["J. ACM", "JACM"]),
+    ("LMCS",    "Logical Methods in Computer Science", &["Log. Methods Comput. Sci."]),
+    ("TOCL",    "ACM Transactions on Computational Logic", &["ACM Trans. Comput. Log."]),
+    ("TCS",     "Theoretical Computer Science", &["Theor. Comput. Sci."]),
+    ("IC",      "Information and Computation", &["Inf. Comput."]),
+    ("SICOMP",  "SIAM Journal on Computing", &["SIAM J. Comput."]),
+    ("JCSS",    "Journal of Computer and System Sciences", &["J. Comput. Syst. Sci."]),
+    ("MSCS",    "Mathematical Structures in Computer Science", &["Math. Struct. Comput. Sci."]),
+    ("FI",      "Fundamenta Informaticae", &["Fundam. Informaticae"]),
+    ("ToC",     "Theory of Computing", &[]),
+    ("JSL",     "Journal of Symbolic Logic", &["J. Symb. Log."]),
+];
+
+/// Words that do not help distinguishing venues and
+/// are dropped during normalization.
+const NOISE_WORDS : &[&str] = &[
+    "proceedings", "proc", "of", "the", "on", "in", "and",
+    "annual", "international", "acm", "ieee", "siam", "sigplan",
+    "sigact", "symposium", "conference", "colloquium", "workshop",
+];
+
+/// Normalizes a venue string to a comparison key:
+/// lowercase alphanumeric words, without noise words, years,
+/// ordinals ("50th") or volume numbers.
+fn venue_key(raw : &str) -> String {
+    raw.to_lowercase()
+       .split(|c : char| !c.is_alphanumeric())
+       .filter(|w| !w.is_empty())
+       .filter(|w| !NOISE_WORDS.contains(w))
+       .filter(|w| !w.starts_with(|c : char| c.is_ascii_digit()))
+       .collect::<Vec<&str>>()
+       .join(" ")
+}
+
+/// The bundled table as a list of venues.
+fn bundled_venues() -> impl Iterator<Item = (Venue, &'static [&'static str])> {
+    KNOWN_VENUES.iter().map(|(acronym, name, aliases)| {
+        (Venue { acronym: (*acronym).into(), name: (*name).into() }, *aliases)
+    })
+}
+
+/// Looks for a venue in the bundled table.
+///
+/// Compares the normalized string against the normalized acronyms,
+/// names and aliases. An acronym thus only matches when it is the whole
+/// string, up to years and noise words (e.g. "ICALP 2023", "Proc. LICS '22"),
+/// and not when it is one of the words of a title.
+pub fn lookup_bundled(raw : &str) -> Option<Venue> {
+    let key = venue_key(raw);
+    if key.is_empty() {
+        return None;
+    }
+
+    bundled_venues()
+        .find(|(v, aliases)| {
+            venue_key(&v.acronym) == key ||
+            venue_key(&v.name) == key ||
+            aliases.iter().any(|a| venue_key(a) == key)
+        })
+        .map(|(v, _)| v)
+}
+
+/// Answer of the DBLP venue search API.
+#[derive(Deserialize, Debug)]
+struct DblpAnswer {
+    result : DblpResult,
+}
+
+#[derive(Deserialize, Debug)]
+struct DblpResult {
+    hits : DblpHits,
+}
+
+#[derive(Deserialize, Debug)]
+struct DblpHits {
+    #[serde(default)]
+    hit : Vec<DblpHit>,
+}
+
+#[derive(Deserialize, Debug)]
+struct DblpHit {
+    info : DblpVenueInfo,
+}
+
+#[derive(Deserialize, Debug)]
+struct DblpVenueInfo {
+    venue   : String,
+    acronym : Option<String>,
+}
+
+/// Asks DBLP for a venue matching the raw string.
+/// Only the best hit is considered, and only if it has an acronym.
+fn lookup_dblp(raw : &str) -> Result<Option<Venue>> {
+    let query = serde_urlencoded::to_string([("q", raw), ("format", "json"), ("h", "1")])?;
+    let url = format!("https://dblp.org/search/venue/api?{query}");
+    log::debug!("Querying DBLP for venue {raw}");
+    let answer : DblpAnswer = crate::polite::get(&url)
+        .send()
+        .context("Querying the DBLP venue API")?
+        .json()
+        .context("Parsing the DBLP venue API answer")?;
+
+    Ok(answer.result.hits.hit.into_iter().next().and_then(|h| {
+        let DblpVenueInfo { venue, acronym } = h.info;
+        // DBLP puts the acronym at the end of the name "... (ICALP)"
+        let name = match venue.rfind(" (") {
+            Some(i) if venue.ends_with(')') => venue[..i].to_string(),
+            _ => venue,
+        };
+        acronym.map(|acronym| Venue { acronym, name })
+    }))
+}
+
+/// Venue normalizer, keeping a disk cache of the DBLP answers
+/// (including negative ones) to avoid querying DBLP repeatedly.
+pub struct VenueNormalizer {
+    /// Path to the cache file.
+    cache_path : PathBuf,
+
+    /// Raw venue key -> canonical venue (or None if unknown).
+    cache : HashMap<String, Option<Venue>>,
+
+    /// Whether the cache has to be written back.
+    dirty : bool,
+}
+
+impl VenueNormalizer {
+    /// Creates a normalizer using the cache stored in the given file.
+    /// A missing or unreadable cache is treated as empty.
+    pub fn new(cache_path : &Path) -> Self {
+        let cache = std::fs::File::open(cache_path)
+            .ok()
+            .and_then(|f| serde_yaml::from_reader(f).ok())
+            .unwrap_or_default();
+        VenueNormalizer { cache_path: cache_path.into(), cache, dirty: false }
+    }
+
+    /// Finds the canonical venue of a raw context string.
+    pub fn lookup(&mut self, raw : &str) -> Option<Venue> {
+        if let Some(v) = lookup_bundled(raw) {
+            return Some(v);
+        }
+        let key = venue_key(raw);
+        if key.is_empty() {
+            return None;
+        }
+        if let Some(v) = self.cache.get(&key) {
+            return v.clone();
+        }
+        match lookup_dblp(raw) {
+            Ok(v) => {
+                self.cache.insert(key, v.clone());
+                self.dirty = true;
+                v
+            }
+            Err(e) => {
+                // do not cache network failures
+                log::warn!("Could not query DBLP for venue {raw}: {e:?}");
+                None
+            }
+        }
+    }
+
+    /// Normalizes a list of context strings: known venues are replaced
+    /// by their acronym, other strings are kept as is. Duplicates
+    /// are removed while preserving the order.
+    pub fn normalize_context(&mut self, context : &[String]) -> Vec<String> {
+        let mut result : Vec<String> = vec![];
+        for raw in context {
+            let c = self.lookup(raw)
+                        .map(|v| v.acronym)
+                        .unwrap_or_else(|| raw.trim().to_string());
+            if !c.is_empty() && !result.contains(&c) {
+                result.push(c);
+            }
+        }
+        result
+    }
+
+    /// Writes the cache back to disk if needed.
+    pub fn save(&self) -> Result<()> {
+        if self.dirty {
+            let file = std::fs::File::create(&self.cache_path)
+                .context("Opening the venue cache")?;
+            serde_yaml::to_writer(file, &self.cache)
+                .context("Writing the venue cache")?;
+        }
+        Ok(())
+    }
+}
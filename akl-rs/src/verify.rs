@@ -0,0 +1,127 @@
+// Integrity check of the library.
+//
+// The original file of every document is hashed again and
+// compared with the hash recorded when it was stored, and both
+// files are parsed again, to detect files corrupted or deleted
+// behind our back.
+//
+// The `checksum` of a document identifies its content, but is
+// computed on the parsed pdf, which does not survive a round trip
+// through the disk byte for byte: the hash of the stored file is
+// recorded separately, in `raw_checksum`.
+
+use std::path::Path;
+
+use anyhow::{Result, Context};
+use sha2::{Digest, Sha256};
+
+use crate::Document;
+use crate::identifiers::{self, IdentifierKind};
+use crate::pdflib::PdfDocument;
+
+/// A problem found on a document.
+#[derive(Debug)]
+pub enum Problem {
+    /// The original file does not exist.
+    MissingRaw,
+    /// The original file cannot be parsed.
+    UnreadableRaw(anyhow::Error),
+    /// The original file changed.
+    ChecksumMismatch(String),
+    /// The modified file does not exist.
+    MissingMod,
+    /// The modified file cannot be parsed.
+    UnreadableMod(anyhow::Error),
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::MissingRaw => write!(f, "the original file is missing"),
+            Problem::UnreadableRaw(e) => write!(f, "the original file cannot be read: {e:#}"),
+            Problem::ChecksumMismatch(c) => write!(f, "the original file changed (its hash is now {c})"),
+            Problem::MissingMod => write!(f, "the modified file is missing"),
+            Problem::UnreadableMod(e) => write!(f, "the modified file cannot be read: {e:#}"),
+        }
+    }
+}
+
+impl Problem {
+    /// What the user can do about it.
+    pub fn suggestion(&self, doc : &Document) -> String {
+        match self {
+            Problem::MissingMod | Problem::UnreadableMod(_) => {
+                format!("akl reconvert --uri {}", doc.checksum)
+            }
+            _ => {
+                let download = doc.identifiers.iter().find(|i| {
+                    matches!(identifiers::kind_of(i), IdentifierKind::Arxiv | IdentifierKind::Url)
+                });
+                match download {
+                    Some(i) => format!("akl import --force --uri {i}"),
+                    None => format!("restore the file from a backup, or akl remove --uri {}", doc.checksum),
+                }
+            }
+        }
+    }
+}
+
+/// Hash of the content of a file.
+pub fn file_checksum(path : &Path) -> Result<String> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Reading {path:?}"))?;
+    Ok(format!("{:x}", Sha256::digest(bytes)))
+}
+
+/// Parses a pdf file.
+fn load(path : &Path) -> Result<PdfDocument> {
+    let pdf = lopdf::Document::load(path)
+        .with_context(|| format!("Parsing {path:?}"))?;
+    Ok(PdfDocument::try_from(pdf)?)
+}
+
+/// Result of the verification of a document.
+#[derive(Debug, Default)]
+pub struct Verification {
+    pub problems : Vec<Problem>,
+
+    /// Hash of the original file, when it could be read.
+    pub raw_checksum : Option<String>,
+}
+
+/// Loads a file of a document, when it is a pdf file.
+fn load_pdf(doc : &Document, path : &Path) -> Result<()> {
+    if doc.format.is_pdf() {
+        load(path)?;
+    }
+    Ok(())
+}
+
+/// Checks the original and modified files of a document.
+pub fn verify(doc : &Document, raw : &Path, modified : &Path) -> Verification {
+    let mut problems = vec![];
+    let mut raw_checksum = None;
+    if !raw.exists() {
+        problems.push(Problem::MissingRaw);
+    } else {
+        match file_checksum(raw) {
+            Ok(c) if doc.raw_checksum.as_ref().is_some_and(|r| *r != c) => {
+                problems.push(Problem::ChecksumMismatch(c));
+            }
+            Ok(c) => {
+                raw_checksum = Some(c);
+                // the files of the other formats are not parsed
+                if let Err(e) = load_pdf(doc, raw) {
+                    problems.push(Problem::UnreadableRaw(e));
+                }
+            }
+            Err(e) => { problems.push(Problem::UnreadableRaw(e)); }
+        }
+    }
+    if !modified.exists() {
+        problems.push(Problem::MissingMod);
+    } else if let Err(e) = load_pdf(doc, modified) {
+        problems.push(Problem::UnreadableMod(e));
+    }
+    Verification { problems, raw_checksum }
+}
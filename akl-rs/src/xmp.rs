@@ -0,0 +1,72 @@
+// XMP metadata of the pdf files.
+//
+// Besides the Info dictionary, pdf files may carry an XMP packet
+// (an RDF/XML document, in the `Metadata` stream of the catalog),
+// where the abstract is the `dc:description`, and the keywords are
+// the `dc:subject` bag or the `pdf:Keywords` property.
+
+use xml::reader::{EventReader, XmlEvent};
+
+const DC : &str = "http://purl.org/dc/elements/1.1/";
+const PDF : &str = "http://ns.adobe.com/pdf/1.3/";
+
+/// The metadata of an XMP packet that the library uses.
+#[derive(Debug, Default)]
+pub struct Xmp {
+    pub description : Option<String>,
+    pub keywords    : Vec<String>,
+}
+
+/// Splits a list of keywords separated by commas or semicolons.
+pub fn split_keywords(s : &str) -> Vec<String> {
+    s.split([',', ';'])
+        .map(str::trim)
+        .filter(|k| !k.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Reads an XMP packet. Malformed packets give what could
+/// be read before the error.
+pub fn parse(src : &str) -> Xmp {
+    let mut xmp = Xmp::default();
+    // (namespace, local name) of the open elements
+    let mut stack : Vec<(String, String)> = vec![];
+    let inside = |stack : &[(String, String)], ns : &str, name : &str| {
+        stack.iter().any(|(n, l)| n == ns && l == name)
+    };
+    for event in EventReader::from_str(src) {
+        match event {
+            Ok(XmlEvent::StartElement { name, attributes, .. }) => {
+                for a in attributes {
+                    if a.name.namespace.as_deref() == Some(PDF) && a.name.local_name == "Keywords" {
+                        xmp.keywords.extend(split_keywords(&a.value));
+                    }
+                }
+                stack.push((name.namespace.unwrap_or_default(), name.local_name));
+            }
+            Ok(XmlEvent::EndElement { .. }) => {
+                stack.pop();
+            }
+            Ok(XmlEvent::Characters(text)) | Ok(XmlEvent::CData(text)) => {
+                let text = text.trim();
+                if text.is_empty() {
+                    continue;
+                }
+                if inside(&stack, DC, "description") {
+                    xmp.description.get_or_insert_with(String::new).push_str(text);
+                } else if inside(&stack, DC, "subject") {
+                    xmp.keywords.push(text.to_string());
+                } else if inside(&stack, PDF, "Keywords") {
+                    xmp.keywords.extend(split_keywords(text));
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                log::debug!("Malformed XMP metadata: {e}");
+                break;
+            }
+        }
+    }
+    xmp
+}
@@ -0,0 +1,456 @@
+// Importing a Zotero library.
+//
+// Two kinds of exports are read:
+//
+// - Zotero RDF ("Export Collection…" with files): the items, their
+//   attachments (stored next to the export, under `files/`), their
+//   tags and the collections containing them.
+// - BibTeX exports (BetterBibTeX, or the built-in exporter), where
+//   the attachments are in the `file` field and the tags in the
+//   `keywords` field. These exports know nothing of collections.
+//
+// The attached pdf files are imported first; items without any are
+// downloaded from their arxiv id, url or doi, like BibTeX imports.
+//
+// Conversely, the documents of the library can be pushed to a
+// running Zotero through its connector server, for colleagues who
+// use Zotero (see `akl export --format zotero`).
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, Context};
+use serde_json::json;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::{Document, bibtex, identifiers, latex};
+use crate::doctype::DocType;
+
+const RDF     : &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const DC      : &str = "http://purl.org/dc/elements/1.1/";
+const DCTERMS : &str = "http://purl.org/dc/terms/";
+const Z       : &str = "http://www.zotero.org/namespaces/export#";
+const BIB     : &str = "http://purl.org/net/biblio#";
+const FOAF    : &str = "http://xmlns.com/foaf/0.1/";
+const LINK    : &str = "http://purl.org/rss/1.0/modules/link/";
+
+/// An item of a Zotero library.
+#[derive(Debug, Clone, Default)]
+pub struct Item {
+    /// Identifier of the item in the export (to report errors).
+    pub key         : String,
+    pub title       : Option<String>,
+    pub authors     : Vec<String>,
+    pub year        : Option<u32>,
+    /// Journal, proceedings or conference.
+    pub venues      : Vec<String>,
+    /// Identifiers of the item (`doi:…`, `arxiv:…`).
+    pub identifiers : Vec<String>,
+    /// Uris to download the item from, when it has no attachment.
+    pub uris        : Vec<String>,
+    pub tags        : Vec<String>,
+    /// Collections containing the item, subcollections
+    /// being named `parent/child`.
+    pub collections : Vec<String>,
+    /// Attached pdf files.
+    pub attachments : Vec<PathBuf>,
+    pub doc_type    : Option<DocType>,
+}
+
+impl Item {
+    /// The item described by a BibTeX entry, without tags,
+    /// collections nor attachments.
+    pub fn from_bibtex(entry : &bibtex::Entry) -> Self {
+        Item {
+            key: entry.key.clone(),
+            title: entry.title(),
+            authors: entry.authors(),
+            year: entry.year(),
+            venues: entry.venues(),
+            identifiers: entry.doi().map(|d| format!("doi:{d}")).into_iter()
+                .chain(entry.arxiv_id().map(|a| format!("arxiv:{a}")))
+                .collect(),
+            uris: entry.uris(),
+            doc_type: DocType::from_bibtex(&entry.kind),
+            ..Default::default()
+        }
+    }
+}
+
+/// Reads a Zotero export, RDF or BibTeX.
+pub fn read(path : &Path) -> Result<Vec<Item>> {
+    let src = std::fs::read_to_string(path)
+        .with_context(|| format!("Reading {path:?}"))?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    if src.trim_start().starts_with('<') {
+        read_rdf(&src, dir)
+    } else {
+        Ok(read_bibtex(&src, dir))
+    }
+}
+
+// BIBTEX //
+
+/// Paths of the `file` field: `path;path` (BetterBibTeX) or
+/// `description:path:mime type` (built-in exporter).
+fn bibtex_files(field : &str, dir : &Path) -> Vec<PathBuf> {
+    field.split(';')
+        .map(|f| {
+            let parts : Vec<&str> = f.split(':').collect();
+            if parts.len() >= 3 && parts[parts.len() - 1].contains('/') {
+                parts[1..parts.len() - 1].join(":")
+            } else {
+                f.to_string()
+            }
+        })
+        .map(|f| dir.join(f.trim()))
+        .filter(|f| f.extension().is_some_and(|e| e.eq_ignore_ascii_case("pdf")))
+        .collect()
+}
+
+fn read_bibtex(src : &str, dir : &Path) -> Vec<Item> {
+    bibtex::parse(src).into_iter()
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                log::warn!("Ignoring an entry of the export: {e:#}");
+                None
+            }
+        })
+        .map(|entry| Item {
+            tags: entry.field("keywords")
+                .map(|k| k.split(',').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect())
+                .unwrap_or_default(),
+            attachments: entry.field("file").map(|f| bibtex_files(&f, dir)).unwrap_or_default(),
+            ..Item::from_bibtex(&entry)
+        })
+        .collect()
+}
+
+// RDF //
+
+/// An element of the xml document.
+#[derive(Debug, Default)]
+struct Element {
+    ns       : String,
+    name     : String,
+    attrs    : Vec<(String, String, String)>,
+    children : Vec<Element>,
+    text     : String,
+}
+
+impl Element {
+    fn is(&self, ns : &str, name : &str) -> bool {
+        self.ns == ns && self.name == name
+    }
+
+    fn attr(&self, ns : &str, name : &str) -> Option<&str> {
+        self.attrs.iter()
+            .find(|(n, a, _)| n == ns && a == name)
+            .map(|(_, _, v)| v.as_str())
+    }
+
+    fn children<'a>(&'a self, ns : &'a str, name : &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |c| c.is(ns, name))
+    }
+
+    fn child(&self, ns : &str, name : &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.is(ns, name))
+    }
+
+    /// Text of the element, if not blank.
+    fn value(&self) -> Option<String> {
+        let text = self.text.split_whitespace().collect::<Vec<_>>().join(" ");
+        (!text.is_empty()).then_some(text)
+    }
+
+    /// Text of a child element.
+    fn child_value(&self, ns : &str, name : &str) -> Option<String> {
+        self.child(ns, name)?.value()
+    }
+
+    /// Every element below this one, in document order.
+    fn descendants(&self) -> Vec<&Element> {
+        let mut all = vec![];
+        for c in &self.children {
+            all.push(c);
+            all.extend(c.descendants());
+        }
+        all
+    }
+}
+
+/// Parses an xml document into a tree of elements.
+fn parse_xml(src : &str) -> Result<Element> {
+    let mut stack = vec![Element::default()];
+    for event in EventReader::new(src.as_bytes()) {
+        match event.context("Parsing the Zotero RDF export")? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                stack.push(Element {
+                    ns: name.namespace.unwrap_or_default(),
+                    name: name.local_name,
+                    attrs: attributes.into_iter()
+                        .map(|a| (a.name.namespace.unwrap_or_default(), a.name.local_name, a.value))
+                        .collect(),
+                    ..Default::default()
+                });
+            }
+            XmlEvent::EndElement { .. } => {
+                let element = stack.pop().context("Unbalanced xml")?;
+                stack.last_mut().context("Unbalanced xml")?.children.push(element);
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some(top) = stack.last_mut() {
+                    top.text.push_str(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut document = stack.pop().context("Empty xml document")?;
+    document.children.pop().context("Empty xml document")
+}
+
+/// The Zotero item type of a resource (`journalArticle`, `attachment`…).
+fn item_type(resource : &Element) -> Option<String> {
+    resource.child_value(Z, "itemType")
+}
+
+/// The description of a resource, following `rdf:resource` references
+/// to the resources described at the top level.
+fn resolve<'a>(element : &'a Element, resources : &HashMap<&str, &'a Element>) -> &'a Element {
+    match element.attr(RDF, "resource").and_then(|r| resources.get(r)) {
+        Some(resource) if element.children.is_empty() => resource,
+        _ => element,
+    }
+}
+
+/// `First Last` names of the people of a list (authors, editors).
+fn people(list : &Element) -> Vec<String> {
+    list.descendants().into_iter()
+        .filter(|e| e.is(FOAF, "Person"))
+        .filter_map(|p| {
+            let family = p.child_value(FOAF, "surname");
+            let given = p.child_value(FOAF, "givenName");
+            match (given, family) {
+                (Some(g), Some(f)) => Some(format!("{g} {f}")),
+                (g, f) => f.or(g),
+            }
+        })
+        .collect()
+}
+
+/// The first four digit number of a date.
+fn parse_year(date : &str) -> Option<u32> {
+    date.split(|c : char| !c.is_ascii_digit())
+        .find(|d| d.len() == 4)?
+        .parse().ok()
+}
+
+fn read_rdf(src : &str, dir : &Path) -> Result<Vec<Item>> {
+    let root = parse_xml(src)?;
+    if !root.is(RDF, "RDF") {
+        anyhow::bail!("Not a Zotero RDF export (the root is {})", root.name);
+    }
+    let resources : HashMap<&str, &Element> = root.children.iter()
+        .filter_map(|r| Some((r.attr(RDF, "about")?, r)))
+        .collect();
+
+    // full names of the collections, and their items
+    let collections : Vec<&Element> = root.children(Z, "Collection").collect();
+    let mut parents : HashMap<&str, &str> = HashMap::new();
+    for c in &collections {
+        for part in c.children(DCTERMS, "hasPart").filter_map(|p| p.attr(RDF, "resource")) {
+            if let Some(about) = c.attr(RDF, "about") {
+                parents.insert(part, about);
+            }
+        }
+    }
+    let full_name = |about : &str| -> String {
+        let mut names = vec![];
+        let mut current = Some(about);
+        // a cycle would be a broken export
+        while let Some(c) = current.filter(|_| names.len() <= collections.len()) {
+            names.push(resources.get(c).and_then(|r| r.child_value(DC, "title")).unwrap_or_default());
+            current = parents.get(c).copied();
+        }
+        names.reverse();
+        names.join("/")
+    };
+    let mut membership : HashMap<&str, Vec<String>> = HashMap::new();
+    for c in &collections {
+        let name = full_name(c.attr(RDF, "about").unwrap_or_default());
+        for part in c.children(DCTERMS, "hasPart").filter_map(|p| p.attr(RDF, "resource")) {
+            membership.entry(part).or_default().push(name.clone());
+        }
+    }
+
+    let mut items = vec![];
+    for resource in &root.children {
+        let Some(kind) = item_type(resource) else {
+            continue;
+        };
+        if matches!(kind.as_str(), "attachment" | "note") {
+            continue;
+        }
+        let about = resource.attr(RDF, "about").unwrap_or_default();
+        let mut item = Item {
+            key: about.to_string(),
+            title: resource.child_value(DC, "title"),
+            authors: resource.children(BIB, "authors").flat_map(people).collect(),
+            year: resource.child_value(DC, "date").as_deref().and_then(parse_year),
+            collections: membership.remove(about).unwrap_or_default(),
+            doc_type: DocType::from_zotero(&kind),
+            ..Default::default()
+        };
+        for part in resource.children(DCTERMS, "isPartOf").chain(resource.children(BIB, "presentedAt")) {
+            let venue = resolve(part, &resources);
+            // a journal is described inside the issue
+            let venue = venue.children.first().filter(|_| venue.child_value(DC, "title").is_none()).unwrap_or(venue);
+            item.venues.extend(venue.child_value(DC, "title"));
+        }
+        for subject in resource.children(DC, "subject") {
+            // plain tags, or automatic tags with their value inside
+            let tag = subject.value().or_else(|| {
+                subject.descendants().into_iter().find(|e| e.is(RDF, "value"))?.value()
+            });
+            item.tags.extend(tag);
+        }
+        for identifier in resource.children(DC, "identifier") {
+            if let Some(value) = identifier.value() {
+                if let Some(doi) = value.strip_prefix("DOI ") {
+                    item.identifiers.push(format!("doi:{doi}"));
+                }
+            }
+            let url = identifier.descendants().into_iter()
+                .find(|e| e.is(RDF, "value"))
+                .and_then(Element::value);
+            item.uris.extend(url.filter(|u| u.starts_with("http")));
+        }
+        item.uris.extend(item.identifiers.iter().cloned());
+        for link in resource.children(LINK, "link") {
+            let attachment = resolve(link, &resources);
+            let is_pdf = attachment.child_value(LINK, "type").as_deref() == Some("application/pdf");
+            let file = attachment.child(RDF, "resource").and_then(|r| r.attr(RDF, "resource"));
+            if let Some(file) = file.filter(|f| is_pdf || f.to_lowercase().ends_with(".pdf")) {
+                item.attachments.push(dir.join(file));
+            }
+        }
+        items.push(item);
+    }
+    Ok(items)
+}
+
+// PUSHING TO ZOTERO //
+
+/// Connector server of a running Zotero (the one used by the
+/// browser extensions).
+const CONNECTOR : &str = "http://127.0.0.1:23119/connector";
+
+/// A document, in the item format of the connector. The citation
+/// key is given in the `extra` field, where BetterBibTeX reads it.
+/// The pdf file cannot be sent, a link opens it with akl instead
+/// (and arxiv documents are downloaded by Zotero).
+pub fn connector_item(key : &str, doc : &Document, open_uri : &str) -> serde_json::Value {
+    let arxiv = identifiers::arxiv_id(&doc.identifiers);
+    let venue = doc.context.first();
+    let kind = match (venue, &arxiv) {
+        (Some(_), _) => "journalArticle",
+        (None, Some(_)) => "preprint",
+        (None, None) => "document",
+    };
+    let creators : Vec<serde_json::Value> = doc.authors.iter()
+        .map(|a| {
+            let (given, family) = latex::split_name(a);
+            json!({ "firstName": given, "lastName": family, "creatorType": "author" })
+        })
+        .collect();
+    let mut attachments = vec![
+        json!({ "title": "Open in akl", "url": open_uri, "mimeType": "text/html", "snapshot": false }),
+    ];
+    if let Some(id) = &arxiv {
+        attachments.push(json!({
+            "title": "Full Text PDF",
+            "url": format!("https://arxiv.org/pdf/{id}"),
+            "mimeType": "application/pdf",
+        }));
+    }
+    let mut item = json!({
+        "id": doc.checksum,
+        "itemType": kind,
+        "title": doc.title,
+        "creators": creators,
+        "date": doc.year.to_string(),
+        "tags": doc.tags.iter().map(|t| json!({ "tag": t })).collect::<Vec<_>>(),
+        "extra": format!("Citation Key: {key}"),
+        "attachments": attachments,
+    });
+    let fields = [
+        ("publicationTitle", venue.cloned()),
+        ("DOI", identifiers::doi(&doc.identifiers)),
+        ("url", identifiers::web_url(&doc.identifiers).cloned()),
+        ("repository", arxiv.as_ref().filter(|_| venue.is_none()).map(|_| "arXiv".to_string())),
+        ("archiveID", arxiv.filter(|_| venue.is_none()).map(|id| format!("arXiv:{id}"))),
+    ];
+    for (name, value) in fields {
+        if let Some(value) = value {
+            item[name] = json!(value);
+        }
+    }
+    item
+}
+
+/// Sends items to the running Zotero, which adds
+/// them to the collection selected in its window.
+pub fn push(items : &[serde_json::Value]) -> Result<()> {
+    let client = reqwest::blocking::Client::new();
+    let running = client.get(format!("{CONNECTOR}/ping")).send()
+        .is_ok_and(|r| r.status().is_success());
+    if !running {
+        anyhow::bail!("Zotero is not running (or its connector server is disabled), \
+                       use --output to write a file to import instead");
+    }
+    let response = client.post(format!("{CONNECTOR}/saveItems"))
+        .json(&json!({
+            "sessionID": format!("akl-{}", chrono::Utc::now().timestamp_millis()),
+            "uri": "akl://",
+            "items": items,
+        }))
+        .send()
+        .context("Sending the items to Zotero")?;
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!("Zotero refused the items ({status}): {}", response.text().unwrap_or_default());
+    }
+    Ok(())
+}
+
+/// Checksums of the documents already sent to Zotero, so that
+/// only the new ones are sent the next time.
+#[derive(Debug)]
+pub struct Exported {
+    path : PathBuf,
+    pub checksums : BTreeSet<String>,
+}
+
+impl Exported {
+    /// Loads the exported documents. A missing file means none.
+    pub fn load(path : &Path) -> Result<Self> {
+        let checksums = if path.exists() {
+            let file = std::fs::File::open(path)
+                .with_context(|| format!("Opening {path:?}"))?;
+            serde_yaml::from_reader(file)
+                .with_context(|| format!("Parsing {path:?}"))?
+        } else {
+            BTreeSet::new()
+        };
+        Ok(Exported { path: path.into(), checksums })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let file = std::fs::File::create(&self.path)
+            .with_context(|| format!("Creating {:?}", self.path))?;
+        serde_yaml::to_writer(file, &self.checksums)
+            .with_context(|| format!("Writing {:?}", self.path))
+    }
+}